@@ -0,0 +1,46 @@
+/* C ABI of the subscan library, built with
+ *
+ *     cargo rustc --lib --release --no-default-features --features ffi --crate-type cdylib
+ *
+ * Strings passed in and returned are NUL-terminated UTF-8 JSON. Returned
+ * strings are owned by the caller and released with subscan_string_free.
+ */
+#ifndef SUBSCAN_H
+#define SUBSCAN_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SubscanScan SubscanScan;
+
+/* Starts a scan. config_json is an object with "domain", "wordlist" and
+ * "resolvers" (file paths), and optionally "threads" (100),
+ * "timeout_secs" (2), "max_queries" and "verify" (false). Returns NULL on
+ * an unusable config; subscan_last_error() says why. */
+SubscanScan *subscan_start_scan(const char *config_json);
+
+/* Findings since the last poll, waiting up to wait_ms for one:
+ * {"found": [{"name": ..., "resolution": {...}}], "done": false}. The first
+ * poll after the scan ends has "done": true and either "result" (the scan's
+ * result document) or "error". */
+char *subscan_poll_results(SubscanScan *scan, uint32_t wait_ms);
+
+/* Stops the scan after the queries in flight; keep polling for the result. */
+void subscan_cancel(SubscanScan *scan);
+
+/* Cancels the scan if it is running, waits for it and frees the handle. */
+void subscan_free(SubscanScan *scan);
+
+void subscan_string_free(char *s);
+
+/* Why the last call on this thread returned NULL; owned by the library. */
+const char *subscan_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
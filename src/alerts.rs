@@ -0,0 +1,524 @@
+//! Alerting rules for monitor mode: each change event is checked against
+//! the rules of an `alerts.yaml`, and every rule it matches runs its
+//! actions.
+//!
+//! ```yaml
+//! rules:
+//!   - name: new vpn host
+//!     when:
+//!       change: [new, back]
+//!       name: "vpn*"
+//!     do:
+//!       - webhook: https://hooks.example.com/services/T000
+//!       - email: secops@example.com
+//!   - name: takeover candidate
+//!     when:
+//!       takeover: true
+//!     do:
+//!       - exec: ./page-oncall.sh
+//!   - name: address outside our ranges
+//!     when:
+//!       outside: [192.0.2.0/24, 2001:db8::/32]
+//!     do:
+//!       - webhook: https://hooks.example.com/services/T000
+//! ```
+//!
+//! Every condition of a rule must hold; a rule without conditions matches
+//! every event. With an `smtp:` section (see [`crate::smtp`]), email
+//! actions go through that server instead of `sendmail`, rules marked
+//! `severity: high` are mailed to its `to` at once, and every change can
+//! be collected into a periodic digest.
+
+use std::net::IpAddr;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::cluster::{netblock_v4, netblock_v6};
+use crate::names;
+use crate::scanner::Resolution;
+use crate::smtp::{Smtp, SmtpConfig};
+
+/// The changes monitor mode reports.
+const CHANGES: &[&str] = &["new", "gone", "back", "cname_changed", "addresses_changed"];
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+const EXEC_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// CNAME targets on services that hand a released name to whoever claims
+/// it next; a name pointing at one that no longer answers can be taken over.
+const TAKEOVER_SUFFIXES: &[&str] = &[
+    ".azurewebsites.net",
+    ".cloudapp.net",
+    ".cloudapp.azure.com",
+    ".trafficmanager.net",
+    ".blob.core.windows.net",
+    ".azureedge.net",
+    ".herokuapp.com",
+    ".herokudns.com",
+    ".github.io",
+    ".s3.amazonaws.com",
+    ".s3-website.amazonaws.com",
+    ".elasticbeanstalk.com",
+    ".bitbucket.io",
+    ".ghost.io",
+    ".myshopify.com",
+    ".netlify.app",
+    ".pantheonsite.io",
+    ".readthedocs.io",
+    ".surge.sh",
+    ".unbouncepages.com",
+    ".wordpress.com",
+    ".zendesk.com",
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    #[serde(default)]
+    rules: Vec<RuleSpec>,
+    smtp: Option<SmtpConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSpec {
+    name: String,
+    #[serde(default)]
+    when: ConditionSpec,
+    #[serde(default)]
+    severity: Severity,
+    // `- webhook: URL` rather than YAML's `- !webhook URL`.
+    #[serde(default, rename = "do", deserialize_with = "serde_yaml_ng::with::singleton_map_recursive::deserialize")]
+    actions: Vec<Action>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConditionSpec {
+    name: Option<String>,
+    #[serde(default)]
+    change: Vec<String>,
+    #[serde(default)]
+    takeover: bool,
+    #[serde(default)]
+    outside: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    #[default]
+    Medium,
+    /// Also mailed to the `smtp` section's `to` as soon as it fires.
+    High,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// POST `{"text", "rule", "severity", "event"}` to a URL; `text` makes it show up
+    /// in Slack and Mattermost incoming webhooks as is.
+    Webhook(String),
+    /// Run a shell command with the event as JSON on stdin.
+    Exec(String),
+    /// Mail the event to an address, through the local `sendmail` unless
+    /// an SMTP server is configured.
+    Email(String),
+}
+
+/// What an event has to be for a rule to fire.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Condition {
+    /// Glob (`*`, `?`) over the whole name, so `vpn*` matches both
+    /// `vpn.example.com` and `vpn2.example.com`.
+    pub name: Option<String>,
+    pub changes: Vec<String>,
+    /// The name points through a CNAME at a service it can be claimed on,
+    /// and the CNAME target no longer answers.
+    pub takeover: bool,
+    /// Fires when an address is in none of these networks.
+    pub outside: Vec<(IpAddr, u8)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub name: String,
+    pub when: Condition,
+    pub severity: Severity,
+    pub actions: Vec<Action>,
+}
+
+pub struct Alerts {
+    pub rules: Vec<Rule>,
+    pub smtp: Option<Smtp>,
+    http: reqwest::Client,
+    /// Changes since the last digest.
+    digest: Mutex<Vec<Value>>,
+}
+
+impl Alerts {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("could not read alerts file '{}': {}", path, e))?;
+        Self::parse(&text).map_err(|e| format!("alerts file '{}' {}", path, e))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let spec: Spec = serde_yaml_ng::from_str(text).map_err(|e| format!("is not valid: {}", e))?;
+        let smtp = spec.smtp.map(Smtp::new).transpose()?;
+        let mails_high = smtp.as_ref().is_some_and(|smtp| !smtp.config.to.is_empty());
+        let rules = spec.rules.into_iter().map(|rule| Rule::compile(rule, mails_high)).collect::<Result<Vec<_>, _>>()?;
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("subscan/", env!("CARGO_PKG_VERSION")))
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .map_err(|e| format!("could not set up webhooks: {}", e))?;
+        Ok(Self {
+            rules,
+            smtp,
+            http,
+            digest: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// The rules `event` (a monitor change event) matches.
+    pub fn matching<'a>(&'a self, event: &'a Value) -> impl Iterator<Item = &'a Rule> + 'a {
+        self.rules.iter().filter(move |rule| rule.when.matches(event))
+    }
+
+    /// Starts the actions of every rule `event` matches on `tasks`, so a
+    /// slow webhook does not hold up the next check.
+    pub fn dispatch(self: &Arc<Self>, event: &Value, tasks: &mut JoinSet<()>) {
+        if self.digest_every().is_some() {
+            self.digest.lock().unwrap().push(event.clone());
+        }
+        for (index, rule) in self.rules.iter().enumerate() {
+            if !rule.when.matches(event) {
+                continue;
+            }
+            info!("alert '{}' fired for {}", rule.name, event["name"].as_str().unwrap_or_default());
+            let (alerts, event) = (self.clone(), event.clone());
+            tasks.spawn(async move {
+                let rule = &alerts.rules[index];
+                for action in &rule.actions {
+                    if let Err(e) = alerts.run(rule, action, &event).await {
+                        warn!("alert '{}' could not {}", rule.name, e);
+                    }
+                }
+                if let Err(e) = alerts.mail_high(rule, &event).await {
+                    warn!("alert '{}' could not mail {}", rule.name, e);
+                }
+            });
+        }
+    }
+
+    fn digest_every(&self) -> Option<Duration> {
+        self.smtp.as_ref()?.config.digest_every.map(Duration::from_secs)
+    }
+
+    /// Mails a high-severity alert to the SMTP recipients its own email
+    /// actions did not already cover.
+    async fn mail_high(&self, rule: &Rule, event: &Value) -> Result<(), String> {
+        let Some(smtp) = self.smtp.as_ref().filter(|_| rule.severity == Severity::High) else {
+            return Ok(());
+        };
+        let to: Vec<String> = smtp
+            .config
+            .to
+            .iter()
+            .filter(|address| !rule.actions.contains(&Action::Email(address.to_string())))
+            .cloned()
+            .collect();
+        if to.is_empty() {
+            return Ok(());
+        }
+        let text = summary(rule, event);
+        smtp.send(&to, &format!("[subscan] {}", text), &mail_body(&text, event)).await
+    }
+
+    /// Mails the changes collected since the last digest every
+    /// `digest_every` seconds, and once more when `stop` is set. Returns
+    /// at once without a digest configured.
+    pub async fn send_digests(self: Arc<Self>, stop: Arc<AtomicBool>) {
+        let (Some(every), Some(smtp)) = (self.digest_every(), &self.smtp) else {
+            return;
+        };
+        let mut since = chrono::Utc::now();
+        loop {
+            let due = tokio::time::Instant::now() + every;
+            while !stop.load(Ordering::Relaxed) && tokio::time::Instant::now() < due {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            let events = std::mem::take(&mut *self.digest.lock().unwrap());
+            let until = chrono::Utc::now();
+            if !events.is_empty() {
+                let (subject, body) = digest(&events, since, until);
+                match smtp.send(&smtp.config.to, &subject, &body).await {
+                    Ok(()) => info!("mailed a digest of {} changes", events.len()),
+                    Err(e) => warn!("could not mail the digest of {} changes: {}", events.len(), e),
+                }
+            }
+            since = until;
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+        }
+    }
+
+    async fn run(&self, rule: &Rule, action: &Action, event: &Value) -> Result<(), String> {
+        let text = summary(rule, event);
+        match action {
+            Action::Webhook(url) => {
+                let body = json!({ "text": text, "rule": rule.name, "severity": rule.severity, "event": event });
+                let response = self
+                    .http
+                    .post(url)
+                    .header("content-type", "application/json")
+                    .body(body.to_string())
+                    .send()
+                    .await
+                    .map_err(|e| format!("call {}: {}", url, e))?;
+                if !response.status().is_success() {
+                    return Err(format!("call {}: HTTP {}", url, response.status()));
+                }
+            }
+            Action::Exec(command) => {
+                // The event goes in through stdin and the environment rather
+                // than into the command line, where a name could inject shell.
+                let mut child = Command::new("sh")
+                    .arg("-c")
+                    .arg(command)
+                    .env("SUBSCAN_RULE", &rule.name)
+                    .env("SUBSCAN_NAME", event["name"].as_str().unwrap_or_default())
+                    .env("SUBSCAN_CHANGE", event["change"].as_str().unwrap_or_default())
+                    .stdin(Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|e| format!("run '{}': {}", command, e))?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    let _ = stdin.write_all(format!("{}\n", event).as_bytes()).await;
+                }
+                let status = tokio::time::timeout(EXEC_TIMEOUT, child.wait())
+                    .await
+                    .map_err(|_| format!("run '{}': still running after {}s", command, EXEC_TIMEOUT.as_secs()))?
+                    .map_err(|e| format!("run '{}': {}", command, e))?;
+                if !status.success() {
+                    return Err(format!("run '{}': {}", command, status));
+                }
+            }
+            Action::Email(to) if let Some(smtp) = &self.smtp => {
+                smtp.send(std::slice::from_ref(to), &format!("[subscan] {}", text), &mail_body(&text, event))
+                    .await
+                    .map_err(|e| format!("mail {}: {}", to, e))?;
+            }
+            Action::Email(to) => {
+                let subject = format!("[subscan] {}", text).replace(['\r', '\n'], " ");
+                let message = format!("To: {}\nSubject: {}\nContent-Type: text/plain; charset=utf-8\n\n{}", to, subject, mail_body(&text, event));
+                let mut child = Command::new("sendmail")
+                    .arg("-t")
+                    .stdin(Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .map_err(|e| format!("mail {} through sendmail: {}", to, e))?;
+                if let Some(mut stdin) = child.stdin.take() {
+                    stdin.write_all(message.as_bytes()).await.map_err(|e| format!("mail {}: {}", to, e))?;
+                }
+                let status = child.wait().await.map_err(|e| format!("mail {}: {}", to, e))?;
+                if !status.success() {
+                    return Err(format!("mail {}: sendmail {}", to, status));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Rule {
+    /// `mails_high`: high-severity rules are mailed even without actions.
+    fn compile(spec: RuleSpec, mails_high: bool) -> Result<Self, String> {
+        let problem = |e: String| format!("rule '{}': {}", spec.name, e);
+        if spec.actions.is_empty() && !(mails_high && spec.severity == Severity::High) {
+            return Err(problem("has no actions under `do`".to_string()));
+        }
+        if let Some(change) = spec.when.change.iter().find(|change| !CHANGES.contains(&change.as_str())) {
+            return Err(problem(format!("unknown change '{}', expected one of {}", change, CHANGES.join(", "))));
+        }
+        let outside = spec.when.outside.iter().map(|network| parse_network(network)).collect::<Result<Vec<_>, _>>().map_err(problem)?;
+        Ok(Self {
+            when: Condition {
+                name: spec.when.name.map(|name| name.to_lowercase()),
+                changes: spec.when.change,
+                takeover: spec.when.takeover,
+                outside,
+            },
+            name: spec.name,
+            severity: spec.severity,
+            actions: spec.actions,
+        })
+    }
+}
+
+impl Condition {
+    pub fn matches(&self, event: &Value) -> bool {
+        let name = event["name"].as_str().unwrap_or_default().to_lowercase();
+        let change = event["change"].as_str().unwrap_or_default();
+        let before: Option<Resolution> = serde_json::from_value(event["before"].clone()).ok();
+        let after: Option<Resolution> = serde_json::from_value(event["after"].clone()).ok();
+        if let Some(pattern) = &self.name
+            && !names::glob(pattern, &name)
+        {
+            return false;
+        }
+        if !self.changes.is_empty() && !self.changes.iter().any(|wanted| wanted == change) {
+            return false;
+        }
+        if self.takeover && !takeover_candidate(change, before.as_ref(), after.as_ref()) {
+            return false;
+        }
+        if !self.outside.is_empty() {
+            let addresses = after.as_ref().map(|after| after.addresses.as_slice()).unwrap_or_default();
+            let stray = addresses.iter().any(|address| !self.outside.iter().any(|(network, prefix)| contains(*network, *prefix, *address)));
+            if !stray {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Whether a name now dangles: it went away, or still has its CNAME chain
+/// but no address, while pointing at a service names can be claimed on.
+pub fn takeover_candidate(change: &str, before: Option<&Resolution>, after: Option<&Resolution>) -> bool {
+    let dangling = match after {
+        Some(after) => after.addresses.is_empty(),
+        None => change == "gone",
+    };
+    let chain = after.filter(|after| !after.cname_chain.is_empty()).or(before).map(|r| r.cname_chain.as_slice()).unwrap_or_default();
+    dangling
+        && chain.iter().any(|cname| {
+            let cname = cname.trim_end_matches('.').to_lowercase();
+            TAKEOVER_SUFFIXES.iter().any(|suffix| cname.ends_with(suffix))
+        })
+}
+
+fn summary(rule: &Rule, event: &Value) -> String {
+    let addresses: Vec<&str> = event["after"]["addresses"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+    let mut text = format!(
+        "{}: {} {}",
+        rule.name,
+        event["name"].as_str().unwrap_or_default(),
+        event["change"].as_str().unwrap_or_default().replace('_', " ")
+    );
+    if !addresses.is_empty() {
+        text.push_str(&format!(" ({})", addresses.join(", ")));
+    }
+    text
+}
+
+fn mail_body(text: &str, event: &Value) -> String {
+    format!("{}\n\n{}\n", text, serde_json::to_string_pretty(event).unwrap_or_default())
+}
+
+/// Subject and body of a digest of `events`, one line per change.
+fn digest(events: &[Value], since: chrono::DateTime<chrono::Utc>, until: chrono::DateTime<chrono::Utc>) -> (String, String) {
+    let subject = format!("[subscan] {} changes since {}", events.len(), since.format("%Y-%m-%d %H:%M UTC"));
+    let mut body = format!("{} changes between {} and {}:\n\n", events.len(), since.to_rfc3339(), until.to_rfc3339());
+    for event in events {
+        let addresses: Vec<&str> = event["after"]["addresses"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+        body.push_str(&format!(
+            "{}  {}  {}  {}\n",
+            event["at"].as_str().unwrap_or_default(),
+            event["change"].as_str().unwrap_or_default(),
+            event["name"].as_str().unwrap_or_default(),
+            addresses.join(", ")
+        ));
+    }
+    (subject, body)
+}
+
+/// `192.0.2.0/24`, `2001:db8::/32`, or a single address.
+fn parse_network(network: &str) -> Result<(IpAddr, u8), String> {
+    let (address, prefix) = network.split_once('/').unwrap_or((network, ""));
+    let address: IpAddr = address.parse().map_err(|_| format!("'{}' is not a network", network))?;
+    let max = if address.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        "" => max,
+        prefix => prefix.parse().ok().filter(|prefix| *prefix <= max).ok_or_else(|| format!("'{}' has a bad prefix length", network))?,
+    };
+    Ok((address, prefix))
+}
+
+fn contains(network: IpAddr, prefix: u8, address: IpAddr) -> bool {
+    match (network, address) {
+        (IpAddr::V4(network), IpAddr::V4(address)) => netblock_v4(address, prefix) == netblock_v4(network, prefix),
+        (IpAddr::V6(network), IpAddr::V6(address)) => netblock_v6(address, prefix) == netblock_v6(network, prefix),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"
+rules:
+  - name: vpn
+    when: { change: [new, back], name: "vpn*" }
+    do: [{ webhook: "https://hooks.example.com/x" }]
+  - name: takeover
+    when: { takeover: true }
+    do: [{ exec: "true" }]
+  - name: stray
+    when: { outside: [192.0.2.0/24, "2001:db8::/32"] }
+    do: [{ email: secops@example.com }]
+"#;
+
+    fn event(name: &str, change: &str, before: Value, after: Value) -> Value {
+        json!({ "name": name, "change": change, "before": before, "after": after })
+    }
+
+    fn fired(alerts: &Alerts, event: &Value) -> Vec<String> {
+        alerts.matching(event).map(|rule| rule.name.clone()).collect()
+    }
+
+    #[test]
+    fn test_rules() {
+        let alerts = Alerts::parse(RULES).unwrap();
+        assert_eq!(alerts.rules[0].actions, [Action::Webhook("https://hooks.example.com/x".to_string())]);
+
+        let address = |ip: &str| json!({ "cname_chain": [], "addresses": [ip] });
+        assert_eq!(fired(&alerts, &event("vpn2.example.com", "new", Value::Null, address("192.0.2.7"))), ["vpn"]);
+        assert!(fired(&alerts, &event("vpn.example.com", "addresses_changed", address("192.0.2.6"), address("192.0.2.7"))).is_empty());
+        assert!(fired(&alerts, &event("www.example.com", "new", Value::Null, address("192.0.2.7"))).is_empty());
+        assert_eq!(fired(&alerts, &event("www.example.com", "addresses_changed", address("192.0.2.6"), address("203.0.113.9"))), ["stray"]);
+
+        let hosted = json!({ "cname_chain": ["shop-example.azurewebsites.net."], "addresses": ["192.0.2.8"] });
+        assert_eq!(fired(&alerts, &event("shop.example.com", "gone", hosted.clone(), Value::Null)), ["takeover"]);
+        let plain = json!({ "cname_chain": ["lb.example.net."], "addresses": ["192.0.2.8"] });
+        assert!(fired(&alerts, &event("shop.example.com", "gone", plain, Value::Null)).is_empty());
+
+        let unknown = RULES.replace("[new, back]", "[appeared]");
+        assert!(Alerts::parse(&unknown).err().unwrap().contains("rule 'vpn': unknown change 'appeared'"));
+        assert!(Alerts::parse(&RULES.replace("192.0.2.0/24", "192.0.2.0/33")).is_err());
+
+        let mailed = "smtp: { server: mx.example.com, from: subscan@example.com, to: [secops@example.com], digest_every: 3600 }\n\
+                      rules:\n  - { name: takeover, severity: high, when: { takeover: true } }\n";
+        let alerts = Alerts::parse(mailed).unwrap();
+        assert_eq!(alerts.rules[0].severity, Severity::High);
+        assert_eq!(alerts.digest_every(), Some(Duration::from_secs(3600)));
+        assert!(Alerts::parse(&mailed.replace("to: [secops@example.com], digest_every: 3600", "")).err().unwrap().contains("has no actions"));
+
+        let at = chrono::Utc::now();
+        let (subject, body) = digest(&[event("www.example.com", "addresses_changed", Value::Null, address("192.0.2.9"))], at, at);
+        assert!(subject.starts_with("[subscan] 1 changes since"));
+        assert!(body.contains("addresses_changed  www.example.com  192.0.2.9"));
+    }
+}
@@ -0,0 +1,241 @@
+//! Which address answers a scan keeps: the families asked for with
+//! `--ip-version`, minus answers that point nowhere a target's host could
+//! be. Filtering resolvers and sinkholes answer blocked names with
+//! 0.0.0.0 or 127.0.0.1, and an external zone's names resolving to RFC1918
+//! space are unreachable from outside; neither says the name is a host.
+//!
+//! Those private answers are still worth reporting: an external zone that
+//! publishes internal addressing is an information disclosure in its own
+//! right, so each such name is listed as a [`Disclosure`].
+
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+/// Pseudo top-level domains used only inside networks, whose names are
+/// expected to resolve to private addresses.
+const INTERNAL_SUFFIXES: &[&str] = &["local", "internal", "intranet", "corp", "lan", "home", "localdomain", "home.arpa", "test"];
+
+/// Address families to ask for and report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum IpVersion {
+    /// A records only, as scans always sent.
+    #[default]
+    V4,
+    /// AAAA records only.
+    V6,
+    /// Both, one query of each type per candidate.
+    Both,
+}
+
+impl IpVersion {
+    pub fn keeps(self, address: &IpAddr) -> bool {
+        match self {
+            IpVersion::V4 => address.is_ipv4(),
+            IpVersion::V6 => address.is_ipv6(),
+            IpVersion::Both => true,
+        }
+    }
+
+    /// Queries sent per candidate.
+    pub fn record_types(self) -> u64 {
+        match self {
+            IpVersion::Both => 2,
+            IpVersion::V4 | IpVersion::V6 => 1,
+        }
+    }
+}
+
+impl FromStr for IpVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "4" | "v4" | "ipv4" => Ok(IpVersion::V4),
+            "6" | "v6" | "ipv6" => Ok(IpVersion::V6),
+            "both" | "46" => Ok(IpVersion::Both),
+            _ => Err(format!("Unknown IP version: {} (expected 4, 6 or both)", s)),
+        }
+    }
+}
+
+/// Why an address answer was left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bogus {
+    /// 0.0.0.0 or `::`.
+    Unspecified,
+    /// 127.0.0.0/8 or `::1`.
+    Loopback,
+    /// RFC1918 or an IPv6 unique local address (fc00::/7).
+    Private,
+}
+
+impl Bogus {
+    pub fn label(self) -> &'static str {
+        match self {
+            Bogus::Unspecified => "unspecified",
+            Bogus::Loopback => "loopback",
+            Bogus::Private => "private",
+        }
+    }
+}
+
+/// Whether `address` is RFC1918 or IPv6 unique local space.
+pub fn is_private(address: &IpAddr) -> bool {
+    match address {
+        IpAddr::V4(a) => a.is_private(),
+        IpAddr::V6(a) => a.is_unique_local() || a.to_ipv4_mapped().is_some_and(|a| a.is_private()),
+    }
+}
+
+/// Whether `name` is under a pseudo-TLD only used inside networks.
+pub fn is_internal_name(name: &str) -> bool {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    INTERNAL_SUFFIXES
+        .iter()
+        .any(|suffix| name == *suffix || name.strip_suffix(suffix).is_some_and(|rest| rest.ends_with('.')))
+}
+
+/// An external name resolving to RFC1918 or unique local space, which
+/// discloses internal hosts and addressing to anyone who asks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Disclosure {
+    pub name: String,
+    /// The private addresses among its answers.
+    pub addresses: Vec<IpAddr>,
+    /// `medium` when every address is private, so the name is an internal
+    /// host; `low` when public ones come with them, as a split-horizon
+    /// record leaking into the public view does.
+    pub severity: &'static str,
+}
+
+/// The disclosure `name` makes with `addresses`, if it is an external name
+/// with private answers.
+pub fn disclosure(name: &str, addresses: &[IpAddr]) -> Option<Disclosure> {
+    if is_internal_name(name) {
+        return None;
+    }
+    let private: Vec<IpAddr> = addresses.iter().filter(|address| is_private(address)).copied().collect();
+    if private.is_empty() {
+        return None;
+    }
+    Some(Disclosure {
+        name: name.to_string(),
+        severity: if private.len() == addresses.len() { "medium" } else { "low" },
+        addresses: private,
+    })
+}
+
+/// What answers to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub struct AnswerPolicy {
+    pub ip_version: IpVersion,
+    /// Keep private answers for external names too (`--allow-private`).
+    pub allow_private: bool,
+}
+
+impl AnswerPolicy {
+    /// Why `address` is not a real answer for `name`, if it is not.
+    pub fn bogus(&self, name: &str, address: &IpAddr) -> Option<Bogus> {
+        let mapped = match address {
+            IpAddr::V6(a) => a.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(*address),
+            IpAddr::V4(_) => *address,
+        };
+        if mapped.is_unspecified() {
+            Some(Bogus::Unspecified)
+        } else if mapped.is_loopback() {
+            Some(Bogus::Loopback)
+        } else if is_private(&mapped) && !self.allow_private && !is_internal_name(name) {
+            Some(Bogus::Private)
+        } else {
+            None
+        }
+    }
+
+    /// Drops the answers of `name` this policy does not keep, counting
+    /// the bogus ones in `report`. Returns false when `name` had addresses
+    /// and none are left, so it is no finding at all.
+    pub fn apply(&self, name: &str, addresses: &mut Vec<IpAddr>, report: &mut AnswerReport) -> bool {
+        let had = !addresses.is_empty();
+        addresses.retain(|address| {
+            if !self.ip_version.keeps(address) {
+                *report.filtered.entry("other_family").or_default() += 1;
+                return false;
+            }
+            match self.bogus(name, address) {
+                Some(bogus) => {
+                    *report.filtered.entry(bogus.label()).or_default() += 1;
+                    false
+                }
+                None => true,
+            }
+        });
+        if had && addresses.is_empty() {
+            report.dropped.push(name.to_string());
+            return false;
+        }
+        true
+    }
+}
+
+/// Address answers the policy left out of a scan.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AnswerReport {
+    /// Addresses removed, by reason: `unspecified`, `loopback`, `private`
+    /// or `other_family`.
+    pub filtered: BTreeMap<&'static str, u64>,
+    /// Names dropped because none of their addresses were kept.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dropped: Vec<String>,
+}
+
+impl AnswerReport {
+    pub fn is_empty(&self) -> bool {
+        self.filtered.is_empty() && self.dropped.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answer_policy() {
+        assert_eq!("6".parse::<IpVersion>(), Ok(IpVersion::V6));
+        assert_eq!("Both".parse::<IpVersion>(), Ok(IpVersion::Both));
+        assert!("5".parse::<IpVersion>().is_err());
+        assert!(is_internal_name("db.corp."));
+        assert!(is_internal_name("printer.home.arpa"));
+        assert!(!is_internal_name("db.example.com"));
+        assert!(!is_internal_name("example.mycorp"));
+
+        let policy = AnswerPolicy::default();
+        let mut report = AnswerReport::default();
+        let mut addresses: Vec<IpAddr> = ["0.0.0.0", "127.0.0.53", "10.1.2.3", "192.0.2.10", "2001:db8::1"].map(|a| a.parse().unwrap()).to_vec();
+        assert!(policy.apply("www.example.com", &mut addresses, &mut report));
+        assert_eq!(addresses, ["192.0.2.10".parse::<IpAddr>().unwrap()]);
+        assert_eq!(report.filtered, BTreeMap::from([("loopback", 1), ("other_family", 1), ("private", 1), ("unspecified", 1)]));
+
+        let mut sinkholed = vec!["0.0.0.0".parse().unwrap()];
+        assert!(!policy.apply("ads.example.com", &mut sinkholed, &mut report));
+        assert_eq!(report.dropped, ["ads.example.com"]);
+        // A CNAME-only answer has nothing to filter and stays a finding.
+        assert!(policy.apply("cdn.example.com", &mut Vec::new(), &mut report));
+
+        let private: IpAddr = "fd00::10".parse().unwrap();
+        let both = AnswerPolicy { ip_version: IpVersion::Both, allow_private: false };
+        assert_eq!(both.bogus("db.example.com", &private), Some(Bogus::Private));
+        assert_eq!(both.bogus("db.internal", &private), None);
+        assert_eq!(AnswerPolicy { allow_private: true, ..both }.bogus("db.example.com", &private), None);
+        assert_eq!(both.bogus("db.example.com", &"::ffff:127.0.0.1".parse().unwrap()), Some(Bogus::Loopback));
+
+        let public: IpAddr = "192.0.2.10".parse().unwrap();
+        assert_eq!(disclosure("db.example.com", &[private]).unwrap().severity, "medium");
+        let mixed = disclosure("www.example.com", &[public, private]).unwrap();
+        assert_eq!((mixed.addresses.as_slice(), mixed.severity), ([private].as_slice(), "low"));
+        assert_eq!(disclosure("www.example.com", &[public]), None);
+        assert_eq!(disclosure("db.corp", &[private]), None);
+    }
+}
@@ -0,0 +1,112 @@
+//! Where each found name came from and how far to trust it, so findings
+//! can be weighed individually and sources compared by what they turn up.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+/// The source of names that came from a wordlist.
+pub const BRUTEFORCE: &str = "dns_bruteforce";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    /// Answered, but by a fallback rather than DNS, or the name looks
+    /// machine-generated.
+    Low,
+    /// Answered by DNS, with nothing else backing it.
+    Medium,
+    /// Confirmed by a second resolver, or produced by more than one source.
+    High,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Attribution {
+    /// Every source that produced the name: `dns_bruteforce` for wordlist
+    /// entries, the passive source's name (`crtsh`, `wayback`) otherwise.
+    pub sources: Vec<String>,
+    pub confidence: Confidence,
+}
+
+/// What is known about a found name besides its answer.
+#[derive(Debug, Clone, Default)]
+pub struct Evidence {
+    pub sources: Vec<String>,
+    /// A second resolver had an answer too (`--verify`).
+    pub verified: bool,
+    /// Answered by a fallback such as NetBIOS instead of DNS.
+    pub fallback: bool,
+    pub random_looking: bool,
+}
+
+impl Attribution {
+    pub fn new(evidence: Evidence) -> Self {
+        let confidence = if evidence.fallback || evidence.random_looking {
+            Confidence::Low
+        } else if evidence.verified || evidence.sources.len() > 1 {
+            Confidence::High
+        } else {
+            Confidence::Medium
+        };
+        Self {
+            sources: evidence.sources,
+            confidence,
+        }
+    }
+}
+
+/// What one source contributed to a scan.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SourceYield {
+    pub found: u64,
+    /// Found names no other source produced.
+    pub unique: u64,
+}
+
+pub fn yields(attribution: &BTreeMap<String, Attribution>) -> BTreeMap<String, SourceYield> {
+    let mut yields = BTreeMap::new();
+    for found in attribution.values() {
+        count(&mut yields, found);
+    }
+    yields
+}
+
+/// Adds one found name to `yields`.
+pub fn count(yields: &mut BTreeMap<String, SourceYield>, found: &Attribution) {
+    for source in &found.sources {
+        let entry = yields.entry(source.clone()).or_default();
+        entry.found += 1;
+        entry.unique += (found.sources.len() == 1) as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evidence(sources: &[&str]) -> Evidence {
+        Evidence {
+            sources: sources.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_attribution() {
+        let mut attribution = BTreeMap::new();
+        attribution.insert("www".to_string(), Attribution::new(evidence(&[BRUTEFORCE])));
+        attribution.insert("api".to_string(), Attribution::new(evidence(&[BRUTEFORCE, "crtsh"])));
+        attribution.insert("old".to_string(), Attribution::new(evidence(&["crtsh"])));
+        assert_eq!(attribution["www"].confidence, Confidence::Medium);
+        assert_eq!(attribution["api"].confidence, Confidence::High);
+
+        let verified = Attribution::new(Evidence { verified: true, ..evidence(&[BRUTEFORCE]) });
+        assert_eq!(verified.confidence, Confidence::High);
+        let fallback = Attribution::new(Evidence { fallback: true, ..evidence(&[BRUTEFORCE, "crtsh"]) });
+        assert_eq!(fallback.confidence, Confidence::Low);
+
+        let yields = yields(&attribution);
+        assert_eq!(yields[BRUTEFORCE], SourceYield { found: 2, unique: 1 });
+        assert_eq!(yields["crtsh"], SourceYield { found: 2, unique: 1 });
+    }
+}
@@ -0,0 +1,177 @@
+//! Batched UDP IO for the raw engine. With the `mmsg` feature on Linux a
+//! batch costs one `sendmmsg` or `recvmmsg` call; elsewhere it is a loop of
+//! single sends and receives.
+
+use std::io;
+use std::net::SocketAddr;
+
+use tokio::net::UdpSocket;
+
+/// Most packets moved per call.
+pub const BATCH: usize = 64;
+
+/// Waits for at least one datagram, then takes what else is already queued,
+/// up to one per buffer. Returns the length and sender of each, in buffer
+/// order.
+#[cfg(all(feature = "mmsg", target_os = "linux"))]
+pub async fn recv_batch(socket: &UdpSocket, bufs: &mut [Vec<u8>]) -> io::Result<Vec<(usize, SocketAddr)>> {
+    use tokio::io::Interest;
+
+    loop {
+        socket.readable().await?;
+        match socket.try_io(Interest::READABLE, || mmsg::recv(socket, bufs)) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            received => return received,
+        }
+    }
+}
+
+#[cfg(not(all(feature = "mmsg", target_os = "linux")))]
+pub async fn recv_batch(socket: &UdpSocket, bufs: &mut [Vec<u8>]) -> io::Result<Vec<(usize, SocketAddr)>> {
+    let mut received = vec![socket.recv_from(&mut bufs[0]).await?];
+    for buf in bufs.iter_mut().take(BATCH).skip(1) {
+        match socket.try_recv_from(buf) {
+            Ok(next) => received.push(next),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(received)
+}
+
+/// Sends every packet, a batch per call. Returns the packets that could not
+/// be sent, by index, with the reason.
+#[cfg(all(feature = "mmsg", target_os = "linux"))]
+pub async fn send_batch(socket: &UdpSocket, packets: &[(&[u8], SocketAddr)]) -> Vec<(usize, io::Error)> {
+    use tokio::io::Interest;
+
+    let mut failed = Vec::new();
+    let mut start = 0;
+    while start < packets.len() {
+        if let Err(e) = socket.writable().await {
+            failed.extend((start..packets.len()).map(|i| (i, io::Error::new(e.kind(), e.to_string()))));
+            break;
+        }
+        let chunk = &packets[start..packets.len().min(start + BATCH)];
+        match socket.try_io(Interest::WRITABLE, || mmsg::send(socket, chunk)) {
+            Ok(n) => start += n,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            // The call stops at the first packet it cannot send; skip that one.
+            Err(e) => {
+                failed.push((start, e));
+                start += 1;
+            }
+        }
+    }
+    failed
+}
+
+#[cfg(not(all(feature = "mmsg", target_os = "linux")))]
+pub async fn send_batch(socket: &UdpSocket, packets: &[(&[u8], SocketAddr)]) -> Vec<(usize, io::Error)> {
+    let mut failed = Vec::new();
+    for (i, (packet, to)) in packets.iter().enumerate() {
+        if let Err(e) = socket.send_to(packet, to).await {
+            failed.push((i, e));
+        }
+    }
+    failed
+}
+
+/// The system calls themselves, on a socket that is ready.
+#[cfg(all(feature = "mmsg", target_os = "linux"))]
+mod mmsg {
+    use std::io;
+    use std::net::SocketAddr;
+    use std::os::fd::AsRawFd;
+
+    use socket2::{SockAddr, SockAddrStorage};
+    use tokio::net::UdpSocket;
+
+    use super::BATCH;
+
+    pub fn recv(socket: &UdpSocket, bufs: &mut [Vec<u8>]) -> io::Result<Vec<(usize, SocketAddr)>> {
+        let count = bufs.len().min(BATCH);
+        let mut names: Vec<SockAddrStorage> = (0..count).map(|_| SockAddrStorage::zeroed()).collect();
+        let mut iovecs: Vec<libc::iovec> = bufs[..count]
+            .iter_mut()
+            .map(|buf| libc::iovec {
+                iov_base: buf.as_mut_ptr().cast(),
+                iov_len: buf.len(),
+            })
+            .collect();
+        // SAFETY: a zeroed mmsghdr is valid; every pointer set below refers to
+        // `names` or `iovecs`, which outlive the call.
+        let mut headers: Vec<libc::mmsghdr> = (0..count).map(|_| unsafe { std::mem::zeroed() }).collect();
+        for ((header, name), iovec) in headers.iter_mut().zip(&mut names).zip(&mut iovecs) {
+            header.msg_hdr.msg_namelen = name.size_of();
+            header.msg_hdr.msg_name = (name as *mut SockAddrStorage).cast();
+            header.msg_hdr.msg_iov = iovec;
+            header.msg_hdr.msg_iovlen = 1;
+        }
+        let fd = socket.as_raw_fd();
+        let flags = libc::MSG_DONTWAIT;
+        let n = unsafe { libc::recvmmsg(fd, headers.as_mut_ptr(), count as libc::c_uint, flags, std::ptr::null_mut()) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut received = Vec::with_capacity(n as usize);
+        for (header, name) in headers.iter().zip(names).take(n as usize) {
+            // SAFETY: the kernel filled in the address and its length.
+            let from = unsafe { SockAddr::new(name, header.msg_hdr.msg_namelen) };
+            // An IP socket only hears from IP addresses; should that ever fail,
+            // an empty datagram keeps the entries in buffer order.
+            match from.as_socket() {
+                Some(from) => received.push((header.msg_len as usize, from)),
+                None => received.push((0, SocketAddr::from(([0, 0, 0, 0], 0)))),
+            }
+        }
+        Ok(received)
+    }
+
+    pub fn send(socket: &UdpSocket, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+        let names: Vec<SockAddr> = packets.iter().map(|(_, to)| SockAddr::from(*to)).collect();
+        let mut iovecs: Vec<libc::iovec> = packets
+            .iter()
+            .map(|(packet, _)| libc::iovec {
+                iov_base: packet.as_ptr() as *mut libc::c_void,
+                iov_len: packet.len(),
+            })
+            .collect();
+        // SAFETY: as in `recv`; the kernel only reads through these.
+        let mut headers: Vec<libc::mmsghdr> = (0..packets.len()).map(|_| unsafe { std::mem::zeroed() }).collect();
+        for ((header, name), iovec) in headers.iter_mut().zip(&names).zip(&mut iovecs) {
+            header.msg_hdr.msg_name = name.as_ptr() as *mut libc::c_void;
+            header.msg_hdr.msg_namelen = name.len();
+            header.msg_hdr.msg_iov = iovec;
+            header.msg_hdr.msg_iovlen = 1;
+        }
+        let fd = socket.as_raw_fd();
+        let n = unsafe { libc::sendmmsg(fd, headers.as_mut_ptr(), packets.len() as libc::c_uint, libc::MSG_DONTWAIT) };
+        if n < 0 { Err(io::Error::last_os_error()) } else { Ok(n as usize) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let to = b.local_addr().unwrap();
+        let packets: Vec<Vec<u8>> = (0..100u8).map(|i| vec![i; i as usize + 1]).collect();
+        let batch: Vec<(&[u8], SocketAddr)> = packets.iter().map(|p| (p.as_slice(), to)).collect();
+        assert!(send_batch(&a, &batch).await.is_empty());
+
+        let mut bufs = vec![vec![0u8; 512]; BATCH];
+        let mut seen = Vec::new();
+        while seen.len() < packets.len() {
+            for (i, (len, from)) in recv_batch(&b, &mut bufs).await.unwrap().into_iter().enumerate() {
+                assert_eq!(from, a.local_addr().unwrap());
+                seen.push(bufs[i][..len].to_vec());
+            }
+        }
+        assert_eq!(seen, packets);
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Upper bound on the number of DNS queries a scan will send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryEstimate {
+    pub candidates: u64,
+    pub record_types: u64,
+    pub attempts_per_query: u64,
+}
+
+impl QueryEstimate {
+    pub fn total(&self) -> u64 {
+        self.candidates * self.record_types * self.attempts_per_query
+    }
+}
+
+impl fmt::Display for QueryEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "up to {} queries ({} candidates x {} record type(s) x {} attempt(s))",
+            self.total(),
+            self.candidates,
+            self.record_types,
+            self.attempts_per_query
+        )
+    }
+}
+
+/// Hard cap on queries sent, shared by every task of a scan.
+#[derive(Debug)]
+pub struct QueryBudget {
+    limit: Option<u64>,
+    sent: AtomicU64,
+}
+
+/// No cap.
+impl Default for QueryBudget {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl QueryBudget {
+    pub fn new(limit: Option<u64>) -> Self {
+        Self::resumed(limit, 0)
+    }
+
+    /// A budget `sent` queries into `limit`, for a scan continuing a paused
+    /// one.
+    pub fn resumed(limit: Option<u64>, sent: u64) -> Self {
+        Self {
+            limit,
+            sent: AtomicU64::new(sent),
+        }
+    }
+
+    /// Reserves one query; returns false once the budget is spent.
+    pub fn try_spend(&self) -> bool {
+        self.try_spend_many(1)
+    }
+
+    /// Reserves `queries` queries at once, or none if they do not all fit.
+    pub fn try_spend_many(&self, queries: u64) -> bool {
+        let Some(limit) = self.limit else {
+            self.sent.fetch_add(queries, Ordering::Relaxed);
+            return true;
+        };
+        // Never counts past the limit, so no caller sees a reservation that
+        // is about to be given back.
+        self.sent
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |sent| sent.checked_add(queries).filter(|&total| total <= limit))
+            .is_ok()
+    }
+
+    /// Gives back `queries` reserved but not sent.
+    pub fn refund(&self, queries: u64) {
+        self.sent.fetch_sub(queries, Ordering::Relaxed);
+    }
+
+    /// Queries left before the limit; `None` without one.
+    pub fn remaining(&self) -> Option<u64> {
+        self.limit.map(|limit| limit.saturating_sub(self.sent()))
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.limit.is_some_and(|limit| self.sent() >= limit)
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.sent.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_stops_at_limit() {
+        let budget = QueryBudget::new(Some(2));
+        assert!(budget.try_spend());
+        assert!(budget.try_spend());
+        assert!(!budget.try_spend());
+        assert!(budget.is_exhausted());
+        assert_eq!(budget.sent(), 2);
+
+        let budget = QueryBudget::new(Some(3));
+        assert!(budget.try_spend_many(2));
+        assert!(!budget.try_spend_many(2));
+        assert_eq!(budget.sent(), 2);
+        assert_eq!(budget.remaining(), Some(1));
+    }
+
+    #[test]
+    fn test_unlimited_budget() {
+        let budget = QueryBudget::new(None);
+        for _ in 0..10 {
+            assert!(budget.try_spend());
+        }
+        assert!(!budget.is_exhausted());
+        assert_eq!(budget.remaining(), None);
+    }
+
+    #[test]
+    fn test_concurrent_spending_never_passes_limit() {
+        let budget = std::sync::Arc::new(QueryBudget::new(Some(1000)));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let budget = budget.clone();
+                std::thread::spawn(move || (0..500).filter(|_| budget.try_spend_many(2)).count() as u64)
+            })
+            .collect();
+        let granted: u64 = threads.into_iter().map(|thread| thread.join().unwrap()).sum();
+        assert_eq!(granted, 500);
+        assert_eq!(budget.sent(), 1000);
+        assert_eq!(budget.remaining(), Some(0));
+    }
+}
@@ -0,0 +1,152 @@
+//! A paused scan, saved with `--state FILE` when the scan is interrupted and
+//! continued with `subscan resume FILE`, in this process or after a reboot.
+//!
+//! Each target keeps how far into its wordlist it got, the names whose
+//! queries failed at every resolver (asked again first), how its resolvers
+//! fared and what it found so far. The resumed scan starts from there and
+//! its findings are merged with the saved ones, so the result reads as one
+//! scan.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::health::ResolverUsage;
+use crate::jsonfile;
+use crate::output::{self, ExistingOutput};
+use crate::scanner::PhaseReport;
+
+/// Bumped when the state file changes shape.
+pub const STATE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanState {
+    pub version: u32,
+    pub crate_version: String,
+    /// The scan's own command line, without `resume`.
+    pub command_line: Vec<String>,
+    pub paused_at: String,
+    /// Targets not listed had not started and are scanned from the start.
+    pub targets: BTreeMap<String, TargetState>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetState {
+    #[serde(flatten)]
+    pub resume: ResumePoint,
+    pub resolver_usage: Vec<ResolverUsage>,
+    /// The scan result so far, as written to the output.
+    pub result: Value,
+    /// The wordlist's sha256 when paused; the saved position only means
+    /// something in that same list. Absent in states saved before it was
+    /// recorded.
+    #[serde(default)]
+    pub wordlist_sha256: Option<String>,
+}
+
+/// Where a target's scan picks up.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResumePoint {
+    /// Wordlist entries already queried, over both phases.
+    pub position: u64,
+    /// Names whose queries failed at every resolver they were sent to.
+    pub retry: Vec<String>,
+    /// Queries sent so far, by this run and the ones it resumed, so
+    /// `--max-queries` caps them all together.
+    #[serde(default)]
+    pub queries_sent: u64,
+    /// Each wordlist phase's counts so far, continued when resumed.
+    #[serde(default)]
+    pub phases: Vec<PhaseReport>,
+}
+
+impl ScanState {
+    pub fn new(command_line: Vec<String>, paused_at: &str) -> Self {
+        Self {
+            version: STATE_VERSION,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            command_line,
+            paused_at: paused_at.to_string(),
+            targets: BTreeMap::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let state: Self = jsonfile::load(path)?;
+        if state.version != STATE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("state version {} is not {}, rerun the scan", state.version, STATE_VERSION),
+            ));
+        }
+        Ok(state)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        jsonfile::save(path, self)
+    }
+
+    /// One problem per paused target whose wordlist, by `wordlists`' sha256
+    /// (`None` when there is none), is not the one it was paused in.
+    pub fn changed_wordlists(&self, wordlists: &BTreeMap<String, Option<String>>) -> Vec<String> {
+        self.targets
+            .iter()
+            .filter(|(domain, paused)| paused.wordlist_sha256.is_some() && wordlists.get(*domain).is_some_and(|sha256| *sha256 != paused.wordlist_sha256))
+            .map(|(domain, paused)| {
+                format!("{}: the wordlist changed since the scan was paused at entry {}, rerun the scan instead of resuming it", domain, paused.resume.position)
+            })
+            .collect()
+    }
+}
+
+/// Folds the saved findings of `paused` into the resumed scan's `result`.
+pub fn merge(result: Value, paused: &Value) -> Value {
+    let total = |field: &str| result["results"][field].as_u64().unwrap_or_default() + paused["results"][field].as_u64().unwrap_or_default();
+    let (sent, failed) = (total("queries_sent"), total("queries_failed"));
+    let mut merged = output::combine(paused.clone(), result, ExistingOutput::Merge);
+    merged["results"]["queries_sent"] = sent.into();
+    merged["results"]["queries_failed"] = failed.into();
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_state() {
+        let dir = std::env::temp_dir().join(format!("subscan-state-{}", std::process::id()));
+        let path = dir.join("state.json");
+        let mut state = ScanState::new(vec!["subscan".to_string(), "-d".to_string(), "example.com".to_string()], "2026-01-01T00:00:00Z");
+        let usage = ResolverUsage { resolver: "192.0.2.1:53".parse().unwrap(), queries: 10, failures: 2, evictions: 0 };
+        let resume = ResumePoint { position: 40, retry: vec!["db.example.com".to_string()], queries_sent: 42, phases: Vec::new() };
+        let result = json!({"target": "example.com", "results": {"subdomain": ["www.example.com"], "records": {"www.example.com": {"addresses": ["192.0.2.10"]}}, "queries_sent": 42}});
+        state.targets.insert("example.com".to_string(), TargetState { resume: resume.clone(), resolver_usage: vec![usage.clone()], result, wordlist_sha256: Some("ab12".to_string()) });
+        state.save(&path).unwrap();
+        let loaded = ScanState::load(&path).unwrap();
+        let target = &loaded.targets["example.com"];
+        assert_eq!(target.resume, resume);
+        assert_eq!(target.resolver_usage, [usage]);
+
+        let mut wordlists = BTreeMap::from([("example.com".to_string(), Some("ab12".to_string()))]);
+        assert!(loaded.changed_wordlists(&wordlists).is_empty());
+        wordlists.insert("example.com".to_string(), Some("cd34".to_string()));
+        assert_eq!(loaded.changed_wordlists(&wordlists).len(), 1);
+        wordlists.insert("example.com".to_string(), None);
+        assert_eq!(loaded.changed_wordlists(&wordlists).len(), 1);
+
+        let resumed = json!({"target": "example.com", "results": {"subdomain": ["api.example.com"], "records": {"api.example.com": {"addresses": ["192.0.2.11"]}}, "queries_sent": 60}});
+        let merged = merge(resumed, &target.result);
+        assert_eq!(merged["results"]["subdomain"], json!(["api.example.com", "www.example.com"]));
+        assert_eq!(merged["results"]["records"]["www.example.com"]["addresses"], json!(["192.0.2.10"]));
+        assert_eq!(merged["results"]["queries_sent"], 102);
+
+        std::fs::write(&path, serde_json::to_string(&json!({"version": 0, "crate_version": "0", "command_line": [], "paused_at": "", "targets": {}})).unwrap()).unwrap();
+        assert!(ScanState::load(&path).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
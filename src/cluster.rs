@@ -0,0 +1,304 @@
+//! Found names grouped by the netblocks they resolve into, and hints at
+//! origin servers: when most names of a domain sit behind a CDN, the one
+//! that resolves straight to a plain netblock is often the origin the CDN
+//! fronts, reachable without its protection.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// A CDN or fronting proxy, recognized by the CNAME its customers point at
+/// or by its address ranges.
+struct Cdn {
+    name: &'static str,
+    cname_suffixes: &'static [&'static str],
+    v4: &'static [(Ipv4Addr, u8)],
+    v6: &'static [(Ipv6Addr, u8)],
+}
+
+const CDNS: &[Cdn] = &[
+    Cdn {
+        name: "Cloudflare",
+        cname_suffixes: &[".cdn.cloudflare.net"],
+        v4: &[
+            (Ipv4Addr::new(104, 16, 0, 0), 13),
+            (Ipv4Addr::new(104, 24, 0, 0), 14),
+            (Ipv4Addr::new(172, 64, 0, 0), 13),
+            (Ipv4Addr::new(162, 158, 0, 0), 15),
+            (Ipv4Addr::new(173, 245, 48, 0), 20),
+            (Ipv4Addr::new(103, 21, 244, 0), 22),
+            (Ipv4Addr::new(103, 22, 200, 0), 22),
+            (Ipv4Addr::new(103, 31, 4, 0), 22),
+            (Ipv4Addr::new(141, 101, 64, 0), 18),
+            (Ipv4Addr::new(108, 162, 192, 0), 18),
+            (Ipv4Addr::new(190, 93, 240, 0), 20),
+            (Ipv4Addr::new(188, 114, 96, 0), 20),
+            (Ipv4Addr::new(197, 234, 240, 0), 22),
+            (Ipv4Addr::new(198, 41, 128, 0), 17),
+            (Ipv4Addr::new(131, 0, 72, 0), 22),
+        ],
+        v6: &[(Ipv6Addr::new(0x2606, 0x4700, 0, 0, 0, 0, 0, 0), 32), (Ipv6Addr::new(0x2400, 0xcb00, 0, 0, 0, 0, 0, 0), 32)],
+    },
+    Cdn {
+        name: "Amazon CloudFront",
+        cname_suffixes: &[".cloudfront.net"],
+        v4: &[
+            (Ipv4Addr::new(13, 32, 0, 0), 15),
+            (Ipv4Addr::new(13, 224, 0, 0), 14),
+            (Ipv4Addr::new(18, 160, 0, 0), 15),
+            (Ipv4Addr::new(54, 230, 0, 0), 16),
+            (Ipv4Addr::new(54, 239, 128, 0), 18),
+            (Ipv4Addr::new(99, 84, 0, 0), 16),
+            (Ipv4Addr::new(143, 204, 0, 0), 16),
+            (Ipv4Addr::new(205, 251, 192, 0), 19),
+        ],
+        v6: &[(Ipv6Addr::new(0x2600, 0x9000, 0, 0, 0, 0, 0, 0), 28)],
+    },
+    Cdn {
+        name: "Akamai",
+        cname_suffixes: &[".akamaiedge.net", ".edgekey.net", ".edgesuite.net", ".akamai.net", ".akamaized.net"],
+        v4: &[
+            (Ipv4Addr::new(23, 32, 0, 0), 11),
+            (Ipv4Addr::new(23, 192, 0, 0), 11),
+            (Ipv4Addr::new(2, 16, 0, 0), 13),
+            (Ipv4Addr::new(104, 64, 0, 0), 10),
+            (Ipv4Addr::new(184, 24, 0, 0), 13),
+        ],
+        v6: &[],
+    },
+    Cdn {
+        name: "Fastly",
+        cname_suffixes: &[".fastly.net", ".fastlylb.net"],
+        v4: &[(Ipv4Addr::new(151, 101, 0, 0), 16), (Ipv4Addr::new(199, 232, 0, 0), 16)],
+        v6: &[(Ipv6Addr::new(0x2a04, 0x4e40, 0, 0, 0, 0, 0, 0), 32)],
+    },
+    Cdn {
+        name: "Azure Front Door",
+        cname_suffixes: &[".azureedge.net", ".azurefd.net", ".trafficmanager.net"],
+        v4: &[],
+        v6: &[],
+    },
+    Cdn {
+        name: "Imperva",
+        cname_suffixes: &[".incapdns.net"],
+        v4: &[(Ipv4Addr::new(45, 60, 0, 0), 16), (Ipv4Addr::new(199, 83, 128, 0), 21)],
+        v6: &[],
+    },
+    Cdn {
+        name: "Sucuri",
+        cname_suffixes: &[".sucuri.net"],
+        v4: &[(Ipv4Addr::new(192, 88, 134, 0), 23), (Ipv4Addr::new(185, 93, 228, 0), 22)],
+        v6: &[],
+    },
+    Cdn {
+        name: "StackPath",
+        cname_suffixes: &[".stackpathdns.com", ".stackpathcdn.com"],
+        v4: &[],
+        v6: &[],
+    },
+    Cdn {
+        name: "Bunny CDN",
+        cname_suffixes: &[".b-cdn.net"],
+        v4: &[],
+        v6: &[],
+    },
+];
+
+/// The CDN in front of a name that resolved through `cname_chain` to
+/// `addresses`, if it is a known one. The chain is the stronger signal;
+/// addresses catch flattened and proxied records.
+pub fn identify_cdn(cname_chain: &[String], addresses: &[IpAddr]) -> Option<&'static str> {
+    let by_cname = CDNS.iter().find(|cdn| {
+        cname_chain.iter().any(|cname| {
+            let cname = cname.trim_end_matches('.').to_lowercase();
+            cdn.cname_suffixes.iter().any(|suffix| cname.ends_with(suffix))
+        })
+    });
+    by_cname
+        .or_else(|| CDNS.iter().find(|cdn| addresses.iter().any(|address| in_ranges(cdn, *address))))
+        .map(|cdn| cdn.name)
+}
+
+fn in_ranges(cdn: &Cdn, address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(v4) => cdn.v4.iter().any(|(network, prefix)| netblock_v4(v4, *prefix) == *network),
+        IpAddr::V6(v6) => cdn.v6.iter().any(|(network, prefix)| netblock_v6(v6, *prefix) == *network),
+    }
+}
+
+pub(crate) fn netblock_v4(address: Ipv4Addr, prefix: u8) -> Ipv4Addr {
+    Ipv4Addr::from(u32::from(address) & u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0))
+}
+
+pub(crate) fn netblock_v6(address: Ipv6Addr, prefix: u8) -> Ipv6Addr {
+    Ipv6Addr::from(u128::from(address) & u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0))
+}
+
+/// The block an address is clustered in: its /24, or /64 for IPv6.
+pub fn netblock(address: IpAddr) -> String {
+    match address {
+        IpAddr::V4(v4) => format!("{}/24", netblock_v4(v4, 24)),
+        IpAddr::V6(v6) => format!("{}/64", netblock_v6(v6, 64)),
+    }
+}
+
+/// Found names that resolve into the same netblock.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Cluster {
+    pub netblock: String,
+    pub names: Vec<String>,
+    pub addresses: Vec<IpAddr>,
+    /// The CDN the block belongs to, if it is a known CDN range or every
+    /// name in it is fronted by the same one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cdn: Option<&'static str>,
+}
+
+/// A name outside any CDN whose siblings (names under the same parent) are
+/// behind one: a candidate for the origin they front.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OriginHint {
+    pub name: String,
+    pub addresses: Vec<IpAddr>,
+    pub cdn: &'static str,
+    pub protected_siblings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ClusterReport {
+    pub clusters: Vec<Cluster>,
+    /// Names fronted by each CDN.
+    pub cdn_names: BTreeMap<&'static str, Vec<String>>,
+    pub origin_hints: Vec<OriginHint>,
+}
+
+#[derive(Default)]
+struct Block {
+    names: BTreeSet<String>,
+    addresses: BTreeSet<IpAddr>,
+    /// The CDN of each name in the block, `None` for names behind none.
+    cdns: BTreeSet<Option<&'static str>>,
+}
+
+/// Clusters the found names of one scan result by netblock and looks for
+/// unprotected siblings of CDN-fronted names. Private addresses are
+/// clustered like any other; they rarely sit behind a CDN, so an internal
+/// name next to fronted ones is flagged too.
+pub fn cluster(result: &Value) -> ClusterReport {
+    let Some(records) = result["results"]["records"].as_object() else {
+        return ClusterReport::default();
+    };
+    let mut report = ClusterReport::default();
+    let mut blocks: BTreeMap<String, Block> = BTreeMap::new();
+    let mut fronted: BTreeMap<&str, &'static str> = BTreeMap::new();
+    let mut plain: Vec<(&str, Vec<IpAddr>)> = Vec::new();
+    for (name, record) in records {
+        let chain: Vec<String> = record["cname_chain"]
+            .as_array()
+            .map(|chain| chain.iter().filter_map(|c| c.as_str().map(str::to_string)).collect())
+            .unwrap_or_default();
+        let addresses: Vec<IpAddr> = record["addresses"]
+            .as_array()
+            .map(|addresses| addresses.iter().filter_map(|a| a.as_str()?.parse().ok()).collect())
+            .unwrap_or_default();
+        let cdn = identify_cdn(&chain, &addresses);
+        match cdn {
+            Some(cdn) => {
+                fronted.insert(name, cdn);
+                report.cdn_names.entry(cdn).or_default().push(name.clone());
+            }
+            None if !addresses.is_empty() => plain.push((name, addresses.clone())),
+            None => {}
+        }
+        for address in addresses {
+            let block = blocks.entry(netblock(address)).or_default();
+            block.names.insert(name.clone());
+            block.addresses.insert(address);
+            block.cdns.insert(cdn);
+        }
+    }
+    report.clusters = blocks
+        .into_iter()
+        .map(|(netblock, block)| Cluster {
+            netblock,
+            names: block.names.into_iter().collect(),
+            addresses: block.addresses.into_iter().collect(),
+            cdn: match block.cdns.len() {
+                1 => block.cdns.into_iter().next().flatten(),
+                _ => None,
+            },
+        })
+        .collect();
+
+    for (name, addresses) in plain {
+        let parent = parent(name);
+        let siblings: Vec<(&str, &'static str)> = fronted
+            .iter()
+            .filter(|(sibling, _)| self::parent(sibling) == parent)
+            .map(|(sibling, cdn)| (*sibling, *cdn))
+            .collect();
+        // The CDN most siblings use; ties go to the first alphabetically.
+        let mut counts: BTreeMap<&'static str, usize> = BTreeMap::new();
+        for (_, cdn) in &siblings {
+            *counts.entry(cdn).or_default() += 1;
+        }
+        let Some((cdn, _)) = counts.into_iter().max_by(|(a, x), (b, y)| x.cmp(y).then(b.cmp(a))) else {
+            continue;
+        };
+        report.origin_hints.push(OriginHint {
+            name: name.to_string(),
+            addresses,
+            cdn,
+            protected_siblings: siblings.into_iter().filter(|(_, c)| *c == cdn).map(|(s, _)| s.to_string()).collect(),
+        });
+    }
+    report.origin_hints.sort_by(|a, b| a.name.cmp(&b.name));
+    for names in report.cdn_names.values_mut() {
+        names.sort();
+    }
+    report
+}
+
+fn parent(name: &str) -> &str {
+    name.split_once('.').map_or("", |(_, parent)| parent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_cluster() {
+        assert_eq!(identify_cdn(&["www.example.com.cdn.cloudflare.net".to_string()], &[]), Some("Cloudflare"));
+        assert_eq!(identify_cdn(&[], &["104.18.2.3".parse().unwrap()]), Some("Cloudflare"));
+        assert_eq!(identify_cdn(&[], &["2606:4700::6810:1".parse().unwrap()]), Some("Cloudflare"));
+        assert_eq!(identify_cdn(&["d111.cloudfront.net".to_string()], &["192.0.2.1".parse().unwrap()]), Some("Amazon CloudFront"));
+        assert_eq!(identify_cdn(&[], &["192.0.2.1".parse().unwrap()]), None);
+        assert_eq!(netblock("192.0.2.77".parse().unwrap()), "192.0.2.0/24");
+
+        let result = json!({
+            "results": {
+                "records": {
+                    "www.example.com": { "cname_chain": [], "addresses": ["104.18.2.3"] },
+                    "shop.example.com": { "cname_chain": [], "addresses": ["104.18.2.4"] },
+                    "origin.example.com": { "cname_chain": [], "addresses": ["192.0.2.10"] },
+                    "mail.example.com": { "cname_chain": [], "addresses": ["192.0.2.11"] },
+                    "a.dev.example.com": { "cname_chain": [], "addresses": ["198.51.100.1"] },
+                }
+            }
+        });
+        let report = cluster(&result);
+        assert_eq!(report.clusters.len(), 3);
+        assert_eq!(report.clusters[1].netblock, "192.0.2.0/24");
+        assert_eq!(report.clusters[1].names, ["mail.example.com", "origin.example.com"]);
+        assert_eq!(report.clusters[1].cdn, None);
+        assert_eq!(report.clusters[0].cdn, Some("Cloudflare"));
+        assert_eq!(report.cdn_names["Cloudflare"], ["shop.example.com", "www.example.com"]);
+        let hinted: Vec<&str> = report.origin_hints.iter().map(|hint| hint.name.as_str()).collect();
+        assert_eq!(hinted, ["mail.example.com", "origin.example.com"]);
+        assert_eq!(report.origin_hints[0].protected_siblings, ["shop.example.com", "www.example.com"]);
+    }
+}
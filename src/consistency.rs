@@ -0,0 +1,217 @@
+//! Asking several resolvers for each found name again and comparing what
+//! they say (`--consistency K`). A poisoned cache, geo-DNS and a resolver
+//! holding a stale record all show up as answers that disagree; a pool of
+//! addresses handed out in rotation (round-robin, a CDN returning
+//! neighbours) is told apart by the answers sharing netblocks.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::task::JoinSet;
+
+use crate::answers::IpVersion;
+use crate::cluster;
+use crate::net::{SocketTuning, TunedRuntimeProvider};
+use crate::scanner::{self, QueryFlags, QueryOutcome, SubdomainScanner};
+use crate::traffic::Traffic;
+
+/// Names checked at once; each sends a query to every chosen resolver.
+pub const CONCURRENCY: usize = 50;
+
+/// What one resolver said about a name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResolverAnswer {
+    pub resolver: SocketAddr,
+    /// `answer`, `no_answer` (NXDOMAIN or no address), `failed` (timeout,
+    /// refused, garbage) or `skipped` (the query budget was spent); the
+    /// last two take no part in the comparison.
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<IpAddr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NameCheck {
+    /// `consistent`, `round_robin` (different addresses from shared
+    /// netblocks), `inconsistent`, or `unchecked` when fewer than two
+    /// resolvers answered.
+    pub verdict: &'static str,
+    /// Addresses most resolvers returned, or the most common answer when
+    /// no address has a majority.
+    pub consensus: Vec<IpAddr>,
+    /// Every resolver's answer, kept unless they all agree.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub answers: Vec<ResolverAnswer>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ConsistencyReport {
+    pub resolvers: Vec<SocketAddr>,
+    /// Names per verdict.
+    pub verdicts: BTreeMap<&'static str, u64>,
+    pub names: BTreeMap<String, NameCheck>,
+    pub queries: u64,
+    /// Queries not sent because the query budget was spent.
+    pub skipped: u64,
+}
+
+impl ConsistencyReport {
+    pub fn inconsistent(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().filter(|(_, check)| check.verdict == "inconsistent").map(|(name, _)| name.as_str())
+    }
+}
+
+/// `count` of `resolvers`, spread evenly over the list.
+pub fn pick(resolvers: &[SocketAddr], count: usize) -> Vec<SocketAddr> {
+    let count = count.min(resolvers.len());
+    let mut picked: Vec<SocketAddr> = (0..count).map(|i| resolvers[i * resolvers.len() / count]).collect();
+    picked.dedup();
+    picked
+}
+
+/// The verdict and consensus for one name's answers.
+pub fn assess(answers: &[ResolverAnswer]) -> (&'static str, Vec<IpAddr>) {
+    let sets: Vec<BTreeSet<IpAddr>> = answers
+        .iter()
+        .filter(|answer| answer.status == "answer" || answer.status == "no_answer")
+        .map(|answer| answer.addresses.iter().copied().collect())
+        .collect();
+    let mut votes: BTreeMap<IpAddr, usize> = BTreeMap::new();
+    for address in sets.iter().flatten() {
+        *votes.entry(*address).or_default() += 1;
+    }
+    let mut consensus: Vec<IpAddr> = votes.iter().filter(|(_, votes)| **votes * 2 > sets.len()).map(|(address, _)| *address).collect();
+    if consensus.is_empty() {
+        let mut tally: BTreeMap<&BTreeSet<IpAddr>, usize> = BTreeMap::new();
+        for set in &sets {
+            *tally.entry(set).or_default() += 1;
+        }
+        let most = tally.values().copied().max().unwrap_or_default();
+        consensus = tally.into_iter().find(|(_, count)| *count == most).map(|(set, _)| set.iter().copied().collect()).unwrap_or_default();
+    }
+    if sets.len() < 2 {
+        return ("unchecked", consensus);
+    }
+    if sets.windows(2).all(|pair| pair[0] == pair[1]) {
+        return ("consistent", consensus);
+    }
+    // Some resolvers do not know the name at all.
+    if sets.iter().any(BTreeSet::is_empty) {
+        return ("inconsistent", consensus);
+    }
+    let blocks: Vec<BTreeSet<String>> = sets.iter().map(|set| set.iter().map(|address| cluster::netblock(*address)).collect()).collect();
+    let related = blocks.iter().all(|first| blocks.iter().all(|second| !first.is_disjoint(second)));
+    (if related { "round_robin" } else { "inconsistent" }, consensus)
+}
+
+/// Asks each of `resolvers` for every name in `names`, for the address
+/// records `ip_version` asks for, and compares the answers.
+pub async fn check_all(names: &[String], resolvers: Vec<SocketAddr>, timeout: Duration, ip_version: IpVersion, traffic: &Traffic) -> ConsistencyReport {
+    let mut report = ConsistencyReport { resolvers: resolvers.clone(), ..Default::default() };
+    let mut set = JoinSet::new();
+    let mut pending = names.iter().cloned();
+    loop {
+        while set.len() < CONCURRENCY
+            && let Some(name) = pending.next()
+        {
+            let (resolvers, traffic) = (resolvers.clone(), traffic.clone());
+            set.spawn(async move {
+                let asked = resolvers.iter().map(|resolver| ask(*resolver, timeout, name.clone(), ip_version, &traffic));
+                let answers = futures_util::future::join_all(asked).await;
+                (name, answers)
+            });
+        }
+        let Some(joined) = set.join_next().await else {
+            break;
+        };
+        let Ok((name, answers)) = joined else {
+            continue;
+        };
+        let answers: Vec<ResolverAnswer> = answers
+            .into_iter()
+            .map(|asked| {
+                report.queries += asked.sent;
+                report.skipped += asked.skipped;
+                asked.answer
+            })
+            .collect();
+        let (verdict, consensus) = assess(&answers);
+        *report.verdicts.entry(verdict).or_default() += 1;
+        let answers = if verdict == "consistent" { Vec::new() } else { answers };
+        report.names.insert(name, NameCheck { verdict, consensus, answers });
+    }
+    report
+}
+
+/// One resolver's answer for a name and the queries it took.
+pub(crate) struct Asked {
+    pub answer: ResolverAnswer,
+    pub sent: u64,
+    pub skipped: u64,
+}
+
+/// What `resolver` answers for `name`. Each query counts against the
+/// query budget; once it is spent the answer is `skipped`.
+pub(crate) async fn ask(resolver: SocketAddr, timeout: Duration, name: String, ip_version: IpVersion, traffic: &Traffic) -> Asked {
+    let types = scanner::address_types(ip_version);
+    let mut outcome: Option<QueryOutcome> = None;
+    for (index, &record_type) in types.iter().enumerate() {
+        if !traffic.budget.try_spend() {
+            let skipped = (types.len() - index) as u64;
+            let answer = ResolverAnswer { resolver, status: "skipped", addresses: Vec::new() };
+            return Asked { answer, sent: index as u64, skipped };
+        }
+        let provider = TunedRuntimeProvider::new(SocketTuning::default());
+        let next = SubdomainScanner::try_resolve_type(resolver, timeout, provider, name.clone(), record_type, &QueryFlags::default(), traffic).await;
+        outcome = Some(match outcome {
+            Some(outcome) => outcome.merge(next),
+            None => next,
+        });
+    }
+    let (status, addresses) = match outcome {
+        Some(QueryOutcome::Found(_, resolution)) if !resolution.addresses.is_empty() => ("answer", resolution.addresses),
+        Some(QueryOutcome::Found(..) | QueryOutcome::NotFound | QueryOutcome::NxDomain) => ("no_answer", Vec::new()),
+        Some(QueryOutcome::Failed(..)) | None => ("failed", Vec::new()),
+    };
+    Asked { answer: ResolverAnswer { resolver, status, addresses }, sent: types.len() as u64, skipped: 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answer(resolver: u8, addresses: &[&str]) -> ResolverAnswer {
+        ResolverAnswer {
+            resolver: SocketAddr::from(([192, 0, 2, resolver], 53)),
+            status: if addresses.is_empty() { "no_answer" } else { "answer" },
+            addresses: addresses.iter().map(|a| a.parse().unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_assess() {
+        let ip = |a: &str| a.parse::<IpAddr>().unwrap();
+        let same = [answer(1, &["198.51.100.1", "198.51.100.2"]), answer(2, &["198.51.100.2", "198.51.100.1"])];
+        assert_eq!(assess(&same), ("consistent", vec![ip("198.51.100.1"), ip("198.51.100.2")]));
+
+        let rotated = [answer(1, &["198.51.100.1"]), answer(2, &["198.51.100.7"]), answer(3, &["198.51.100.1"])];
+        assert_eq!(assess(&rotated), ("round_robin", vec![ip("198.51.100.1")]));
+
+        // One resolver sends the name somewhere else entirely.
+        let poisoned = [answer(1, &["198.51.100.1"]), answer(2, &["203.0.113.66"]), answer(3, &["198.51.100.1"])];
+        assert_eq!(assess(&poisoned), ("inconsistent", vec![ip("198.51.100.1")]));
+        let missing = [answer(1, &["198.51.100.1"]), answer(2, &[])];
+        assert_eq!(assess(&missing).0, "inconsistent");
+
+        let failed = ResolverAnswer { status: "failed", ..answer(2, &[]) };
+        assert_eq!(assess(&[answer(1, &["198.51.100.1"]), failed]), ("unchecked", vec![ip("198.51.100.1")]));
+        let skipped = ResolverAnswer { status: "skipped", ..answer(2, &[]) };
+        assert_eq!(assess(&[answer(1, &["198.51.100.1"]), skipped]).0, "unchecked");
+
+        let resolvers: Vec<SocketAddr> = (1..=6).map(|i| SocketAddr::from(([192, 0, 2, i], 53))).collect();
+        assert_eq!(pick(&resolvers, 3), [resolvers[0], resolvers[2], resolvers[4]]);
+        assert_eq!(pick(&resolvers[..2], 3), resolvers[..2]);
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::BTreeMap;
+
+use psl::Psl;
+use tracing::{info, warn};
+
+/// Public Suffix List used for registrable-domain logic: the copy compiled
+/// into the binary, or a newer one loaded from disk with `--psl`.
+#[derive(Default)]
+pub enum SuffixList {
+    #[default]
+    Embedded,
+    Loaded(publicsuffix::List),
+}
+
+impl SuffixList {
+    /// Loads a list in the official `public_suffix_list.dat` format.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("could not read public suffix list '{}': {}", path, e))?;
+        let list = publicsuffix::List::from_bytes(&bytes)
+            .map_err(|e| format!("could not parse public suffix list '{}': {}", path, e))?;
+        Ok(SuffixList::Loaded(list))
+    }
+
+    fn lookup<'a>(&self, host: &'a str) -> Option<psl::Domain<'a>> {
+        match self {
+            SuffixList::Embedded => psl::List.domain(host.as_bytes()),
+            SuffixList::Loaded(list) => list.domain(host.as_bytes()),
+        }
+    }
+
+    /// The registrable domain (public suffix plus one label) of `host`, e.g.
+    /// `example.co.uk` for `www.example.co.uk`. `None` when `host` is itself a
+    /// public suffix.
+    pub fn registrable_domain(&self, host: &str) -> Option<String> {
+        let domain = self.lookup(host)?;
+        std::str::from_utf8(domain.as_bytes()).ok().map(str::to_string)
+    }
+
+    /// Whether the suffix of `host` is listed, rather than matched by the
+    /// implicit `*` rule that applies to unknown TLDs.
+    pub fn has_known_suffix(&self, host: &str) -> bool {
+        self.lookup(host).is_some_and(|domain| domain.suffix().is_known())
+    }
+
+    /// Buckets targets by registrable domain, for reporting multi-target scans.
+    pub fn group_by_registrable<'a>(&self, targets: impl IntoIterator<Item = &'a str>) -> BTreeMap<String, Vec<String>> {
+        let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for target in targets {
+            let key = self.registrable_domain(target).unwrap_or_else(|| target.to_string());
+            groups.entry(key).or_default().push(target.to_string());
+        }
+        groups
+    }
+}
+
+/// Reduces a URL or scope string (`https://user@www.example.com:8443/path`,
+/// `*.example.com`, `Example.COM.`) to a bare lowercase hostname.
+pub fn normalize_host(input: &str) -> String {
+    let mut host = input.trim();
+    if let Some((_, rest)) = host.split_once("://") {
+        host = rest;
+    }
+    host = host.split(['/', '?', '#']).next().unwrap_or(host);
+    host = host.rsplit('@').next().unwrap_or(host);
+    if let Some((name, port)) = host.rsplit_once(':')
+        && !name.contains(':')
+        && port.chars().all(|c| c.is_ascii_digit())
+    {
+        host = name;
+    }
+    while let Some(rest) = host.strip_prefix("*.") {
+        host = rest;
+    }
+    host.trim_start_matches('.').trim_end_matches('.').to_lowercase()
+}
+
+/// Turns the `--domain` argument into the domain to brute force under. By
+/// default that's the registrable domain; with `keep_subdomain` a subdomain
+/// target is kept as given. Public suffixes themselves are rejected.
+pub fn normalize_target(input: &str, keep_subdomain: bool, list: &SuffixList) -> Result<String, String> {
+    let host = normalize_host(input);
+    let registrable = list.registrable_domain(&host);
+    if registrable.is_some() && !list.has_known_suffix(&host) {
+        warn!(
+            "the suffix of {} is not in the public suffix list, treating it as a private TLD",
+            host
+        );
+    }
+    let target = match registrable {
+        None if host.contains('.') => {
+            return Err(format!(
+                "'{}' is a public suffix, brute forcing under it would enumerate unrelated registrants; pass a registrable domain such as example.{}",
+                host, host
+            ));
+        }
+        Some(registrable) if registrable != host => {
+            if keep_subdomain {
+                warn!("target {} is a subdomain of {}", host, registrable);
+                host.clone()
+            } else {
+                warn!(
+                    "{} is a subdomain of {}, scanning {} (pass --subdomain-scope to stay under {})",
+                    host, registrable, registrable, host
+                );
+                registrable
+            }
+        }
+        _ => host.clone(),
+    };
+
+    if target != input {
+        info!("normalized target '{}' to {}", input, target);
+    }
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_host() {
+        assert_eq!(normalize_host("https://www.example.com/path?q=1"), "www.example.com");
+        assert_eq!(normalize_host("http://user:pw@Example.com:8080"), "example.com");
+        assert_eq!(normalize_host("*.example.com"), "example.com");
+        assert_eq!(normalize_host(" example.com. "), "example.com");
+        assert_eq!(normalize_host("example.com:443"), "example.com");
+    }
+
+    #[test]
+    fn test_normalize_target() {
+        let list = SuffixList::Embedded;
+        let normalize = |input| normalize_target(input, false, &list).unwrap();
+        assert_eq!(normalize("https://www.example.com/path"), "example.com");
+        assert_eq!(normalize("*.shop.example.co.uk"), "example.co.uk");
+        assert_eq!(normalize("example.com"), "example.com");
+        assert_eq!(normalize_target("dev.example.com", true, &list).unwrap(), "dev.example.com");
+        assert!(normalize_target("co.uk", false, &list).unwrap_err().contains("public suffix"));
+        assert!(normalize_target("github.io", false, &list).is_err());
+    }
+
+    #[test]
+    fn test_loaded_list_and_grouping() {
+        let list = SuffixList::Loaded(publicsuffix::List::from_bytes(b"// ===BEGIN ICANN DOMAINS===\ncom\nexample.com\n// ===END ICANN DOMAINS===\n").unwrap());
+        assert_eq!(list.registrable_domain("a.b.example.com").as_deref(), Some("b.example.com"));
+        assert_eq!(list.registrable_domain("example.com"), None);
+
+        let groups = SuffixList::Embedded.group_by_registrable(["dev.example.com", "example.org", "api.example.com"]);
+        assert_eq!(groups["example.com"], vec!["dev.example.com", "api.example.com"]);
+        assert_eq!(groups["example.org"], vec!["example.org"]);
+    }
+}
@@ -0,0 +1,259 @@
+//! DNS over QUIC (RFC 9250) resolvers. A resolver file line
+//!
+//! ```text
+//! quic://94.140.14.14                         # port 853, certificate for the address
+//! quic://94.140.14.14:853#dns.adguard-dns.com # certificate name after '#'
+//! quic://dns.adguard-dns.com                  # looked up once, at startup
+//! ```
+//!
+//! makes that resolver answer over one QUIC connection, reused for every
+//! query and reopened after it fails, instead of a UDP socket per query.
+//! A host name with both A and AAAA records is connected to Happy
+//! Eyeballs style (RFC 8305): IPv6 first, the next address after a short
+//! delay, the first handshake to finish wins, so a broken IPv6 path costs
+//! a quarter second instead of a stalled scan.
+//! Everything else still knows the resolver by its address, so health,
+//! pins and statistics work unchanged; [`crate::scanner::exchange`] looks
+//! the address up here to pick the transport.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use futures_util::stream::FuturesUnordered;
+use hickory_client::client::Client;
+use hickory_client::proto::quic::QuicClientStream;
+use hickory_client::proto::rustls::client_config;
+use tracing::debug;
+
+use crate::scanner::ConnectionReport;
+
+pub const SCHEME: &str = "quic://";
+const DEFAULT_PORT: u16 = 853;
+/// How long one connection attempt runs alone before the next address is
+/// tried alongside it; RFC 8305 recommends 250ms.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// One DoQ resolver and its connection, opened on first use.
+pub struct QuicResolver {
+    /// The address the rest of the scan knows it by: the first one tried.
+    pub address: SocketAddr,
+    /// Every address to try, in Happy Eyeballs order.
+    pub addresses: Vec<SocketAddr>,
+    /// The name its certificate is checked against.
+    pub server_name: String,
+    client: tokio::sync::Mutex<Option<Client>>,
+    report: Mutex<ConnectionReport>,
+}
+
+static RESOLVERS: OnceLock<RwLock<HashMap<SocketAddr, Arc<QuicResolver>>>> = OnceLock::new();
+
+fn resolvers() -> &'static RwLock<HashMap<SocketAddr, Arc<QuicResolver>>> {
+    RESOLVERS.get_or_init(Default::default)
+}
+
+/// Parses what follows `quic://` and registers the resolver, returning
+/// the address it is known by.
+pub fn register(spec: &str) -> Option<SocketAddr> {
+    let (addresses, server_name) = parse(spec)?;
+    let address = addresses[0];
+    resolvers().write().unwrap().entry(address).or_insert_with(|| {
+        Arc::new(QuicResolver {
+            address,
+            report: Mutex::new(ConnectionReport {
+                resolver: address,
+                server_name: server_name.clone(),
+                transport: "doq",
+                family: None,
+                connected_to: None,
+                connects: 0,
+                fallbacks: 0,
+                failed_connects: 0,
+            }),
+            addresses,
+            server_name,
+            client: tokio::sync::Mutex::new(None),
+        })
+    });
+    Some(address)
+}
+
+/// `IP[:port][#name]`, `[IPv6]:port[#name]` or `host[:port]`, with the
+/// addresses to try in order.
+fn parse(spec: &str) -> Option<(Vec<SocketAddr>, String)> {
+    let (target, name) = match spec.split_once('#') {
+        Some((target, name)) if !name.is_empty() => (target, Some(name.to_string())),
+        Some(_) => return None,
+        None => (spec, None),
+    };
+    if let Ok(address) = target.parse::<SocketAddr>() {
+        return Some((vec![address], name.unwrap_or_else(|| address.ip().to_string())));
+    }
+    if let Ok(ip) = target.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        let address = SocketAddr::new(ip, DEFAULT_PORT);
+        return Some((vec![address], name.unwrap_or_else(|| ip.to_string())));
+    }
+    let (host, port) = match target.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().ok()?),
+        None => (target, DEFAULT_PORT),
+    };
+    if host.is_empty() || !host.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.')) {
+        return None;
+    }
+    let addresses = interleave((host, port).to_socket_addrs().ok()?.collect());
+    if addresses.is_empty() {
+        return None;
+    }
+    Some((addresses, name.unwrap_or_else(|| host.to_string())))
+}
+
+/// IPv6 first, then alternating families, each in lookup order.
+fn interleave(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (mut v6, mut v4): (Vec<_>, Vec<_>) = addresses.into_iter().partition(SocketAddr::is_ipv6);
+    v6.dedup();
+    v4.dedup();
+    let mut ordered = Vec::with_capacity(v6.len() + v4.len());
+    let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => break,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+    ordered
+}
+
+/// Connects to the first of `candidates` that answers, starting the next
+/// attempt `delay` after the previous one or as soon as it fails.
+pub(crate) async fn race<T, Fut>(candidates: &[SocketAddr], delay: Duration, connect: impl Fn(SocketAddr) -> Fut) -> Result<(SocketAddr, T), String>
+where
+    Fut: Future<Output = Result<T, String>>,
+{
+    let attempt = |address: SocketAddr| {
+        let connecting = connect(address);
+        async move { (address, connecting.await) }
+    };
+    let mut pending = FuturesUnordered::new();
+    let mut next = 0;
+    let mut errors = Vec::new();
+    loop {
+        if pending.is_empty() {
+            let Some(&address) = candidates.get(next) else {
+                return Err(errors.join("; "));
+            };
+            pending.push(attempt(address));
+            next += 1;
+        }
+        tokio::select! {
+            Some((address, result)) = pending.next() => match result {
+                Ok(connected) => return Ok((address, connected)),
+                Err(e) => errors.push(format!("{}: {}", address, e)),
+            },
+            _ = tokio::time::sleep(delay), if next < candidates.len() => {
+                pending.push(attempt(candidates[next]));
+                next += 1;
+            }
+        }
+    }
+}
+
+/// The DoQ resolver at `address`, if one was registered.
+pub fn lookup(address: SocketAddr) -> Option<Arc<QuicResolver>> {
+    RESOLVERS.get()?.read().unwrap().get(&address).cloned()
+}
+
+/// How many DoQ resolvers the resolver files listed.
+pub fn registered() -> usize {
+    RESOLVERS.get().map_or(0, |resolvers| resolvers.read().unwrap().len())
+}
+
+/// How the connections to those of `resolvers` that are DoQ went.
+pub fn reports(resolvers: &[SocketAddr]) -> Vec<ConnectionReport> {
+    resolvers.iter().filter_map(|resolver| lookup(*resolver)).map(|quic| quic.report.lock().unwrap().clone()).collect()
+}
+
+impl QuicResolver {
+    /// The open connection, or a new one.
+    pub async fn client(&self) -> Result<Client, String> {
+        let mut client = self.client.lock().await;
+        if let Some(client) = client.as_ref() {
+            return Ok(client.clone());
+        }
+        let handshake = |address: SocketAddr| async move {
+            let mut builder = QuicClientStream::builder();
+            builder.crypto_config(client_config());
+            Client::connect(builder.build(address, self.server_name.clone())).await.map_err(|e| e.to_string())
+        };
+        let (address, (connected, bg)) = match race(&self.addresses, ATTEMPT_DELAY, handshake).await {
+            Ok(won) => won,
+            Err(e) => {
+                self.report.lock().unwrap().failed_connects += 1;
+                return Err(format!("QUIC connection to {} failed: {}", self.server_name, e));
+            }
+        };
+        tokio::spawn(bg);
+        debug!("opened a QUIC connection to {} at {}", self.server_name, address);
+        let mut report = self.report.lock().unwrap();
+        report.connects += 1;
+        report.connected_to = Some(address);
+        report.family = Some(if address.is_ipv6() { "ipv6" } else { "ipv4" });
+        report.fallbacks += (address.is_ipv6() != self.address.is_ipv6()) as u64;
+        drop(report);
+        *client = Some(connected.clone());
+        Ok(connected)
+    }
+
+    /// Drops the connection after an error, so the next query opens a new one.
+    pub async fn reset(&self) {
+        self.client.lock().await.take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("94.140.14.14"), Some((vec!["94.140.14.14:853".parse().unwrap()], "94.140.14.14".to_string())));
+        assert_eq!(
+            parse("94.140.14.14:8853#dns.adguard-dns.com"),
+            Some((vec!["94.140.14.14:8853".parse().unwrap()], "dns.adguard-dns.com".to_string()))
+        );
+        assert_eq!(parse("[2a10:50c0::ad1:ff]#dns.adguard-dns.com").unwrap().0, ["[2a10:50c0::ad1:ff]:853".parse().unwrap()]);
+        assert_eq!(parse("localhost:8853").map(|(addresses, name)| (addresses[0].port(), name)), Some((8853, "localhost".to_string())));
+        assert_eq!(parse("94.140.14.14#"), None);
+        assert_eq!(parse("bad host"), None);
+
+        let address = register("192.0.2.53#doq.example.net").unwrap();
+        assert_eq!(lookup(address).unwrap().server_name, "doq.example.net");
+        assert!(lookup("192.0.2.54:853".parse().unwrap()).is_none());
+
+        let addresses: Vec<SocketAddr> = ["192.0.2.1:853", "192.0.2.2:853", "[2001:db8::1]:853"].iter().map(|a| a.parse().unwrap()).collect();
+        assert_eq!(interleave(addresses.clone()), [addresses[2], addresses[0], addresses[1]]);
+    }
+
+    #[tokio::test]
+    async fn test_race() {
+        let v6: SocketAddr = "[2001:db8::1]:853".parse().unwrap();
+        let v4: SocketAddr = "192.0.2.1:853".parse().unwrap();
+        // A black-holed IPv6 path: the IPv4 attempt starts after the delay and wins.
+        let stalled_v6 = |address: SocketAddr| async move {
+            if address.is_ipv6() {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+            }
+            Ok::<_, String>(address.is_ipv4())
+        };
+        let started = std::time::Instant::now();
+        assert_eq!(race(&[v6, v4], Duration::from_millis(20), stalled_v6).await, Ok((v4, true)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let refused = |address: SocketAddr| async move { if address.is_ipv6() { Err("unreachable".to_string()) } else { Ok(()) } };
+        assert_eq!(race(&[v6, v4], Duration::from_secs(30), refused).await, Ok((v4, ())));
+        let nothing = |_: SocketAddr| async { Err::<(), _>("refused".to_string()) };
+        assert_eq!(race(&[v6, v4], Duration::from_millis(1), nothing).await, Err("[2001:db8::1]:853: refused; 192.0.2.1:853: refused".to_string()));
+    }
+}
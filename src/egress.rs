@@ -0,0 +1,449 @@
+//! Process-wide caps on outgoing DNS packets. Every query leaves through
+//! [`crate::scanner::exchange`] or the raw engine's sender, and both wait
+//! here first, so the caps hold for verification, enrichment and monitor
+//! queries as well as the scan itself.
+//!
+//! Each cap is a [`RateLimiter`], a token bucket or a sliding window: on
+//! everything sent, and optionally one per resolver and one per
+//! authoritative zone (the registrable domain of the name asked for, whose
+//! nameservers take every cache miss), created as keys first show up.
+//!
+//! A [`Ramp`] eases every cap in and out: a scan starts at a tenth of the
+//! rate, reaches it over the warm-up, and slows down again as the last
+//! candidates go out, instead of opening with the burst that gets a source
+//! address blocked within seconds.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::domain::SuffixList;
+
+// IP and UDP headers, counted towards --max-bandwidth.
+const IPV4_UDP_HEADERS: usize = 28;
+const IPV6_UDP_HEADERS: usize = 48;
+// How far ahead of the rate a quiet sender may get.
+const BURST: Duration = Duration::from_millis(100);
+// What a sliding window counts over; rates are per second.
+const WINDOW: Duration = Duration::from_secs(1);
+
+// The fraction of the rate a ramp starts and ends at.
+const RAMP_FLOOR: f64 = 0.1;
+
+static LIMIT: OnceLock<EgressLimit> = OnceLock::new();
+/// Queries the running scans have yet to send, for the cool-down.
+static REMAINING: AtomicU64 = AtomicU64::new(0);
+
+/// Installs the caps for the rest of the process. Only the first call has
+/// an effect.
+pub fn set_limit(limit: EgressLimit) {
+    let _ = LIMIT.set(limit);
+}
+
+pub fn is_limited() -> bool {
+    LIMIT.get().is_some()
+}
+
+/// Waits until `packets` packets with `payload` bytes of DNS messages in
+/// total may be sent to `to`. Returns at once when no cap is set.
+pub async fn admit(to: SocketAddr, packets: usize, payload: usize) {
+    let Some(limit) = LIMIT.get() else {
+        return;
+    };
+    let _ = REMAINING.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| Some(left.saturating_sub(packets as u64)));
+    let headers = if to.is_ipv4() { IPV4_UDP_HEADERS } else { IPV6_UDP_HEADERS };
+    let wait = limit.reserve(packets, payload + packets * headers, Instant::now());
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Waits until one query for `name` may go to `resolver` under the
+/// per-resolver and per-zone caps. Returns at once when neither is set.
+pub async fn admit_query(resolver: SocketAddr, name: &str) {
+    let Some(limit) = LIMIT.get() else {
+        return;
+    };
+    let zone = limit.per_zone.as_ref().map(|_| {
+        let name = name.trim_end_matches('.').to_lowercase();
+        SuffixList::Embedded.registrable_domain(&name).unwrap_or(name)
+    });
+    let wait = limit.paced(Instant::now(), |now, scale| {
+        let by_resolver = limit.per_resolver.as_ref().map_or(Duration::ZERO, |keyed| keyed.reserve(resolver, scale, now));
+        let by_zone = limit.per_zone.as_ref().zip(zone).map_or(Duration::ZERO, |(keyed, zone)| keyed.reserve(zone, scale, now));
+        by_resolver.max(by_zone)
+    });
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Counts `candidates` more queries the running scans are going to send,
+/// for the cool-down. Each admitted packet counts one off.
+pub fn expect(candidates: u64) {
+    REMAINING.fetch_add(candidates, Ordering::Relaxed);
+}
+
+/// How long a scan takes to reach the full rate and to slow down again at
+/// the end. The cool-down begins once the candidates left would take that
+/// long at the full `--max-pps`; without it there is nothing to time it by.
+///
+/// The warm-up runs the limiters on a clock that starts at a tenth of real
+/// speed and speeds up to it, so a reservation made early and due late is
+/// due as soon as the rising rate allows it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Ramp {
+    pub warm_up: Duration,
+    pub cool_down: Duration,
+}
+
+impl Ramp {
+    /// The limiters' clock `elapsed` into the scan: the integral of a rate
+    /// rising linearly from the floor to one over the warm-up.
+    fn warm_clock(&self, elapsed: Duration) -> f64 {
+        let (t, w) = (elapsed.as_secs_f64(), self.warm_up.as_secs_f64());
+        if t >= w {
+            w * (1.0 + RAMP_FLOOR) / 2.0 + (t - w)
+        } else {
+            RAMP_FLOOR * t + (1.0 - RAMP_FLOOR) * t * t / (2.0 * w)
+        }
+    }
+
+    /// The real time into the scan at which the limiters' clock shows `clock`.
+    fn real_time(&self, clock: f64) -> Duration {
+        let w = self.warm_up.as_secs_f64();
+        let warmed = w * (1.0 + RAMP_FLOOR) / 2.0;
+        let t = if clock >= warmed {
+            w + (clock - warmed)
+        } else {
+            let slope = (1.0 - RAMP_FLOOR) / w;
+            ((RAMP_FLOOR * RAMP_FLOOR + 2.0 * slope * clock).sqrt() - RAMP_FLOOR) / slope
+        };
+        Duration::from_secs_f64(t.max(0.0))
+    }
+
+    /// The fraction of the rate left with `remaining` candidates to go at
+    /// `pps` packets per second.
+    fn cool_factor(&self, remaining: u64, pps: Option<f64>) -> f64 {
+        match pps {
+            Some(pps) if !self.cool_down.is_zero() => {
+                RAMP_FLOOR + (1.0 - RAMP_FLOOR) * (remaining as f64 / (pps * self.cool_down.as_secs_f64())).min(1.0)
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+/// Takes what is about to be sent and says how long to wait first.
+/// Reservations are never refused: a sender over the limit is told to
+/// wait longer, so a batch larger than the limit still goes out whole.
+pub trait RateLimiter: Debug + Send + Sync {
+    fn reserve(&self, amount: f64, now: Instant) -> Duration;
+}
+
+/// How rates are enforced; every cap uses the same.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LimiterKind {
+    /// Smooth: a burst of a tenth of a second, then the rate.
+    #[default]
+    TokenBucket,
+    /// At most the rate in any one-second window, however it bunches up.
+    SlidingWindow,
+}
+
+impl FromStr for LimiterKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "token-bucket" => Ok(LimiterKind::TokenBucket),
+            "sliding-window" => Ok(LimiterKind::SlidingWindow),
+            _ => Err(format!("Unknown rate limiter: {}", s)),
+        }
+    }
+}
+
+impl LimiterKind {
+    /// A limiter for `rate` per second.
+    pub fn build(self, rate: f64) -> Arc<dyn RateLimiter> {
+        match self {
+            LimiterKind::TokenBucket => Arc::new(TokenBucket::new(rate)),
+            LimiterKind::SlidingWindow => Arc::new(SlidingWindow::new(rate, WINDOW)),
+        }
+    }
+}
+
+/// One limiter per key, each with the same rate.
+#[derive(Debug)]
+struct Keyed<K> {
+    kind: LimiterKind,
+    rate: f64,
+    limiters: Mutex<HashMap<K, Arc<dyn RateLimiter>>>,
+}
+
+impl<K: Eq + Hash> Keyed<K> {
+    fn new(kind: LimiterKind, rate: f64) -> Self {
+        Self { kind, rate, limiters: Mutex::default() }
+    }
+
+    fn reserve(&self, key: K, amount: f64, now: Instant) -> Duration {
+        let limiter = self.limiters.lock().unwrap().entry(key).or_insert_with(|| self.kind.build(self.rate)).clone();
+        limiter.reserve(amount, now)
+    }
+}
+
+/// A bandwidth in bits per second, written as a number with an optional
+/// decimal `k`, `M` or `G` suffix (`10M` is 10 Mbit/s).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bandwidth(pub u64);
+
+impl FromStr for Bandwidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim().trim_end_matches("bit").trim_end_matches("bps");
+        let (number, scale) = match trimmed.char_indices().last() {
+            Some((i, 'k' | 'K')) => (&trimmed[..i], 1_000),
+            Some((i, 'm' | 'M')) => (&trimmed[..i], 1_000_000),
+            Some((i, 'g' | 'G')) => (&trimmed[..i], 1_000_000_000),
+            _ => (trimmed, 1),
+        };
+        match number.parse::<f64>() {
+            Ok(n) if n > 0.0 && n.is_finite() => Ok(Bandwidth((n * scale as f64) as u64)),
+            _ => Err(format!("Unknown bandwidth: {}", s)),
+        }
+    }
+}
+
+/// Caps on packets and bytes per second overall, and on queries per
+/// second per resolver and per zone.
+#[derive(Debug)]
+pub struct EgressLimit {
+    kind: LimiterKind,
+    packets: Option<Arc<dyn RateLimiter>>,
+    bytes: Option<Arc<dyn RateLimiter>>,
+    per_resolver: Option<Keyed<SocketAddr>>,
+    per_zone: Option<Keyed<String>>,
+    max_pps: Option<f64>,
+    ramp: Ramp,
+    /// When the first packet was admitted, for the warm-up.
+    started: OnceLock<Instant>,
+}
+
+impl EgressLimit {
+    pub fn new(max_pps: Option<u32>, max_bandwidth: Option<Bandwidth>) -> Self {
+        Self::with_kind(LimiterKind::default(), max_pps, max_bandwidth)
+    }
+
+    pub fn with_kind(kind: LimiterKind, max_pps: Option<u32>, max_bandwidth: Option<Bandwidth>) -> Self {
+        Self {
+            kind,
+            packets: max_pps.map(|pps| kind.build(pps as f64)),
+            bytes: max_bandwidth.map(|Bandwidth(bits)| kind.build(bits as f64 / 8.0)),
+            per_resolver: None,
+            per_zone: None,
+            max_pps: max_pps.map(f64::from),
+            ramp: Ramp::default(),
+            started: OnceLock::new(),
+        }
+    }
+
+    pub fn with_ramp(mut self, ramp: Ramp) -> Self {
+        self.ramp = ramp;
+        self
+    }
+
+    /// At most `qps` queries per second to each resolver.
+    pub fn with_per_resolver(mut self, qps: Option<u32>) -> Self {
+        self.per_resolver = qps.map(|qps| Keyed::new(self.kind, qps as f64));
+        self
+    }
+
+    /// At most `qps` queries per second for names in each zone.
+    pub fn with_per_zone(mut self, qps: Option<u32>) -> Self {
+        self.per_zone = qps.map(|qps| Keyed::new(self.kind, qps as f64));
+        self
+    }
+
+    /// Whether any cap is set.
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_none() && self.bytes.is_none() && self.per_resolver.is_none() && self.per_zone.is_none()
+    }
+
+    fn reserve(&self, packets: usize, bytes: usize, now: Instant) -> Duration {
+        self.paced(now, |now, scale| {
+            let packets = self.packets.as_ref().map_or(Duration::ZERO, |b| b.reserve(packets as f64 * scale, now));
+            let bytes = self.bytes.as_ref().map_or(Duration::ZERO, |b| b.reserve(bytes as f64 * scale, now));
+            packets.max(bytes)
+        })
+    }
+
+    /// Runs `reserve` (given the instant to reserve at and how much to
+    /// scale the amount by) under the ramp, returning the real wait.
+    fn paced(&self, now: Instant, reserve: impl FnOnce(Instant, f64) -> Duration) -> Duration {
+        if self.ramp == Ramp::default() {
+            return reserve(now, 1.0);
+        }
+        let started = *self.started.get_or_init(|| now);
+        // Taking more of a limiter is sending at a lower rate.
+        let scale = 1.0 / self.ramp.cool_factor(REMAINING.load(Ordering::Relaxed), self.max_pps);
+        if self.ramp.warm_up.is_zero() {
+            return reserve(now, scale);
+        }
+        let elapsed = now.saturating_duration_since(started);
+        let clock = self.ramp.warm_clock(elapsed);
+        let wait = reserve(started + Duration::from_secs_f64(clock), scale);
+        self.ramp.real_time(clock + wait.as_secs_f64()).saturating_sub(elapsed)
+    }
+}
+
+/// Tokens refill at the rate up to a small burst; senders sleep off any
+/// debt.
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    /// Tokens available (negative while in debt) as of the instant.
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64) -> Self {
+        let burst = (rate * BURST.as_secs_f64()).max(1.0);
+        Self {
+            rate,
+            burst,
+            state: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+}
+
+impl RateLimiter for TokenBucket {
+    /// Takes `amount` tokens and returns how long to wait before using them.
+    fn reserve(&self, amount: f64, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let (tokens, at) = *state;
+        let refilled = (tokens + now.saturating_duration_since(at).as_secs_f64() * self.rate).min(self.burst);
+        let left = refilled - amount;
+        *state = (left, now.max(at));
+        if left >= 0.0 { Duration::ZERO } else { Duration::from_secs_f64(-left / self.rate) }
+    }
+}
+
+/// Remembers when each reservation is due to go out and admits a new one
+/// at the first instant the window ending there holds room for it.
+#[derive(Debug)]
+pub struct SlidingWindow {
+    limit: f64,
+    window: Duration,
+    sent: Mutex<VecDeque<(Instant, f64)>>,
+}
+
+impl SlidingWindow {
+    /// `rate` per second, counted over `window`.
+    pub fn new(rate: f64, window: Duration) -> Self {
+        Self {
+            limit: (rate * window.as_secs_f64()).max(1.0),
+            window,
+            sent: Mutex::default(),
+        }
+    }
+}
+
+impl RateLimiter for SlidingWindow {
+    fn reserve(&self, amount: f64, now: Instant) -> Duration {
+        let mut sent = self.sent.lock().unwrap();
+        while sent.front().is_some_and(|(at, _)| *at + self.window <= now) {
+            sent.pop_front();
+        }
+        // More than fits in a window waits for an empty one.
+        let amount = amount.min(self.limit);
+        let mut in_window: f64 = sent.iter().map(|(_, n)| n).sum();
+        let mut due = now;
+        for (at, n) in sent.iter() {
+            if in_window + amount <= self.limit {
+                break;
+            }
+            in_window -= n;
+            due = *at + self.window;
+        }
+        let due = sent.back().map_or(due, |(last, _)| due.max(*last));
+        sent.push_back((due, amount));
+        due - now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_debt() {
+        let bucket = TokenBucket::new(100.0);
+        let start = Instant::now();
+        // The burst is 10 packets; the next 10 are a tenth of a second late.
+        assert_eq!(bucket.reserve(10.0, start), Duration::ZERO);
+        let wait = bucket.reserve(10.0, start);
+        assert!((wait.as_secs_f64() - 0.1).abs() < 1e-6);
+        // After sleeping off the debt, a packet is again free.
+        assert_eq!(bucket.reserve(0.0, start + Duration::from_millis(100)), Duration::ZERO);
+        assert!(!bucket.reserve(1.0, start + Duration::from_millis(100)).is_zero());
+    }
+
+    #[test]
+    fn test_sliding_window() {
+        let window = SlidingWindow::new(10.0, Duration::from_secs(1));
+        let start = Instant::now();
+        // The whole second's worth may go at once, unlike the bucket.
+        assert_eq!(window.reserve(10.0, start), Duration::ZERO);
+        // The next waits for the first to leave the window.
+        assert_eq!(window.reserve(1.0, start + Duration::from_millis(200)), Duration::from_millis(800));
+        assert_eq!(window.reserve(1.0, start + Duration::from_millis(1500)), Duration::ZERO);
+
+        let keyed = Keyed::new(LimiterKind::SlidingWindow, 1.0);
+        let a: SocketAddr = "192.0.2.1:53".parse().unwrap();
+        let b: SocketAddr = "192.0.2.2:53".parse().unwrap();
+        assert_eq!(keyed.reserve(a, 1.0, start), Duration::ZERO);
+        assert_eq!(keyed.reserve(b, 1.0, start), Duration::ZERO);
+        assert_eq!(keyed.reserve(a, 1.0, start), Duration::from_secs(1));
+
+        assert_eq!("sliding-window".parse::<LimiterKind>(), Ok(LimiterKind::SlidingWindow));
+        assert!("leaky".parse::<LimiterKind>().is_err());
+    }
+
+    #[test]
+    fn test_ramp() {
+        let ramp = Ramp { warm_up: Duration::from_secs(10), cool_down: Duration::from_secs(5) };
+        // A tenth of the speed at first, half the way through the warm-up
+        // on average, then full speed.
+        assert!((ramp.warm_clock(Duration::from_secs(1)) - 0.145).abs() < 1e-9);
+        assert!((ramp.warm_clock(Duration::from_secs(10)) - 5.5).abs() < 1e-9);
+        assert!((ramp.warm_clock(Duration::from_secs(12)) - 7.5).abs() < 1e-9);
+        for secs in [0.0, 0.5, 3.0, 10.0, 42.0] {
+            let elapsed = Duration::from_secs_f64(secs);
+            assert!((ramp.real_time(ramp.warm_clock(elapsed)).as_secs_f64() - secs).abs() < 1e-6);
+        }
+
+        // 250 candidates left is half the 500 the cool-down spans at 100/s.
+        let pps = Some(100.0);
+        assert_eq!(ramp.cool_factor(10_000, pps), 1.0);
+        assert!((ramp.cool_factor(250, pps) - 0.55).abs() < 1e-9);
+        assert_eq!(ramp.cool_factor(0, pps), RAMP_FLOOR);
+        assert_eq!(ramp.cool_factor(0, None), 1.0);
+    }
+
+    #[test]
+    fn test_parse_bandwidth() {
+        assert_eq!("10M".parse::<Bandwidth>(), Ok(Bandwidth(10_000_000)));
+        assert_eq!("1.5kbit".parse::<Bandwidth>(), Ok(Bandwidth(1_500)));
+        assert_eq!("800".parse::<Bandwidth>(), Ok(Bandwidth(800)));
+        assert!("fast".parse::<Bandwidth>().is_err());
+        assert!("0".parse::<Bandwidth>().is_err());
+    }
+}
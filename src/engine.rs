@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::collections::hash_map::{Entry, RandomState};
+use std::hash::BuildHasher;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use hickory_client::proto::op::{Message, ResponseCode};
+use serde::Serialize;
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, oneshot};
+use tracing::debug;
+
+use crate::batch::{self, BATCH};
+use crate::egress;
+use crate::net::{self, SocketTuning, TunedRuntimeProvider};
+use crate::scanner::{self, QueryFailure, QueryOutcome, Resolution};
+use crate::traffic::Traffic;
+use crate::wire::{self, QueryTemplate};
+
+// Largest response accepted; resolvers are told 1232 but some send more.
+const MAX_RESPONSE: usize = 4096;
+
+/// How queries leave the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum EngineKind {
+    /// hickory's client: one socket and message encoder per query.
+    #[default]
+    Hickory,
+    /// [`RawEngine`]: pre-encoded queries over a few shared sockets.
+    Raw,
+}
+
+impl FromStr for EngineKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hickory" => Ok(EngineKind::Hickory),
+            "raw" => Ok(EngineKind::Raw),
+            _ => Err(format!("Unknown engine: {}", s)),
+        }
+    }
+}
+
+// Power of two, so consecutive IDs land in different shards.
+const SHARDS: usize = 64;
+
+type Waiter = oneshot::Sender<Vec<u8>>;
+type Shard = Mutex<HashMap<(SocketAddr, u16), Waiter>>;
+
+/// Queries waiting for a response on one socket, keyed by resolver and ID.
+/// Split into shards by ID so senders and the receiver rarely contend for
+/// the same lock, and each lock is held only for one map operation.
+struct InFlight {
+    shards: Box<[Shard]>,
+}
+
+impl InFlight {
+    fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    fn shard(&self, id: u16) -> &Shard {
+        &self.shards[id as usize % SHARDS]
+    }
+
+    /// Records `waiter` unless `id` is already in flight to `resolver`.
+    fn insert(&self, resolver: SocketAddr, id: u16, waiter: Waiter) -> Result<(), Waiter> {
+        match self.shard(id).lock().unwrap().entry((resolver, id)) {
+            Entry::Vacant(slot) => {
+                slot.insert(waiter);
+                Ok(())
+            }
+            Entry::Occupied(_) => Err(waiter),
+        }
+    }
+
+    fn take(&self, resolver: SocketAddr, id: u16) -> Option<Waiter> {
+        self.shard(id).lock().unwrap().remove(&(resolver, id))
+    }
+}
+
+/// A query waiting for the sender task.
+struct Outbound {
+    packet: Vec<u8>,
+    resolver: SocketAddr,
+    id: u16,
+}
+
+/// One socket served by its own IO thread, with its own in-flight table
+/// and ID sequence. Queries go through the thread's sender task so they
+/// leave in batches.
+struct Lane {
+    outbound: mpsc::Sender<Outbound>,
+    in_flight: Arc<InFlight>,
+    /// This lane's position and the number of lanes in its family: IDs
+    /// are handed out so that `id % lanes == index`.
+    index: usize,
+    lanes: usize,
+    next_id: AtomicU16,
+}
+
+/// Sends queries from a [`QueryTemplate`] over a set of UDP sockets per
+/// address family and matches responses to queries by resolver and ID.
+///
+/// The sockets of a family share one port through SO_REUSEPORT, so the
+/// kernel may hand a response to any of them; the ID tells the receiving
+/// thread which lane's table the query is in.
+pub struct RawEngine {
+    v4: Vec<Lane>,
+    v6: Vec<Lane>,
+    /// Spreads candidates over the lanes.
+    hasher: RandomState,
+}
+
+impl RawEngine {
+    /// Binds `sockets` sockets (at least one) for each address family in
+    /// `resolvers`, each with a thread of its own, once
+    /// [`net::check_socket_count`] allows that many.
+    pub fn bind(resolvers: &[SocketAddr], tuning: &SocketTuning, sockets: usize) -> std::io::Result<Self> {
+        net::check_socket_count(sockets.max(1)).map_err(std::io::Error::other)?;
+        let v4 = match resolvers.iter().any(SocketAddr::is_ipv4) {
+            true => bind_lanes(Ipv4Addr::UNSPECIFIED.into(), sockets.max(1), tuning)?,
+            false => Vec::new(),
+        };
+        let v6 = match resolvers.iter().any(SocketAddr::is_ipv6) {
+            true => bind_lanes(Ipv6Addr::UNSPECIFIED.into(), sockets.max(1), tuning)?,
+            false => Vec::new(),
+        };
+        Ok(Self {
+            v4,
+            v6,
+            hasher: RandomState::new(),
+        })
+    }
+
+    pub(crate) async fn resolve(
+        &self,
+        template: &QueryTemplate,
+        resolver: SocketAddr,
+        name: String,
+        timeout: Duration,
+        traffic: &Traffic,
+    ) -> QueryOutcome {
+        let log = &traffic.log;
+        if egress::is_limited() {
+            egress::admit_query(resolver, &name).await;
+        }
+        #[cfg(feature = "fault-injection")]
+        let (timeout, corrupt_at) = match crate::faults::before_send(&name, timeout).await {
+            Ok(injected) => injected,
+            Err(e) => return QueryOutcome::Failed(QueryFailure::Timeout, format!("{} via {}: {}", name, resolver, e)),
+        };
+        let lanes = if resolver.is_ipv4() { &self.v4 } else { &self.v6 };
+        let lane = &lanes[self.hasher.hash_one(&name) as usize % lanes.len()];
+        let (tx, rx) = oneshot::channel();
+        let Some(id) = lane.register(resolver, tx) else {
+            return QueryOutcome::Failed(QueryFailure::Connect, format!("{} via {}: no free query id", name, resolver));
+        };
+        let mut query = Vec::with_capacity(name.len() + 32);
+        if let Err(e) = template.encode(&name, id, &mut query) {
+            lane.in_flight.take(resolver, id);
+            return QueryOutcome::Failed(QueryFailure::Parse, e);
+        }
+        let logged = log.is_enabled().then(|| query.clone());
+
+        let sent = SystemTime::now();
+        let outbound = Outbound {
+            packet: query,
+            resolver,
+            id,
+        };
+        if lane.outbound.send(outbound).await.is_err() {
+            lane.in_flight.take(resolver, id);
+            return QueryOutcome::Failed(QueryFailure::Connect, format!("{} via {}: engine stopped", name, resolver));
+        }
+        let response = match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(response)) => response,
+            // The sender drops the waiter of a query it could not send.
+            Ok(Err(_)) => {
+                return QueryOutcome::Failed(QueryFailure::Connect, format!("{} via {}: send failed", name, resolver));
+            }
+            Err(_) => {
+                lane.in_flight.take(resolver, id);
+                if let Some(query) = &logged {
+                    log.exchange(resolver, query, sent, None);
+                }
+                return QueryOutcome::Failed(QueryFailure::Timeout, format!("{} via {}: request timed out", name, resolver));
+            }
+        };
+        #[cfg(feature = "fault-injection")]
+        let response = match corrupt_at {
+            Some(at) => crate::faults::corrupt(response, at),
+            None => response,
+        };
+        if let Some(query) = &logged {
+            log.exchange(resolver, query, sent, Some((&response, SystemTime::now())));
+        }
+
+        let mut message = match Message::from_vec(&response) {
+            Ok(message) => message,
+            Err(e) => return QueryOutcome::Failed(QueryFailure::Protocol, format!("{} via {}: {}", name, resolver, e)),
+        };
+        let asked = message.queries().first().map(|q| q.name().to_ascii());
+        if !asked.is_some_and(|asked| asked.trim_end_matches('.').eq_ignore_ascii_case(name.trim_end_matches('.'))) {
+            return QueryOutcome::Failed(
+                QueryFailure::Protocol,
+                format!("{} via {}: response is for a different question", name, resolver),
+            );
+        }
+        if message.truncated() {
+            let mut query = Vec::with_capacity(name.len() + 32);
+            let retry = template.encode(&name, id, &mut query).ok().and_then(|()| Message::from_vec(&query).ok());
+            if let Some(retry) = retry
+                && let Some(full) = scanner::retry_truncated(resolver, timeout, TunedRuntimeProvider::default(), retry, traffic).await
+            {
+                message = full.into_message();
+            }
+        }
+        if message.answers().is_empty() && message.response_code() == ResponseCode::NXDomain {
+            QueryOutcome::NxDomain
+        } else if message.answers().is_empty() {
+            QueryOutcome::NotFound
+        } else {
+            QueryOutcome::Found(name, Resolution::from_answers(message.answers()))
+        }
+    }
+}
+
+impl Lane {
+    /// Picks an ID of this lane not in flight to `resolver` and records the
+    /// waiter.
+    fn register(&self, resolver: SocketAddr, mut waiter: Waiter) -> Option<u16> {
+        for _ in 0..slots(self.lanes) {
+            let id = lane_id(self.index, self.lanes, self.next_id.fetch_add(1, Ordering::Relaxed));
+            match self.in_flight.insert(resolver, id, waiter) {
+                Ok(()) => return Some(id),
+                Err(returned) => waiter = returned,
+            }
+        }
+        None
+    }
+}
+
+/// IDs available to each of `lanes` lanes.
+fn slots(lanes: usize) -> usize {
+    (u16::MAX as usize + 1) / lanes
+}
+
+/// The `n`th ID of lane `index`, cycling through the lane's share of the ID
+/// space.
+fn lane_id(index: usize, lanes: usize, n: u16) -> u16 {
+    (index + lanes * (n as usize % slots(lanes))) as u16
+}
+
+/// Binds the lanes of one address family on a shared port and starts their
+/// IO threads. The threads stop once the engine, and with it every lane's
+/// sender, is dropped.
+fn bind_lanes(ip: IpAddr, count: usize, tuning: &SocketTuning) -> std::io::Result<Vec<Lane>> {
+    let mut tuning = tuning.clone();
+    tuning.reuse_port |= count > 1;
+    let first = net::bind_udp(SocketAddr::new(ip, 0), &tuning)?;
+    let local = first.local_addr()?;
+    let mut sockets = vec![first];
+    for _ in 1..count {
+        sockets.push(net::bind_udp(local, &tuning)?);
+    }
+
+    let tables: Arc<[Arc<InFlight>]> = (0..count).map(|_| Arc::new(InFlight::new())).collect();
+    let mut lanes = Vec::with_capacity(count);
+    for (index, socket) in sockets.into_iter().enumerate() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let socket = {
+            let _guard = runtime.enter();
+            Arc::new(UdpSocket::from_std(socket)?)
+        };
+        let (outbound, queued) = mpsc::channel(BATCH * 16);
+        let own = tables[index].clone();
+        let all = tables.clone();
+        std::thread::Builder::new()
+            .name(format!("subscan-io-{}", index))
+            .spawn(move || {
+                runtime.block_on(async move {
+                    tokio::select! {
+                        _ = send(socket.clone(), own, queued) => {}
+                        _ = receive(socket, all) => {}
+                    }
+                })
+            })?;
+        lanes.push(Lane {
+            outbound,
+            in_flight: tables[index].clone(),
+            index,
+            lanes: count,
+            next_id: AtomicU16::new(rand::random()),
+        });
+    }
+    Ok(lanes)
+}
+
+/// Sends whatever queries are queued, up to a batch at a time.
+async fn send(socket: Arc<UdpSocket>, in_flight: Arc<InFlight>, mut queued: mpsc::Receiver<Outbound>) {
+    let mut pending = Vec::with_capacity(BATCH);
+    while queued.recv_many(&mut pending, BATCH).await > 0 {
+        let packets: Vec<(&[u8], SocketAddr)> = pending.iter().map(|q| (q.packet.as_slice(), q.resolver)).collect();
+        if egress::is_limited() {
+            let bytes = packets.iter().map(|(packet, _)| packet.len()).sum();
+            egress::admit(packets[0].1, packets.len(), bytes).await;
+        }
+        for (i, e) in batch::send_batch(&socket, &packets).await {
+            debug!("send to {} failed: {}", pending[i].resolver, e);
+            in_flight.take(pending[i].resolver, pending[i].id);
+        }
+        pending.clear();
+    }
+}
+
+/// Hands each response to the query waiting for it, in whichever lane's
+/// table. Responses nobody waits for (late, spoofed or duplicated) are
+/// dropped.
+async fn receive(socket: Arc<UdpSocket>, tables: Arc<[Arc<InFlight>]>) {
+    let mut bufs = vec![vec![0u8; MAX_RESPONSE]; BATCH];
+    loop {
+        let Ok(received) = batch::recv_batch(&socket, &mut bufs).await else {
+            continue;
+        };
+        for (buf, (len, from)) in bufs.iter().zip(received) {
+            let Some(id) = wire::message_id(&buf[..len]) else {
+                continue;
+            };
+            if let Some(waiter) = tables[id as usize % tables.len()].take(from, id) {
+                let _ = waiter.send(buf[..len].to_vec());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_flight_keys() {
+        let table = InFlight::new();
+        let a: SocketAddr = "127.0.0.1:53".parse().unwrap();
+        let b: SocketAddr = "127.0.0.2:53".parse().unwrap();
+        assert!(table.insert(a, 7, oneshot::channel().0).is_ok());
+        assert!(table.insert(a, 7, oneshot::channel().0).is_err());
+        assert!(table.insert(b, 7, oneshot::channel().0).is_ok());
+        assert!(table.insert(a, 7 + SHARDS as u16, oneshot::channel().0).is_ok());
+
+        assert!(table.take(b, 7).is_some());
+        assert!(table.take(b, 7).is_none());
+        assert!(table.take(a, 8).is_none());
+        assert!(table.take(a, 7).is_some());
+    }
+
+    #[test]
+    fn test_lane_ids() {
+        for lanes in [1, 3, 8, 12] {
+            for index in 0..lanes {
+                let ids: std::collections::HashSet<u16> =
+                    (0..=u16::MAX).map(|n| lane_id(index, lanes, n)).collect();
+                assert_eq!(ids.len(), slots(lanes));
+                assert!(ids.iter().all(|&id| id as usize % lanes == index));
+            }
+        }
+    }
+}
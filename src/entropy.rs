@@ -0,0 +1,130 @@
+//! Spotting found names that look machine-generated: DGA output, tracking
+//! and per-session hostnames, hashes and UUIDs. They resolve like any other
+//! name but are rarely worth a look, so they are scored and flagged for
+//! filtering.
+
+use serde::Serialize;
+
+/// Labels shorter than this are never flagged; short words are too
+/// varied to judge.
+const MIN_LABEL_LEN: usize = 10;
+/// Shannon entropy, in bits per character, above which a label may be
+/// random. Long words with few repeated letters get there too, so it only
+/// counts together with digits mixed in or too few vowels for a word.
+const HIGH_ENTROPY: f64 = 3.4;
+const MIN_VOWEL_SHARE: f64 = 0.25;
+const MIN_HEX_LEN: usize = 12;
+const MAX_CONSONANT_RUN: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NameScore {
+    /// Entropy of the most random-looking label below the target, in bits
+    /// per character.
+    pub entropy: f64,
+    /// Why the name looks generated: `high_entropy`, `hex`, `consonants`
+    /// or `digits`. Empty for names that look chosen by a person.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<&'static str>,
+}
+
+impl NameScore {
+    pub fn is_random_looking(&self) -> bool {
+        !self.flags.is_empty()
+    }
+}
+
+/// Scores the labels of `name` below `domain`; the worst one decides.
+pub fn score(name: &str, domain: &str) -> NameScore {
+    let below = name.strip_suffix(domain).map_or(name, |rest| rest.trim_end_matches('.'));
+    let mut worst = NameScore { entropy: 0.0, flags: Vec::new() };
+    for label in below.split('.').filter(|label| !label.is_empty()) {
+        let score = score_label(label);
+        if (score.flags.len(), score.entropy) > (worst.flags.len(), worst.entropy) {
+            worst = score;
+        }
+    }
+    worst
+}
+
+fn score_label(label: &str) -> NameScore {
+    let label = label.to_ascii_lowercase();
+    let entropy = (shannon_entropy(&label) * 100.0).round() / 100.0;
+    let mut flags = Vec::new();
+    // Hyphenated words are judged word by word: a long name made of short
+    // parts is not random for being long.
+    let longest = label.split('-').map(str::len).max().unwrap_or(0);
+    if longest >= MIN_LABEL_LEN {
+        let letters = label.chars().filter(char::is_ascii_alphabetic).count();
+        let vowels = label.chars().filter(|c| "aeiouy".contains(*c)).count();
+        let mixed = letters > 0 && label.chars().any(|c| c.is_ascii_digit());
+        if entropy >= HIGH_ENTROPY && (mixed || (vowels as f64) < letters as f64 * MIN_VOWEL_SHARE) {
+            flags.push("high_entropy");
+        }
+        let hex = label.chars().filter(|c| c.is_ascii_hexdigit()).count();
+        if hex >= MIN_HEX_LEN && hex == label.chars().filter(|c| *c != '-').count() && label.chars().any(|c| c.is_ascii_digit()) {
+            flags.push("hex");
+        }
+        if consonant_run(&label) > MAX_CONSONANT_RUN {
+            flags.push("consonants");
+        }
+        let digits = label.chars().filter(char::is_ascii_digit).count();
+        if digits * 3 >= label.len() && digits < label.len() {
+            flags.push("digits");
+        }
+    }
+    NameScore { entropy, flags }
+}
+
+/// Shannon entropy of the characters of `text`, in bits per character.
+pub fn shannon_entropy(text: &str) -> f64 {
+    let mut counts = [0u32; 256];
+    for byte in text.bytes() {
+        counts[byte as usize] += 1;
+    }
+    let len = text.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn consonant_run(label: &str) -> usize {
+    let (mut longest, mut run) = (0, 0);
+    for c in label.chars() {
+        if c.is_ascii_alphabetic() && !"aeiouy".contains(c) {
+            run += 1;
+            longest = longest.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score() {
+        assert_eq!(shannon_entropy("aaaa"), 0.0);
+        assert_eq!(shannon_entropy("abcd"), 2.0);
+
+        for name in [
+            "www.example.com",
+            "customer-portal.example.com",
+            "autodiscover.example.com",
+            "productionserver.example.com",
+            "v1.api.example.com",
+        ] {
+            assert!(!score(name, "example.com").is_random_looking(), "{}", name);
+        }
+        assert_eq!(score("x7k2qz9wmv4t.example.com", "example.com").flags, ["high_entropy", "digits"]);
+        assert_eq!(score("3f9a0c21d4e8b7.cdn.example.com", "example.com").flags, ["high_entropy", "hex", "digits"]);
+        assert!(score("xkcdqwrtzp.example.com", "example.com").flags.contains(&"consonants"));
+    }
+}
@@ -0,0 +1,31 @@
+use std::io;
+
+/// Why a scan could not be set up or produced nothing meaningful, for
+/// library callers that want to act on the cause rather than print it.
+#[derive(Debug, thiserror::Error)]
+pub enum ScanError {
+    /// An input file could not be opened or read.
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The inputs were readable but describe no scan that can run.
+    #[error("{0}")]
+    Config(String),
+    /// Every query sent failed at the resolvers, so an empty result says
+    /// nothing about the target.
+    #[error("resolvers unusable: {failed} of {sent} queries failed")]
+    ResolversExhausted { sent: u64, failed: u64 },
+}
+
+impl ScanError {
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) fn io(path: &str, source: io::Error) -> Self {
+        ScanError::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+}
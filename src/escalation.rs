@@ -0,0 +1,199 @@
+//! Record types beyond addresses for the found names whose role calls for
+//! them. A scan only asks for A/AAAA; a mail host's MX and TXT, a DMARC
+//! or DKIM name's TXT and the SRV records autodiscover clients look up
+//! first are asked for after the scan, for the few names matching a
+//! [`Rule`], so a default scan stays cheap. Each lookup counts against
+//! the scan's query budget, and the types left for a name are not asked
+//! once one comes back NXDOMAIN.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use hickory_client::proto::rr::RecordType;
+use serde::Serialize;
+use tokio::task::JoinSet;
+
+use crate::names;
+use crate::posture::{self, Lookup};
+use crate::traffic::Traffic;
+
+/// Lookups sent at once.
+pub const CONCURRENCY: usize = 50;
+
+/// Where a rule's query goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Owner {
+    /// The found name itself.
+    Name,
+    /// The given labels in front of the found name's parent, as
+    /// `_autodiscover._tcp` for `autodiscover.example.com`; the parent
+    /// itself when empty.
+    Parent(&'static str),
+}
+
+/// Extra queries for the found names matching `pattern` (see
+/// [`names::glob`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rule {
+    pub pattern: &'static str,
+    pub queries: &'static [(RecordType, Owner)],
+}
+
+const MAIL: &[(RecordType, Owner)] = &[(RecordType::MX, Owner::Name), (RecordType::TXT, Owner::Name)];
+const POLICY: &[(RecordType, Owner)] = &[(RecordType::TXT, Owner::Name)];
+const SERVICE: &[(RecordType, Owner)] = &[(RecordType::SRV, Owner::Name)];
+
+/// The rules every scan applies.
+pub const RULES: &[Rule] = &[
+    Rule { pattern: "mail*", queries: MAIL },
+    Rule { pattern: "smtp*", queries: MAIL },
+    Rule { pattern: "mx*", queries: MAIL },
+    Rule { pattern: "_dmarc.*", queries: POLICY },
+    Rule { pattern: "*._domainkey.*", queries: POLICY },
+    Rule { pattern: "_mta-sts.*", queries: POLICY },
+    Rule { pattern: "_*._tcp.*", queries: SERVICE },
+    Rule { pattern: "_*._udp.*", queries: SERVICE },
+    Rule { pattern: "autodiscover.*", queries: &[(RecordType::SRV, Owner::Parent("_autodiscover._tcp"))] },
+];
+
+/// Records one extra query found.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Escalated {
+    pub owner: String,
+    pub record_type: String,
+    pub records: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct EscalationReport {
+    /// What the extra queries for each found name returned; queries that
+    /// came back empty or failed are left out.
+    pub names: BTreeMap<String, Vec<Escalated>>,
+    pub queries: u64,
+    /// Queries not sent: their owner was NXDOMAIN for an earlier type, or
+    /// the query budget was spent.
+    pub skipped: u64,
+}
+
+/// The queries `rules` ask for `name`, in rule order without repeats.
+pub fn queries_for(name: &str, rules: &[Rule]) -> Vec<(String, RecordType)> {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let mut queries = Vec::new();
+    for rule in rules.iter().filter(|rule| names::glob(rule.pattern, &name)) {
+        for (record_type, owner) in rule.queries {
+            let owner = match owner {
+                Owner::Name => name.clone(),
+                Owner::Parent(labels) => match name.split_once('.') {
+                    Some((_, parent)) if labels.is_empty() => parent.to_string(),
+                    Some((_, parent)) => format!("{}.{}", labels, parent),
+                    None => continue,
+                },
+            };
+            if !queries.contains(&(owner.clone(), *record_type)) {
+                queries.push((owner, *record_type));
+            }
+        }
+    }
+    queries
+}
+
+/// Sends the extra queries `rules` ask for each of `names` to `resolver`,
+/// each distinct one once. The types of one owner are asked in turn.
+pub async fn escalate(names: &[String], rules: &[Rule], resolver: SocketAddr, timeout: Duration, traffic: &Traffic) -> EscalationReport {
+    let wanted: Vec<(&String, Vec<(String, RecordType)>)> =
+        names.iter().map(|name| (name, queries_for(name, rules))).filter(|(_, queries)| !queries.is_empty()).collect();
+    let mut owners: BTreeMap<String, Vec<RecordType>> = BTreeMap::new();
+    for (owner, record_type) in wanted.iter().flat_map(|(_, queries)| queries.iter().cloned()) {
+        let types = owners.entry(owner).or_default();
+        if !types.contains(&record_type) {
+            types.push(record_type);
+        }
+    }
+    let mut report = EscalationReport::default();
+
+    let mut answers: BTreeMap<(String, RecordType), Vec<String>> = BTreeMap::new();
+    let mut set = JoinSet::new();
+    let mut pending = owners.into_iter();
+    loop {
+        while set.len() < CONCURRENCY
+            && let Some((owner, types)) = pending.next()
+        {
+            let traffic = traffic.clone();
+            set.spawn(async move { ask_owner(resolver, timeout, owner, types, &traffic).await });
+        }
+        let Some(joined) = set.join_next().await else {
+            break;
+        };
+        if let Ok(asked) = joined {
+            report.queries += asked.sent;
+            report.skipped += asked.skipped;
+            answers.extend(asked.answers.into_iter().filter(|(_, records)| !records.is_empty()));
+        }
+    }
+
+    for (name, queries) in wanted {
+        let escalated: Vec<Escalated> = queries
+            .into_iter()
+            .filter_map(|query| {
+                let records = answers.get(&query)?.clone();
+                Some(Escalated { owner: query.0, record_type: query.1.to_string(), records })
+            })
+            .collect();
+        if !escalated.is_empty() {
+            report.names.insert(name.clone(), escalated);
+        }
+    }
+    report
+}
+
+/// What the lookups of one owner returned.
+struct Asked {
+    answers: Vec<((String, RecordType), Vec<String>)>,
+    sent: u64,
+    skipped: u64,
+}
+
+async fn ask_owner(resolver: SocketAddr, timeout: Duration, owner: String, types: Vec<RecordType>, traffic: &Traffic) -> Asked {
+    let mut asked = Asked { answers: Vec::new(), sent: 0, skipped: 0 };
+    for (index, &record_type) in types.iter().enumerate() {
+        if !traffic.budget.try_spend() {
+            asked.skipped += (types.len() - index) as u64;
+            break;
+        }
+        asked.sent += 1;
+        match posture::lookup(resolver, timeout, &owner, record_type, traffic).await {
+            Lookup::NxDomain => {
+                asked.skipped += (types.len() - index - 1) as u64;
+                break;
+            }
+            lookup => {
+                let records = lookup.records().unwrap_or_default();
+                asked.answers.push(((owner.clone(), record_type), records.iter().map(ToString::to_string).collect()));
+            }
+        }
+    }
+    asked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queries_for() {
+        let owners = |name: &str| -> Vec<String> {
+            queries_for(name, RULES).into_iter().map(|(owner, record_type)| format!("{} {}", record_type, owner)).collect()
+        };
+        assert_eq!(owners("mail2.example.com"), ["MX mail2.example.com", "TXT mail2.example.com"]);
+        assert_eq!(owners("_dmarc.example.com"), ["TXT _dmarc.example.com"]);
+        assert_eq!(owners("s1._domainkey.example.com"), ["TXT s1._domainkey.example.com"]);
+        assert_eq!(owners("_sip._tcp.example.com"), ["SRV _sip._tcp.example.com"]);
+        assert_eq!(owners("Autodiscover.example.com."), ["SRV _autodiscover._tcp.example.com"]);
+        assert!(owners("www.example.com").is_empty());
+        assert!(owners("webmail.example.com").is_empty());
+
+        let apex = Rule { pattern: "autodiscover.*", queries: &[(RecordType::MX, Owner::Parent(""))] };
+        assert_eq!(queries_for("autodiscover.example.com", &[apex]), [("example.com".to_string(), RecordType::MX)]);
+    }
+}
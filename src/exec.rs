@@ -0,0 +1,230 @@
+//! `--exec 'cmd {name} {ip}'`: a command run for every confirmed finding
+//! while the scan goes on, for ad-hoc pipelines (an HTTP probe, a port
+//! scan, a ticket) without a consumer for the output format.
+//!
+//! The command is split into words once, shell-style quotes respected, and
+//! placeholders are filled in per word; it runs without a shell, so a name
+//! cannot inject one. Wrap it in `sh -c '...' sh {name}` for pipes.
+
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::process::Command;
+use tokio::sync::{Semaphore, mpsc};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::{debug, warn};
+
+use crate::scanner::Resolution;
+
+/// A command still running after this long is killed.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(300);
+const PLACEHOLDERS: &[&str] = &["{name}", "{ip}", "{ips}", "{cname}"];
+
+/// A parsed `--exec` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecCommand {
+    words: Vec<String>,
+}
+
+impl ExecCommand {
+    pub fn parse(command: &str) -> Result<Self, String> {
+        let words = split_words(command)?;
+        if words.is_empty() {
+            return Err("--exec is empty".to_string());
+        }
+        for word in &words {
+            let mut rest = word.as_str();
+            while let Some(start) = rest.find('{') {
+                let placeholder = rest[start..].find('}').map(|end| &rest[start..start + end + 1]).unwrap_or(&rest[start..]);
+                if !PLACEHOLDERS.contains(&placeholder) {
+                    return Err(format!("--exec has unknown placeholder '{}', expected one of {}", placeholder, PLACEHOLDERS.join(", ")));
+                }
+                rest = &rest[start + placeholder.len()..];
+            }
+        }
+        Ok(Self { words })
+    }
+
+    /// The program and its arguments for one finding. `{ip}` is the first
+    /// address, `{ips}` all of them comma-separated, `{cname}` the end of
+    /// the CNAME chain; each is empty when there is none.
+    pub fn argv(&self, name: &str, resolution: &Resolution) -> Vec<String> {
+        let ips: Vec<String> = resolution.addresses.iter().map(|ip| ip.to_string()).collect();
+        let ip = ips.first().cloned().unwrap_or_default();
+        let cname = resolution.cname_chain.last().map(|cname| cname.trim_end_matches('.')).unwrap_or_default();
+        self.words
+            .iter()
+            .map(|word| word.replace("{name}", name).replace("{ips}", &ips.join(",")).replace("{ip}", &ip).replace("{cname}", cname))
+            .collect()
+    }
+}
+
+/// How the commands fared.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ExecReport {
+    pub ran: u64,
+    /// Exited non-zero, could not start, or timed out.
+    pub failed: u64,
+}
+
+/// Runs an [`ExecCommand`] for everything sent to it, at most
+/// `concurrency` at a time. Scanners get a [`ExecHook::sender`] each; the
+/// hook finishes once they are all dropped and the last command exits.
+pub struct ExecHook {
+    tx: mpsc::UnboundedSender<(String, Resolution)>,
+    handle: JoinHandle<ExecReport>,
+}
+
+impl ExecHook {
+    pub fn start(command: ExecCommand, concurrency: usize) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = tokio::spawn(run(command, concurrency.max(1), rx));
+        Self { tx, handle }
+    }
+
+    pub fn sender(&self) -> mpsc::UnboundedSender<(String, Resolution)> {
+        self.tx.clone()
+    }
+
+    pub async fn finish(self) -> ExecReport {
+        drop(self.tx);
+        self.handle.await.unwrap_or_default()
+    }
+}
+
+async fn run(command: ExecCommand, concurrency: usize, mut rx: mpsc::UnboundedReceiver<(String, Resolution)>) -> ExecReport {
+    let slots = Arc::new(Semaphore::new(concurrency));
+    let mut running = JoinSet::new();
+    let mut report = ExecReport::default();
+    while let Some((name, resolution)) = rx.recv().await {
+        let Ok(slot) = slots.clone().acquire_owned().await else {
+            break;
+        };
+        let argv = command.argv(&name, &resolution);
+        running.spawn(async move {
+            let ok = run_one(&argv).await;
+            drop(slot);
+            ok
+        });
+        while let Some(done) = running.try_join_next() {
+            report.ran += 1;
+            report.failed += !done.unwrap_or(false) as u64;
+        }
+    }
+    while let Some(done) = running.join_next().await {
+        report.ran += 1;
+        report.failed += !done.unwrap_or(false) as u64;
+    }
+    report
+}
+
+async fn run_one(argv: &[String]) -> bool {
+    // Its output goes to stderr, so stdout stays the scan's own.
+    let child = Command::new(&argv[0])
+        .args(&argv[1..])
+        .stdin(Stdio::null())
+        .stdout(std::io::stderr())
+        .kill_on_drop(true)
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("--exec could not run {}: {}", argv[0], e);
+            return false;
+        }
+    };
+    match tokio::time::timeout(COMMAND_TIMEOUT, child.wait()).await {
+        Ok(Ok(status)) if status.success() => true,
+        Ok(Ok(status)) => {
+            debug!("--exec {:?} {}", argv, status);
+            false
+        }
+        Ok(Err(e)) => {
+            warn!("--exec {:?}: {}", argv, e);
+            false
+        }
+        Err(_) => {
+            warn!("--exec {:?} still running after {}s, killed", argv, COMMAND_TIMEOUT.as_secs());
+            false
+        }
+    }
+}
+
+/// Splits on whitespace outside quotes; `'...'` is literal, `"..."` and
+/// bare words take backslash escapes.
+fn split_words(command: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("--exec has an unclosed '".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => return Err("--exec has an unclosed \"".to_string()),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                word.extend(chars.next());
+            }
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exec_command() {
+        let command = ExecCommand::parse(r#"sh -c 'echo "$1" >> found.txt' sh {name} --ip={ip} "{ips}" {cname}"#).unwrap();
+        let resolution = Resolution {
+            cname_chain: vec!["lb.example.net.".to_string()],
+            addresses: vec!["192.0.2.1".parse().unwrap(), "192.0.2.2".parse().unwrap()],
+            ttl: None,
+            ..Resolution::default()
+        };
+        assert_eq!(
+            command.argv("www.example.com", &resolution),
+            ["sh", "-c", r#"echo "$1" >> found.txt"#, "sh", "www.example.com", "--ip=192.0.2.1", "192.0.2.1,192.0.2.2", "lb.example.net"]
+        );
+        assert_eq!(command.argv("www.example.com", &Resolution::default())[5], "--ip=");
+
+        assert!(ExecCommand::parse("curl {host}").unwrap_err().contains("unknown placeholder '{host}'"));
+        assert!(ExecCommand::parse("echo 'unclosed").is_err());
+        assert!(ExecCommand::parse("   ").is_err());
+    }
+}
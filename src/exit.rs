@@ -0,0 +1,85 @@
+use std::process::ExitCode;
+
+use serde_json::Value;
+
+/// Process exit status, so wrappers can branch on how a run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    /// The scan completed and found at least one name.
+    Findings = 0,
+    /// The scan completed and found nothing.
+    NoFindings = 1,
+    /// Bad arguments or input files; nothing was scanned.
+    InputError = 2,
+    /// Every query failed at the resolver (timeouts, refused sockets,
+    /// garbage answers), so an empty result says nothing about the target.
+    ResolversUnusable = 3,
+    /// Stopped by a signal; whatever was found so far was written.
+    Interrupted = 4,
+    /// `subscan selftest` ran but fell short of its `--qps-target`.
+    BelowTarget = 5,
+}
+
+impl Exit {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    /// Classifies a finished run from its per-target scan results.
+    pub fn from_results(results: &[Value], interrupted: bool) -> Self {
+        if interrupted {
+            return Exit::Interrupted;
+        }
+        let found: usize = results
+            .iter()
+            .map(|r| {
+                let spilled = r["results"]["spilled"]["names"].as_u64().unwrap_or_default() as usize;
+                r["results"]["subdomain"].as_array().map_or(0, Vec::len) + spilled
+            })
+            .sum();
+        if found > 0 {
+            return Exit::Findings;
+        }
+
+        // Both count queries, not names: each attempt of each record type.
+        let mut sent = 0;
+        let mut resolver_failures = 0;
+        for result in results {
+            sent += result["results"]["queries_sent"].as_u64().unwrap_or_default();
+            resolver_failures += result["results"]["queries_failed"].as_u64().unwrap_or_default();
+        }
+        if sent > 0 && resolver_failures >= sent {
+            Exit::ResolversUnusable
+        } else {
+            Exit::NoFindings
+        }
+    }
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> Self {
+        ExitCode::from(exit as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_from_results() {
+        let result = |found: Vec<&str>, sent: u64, failed: u64| {
+            json!({ "results": {
+                "subdomain": found,
+                "queries_sent": sent,
+                "queries_failed": failed
+            }})
+        };
+        assert_eq!(Exit::from_results(&[result(vec!["www.example.com"], 5, 4)], false), Exit::Findings);
+        assert_eq!(Exit::from_results(&[result(vec![], 5, 1)], false), Exit::NoFindings);
+        assert_eq!(Exit::from_results(&[result(vec![], 5, 5)], false), Exit::ResolversUnusable);
+        assert_eq!(Exit::from_results(&[result(vec![], 0, 0)], false), Exit::NoFindings);
+        assert_eq!(Exit::from_results(&[result(vec!["www.example.com"], 5, 0)], true), Exit::Interrupted);
+    }
+}
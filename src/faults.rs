@@ -0,0 +1,241 @@
+//! Fault injection for working on the engine (`fault-injection` feature):
+//! queries are dropped, delayed or get a corrupted response at set rates,
+//! so the rate limiters, retries and resolver eviction can be exercised
+//! without a flaky network.
+//!
+//! Whether a query is hit depends only on the seed, the name and how many
+//! times the name was asked before, not on timing or which worker sends
+//! it, so a run with the same seed and wordlist meets the same faults.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+static FAULTS: OnceLock<Faults> = OnceLock::new();
+
+/// Rates and delays to inject, parsed from `drop=5,delay=10-200,corrupt=1,seed=7`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultPlan {
+    /// Share of queries that never get an answer, 0 to 1.
+    pub drop: f64,
+    /// Added before each query is sent, drawn evenly from the range.
+    pub delay: (Duration, Duration),
+    /// Share of responses damaged before they are parsed, 0 to 1.
+    pub corrupt: f64,
+    pub seed: u64,
+}
+
+impl Default for FaultPlan {
+    fn default() -> Self {
+        Self {
+            drop: 0.0,
+            delay: (Duration::ZERO, Duration::ZERO),
+            corrupt: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+impl FromStr for FaultPlan {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut plan = Self::default();
+        let percent = |value: &str| match value.trim_end_matches('%').parse::<f64>() {
+            Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent / 100.0),
+            _ => Err(format!("Unknown fault rate: {} (expected a percentage from 0 to 100)", value)),
+        };
+        let millis = |value: &str| {
+            value
+                .trim_end_matches("ms")
+                .parse::<u64>()
+                .map(Duration::from_millis)
+                .map_err(|_| format!("Unknown fault delay: {} (expected milliseconds)", value))
+        };
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Unknown fault: {} (expected drop=PCT, delay=MS or MIN-MAX, corrupt=PCT or seed=N)", part))?;
+            match key {
+                "drop" => plan.drop = percent(value)?,
+                "corrupt" => plan.corrupt = percent(value)?,
+                "delay" => {
+                    plan.delay = match value.split_once('-') {
+                        Some((min, max)) => (millis(min)?, millis(max)?),
+                        None => (millis(value)?, millis(value)?),
+                    };
+                    if plan.delay.0 > plan.delay.1 {
+                        return Err(format!("Unknown fault delay: {} (the minimum is above the maximum)", value));
+                    }
+                }
+                "seed" => plan.seed = value.parse().map_err(|_| format!("Unknown fault seed: {}", value))?,
+                _ => return Err(format!("Unknown fault: {} (expected drop, delay, corrupt or seed)", key)),
+            }
+        }
+        Ok(plan)
+    }
+}
+
+/// What happens to one query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    pub drop: bool,
+    pub delay: Duration,
+    /// Where to damage the response, when it is.
+    pub corrupt: Option<u64>,
+}
+
+/// Injected faults so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaultCounts {
+    pub dropped: u64,
+    pub delayed: u64,
+    pub corrupted: u64,
+}
+
+#[derive(Debug)]
+pub struct Faults {
+    plan: FaultPlan,
+    /// Times each name was asked, so a retry meets its own fault.
+    attempts: Mutex<HashMap<String, u32>>,
+    dropped: AtomicU64,
+    delayed: AtomicU64,
+    corrupted: AtomicU64,
+}
+
+impl Faults {
+    pub fn new(plan: FaultPlan) -> Self {
+        Self {
+            plan,
+            attempts: Mutex::new(HashMap::new()),
+            dropped: AtomicU64::new(0),
+            delayed: AtomicU64::new(0),
+            corrupted: AtomicU64::new(0),
+        }
+    }
+
+    /// The faults the next query for `name` meets.
+    pub fn decide(&self, name: &str) -> Decision {
+        let name = name.trim_end_matches('.').to_lowercase();
+        let attempt = {
+            let mut attempts = self.attempts.lock().unwrap();
+            let attempt = attempts.entry(name.clone()).or_default();
+            *attempt += 1;
+            *attempt
+        };
+        // FNV-1a of the name, mixed with the seed and the attempt.
+        let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3));
+        let mut rng = ChaCha8Rng::seed_from_u64(hash ^ self.plan.seed.rotate_left(17) ^ (attempt as u64).rotate_left(41));
+        let drop = rng.random::<f64>() < self.plan.drop;
+        let (min, max) = self.plan.delay;
+        let delay = if max > min { rng.random_range(min..=max) } else { min };
+        let corrupt = (rng.random::<f64>() < self.plan.corrupt).then(|| rng.random());
+        let decision = Decision { drop, delay, corrupt };
+        if drop {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        } else {
+            if !delay.is_zero() {
+                self.delayed.fetch_add(1, Ordering::Relaxed);
+            }
+            if corrupt.is_some() {
+                self.corrupted.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        decision
+    }
+
+    pub fn counts(&self) -> FaultCounts {
+        FaultCounts {
+            dropped: self.dropped.load(Ordering::Relaxed),
+            delayed: self.delayed.load(Ordering::Relaxed),
+            corrupted: self.corrupted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Injects `plan` into every query for the rest of the process. Only the
+/// first call has an effect.
+pub fn set_plan(plan: FaultPlan) {
+    let _ = FAULTS.set(Faults::new(plan));
+}
+
+/// Injected faults so far, when any are planned.
+pub fn counts() -> Option<FaultCounts> {
+    FAULTS.get().map(Faults::counts)
+}
+
+/// Runs the faults planned for a query for `name` that may take `timeout`
+/// to answer: waits out the delay and returns the time left to answer and
+/// where to corrupt the response, or fails the way a dropped query does.
+pub async fn before_send(name: &str, timeout: Duration) -> Result<(Duration, Option<u64>), String> {
+    let Some(faults) = FAULTS.get() else {
+        return Ok((timeout, None));
+    };
+    let decision = faults.decide(name);
+    if decision.drop || decision.delay >= timeout {
+        tokio::time::sleep(timeout).await;
+        return Err("request timed out (injected)".to_string());
+    }
+    tokio::time::sleep(decision.delay).await;
+    Ok((timeout - decision.delay, decision.corrupt))
+}
+
+/// Damages a response: cut short for even `at`, one byte past the header
+/// flipped for odd.
+pub fn corrupt(mut response: Vec<u8>, at: u64) -> Vec<u8> {
+    if response.len() <= 12 {
+        response.clear();
+    } else if at.is_multiple_of(2) {
+        response.truncate(12 + (at / 2) as usize % (response.len() - 12));
+    } else {
+        let index = 12 + (at / 2) as usize % (response.len() - 12);
+        response[index] ^= 0xff;
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_faults() {
+        let plan: FaultPlan = "drop=10, delay=5-50ms, corrupt=2%, seed=7".parse().unwrap();
+        assert_eq!(plan.drop, 0.1);
+        assert_eq!(plan.delay, (Duration::from_millis(5), Duration::from_millis(50)));
+        assert_eq!(plan.seed, 7);
+        assert!("drop=150".parse::<FaultPlan>().is_err());
+        assert!("delay=50-5".parse::<FaultPlan>().is_err());
+        assert!("lose=5".parse::<FaultPlan>().is_err());
+
+        let names: Vec<String> = (0..5000).map(|i| format!("w{}.example.com", i)).collect();
+        let run = |plan: &FaultPlan| {
+            let faults = Faults::new(plan.clone());
+            let decisions: Vec<Decision> = names.iter().map(|name| faults.decide(name)).collect();
+            (decisions, faults.counts())
+        };
+        let (first, counts) = run(&plan);
+        let (again, _) = run(&plan);
+        assert_eq!(first, again);
+        assert!((400..600).contains(&counts.dropped), "{:?}", counts);
+        assert!((50..150).contains(&counts.corrupted), "{:?}", counts);
+        assert!(first.iter().all(|d| (plan.delay.0..=plan.delay.1).contains(&d.delay)));
+        let (reseeded, _) = run(&FaultPlan { seed: 8, ..plan.clone() });
+        assert_ne!(first, reseeded);
+
+        // A retry is a new draw, not the same fault again.
+        let faults = Faults::new(FaultPlan { drop: 0.5, ..plan });
+        let retried: Vec<bool> = (0..64).map(|_| faults.decide("db.example.com.").drop).collect();
+        assert!(retried.contains(&true) && retried.contains(&false));
+
+        let response = vec![0u8; 40];
+        assert_eq!(corrupt(response.clone(), 10).len(), 17);
+        assert_eq!(corrupt(response.clone(), 11)[17], 0xff);
+        assert!(corrupt(vec![0u8; 8], 3).is_empty());
+    }
+}
@@ -0,0 +1,314 @@
+//! C ABI over the scanner, for embedding it from Python, Go or anything
+//! else with a C FFI instead of running the binary and parsing its output.
+//! Build the shared library with
+//!
+//! ```text
+//! cargo rustc --lib --release --no-default-features --features ffi --crate-type cdylib
+//! ```
+//!
+//! and see `include/subscan.h` for the declarations. A scan runs on its own
+//! thread with its own runtime; the caller polls it for findings:
+//!
+//! ```c
+//! SubscanScan *scan = subscan_start_scan("{\"domain\": \"example.com\", \"wordlist\": \"words.txt\", \"resolvers\": \"resolvers.txt\"}");
+//! for (;;) {
+//!     char *update = subscan_poll_results(scan, 500);
+//!     /* {"found": [...], "done": false}; the last update has "done": true and "result" */
+//!     subscan_string_free(update);
+//!     if (done) break;
+//! }
+//! subscan_free(scan);
+//! ```
+//!
+//! Every string the library returns is JSON and must be released with
+//! `subscan_string_free`.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+
+use crate::printer::ShowMode;
+use crate::scanner::{Resolution, SubdomainScanner};
+
+/// What `subscan_start_scan` takes, as a JSON object.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScanConfig {
+    pub domain: String,
+    /// Wordlist file.
+    pub wordlist: String,
+    /// Resolver file, one `IP` or `IP:port` per line.
+    pub resolvers: String,
+    #[serde(default = "default_threads")]
+    pub threads: u32,
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+    #[serde(default)]
+    pub max_queries: Option<u64>,
+    #[serde(default)]
+    pub verify: bool,
+}
+
+fn default_threads() -> u32 {
+    100
+}
+
+fn default_timeout() -> u64 {
+    2
+}
+
+/// A running or finished scan. Opaque to C.
+pub struct SubscanScan {
+    found: Mutex<mpsc::UnboundedReceiver<(String, Resolution)>>,
+    /// The scan's result document, or why it could not run, once it ends.
+    outcome: Arc<Mutex<Option<Result<Value, String>>>>,
+    delivered: AtomicBool,
+    interrupt: Arc<AtomicBool>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(message: String) {
+    LAST_ERROR.with(|last| *last.borrow_mut() = CString::new(message).ok());
+}
+
+fn to_c(value: Value) -> *mut c_char {
+    // serde_json escapes NUL inside strings, so this cannot fail.
+    CString::new(value.to_string()).map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+impl SubscanScan {
+    fn start(config: ScanConfig) -> Self {
+        Self::spawn(move |found, interrupt| {
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| format!("could not start a runtime: {}", e))
+                .and_then(|runtime| runtime.block_on(run(config, found, interrupt)))
+        })
+    }
+
+    /// Runs `job` on its own thread. A panic ends the scan with an error
+    /// instead of leaving the caller polling forever.
+    fn spawn<F>(job: F) -> Self
+    where
+        F: FnOnce(mpsc::UnboundedSender<(String, Resolution)>, Arc<AtomicBool>) -> Result<Value, String> + Send + 'static,
+    {
+        let (found_tx, found_rx) = mpsc::unbounded_channel();
+        let outcome = Arc::new(Mutex::new(None));
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let (outcome, interrupt) = (outcome.clone(), interrupt.clone());
+            std::thread::spawn(move || {
+                let result = std::panic::catch_unwind(AssertUnwindSafe(|| job(found_tx, interrupt))).unwrap_or_else(|panic| {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    Err(format!("the scan panicked: {}", message))
+                });
+                *outcome.lock().unwrap() = Some(result);
+            })
+        };
+        Self {
+            found: Mutex::new(found_rx),
+            outcome,
+            delivered: AtomicBool::new(false),
+            interrupt,
+            thread: Mutex::new(Some(thread)),
+        }
+    }
+
+    /// Findings since the last poll, waiting up to `wait` for the first one.
+    fn poll(&self, wait: Duration) -> Value {
+        let mut found = self.found.lock().unwrap();
+        let mut names = Vec::new();
+        let deadline = std::time::Instant::now() + wait;
+        loop {
+            while let Ok((name, resolution)) = found.try_recv() {
+                names.push(json!({ "name": name, "resolution": resolution }));
+            }
+            let finished = self.outcome.lock().unwrap().is_some();
+            if !names.is_empty() || finished || std::time::Instant::now() >= deadline {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // The outcome is set after the last finding was sent, so a drain
+        // after seeing it collects everything.
+        let outcome = self.outcome.lock().unwrap();
+        if outcome.is_some() {
+            while let Ok((name, resolution)) = found.try_recv() {
+                names.push(json!({ "name": name, "resolution": resolution }));
+            }
+        }
+        let mut update = json!({ "found": names, "done": outcome.is_some() });
+        if let Some(outcome) = outcome.as_ref()
+            && !self.delivered.swap(true, Ordering::Relaxed)
+        {
+            match outcome {
+                Ok(result) => update["result"] = result.clone(),
+                Err(e) => update["error"] = e.clone().into(),
+            }
+        }
+        update
+    }
+}
+
+async fn run(config: ScanConfig, found: mpsc::UnboundedSender<(String, Resolution)>, interrupt: Arc<AtomicBool>) -> Result<Value, String> {
+    let scanner = SubdomainScanner::new(&config.resolvers, &config.wordlist, &config.domain, config.timeout_secs, config.threads)
+        .await
+        .map_err(|e| e.to_string())?
+        .with_show(ShowMode::None)
+        .with_max_queries(config.max_queries)
+        .with_verification(config.verify, 50)
+        .with_interrupt(interrupt)
+        .with_found_sender(found);
+    let result = scanner.scan().await;
+    serde_json::to_value(result).map_err(|e| e.to_string())
+}
+
+/// Starts a scan described by `config_json` (see [`ScanConfig`]). Returns
+/// NULL when the configuration is unusable; `subscan_last_error` says why.
+/// Errors reading the files are reported by the final poll instead.
+///
+/// # Safety
+///
+/// `config_json` must be a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subscan_start_scan(config_json: *const c_char) -> *mut SubscanScan {
+    if config_json.is_null() {
+        set_error("config is NULL".to_string());
+        return std::ptr::null_mut();
+    }
+    // SAFETY: the caller passes a NUL-terminated string.
+    let text = unsafe { CStr::from_ptr(config_json) };
+    let config = match text.to_str().map_err(|e| e.to_string()).and_then(|text| serde_json::from_str::<ScanConfig>(text).map_err(|e| e.to_string())) {
+        Ok(config) => config,
+        Err(e) => {
+            set_error(format!("invalid scan config: {}", e));
+            return std::ptr::null_mut();
+        }
+    };
+    Box::into_raw(Box::new(SubscanScan::start(config)))
+}
+
+/// Findings since the last poll as `{"found": [{"name", "resolution"}], "done": bool}`,
+/// waiting up to `wait_ms` for one to arrive. The first poll after the
+/// scan ends adds `result` (the scan's result document) or `error`.
+///
+/// # Safety
+///
+/// `scan` must come from `subscan_start_scan` and not have been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subscan_poll_results(scan: *mut SubscanScan, wait_ms: u32) -> *mut c_char {
+    // SAFETY: the caller passes a live handle.
+    let Some(scan) = (unsafe { scan.as_ref() }) else {
+        set_error("scan is NULL".to_string());
+        return std::ptr::null_mut();
+    };
+    to_c(scan.poll(Duration::from_millis(wait_ms as u64)))
+}
+
+/// Asks the scan to stop; it finishes the queries in flight and its final
+/// poll carries what was found so far.
+///
+/// # Safety
+///
+/// `scan` must come from `subscan_start_scan` and not have been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subscan_cancel(scan: *mut SubscanScan) {
+    // SAFETY: the caller passes a live handle.
+    if let Some(scan) = unsafe { scan.as_ref() } {
+        scan.interrupt.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Cancels the scan if it is still running, waits for it and frees it.
+///
+/// # Safety
+///
+/// `scan` must come from `subscan_start_scan`, and is invalid afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subscan_free(scan: *mut SubscanScan) {
+    if scan.is_null() {
+        return;
+    }
+    // SAFETY: the caller hands back ownership of a handle from subscan_start_scan.
+    let scan = unsafe { Box::from_raw(scan) };
+    scan.interrupt.store(true, Ordering::Relaxed);
+    if let Some(thread) = scan.thread.lock().unwrap().take() {
+        let _ = thread.join();
+    }
+}
+
+/// Frees a string returned by this library.
+///
+/// # Safety
+///
+/// `s` must come from this library and not have been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn subscan_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: the string was made by CString::into_raw.
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Why the last call on this thread returned NULL, or NULL. Valid until
+/// the next failing call on the same thread; not to be freed.
+#[unsafe(no_mangle)]
+pub extern "C" fn subscan_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(std::ptr::null(), |e| e.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_lifecycle() {
+        let bad = CString::new(r#"{"domain": "example.com", "wordlist": "w.txt"}"#).unwrap();
+        assert!(unsafe { subscan_start_scan(bad.as_ptr()) }.is_null());
+        let error = unsafe { CStr::from_ptr(subscan_last_error()) }.to_str().unwrap();
+        assert!(error.contains("missing field `resolvers`"), "{}", error);
+
+        let config = CString::new(r#"{"domain": "example.com", "wordlist": "/nonexistent/w.txt", "resolvers": "/nonexistent/r.txt"}"#).unwrap();
+        let scan = unsafe { subscan_start_scan(config.as_ptr()) };
+        assert!(!scan.is_null());
+        let update = loop {
+            let raw = unsafe { subscan_poll_results(scan, 1000) };
+            let update: Value = serde_json::from_str(unsafe { CStr::from_ptr(raw) }.to_str().unwrap()).unwrap();
+            unsafe { subscan_string_free(raw) };
+            if update["done"] == true {
+                break update;
+            }
+        };
+        assert!(update["error"].as_str().unwrap().contains("/nonexistent/r.txt"));
+        unsafe { subscan_free(scan) };
+    }
+
+    #[test]
+    fn test_panicked_scan_is_done() {
+        let scan = SubscanScan::spawn(|_, _| panic!("resolver list empty"));
+        let update = loop {
+            let update = scan.poll(Duration::from_secs(1));
+            if update["done"] == true {
+                break update;
+            }
+        };
+        assert_eq!(update["error"], "the scan panicked: resolver list empty");
+    }
+}
@@ -0,0 +1,404 @@
+//! Filter expressions over found names, applied when results are written
+//! (`--filter`, `--view`), so one scan can be cut into several views:
+//!
+//! ```text
+//! type==A && !cdn && confidence>=medium
+//! name~staging || (source==crtsh && ttl<300)
+//! ```
+//!
+//! Fields: `name`, `type` (`a`, `aaaa`, `cname`), `address`, `cname`,
+//! `cdn` (its name, false when none), `ttl`, `addresses` (how many),
+//! `depth` (labels below the target), `source`, `confidence` (`low` <
+//! `medium` < `high`), `score` (entropy of the most random-looking label)
+//! and `random`. A field with several values (`type`, `address`, `source`)
+//! matches when any value does, and `!=` when none does. `~` is substring
+//! match; a field on its own is true when it is set and not false or zero.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::cluster;
+
+const FIELDS: &[&str] = &["name", "type", "address", "cname", "cdn", "ttl", "addresses", "depth", "source", "confidence", "score", "random"];
+const CONFIDENCE: &[&str] = &["low", "medium", "high"];
+
+/// Per-name maps in a result that follow the names kept.
+const PER_NAME: &[&str] = &["records", "origins", "attribution", "name_scores", "transport", "search_domains"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    /// A field on its own: set and not false or zero.
+    Truthy(String),
+    Compare(String, Op, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens: &tokens, at: 0 };
+        let filter = parser.or()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some(token) => Err(format!("Unexpected '{}' in filter '{}'", token, s)),
+        }
+    }
+}
+
+/// An extra output written through a filter, `FILE=EXPR`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct View {
+    pub path: String,
+    pub filter: Filter,
+}
+
+impl FromStr for View {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((path, expression)) if !path.is_empty() => Ok(View { path: path.to_string(), filter: expression.parse()? }),
+            _ => Err(format!("Unknown view '{}', expected FILE=EXPRESSION", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Op(op) => {
+                let symbol = match op {
+                    Op::Eq => "==",
+                    Op::Ne => "!=",
+                    Op::Lt => "<",
+                    Op::Le => "<=",
+                    Op::Gt => ">",
+                    Op::Ge => ">=",
+                    Op::Contains => "~",
+                };
+                write!(f, "{}", symbol)
+            }
+            Token::And => write!(f, "&&"),
+            Token::Or => write!(f, "||"),
+            Token::Not => write!(f, "!"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let (token, len) = match (c, next) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            ('&', Some('&')) => (Token::And, 2),
+            ('|', Some('|')) => (Token::Or, 2),
+            ('=', Some('=')) => (Token::Op(Op::Eq), 2),
+            ('!', Some('=')) => (Token::Op(Op::Ne), 2),
+            ('<', Some('=')) => (Token::Op(Op::Le), 2),
+            ('>', Some('=')) => (Token::Op(Op::Ge), 2),
+            ('<', _) => (Token::Op(Op::Lt), 1),
+            ('>', _) => (Token::Op(Op::Gt), 1),
+            ('~', _) => (Token::Op(Op::Contains), 1),
+            ('!', _) => (Token::Not, 1),
+            ('(', _) => (Token::Open, 1),
+            (')', _) => (Token::Close, 1),
+            ('"' | '\'', _) => {
+                let end = chars[i + 1..].iter().position(|&q| q == c).ok_or_else(|| format!("Unterminated string in filter '{}'", s))?;
+                tokens.push(Token::Word(chars[i + 1..i + 1 + end].iter().collect()));
+                i += end + 2;
+                continue;
+            }
+            _ => {
+                let len = chars[i..].iter().take_while(|c| c.is_alphanumeric() || matches!(c, '.' | '-' | '_' | ':' | '*' | '/')).count();
+                if len == 0 {
+                    return Err(format!("Unexpected '{}' in filter '{}'", c, s));
+                }
+                (Token::Word(chars[i..i + len].iter().collect()), len)
+            }
+        };
+        tokens.push(token);
+        i += len;
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    at: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.at += 1;
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.at += 1;
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Filter::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let filter = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(filter),
+                    _ => Err("Missing ')' in filter".to_string()),
+                }
+            }
+            Some(Token::Word(field)) => {
+                let field = field.to_lowercase();
+                if !FIELDS.contains(&field.as_str()) {
+                    return Err(format!("Unknown filter field: {}, expected one of {}", field, FIELDS.join(", ")));
+                }
+                let Some(Token::Op(op)) = self.peek().cloned() else {
+                    return Ok(Filter::Truthy(field));
+                };
+                self.at += 1;
+                match self.next() {
+                    Some(Token::Word(value)) => Ok(Filter::Compare(field, op, value)),
+                    _ => Err(format!("Missing value after {} in filter", field)),
+                }
+            }
+            Some(token) => Err(format!("Unexpected '{}' in filter", token)),
+            None => Err("Filter ends too early".to_string()),
+        }
+    }
+}
+
+/// What a filter sees of one found name.
+struct Facts<'a> {
+    name: &'a str,
+    target: &'a str,
+    record: &'a Value,
+    attribution: &'a Value,
+    score: &'a Value,
+}
+
+impl Facts<'_> {
+    /// Every value of `field`, as text.
+    fn values(&self, field: &str) -> Vec<String> {
+        let strings = |value: &Value| -> Vec<String> { value.as_array().into_iter().flatten().filter_map(|v| v.as_str().map(str::to_lowercase)).collect() };
+        match field {
+            "name" => vec![self.name.to_string()],
+            "type" => {
+                let addresses = strings(&self.record["addresses"]);
+                let mut types = Vec::new();
+                if addresses.iter().any(|a| !a.contains(':')) {
+                    types.push("a".to_string());
+                }
+                if addresses.iter().any(|a| a.contains(':')) {
+                    types.push("aaaa".to_string());
+                }
+                if !strings(&self.record["cname_chain"]).is_empty() {
+                    types.push("cname".to_string());
+                }
+                types
+            }
+            "address" => strings(&self.record["addresses"]),
+            "cname" => strings(&self.record["cname_chain"]),
+            "cdn" => {
+                let chain = strings(&self.record["cname_chain"]);
+                let addresses: Vec<IpAddr> = strings(&self.record["addresses"]).iter().filter_map(|a| a.parse().ok()).collect();
+                cluster::identify_cdn(&chain, &addresses).map(|cdn| cdn.to_lowercase()).into_iter().collect()
+            }
+            "ttl" => self.record["ttl"].as_u64().map(|ttl| ttl.to_string()).into_iter().collect(),
+            "addresses" => vec![self.record["addresses"].as_array().map_or(0, Vec::len).to_string()],
+            "depth" => {
+                let below = self.name.strip_suffix(self.target).unwrap_or(self.name).trim_end_matches('.');
+                vec![if below.is_empty() { 0 } else { below.split('.').count() }.to_string()]
+            }
+            "source" => strings(&self.attribution["sources"]),
+            "confidence" => self.attribution["confidence"].as_str().map(str::to_string).into_iter().collect(),
+            "score" => self.score["entropy"].as_f64().map(|entropy| entropy.to_string()).into_iter().collect(),
+            "random" => vec![(!strings(&self.score["flags"]).is_empty()).to_string()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Filter {
+    fn matches(&self, facts: &Facts) -> bool {
+        match self {
+            Filter::And(a, b) => a.matches(facts) && b.matches(facts),
+            Filter::Or(a, b) => a.matches(facts) || b.matches(facts),
+            Filter::Not(a) => !a.matches(facts),
+            Filter::Truthy(field) => facts.values(field).iter().any(|v| !matches!(v.as_str(), "false" | "0" | "")),
+            Filter::Compare(field, Op::Ne, value) => !facts.values(field).iter().any(|v| compare(field, v, value) == Some(Ordering::Equal)),
+            Filter::Compare(field, op, value) => facts.values(field).iter().any(|v| match op {
+                Op::Contains => v.contains(&value.to_lowercase()),
+                Op::Eq => compare(field, v, value) == Some(Ordering::Equal),
+                Op::Lt => compare(field, v, value) == Some(Ordering::Less),
+                Op::Le => matches!(compare(field, v, value), Some(Ordering::Less | Ordering::Equal)),
+                Op::Gt => compare(field, v, value) == Some(Ordering::Greater),
+                Op::Ge => matches!(compare(field, v, value), Some(Ordering::Greater | Ordering::Equal)),
+                Op::Ne => unreachable!(),
+            }),
+        }
+    }
+
+    /// Keeps the found names in one scan result that match, in the name
+    /// list and in every per-name map.
+    pub fn retain(&self, result: &mut Value) {
+        let target = result["target"].as_str().unwrap_or_default().to_string();
+        let findings = &result["results"];
+        let null = Value::Null;
+        let kept: Vec<Value> = findings["subdomain"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|name| {
+                let name = name.as_str().unwrap_or_default();
+                let facts = Facts {
+                    name,
+                    target: &target,
+                    record: findings["records"].get(name).unwrap_or(&null),
+                    attribution: findings["attribution"].get(name).unwrap_or(&null),
+                    score: findings["name_scores"].get(name).unwrap_or(&null),
+                };
+                self.matches(&facts)
+            })
+            .cloned()
+            .collect();
+        let names: HashSet<&str> = kept.iter().filter_map(Value::as_str).collect();
+        let findings = &mut result["results"];
+        for map in PER_NAME {
+            if let Some(map) = findings.get_mut(*map).and_then(Value::as_object_mut) {
+                map.retain(|name, _| names.contains(name.as_str()));
+            }
+        }
+        if findings.get("subdomain").is_some() {
+            findings["subdomain"] = kept.into();
+        }
+    }
+}
+
+/// Numbers by value, confidence by rank, anything else as lowercase text
+/// (equal or not only).
+fn compare(field: &str, value: &str, wanted: &str) -> Option<Ordering> {
+    let wanted = wanted.to_lowercase();
+    if field == "confidence" {
+        let rank = |c: &str| CONFIDENCE.iter().position(|known| *known == c);
+        return Some(rank(value)?.cmp(&rank(&wanted)?));
+    }
+    if let (Ok(a), Ok(b)) = (value.parse::<f64>(), wanted.parse::<f64>()) {
+        return a.partial_cmp(&b);
+    }
+    (value == wanted).then_some(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn result() -> Value {
+        json!({
+            "target": "example.com",
+            "results": {
+                "subdomain": ["www.example.com", "cdn.example.com", "v6.dev.example.com"],
+                "records": {
+                    "www.example.com": {"cname_chain": [], "addresses": ["93.184.216.34"], "ttl": 3600},
+                    "cdn.example.com": {"cname_chain": ["example.com.cdn.cloudflare.net"], "addresses": ["104.16.1.1"], "ttl": 300},
+                    "v6.dev.example.com": {"cname_chain": [], "addresses": ["2001:db8::1"], "ttl": 60}
+                },
+                "attribution": {
+                    "www.example.com": {"sources": ["dns_bruteforce", "crtsh"], "confidence": "high"},
+                    "cdn.example.com": {"sources": ["dns_bruteforce"], "confidence": "medium"},
+                    "v6.dev.example.com": {"sources": ["crtsh"], "confidence": "low"}
+                },
+                "name_scores": {"v6.dev.example.com": {"entropy": 3.5, "flags": ["digits"]}}
+            }
+        })
+    }
+
+    fn kept(expression: &str) -> Vec<String> {
+        let mut result = result();
+        expression.parse::<Filter>().unwrap().retain(&mut result);
+        serde_json::from_value(result["results"]["subdomain"].clone()).unwrap()
+    }
+
+    #[test]
+    fn test_filter() {
+        assert_eq!(kept("type==A && !cdn"), ["www.example.com"]);
+        assert_eq!(kept("cdn==cloudflare || depth>1"), ["cdn.example.com", "v6.dev.example.com"]);
+        assert_eq!(kept("confidence>=medium && ttl<1000"), ["cdn.example.com"]);
+        assert_eq!(kept("source!=crtsh"), ["cdn.example.com"]);
+        assert_eq!(kept("!(random || score>3) && name~ww"), ["www.example.com"]);
+        assert_eq!(kept("address=='2001:db8::1'"), ["v6.dev.example.com"]);
+
+        let mut filtered = result();
+        "type==aaaa".parse::<Filter>().unwrap().retain(&mut filtered);
+        assert_eq!(filtered["results"]["records"].as_object().unwrap().len(), 1);
+        assert!(filtered["results"]["attribution"].get("www.example.com").is_none());
+
+        assert!("type==".parse::<Filter>().is_err());
+        assert!("colour==red".parse::<Filter>().is_err());
+        assert!("(cdn".parse::<Filter>().is_err());
+        assert!("cdn cdn".parse::<Filter>().is_err());
+        assert_eq!(
+            "prod.json=!cdn".parse::<View>(),
+            Ok(View { path: "prod.json".to_string(), filter: Filter::Not(Box::new(Filter::Truthy("cdn".to_string()))) })
+        );
+    }
+}
@@ -0,0 +1,194 @@
+//! Questions about stored scan results, for `subscan query`: which names
+//! point at an address, which a source reported, what is new since a date.
+//! Every scan kept in the output file (`--append` history included) counts.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+
+use crate::output;
+
+pub const HELP: &str = "\
+names                 every found name
+name <NAME>           addresses, CNAMEs, sources and sightings of one name
+ip <ADDRESS>          names that resolved to an address
+ips                   every address, with how many names point at it
+source <SOURCE>       names a passive source reported (dns_bruteforce for the wordlist)
+sources               every source, with how many names it reported
+new <DATE>            names first seen in a scan started on or after DATE (YYYY-MM-DD or RFC 3339)
+targets               scanned targets and when
+help                  this list
+quit                  leave";
+
+/// What the stored scans say about one name, merged over all of them.
+#[derive(Debug, Default)]
+struct Sighting {
+    target: String,
+    first_seen: String,
+    last_seen: String,
+    addresses: BTreeSet<String>,
+    cname_chain: Vec<String>,
+    sources: BTreeSet<String>,
+}
+
+/// The found names of an output document, indexed for [`FindingsIndex::ask`].
+#[derive(Debug, Default)]
+pub struct FindingsIndex {
+    names: BTreeMap<String, Sighting>,
+    /// Target and start time of each scan, oldest first.
+    scans: Vec<(String, String)>,
+}
+
+impl FindingsIndex {
+    pub fn new(document: &Value) -> Self {
+        let mut index = FindingsIndex::default();
+        for scan in output::all_scans(document) {
+            let target = scan["target"].as_str().unwrap_or_default();
+            let started_at = scan["started_at"].as_str().unwrap_or_default();
+            index.scans.push((target.to_string(), started_at.to_string()));
+            let results = &scan["results"];
+            for name in results["subdomain"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                let sighting = index.names.entry(name.to_string()).or_insert_with(|| Sighting {
+                    target: target.to_string(),
+                    first_seen: started_at.to_string(),
+                    ..Sighting::default()
+                });
+                sighting.first_seen = sighting.first_seen.clone().min(started_at.to_string());
+                sighting.last_seen = sighting.last_seen.clone().max(started_at.to_string());
+                let record = &results["records"][name];
+                sighting.addresses.extend(strings(&record["addresses"]));
+                let chain = strings(&record["cname_chain"]);
+                if !chain.is_empty() {
+                    sighting.cname_chain = chain;
+                }
+                sighting.sources.extend(output::sources(scan, name));
+            }
+        }
+        index
+    }
+
+    /// Answers one question (see [`HELP`]) with output lines.
+    pub fn ask(&self, question: &str) -> Result<Vec<String>, String> {
+        let mut words = question.split_whitespace();
+        let command = words.next().unwrap_or_default().to_lowercase();
+        let argument = words.next();
+        if words.next().is_some() {
+            return Err(format!("too many arguments: {}", question.trim()));
+        }
+        let required = || argument.ok_or_else(|| format!("{} needs an argument, see help", command));
+        match command.as_str() {
+            "names" => Ok(self.names.keys().cloned().collect()),
+            "name" => {
+                let name = required()?.trim_end_matches('.').to_lowercase();
+                let sighting = self.names.get(&name).ok_or_else(|| format!("{} is not among the findings", name))?;
+                let mut lines = vec![
+                    format!("target      {}", sighting.target),
+                    format!("first seen  {}", sighting.first_seen),
+                    format!("last seen   {}", sighting.last_seen),
+                    format!("sources     {}", join(&sighting.sources)),
+                ];
+                if !sighting.cname_chain.is_empty() {
+                    lines.push(format!("cname       {}", sighting.cname_chain.join(" -> ")));
+                }
+                lines.push(format!("addresses   {}", join(&sighting.addresses)));
+                Ok(lines)
+            }
+            "ip" => {
+                let address = required()?;
+                Ok(self.matching(|s| s.addresses.contains(address)))
+            }
+            "ips" => Ok(counts(self.names.values().flat_map(|s| &s.addresses))),
+            "source" => {
+                let source = required()?.to_lowercase();
+                Ok(self.matching(|s| s.sources.contains(&source)))
+            }
+            "sources" => Ok(counts(self.names.values().flat_map(|s| &s.sources))),
+            "new" => {
+                let since = parse_since(required()?)?;
+                Ok(self.matching(|s| {
+                    DateTime::parse_from_rfc3339(&s.first_seen).is_ok_and(|seen| seen.with_timezone(&Utc) >= since)
+                }))
+            }
+            "targets" => Ok(self.scans.iter().map(|(target, at)| format!("{}  {}", at, target)).collect()),
+            "help" | "" => Ok(HELP.lines().map(str::to_string).collect()),
+            _ => Err(format!("Unknown question: {}, see help", command)),
+        }
+    }
+
+    fn matching(&self, keep: impl Fn(&Sighting) -> bool) -> Vec<String> {
+        self.names.iter().filter(|(_, s)| keep(s)).map(|(name, _)| name.clone()).collect()
+    }
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    value.as_array().into_iter().flatten().filter_map(|v| v.as_str().map(str::to_string)).collect()
+}
+
+fn join(values: &BTreeSet<String>) -> String {
+    values.iter().cloned().collect::<Vec<_>>().join(", ")
+}
+
+/// `count value` lines, most common first.
+fn counts<'a>(values: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for value in values {
+        *counts.entry(value).or_default() += 1;
+    }
+    let mut counts: Vec<(&str, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts.into_iter().map(|(value, count)| format!("{:>6}  {}", count, value)).collect()
+}
+
+fn parse_since(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(s) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+        .map_err(|_| format!("Unknown date: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn scan(started_at: &str, names: &[(&str, &str)]) -> Value {
+        let records: serde_json::Map<String, Value> = names
+            .iter()
+            .map(|(name, address)| (name.to_string(), json!({ "cname_chain": [], "addresses": [address], "ttl": 300 })))
+            .collect();
+        json!({
+            "target": "example.com",
+            "started_at": started_at,
+            "results": {
+                "subdomain": names.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+                "records": records,
+                "origins": { "api.example.com": [{ "name": "api.example.com", "source": "crtsh", "tags": [] }] },
+            }
+        })
+    }
+
+    #[test]
+    fn test_ask() {
+        let document = json!({ "scans": [
+            scan("2026-01-01T00:00:00+00:00", &[("www.example.com", "192.0.2.1"), ("api.example.com", "192.0.2.1")]),
+            scan("2026-03-01T00:00:00+00:00", &[("www.example.com", "192.0.2.1"), ("dev.example.com", "192.0.2.9")]),
+        ]});
+        let index = FindingsIndex::new(&document);
+
+        assert_eq!(index.ask("ip 192.0.2.1").unwrap(), ["api.example.com", "www.example.com"]);
+        assert_eq!(index.ask("source crtsh").unwrap(), ["api.example.com"]);
+        assert_eq!(index.ask("source dns_bruteforce").unwrap(), ["dev.example.com", "www.example.com"]);
+        assert_eq!(index.ask("new 2026-02-01").unwrap(), ["dev.example.com"]);
+        assert_eq!(index.ask("ips").unwrap(), ["     2  192.0.2.1", "     1  192.0.2.9"]);
+        let www = index.ask("name WWW.example.com.").unwrap();
+        assert!(www.contains(&"first seen  2026-01-01T00:00:00+00:00".to_string()));
+        assert!(www.contains(&"last seen   2026-03-01T00:00:00+00:00".to_string()));
+
+        assert!(index.ask("new yesterday").is_err());
+        assert!(index.ask("ip").is_err());
+        assert!(index.ask("drop table").is_err());
+    }
+}
@@ -0,0 +1,166 @@
+//! Answers per region (`--resolve-per-tag`). Resolvers are tagged in the
+//! resolver file (`9.9.9.9 eu`, `114.114.114.114 cn`), and every found
+//! name is asked of each tag's resolvers after the scan. A name only one
+//! region resolves is geo-fenced; one each region sends to unrelated
+//! addresses is served by region-specific infrastructure.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::task::JoinSet;
+
+use crate::answers::IpVersion;
+use crate::consistency::{self, ResolverAnswer};
+use crate::scanner;
+use crate::traffic::Traffic;
+
+/// Names resolved at once; each sends a query to one resolver per tag.
+pub const CONCURRENCY: usize = 50;
+
+/// Resolvers of a tag tried in turn while they fail.
+pub const ATTEMPTS: usize = 3;
+
+/// The resolvers in `path` by tag, in file order. Untagged resolvers are
+/// left out.
+pub fn read_tags(path: &str) -> io::Result<BTreeMap<String, Vec<SocketAddr>>> {
+    let mut tags: BTreeMap<String, Vec<SocketAddr>> = BTreeMap::new();
+    for line in BufReader::new(std::fs::File::open(path)?).lines() {
+        if let Some((resolver, names)) = scanner::parse_tagged_resolver(&line?) {
+            for name in names {
+                tags.entry(name).or_default().push(resolver);
+            }
+        }
+    }
+    Ok(tags)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TaggedName {
+    /// How the tags' answers compare, as `--consistency` verdicts do:
+    /// `inconsistent` means region-specific.
+    pub verdict: &'static str,
+    /// Tags that answered while others had no answer.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub only_in: Vec<String>,
+    pub answers: BTreeMap<String, ResolverAnswer>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PerTagReport {
+    pub tags: BTreeMap<String, Vec<SocketAddr>>,
+    pub names: BTreeMap<String, TaggedName>,
+    /// Names the tags answer differently, geo-fenced ones included.
+    pub region_specific: Vec<String>,
+    pub queries: u64,
+    /// Queries not sent because the query budget was spent.
+    pub skipped: u64,
+}
+
+/// Compares one name's answers by tag.
+pub fn compare(answers: BTreeMap<String, ResolverAnswer>) -> TaggedName {
+    let listed: Vec<ResolverAnswer> = answers.values().cloned().collect();
+    let (verdict, _) = consistency::assess(&listed);
+    let only_in = if answers.values().any(|answer| answer.status == "no_answer") {
+        answers.iter().filter(|(_, answer)| answer.status == "answer").map(|(tag, _)| tag.clone()).collect()
+    } else {
+        Vec::new()
+    };
+    TaggedName { verdict, only_in, answers }
+}
+
+/// Asks each tag's resolvers for every name in `names`, moving on to the
+/// tag's next resolver while one fails. Each query counts against the
+/// query budget.
+pub async fn resolve_all(names: &[String], tags: BTreeMap<String, Vec<SocketAddr>>, timeout: Duration, ip_version: IpVersion, traffic: &Traffic) -> PerTagReport {
+    let mut report = PerTagReport { tags: tags.clone(), ..Default::default() };
+    let mut set = JoinSet::new();
+    let mut pending = names.iter().cloned();
+    loop {
+        while set.len() < CONCURRENCY
+            && let Some(name) = pending.next()
+        {
+            let (tags, traffic) = (tags.clone(), traffic.clone());
+            set.spawn(async move {
+                let asked = tags.into_iter().map(|(tag, resolvers)| {
+                    let (name, traffic) = (name.clone(), traffic.clone());
+                    async move {
+                        let (mut queries, mut skipped) = (0, 0);
+                        let mut answer = None;
+                        for resolver in resolvers.into_iter().take(ATTEMPTS) {
+                            let next = consistency::ask(resolver, timeout, name.clone(), ip_version, &traffic).await;
+                            queries += next.sent;
+                            skipped += next.skipped;
+                            let failed = next.answer.status == "failed";
+                            answer = Some(next.answer);
+                            if !failed {
+                                break;
+                            }
+                        }
+                        (tag, answer, queries, skipped)
+                    }
+                });
+                let answers = futures_util::future::join_all(asked).await;
+                (name, answers)
+            });
+        }
+        let Some(joined) = set.join_next().await else {
+            break;
+        };
+        let Ok((name, asked)) = joined else {
+            continue;
+        };
+        let mut answers = BTreeMap::new();
+        for (tag, answer, queries, skipped) in asked {
+            report.queries += queries;
+            report.skipped += skipped;
+            if let Some(answer) = answer {
+                answers.insert(tag, answer);
+            }
+        }
+        let tagged = compare(answers);
+        if tagged.verdict == "inconsistent" {
+            report.region_specific.push(name.clone());
+        }
+        report.names.insert(name, tagged);
+    }
+    report.region_specific.sort();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn answer(resolver: u8, addresses: &[&str]) -> ResolverAnswer {
+        ResolverAnswer {
+            resolver: SocketAddr::from(([192, 0, 2, resolver], 53)),
+            status: if addresses.is_empty() { "no_answer" } else { "answer" },
+            addresses: addresses.iter().map(|a| a.parse().unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_resolver_tags() {
+        let path = std::env::temp_dir().join(format!("subscan-geo-{}.txt", std::process::id()));
+        std::fs::File::create(&path).unwrap().write_all(b"192.0.2.1 us\n192.0.2.2:5353 EU,de\n# eu\n192.0.2.3\n192.0.2.4 eu # frankfurt\n").unwrap();
+        let tags = read_tags(path.to_str().unwrap()).unwrap();
+        let resolver = |i: u8, port: u16| SocketAddr::from(([192, 0, 2, i], port));
+        assert_eq!(tags.keys().collect::<Vec<_>>(), ["de", "eu", "us"]);
+        assert_eq!(tags["eu"], [resolver(2, 5353), resolver(4, 53)]);
+        assert_eq!(scanner::parse_resolver("192.0.2.4 eu # frankfurt"), Some(resolver(4, 53)));
+        std::fs::remove_file(path).unwrap();
+
+        let mut answers = BTreeMap::from([("eu".to_string(), answer(1, &["198.51.100.1"])), ("us".to_string(), answer(2, &["198.51.100.1"]))]);
+        assert_eq!(compare(answers.clone()).verdict, "consistent");
+        answers.insert("cn".to_string(), answer(3, &[]));
+        let fenced = compare(answers.clone());
+        assert_eq!((fenced.verdict, fenced.only_in.as_slice()), ("inconsistent", ["eu".to_string(), "us".to_string()].as_slice()));
+        answers.insert("cn".to_string(), answer(3, &["203.0.113.9"]));
+        let split = compare(answers);
+        assert_eq!((split.verdict, split.only_in.len()), ("inconsistent", 0));
+    }
+}
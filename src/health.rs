@@ -0,0 +1,420 @@
+//! Resolver eviction. A resolver that keeps failing is taken out of the
+//! rotation for a cooldown, then let back in on probation. When every
+//! resolver is out the scan pauses instead of grinding out timeouts, and
+//! gives up once the pool has been empty for the grace period. Exempt
+//! resolvers, typically self-hosted ones built for scanning, are never
+//! evicted.
+//!
+//! Resolvers can carry weights, from their reputation in past scans: each
+//! gets a share of the rotation in proportion to its weight, so one known
+//! to time out half the time is tried rarely from the first query on.
+
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+/// When to evict a resolver and how long to wait for the pool to recover.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct HealthPolicy {
+    /// Consecutive failed queries (timeouts, socket errors, unusable
+    /// answers) that evict a resolver.
+    pub evict_after: u32,
+    /// How long an evicted resolver stays out before it is tried again.
+    pub cooldown: Duration,
+    /// How long the scan waits with every resolver evicted before it stops.
+    pub grace: Duration,
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        Self {
+            evict_after: 50,
+            cooldown: Duration::from_secs(30),
+            grace: Duration::from_secs(300),
+        }
+    }
+}
+
+/// How one resolver fared over a scan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolverUsage {
+    pub resolver: SocketAddr,
+    pub queries: u64,
+    pub failures: u64,
+    pub evictions: u64,
+}
+
+/// Queries, failures and evictions per resolver slot. A pool keeps one
+/// over everything sent through it; a scan sharing its pool with other
+/// targets keeps its own for its share.
+pub struct UsageTally {
+    queries: Vec<AtomicU64>,
+    failures: Vec<AtomicU64>,
+    evictions: Vec<AtomicU64>,
+}
+
+impl UsageTally {
+    pub fn new(count: usize) -> Self {
+        let counters = || (0..count).map(|_| AtomicU64::new(0)).collect();
+        Self {
+            queries: counters(),
+            failures: counters(),
+            evictions: counters(),
+        }
+    }
+
+    /// Counts a query to the resolver at `index`, and whether it failed
+    /// and got the resolver evicted.
+    pub fn record(&self, index: usize, failed: bool, evicted: bool) {
+        self.queries[index].fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.failures[index].fetch_add(1, Ordering::Relaxed);
+        }
+        if evicted {
+            self.evictions[index].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The counts, for the pool's `resolvers` in slot order.
+    pub fn usage(&self, resolvers: &[SocketAddr]) -> Vec<ResolverUsage> {
+        resolvers
+            .iter()
+            .enumerate()
+            .map(|(i, resolver)| ResolverUsage {
+                resolver: *resolver,
+                queries: self.queries[i].load(Ordering::Relaxed),
+                failures: self.failures[i].load(Ordering::Relaxed),
+                evictions: self.evictions[i].load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+/// The resolvers of one scan, or of every target of a run sharing them,
+/// and which of them are in rotation.
+pub struct ResolverPool {
+    resolvers: Vec<SocketAddr>,
+    policy: HealthPolicy,
+    failures: Vec<AtomicU32>,
+    exempt: Vec<bool>,
+    /// Running totals of the weights, one more than there are resolvers;
+    /// `None` while every resolver weighs the same.
+    cumulative: Option<Vec<u64>>,
+    tally: UsageTally,
+    // Lets the common case, nobody evicted, skip the lock.
+    evicted: AtomicUsize,
+    paused: AtomicBool,
+    state: Mutex<PoolState>,
+    exhausted: AtomicBool,
+}
+
+struct PoolState {
+    evicted_until: Vec<Option<Instant>>,
+    /// Since when every resolver has been evicted without an answer since.
+    empty_since: Option<Instant>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Pick {
+    Use(usize),
+    Wait(Duration),
+    GiveUp,
+}
+
+impl ResolverPool {
+    pub fn new(resolvers: Vec<SocketAddr>, policy: HealthPolicy) -> Self {
+        let count = resolvers.len();
+        Self {
+            resolvers,
+            policy,
+            failures: (0..count).map(|_| AtomicU32::new(0)).collect(),
+            exempt: vec![false; count],
+            cumulative: None,
+            tally: UsageTally::new(count),
+            evicted: AtomicUsize::new(0),
+            paused: AtomicBool::new(false),
+            state: Mutex::new(PoolState {
+                evicted_until: vec![None; count],
+                empty_since: None,
+            }),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+
+    /// Exempts the resolvers at `exempt` from eviction and from the
+    /// timeout and concurrency heuristics tuned for public resolvers.
+    pub fn with_exempt(mut self, exempt: &[SocketAddr]) -> Self {
+        self.exempt = self.resolvers.iter().map(|resolver| exempt.contains(resolver)).collect();
+        self
+    }
+
+    /// Gives each resolver a share of the rotation in proportion to its
+    /// weight (at least one); resolvers past the end of `weights` weigh one.
+    pub fn with_weights(mut self, weights: &[u32]) -> Self {
+        let weight = |i: usize| weights.get(i).copied().unwrap_or(1).max(1) as u64;
+        if (0..self.resolvers.len()).any(|i| weight(i) != weight(0)) {
+            let mut cumulative = vec![0];
+            for i in 0..self.resolvers.len() {
+                cumulative.push(cumulative[i] + weight(i));
+            }
+            self.cumulative = Some(cumulative);
+        }
+        self
+    }
+
+    pub fn is_exempt(&self, index: usize) -> bool {
+        self.exempt[index]
+    }
+
+    pub fn resolvers(&self) -> &[SocketAddr] {
+        &self.resolvers
+    }
+
+    /// How each resolver fared so far.
+    pub fn usage(&self) -> Vec<ResolverUsage> {
+        self.tally.usage(&self.resolvers)
+    }
+
+    /// The first resolver among `slots` in rotation at or after `index`,
+    /// waiting while every one of them is evicted. `None` once the grace
+    /// period has run out.
+    pub async fn pick(&self, index: usize, slots: Range<usize>) -> Option<(usize, SocketAddr)> {
+        loop {
+            match self.choose(index, slots.clone(), Instant::now()) {
+                Pick::Use(i) => return Some((i, self.resolvers[i])),
+                Pick::Wait(wait) => tokio::time::sleep(wait).await,
+                Pick::GiveUp => return None,
+            }
+        }
+    }
+
+    /// Counts a query against the resolver at `index`; true when the
+    /// failure got it evicted.
+    pub fn record(&self, index: usize, failed: bool) -> bool {
+        self.record_at(index, failed, Instant::now())
+    }
+
+    /// Whether the scan gave up on the pool.
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+
+    fn choose(&self, index: usize, slots: Range<usize>, now: Instant) -> Pick {
+        let count = self.resolvers.len();
+        let (first, len) = (slots.start, slots.len());
+        let start = self.rotation(index, &slots);
+        if self.evicted.load(Ordering::Relaxed) == 0 && !self.paused.load(Ordering::Relaxed) {
+            return Pick::Use(first + start);
+        }
+        let mut state = self.state.lock().unwrap();
+        if self.exhausted.load(Ordering::Relaxed) {
+            return Pick::GiveUp;
+        }
+        // Only an answer ends a pause, so resolvers that are let back in
+        // and fail again do not hold the scan up past the grace period.
+        if let Some(since) = state.empty_since
+            && now >= since + self.policy.grace
+        {
+            warn!("no resolver recovered within {:?}, finalizing", self.policy.grace);
+            self.exhausted.store(true, Ordering::Relaxed);
+            return Pick::GiveUp;
+        }
+        for (i, until) in state.evicted_until.iter_mut().enumerate() {
+            if until.is_some_and(|until| until <= now) {
+                *until = None;
+                self.evicted.fetch_sub(1, Ordering::Relaxed);
+                // On probation: one more failure and it is out again.
+                self.failures[i].store(self.policy.evict_after.saturating_sub(1), Ordering::Relaxed);
+                info!("resolver {} back in rotation on probation", self.resolvers[i]);
+            }
+        }
+        if let Some(i) = (0..len).map(|k| first + (start + k) % len).find(|&i| state.evicted_until[i].is_none()) {
+            return Pick::Use(i);
+        }
+        // Only these slots are out (a pinned group): wait for one of them
+        // without pausing the rest of the scan.
+        if state.evicted_until.iter().any(Option::is_none) {
+            let revival = state.evicted_until[slots].iter().flatten().min().copied().unwrap_or(now);
+            return Pick::Wait(revival.saturating_duration_since(now));
+        }
+        let since = *state.empty_since.get_or_insert_with(|| {
+            warn!("all {} resolvers evicted, pausing for up to {:?}", count, self.policy.grace);
+            self.paused.store(true, Ordering::Relaxed);
+            now
+        });
+        let deadline = since + self.policy.grace;
+        let revival = state.evicted_until.iter().flatten().min().copied().unwrap_or(deadline);
+        Pick::Wait(revival.min(deadline).saturating_duration_since(now))
+    }
+
+    /// The resolver `index` falls on among `slots`, as an offset into them.
+    fn rotation(&self, index: usize, slots: &Range<usize>) -> usize {
+        let Some(cumulative) = &self.cumulative else {
+            return index % slots.len();
+        };
+        let (low, high) = (cumulative[slots.start], cumulative[slots.end]);
+        let point = low + index as u64 % (high - low);
+        // The last resolver whose share starts at or before the point.
+        cumulative[slots.start..slots.end].partition_point(|&start| start <= point) - 1
+    }
+
+    fn record_at(&self, index: usize, failed: bool, now: Instant) -> bool {
+        let evicted = self.evict(index, failed, now);
+        self.tally.record(index, failed, evicted);
+        evicted
+    }
+
+    fn evict(&self, index: usize, failed: bool, now: Instant) -> bool {
+        if self.policy.evict_after == 0 {
+            return false;
+        }
+        let failures = &self.failures[index];
+        if !failed {
+            if failures.load(Ordering::Relaxed) != 0 {
+                failures.store(0, Ordering::Relaxed);
+            }
+            if self.paused.load(Ordering::Relaxed) {
+                let mut state = self.state.lock().unwrap();
+                if state.empty_since.take().is_some() {
+                    self.paused.store(false, Ordering::Relaxed);
+                    info!("resolver {} answered, resuming", self.resolvers[index]);
+                }
+            }
+            return false;
+        }
+        if self.exempt[index] || failures.fetch_add(1, Ordering::Relaxed) + 1 < self.policy.evict_after {
+            return false;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.evicted_until[index].is_some() {
+            return false;
+        }
+        state.evicted_until[index] = Some(now + self.policy.cooldown);
+        self.evicted.fetch_add(1, Ordering::Relaxed);
+        warn!(
+            "evicting resolver {} for {:?} after {} consecutive failures",
+            self.resolvers[index],
+            self.policy.cooldown,
+            failures.load(Ordering::Relaxed)
+        );
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: Range<usize> = 0..2;
+
+    #[test]
+    fn test_evict_revive_give_up() {
+        let resolvers = vec!["127.0.0.1:53".parse().unwrap(), "127.0.0.2:53".parse().unwrap()];
+        let policy = HealthPolicy {
+            evict_after: 2,
+            cooldown: Duration::from_secs(10),
+            grace: Duration::from_secs(25),
+        };
+        let pool = ResolverPool::new(resolvers, policy);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // A success in between resets the count.
+        pool.record_at(0, true, start);
+        pool.record_at(0, false, start);
+        pool.record_at(0, true, start);
+        assert_eq!(pool.choose(0, ALL, start), Pick::Use(0));
+        pool.record_at(0, true, start);
+        assert_eq!(pool.choose(0, ALL, start), Pick::Use(1));
+        // A group of just the evicted one waits without pausing the pool.
+        assert_eq!(pool.choose(0, 0..1, start), Pick::Wait(Duration::from_secs(10)));
+        assert!(!pool.paused.load(Ordering::Relaxed));
+
+        pool.record_at(1, true, at(5));
+        pool.record_at(1, true, at(5));
+        assert_eq!(pool.choose(0, ALL, at(5)), Pick::Wait(Duration::from_secs(5)));
+        // Back after the cooldown, and out again on the first failure.
+        assert_eq!(pool.choose(1, ALL, at(10)), Pick::Use(0));
+        pool.record_at(0, true, at(10));
+        assert_eq!(pool.choose(0, ALL, at(14)), Pick::Wait(Duration::from_secs(1)));
+        assert_eq!(pool.choose(0, ALL, at(15)), Pick::Use(1));
+        pool.record_at(1, true, at(15));
+
+        // Still no answer since the pool emptied at 5s, so the grace
+        // period runs out at 30s.
+        assert_eq!(pool.choose(0, ALL, at(15)), Pick::Wait(Duration::from_secs(5)));
+        assert_eq!(pool.choose(0, ALL, at(20)), Pick::Use(0));
+        pool.record_at(0, true, at(20));
+        assert!(!pool.is_exhausted());
+        assert_eq!(pool.choose(0, ALL, at(30)), Pick::GiveUp);
+        assert!(pool.is_exhausted());
+    }
+
+    #[test]
+    fn test_answer_ends_pause() {
+        let policy = HealthPolicy {
+            evict_after: 1,
+            cooldown: Duration::from_secs(10),
+            grace: Duration::from_secs(15),
+        };
+        let pool = ResolverPool::new(vec!["127.0.0.1:53".parse().unwrap()], policy);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        pool.record_at(0, true, start);
+        assert_eq!(pool.choose(0, 0..1, start), Pick::Wait(Duration::from_secs(10)));
+        assert_eq!(pool.choose(0, 0..1, at(10)), Pick::Use(0));
+        pool.record_at(0, false, at(10));
+        assert_eq!(pool.choose(0, 0..1, at(20)), Pick::Use(0));
+        pool.record_at(0, true, at(20));
+        assert_eq!(pool.choose(0, 0..1, at(20)), Pick::Wait(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_eviction_disabled() {
+        let policy = HealthPolicy {
+            evict_after: 0,
+            ..HealthPolicy::default()
+        };
+        let pool = ResolverPool::new(vec!["127.0.0.1:53".parse().unwrap()], policy);
+        for _ in 0..100 {
+            pool.record(0, true);
+        }
+        assert_eq!(pool.choose(3, 0..1, Instant::now()), Pick::Use(0));
+    }
+
+    #[test]
+    fn test_weights() {
+        let resolvers: Vec<SocketAddr> = (1..=3).map(|i| format!("127.0.0.{}:53", i).parse().unwrap()).collect();
+        let pool = ResolverPool::new(resolvers, HealthPolicy::default()).with_weights(&[3, 1, 0]);
+        let now = Instant::now();
+        let picks: Vec<Pick> = (0..5).map(|index| pool.choose(index, 0..3, now)).collect();
+        assert_eq!(picks, [Pick::Use(0), Pick::Use(0), Pick::Use(0), Pick::Use(1), Pick::Use(2)]);
+        // A pinned group is weighted among itself.
+        assert_eq!(pool.choose(1, 1..3, now), Pick::Use(2));
+
+        pool.record_at(1, true, now);
+        pool.record_at(1, false, now);
+        assert_eq!(pool.usage()[1], ResolverUsage { resolver: "127.0.0.2:53".parse().unwrap(), queries: 2, failures: 1, evictions: 0 });
+    }
+
+    #[test]
+    fn test_exempt_never_evicted() {
+        let policy = HealthPolicy {
+            evict_after: 1,
+            ..HealthPolicy::default()
+        };
+        let resolvers = vec!["127.0.0.1:53".parse().unwrap(), "10.0.0.53:53".parse().unwrap()];
+        let pool = ResolverPool::new(resolvers, policy).with_exempt(&["10.0.0.53:53".parse().unwrap()]);
+        assert!(!pool.is_exempt(0) && pool.is_exempt(1));
+        let now = Instant::now();
+        pool.record_at(0, true, now);
+        pool.record_at(1, true, now);
+        assert_eq!(pool.choose(0, ALL, now), Pick::Use(1));
+        assert_eq!(pool.choose(1, ALL, now), Pick::Use(1));
+    }
+}
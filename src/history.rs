@@ -0,0 +1,100 @@
+//! Which wordlist entries have produced findings before, kept across scans
+//! so `--prioritize-by-history` can try the words most likely to hit
+//! first. Only words that were found at least once are stored: a word that
+//! never hit has nothing to rank, and keeping it would grow the file to
+//! the size of every wordlist ever used.
+
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::jsonfile;
+
+pub const HISTORY_FILE: &str = "word-history.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WordHistory {
+    #[serde(default)]
+    pub words: BTreeMap<String, WordStats>,
+}
+
+/// Scans a word was tried in since it first hit, and how many it hit in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WordStats {
+    pub tried: u64,
+    pub hits: u64,
+}
+
+impl WordStats {
+    /// Hit rate pulled toward a half while there are few scans to go on,
+    /// so one lucky hit does not outrank a word that hits every time.
+    fn score(&self) -> f64 {
+        (self.hits as f64 + 1.0) / (self.tried as f64 + 2.0)
+    }
+}
+
+impl WordHistory {
+    /// `$SUBSCAN_HOME/word-history.json`, by default `~/.subscan/word-history.json`.
+    pub fn default_path() -> Option<PathBuf> {
+        std::env::var_os("SUBSCAN_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".subscan")))
+            .map(|base| base.join(HISTORY_FILE))
+    }
+
+    /// An empty history when the file does not exist yet.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        jsonfile::load_or_default(path)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        jsonfile::save(path, self)
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains_key(word)
+    }
+
+    /// Counts one scan: `tried` are the known words it queried, `hits` the
+    /// words it found (known or not).
+    pub fn record(&mut self, tried: &HashSet<String>, hits: &HashSet<String>) {
+        for word in tried.union(hits) {
+            if let Some(stats) = self.words.get_mut(word) {
+                stats.tried += 1;
+                stats.hits += hits.contains(word) as u64;
+            } else if hits.contains(word) {
+                self.words.insert(word.clone(), WordStats { tried: 1, hits: 1 });
+            }
+        }
+    }
+
+    /// Every word that ever hit, most likely to hit again first.
+    pub fn ranked(&self) -> Vec<String> {
+        let mut words: Vec<(&String, &WordStats)> = self.words.iter().filter(|(_, stats)| stats.hits > 0).collect();
+        words.sort_by(|(a, x), (b, y)| y.score().total_cmp(&x.score()).then(y.hits.cmp(&x.hits)).then(a.cmp(b)));
+        words.into_iter().map(|(word, _)| word.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(words: &[&str]) -> HashSet<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_record_and_rank() {
+        let mut history = WordHistory::default();
+        history.record(&set(&[]), &set(&["www", "vpn"]));
+        history.record(&set(&["www", "vpn"]), &set(&["www"]));
+        history.record(&set(&["www", "vpn"]), &set(&["www", "dev"]));
+        assert_eq!(history.words["www"], WordStats { tried: 3, hits: 3 });
+        assert_eq!(history.words["vpn"], WordStats { tried: 3, hits: 1 });
+        assert_eq!(history.words["dev"], WordStats { tried: 1, hits: 1 });
+        assert_eq!(history.ranked(), ["www", "dev", "vpn"]);
+    }
+}
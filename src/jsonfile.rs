@@ -0,0 +1,33 @@
+//! The JSON files kept between runs (word history, resolver reputation,
+//! paused scans) are read and written the same way: a missing file is an
+//! empty one, and a save never leaves a half-written file behind.
+
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Parses `path`; malformed JSON is `InvalidData`.
+pub fn load<T: DeserializeOwned>(path: &Path) -> io::Result<T> {
+    serde_json::from_str(&std::fs::read_to_string(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Like [`load`], with the default value when `path` does not exist yet.
+pub fn load_or_default<T: DeserializeOwned + Default>(path: &Path) -> io::Result<T> {
+    match load(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(T::default()),
+        loaded => loaded,
+    }
+}
+
+/// Writes `value` through a temporary file next to `path`, creating its
+/// directory, so an interrupted save keeps the old file.
+pub fn save<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temporary = path.with_extension("json.tmp");
+    std::fs::write(&temporary, serde_json::to_string_pretty(value)?)?;
+    std::fs::rename(temporary, path)
+}
@@ -1,3 +1,117 @@
+#[cfg(all(feature = "alerts", not(target_family = "wasm")))]
+pub mod alerts;
+pub mod answers;
+pub mod attribution;
+#[cfg(not(target_family = "wasm"))]
+pub(crate) mod batch;
+pub mod budget;
+#[cfg(not(target_family = "wasm"))]
+pub mod checkpoint;
+pub mod cluster;
+#[cfg(not(target_family = "wasm"))]
+pub mod consistency;
+#[cfg(all(feature = "doq", not(target_family = "wasm")))]
+pub mod doq;
+pub mod domain;
+#[cfg(not(target_family = "wasm"))]
+pub mod egress;
+#[cfg(not(target_family = "wasm"))]
+pub mod engine;
+pub mod entropy;
+pub mod error;
+#[cfg(not(target_family = "wasm"))]
+pub mod escalation;
+#[cfg(not(target_family = "wasm"))]
+pub mod exec;
+pub mod exit;
+#[cfg(all(feature = "fault-injection", not(target_family = "wasm")))]
+pub mod faults;
+#[cfg(all(feature = "ffi", not(target_family = "wasm")))]
+pub mod ffi;
+pub mod filter;
+pub mod findings;
+#[cfg(not(target_family = "wasm"))]
+pub mod geo;
+#[cfg(not(target_family = "wasm"))]
+pub mod health;
+pub mod history;
+pub mod jsonfile;
+#[cfg(not(target_family = "wasm"))]
+pub mod limits;
+#[cfg(not(target_family = "wasm"))]
+pub mod local;
+#[cfg(not(target_family = "wasm"))]
+pub mod manifest;
+#[cfg(not(target_family = "wasm"))]
+pub mod monitor;
+pub mod names;
+#[cfg(not(target_family = "wasm"))]
+pub mod nameservers;
+#[cfg(not(target_family = "wasm"))]
+pub mod negative;
+#[cfg(not(target_family = "wasm"))]
+pub mod net;
+#[cfg(not(target_family = "wasm"))]
+pub mod netbios;
+pub mod output;
+#[cfg(not(target_family = "wasm"))]
+pub mod packs;
+pub mod passive;
+pub mod pin;
+#[cfg(not(target_family = "wasm"))]
+pub(crate) mod pipeline;
+#[cfg(not(target_family = "wasm"))]
+pub mod posture;
+#[cfg(not(target_family = "wasm"))]
+pub mod printer;
+pub mod project;
+#[cfg(not(target_family = "wasm"))]
+pub mod ptr;
+#[cfg(not(target_family = "wasm"))]
+pub mod querylog;
+pub mod related;
+#[cfg(not(target_family = "wasm"))]
+pub mod replay;
+pub mod resolvconf;
+#[cfg(not(target_family = "wasm"))]
+pub mod reputation;
+pub mod rtt;
+#[cfg(not(target_family = "wasm"))]
 pub mod scanner;
+#[cfg(not(target_family = "wasm"))]
+pub mod schedule;
+#[cfg(not(target_family = "wasm"))]
+pub mod screenshot;
+#[cfg(all(feature = "testing", not(target_family = "wasm")))]
+pub mod selftest;
+#[cfg(feature = "sources")]
+pub mod sources;
+#[cfg(all(feature = "alerts", not(target_family = "wasm")))]
+pub mod smtp;
+#[cfg(not(target_family = "wasm"))]
+pub mod spill;
+pub mod stats;
+pub mod targets;
+pub mod template;
+#[cfg(all(any(test, feature = "testing"), not(target_family = "wasm")))]
+pub mod testing;
+pub mod tokens;
+#[cfg(not(target_family = "wasm"))]
+pub mod traffic;
+#[cfg(not(target_family = "wasm"))]
+pub mod truncation;
+#[cfg(not(target_family = "wasm"))]
+pub mod tune;
+#[cfg(not(target_family = "wasm"))]
+pub mod typosquat;
+#[cfg(not(target_family = "wasm"))]
+pub mod unbound;
+#[cfg(not(target_family = "wasm"))]
+pub mod validate;
+#[cfg(not(target_family = "wasm"))]
+pub mod wire;
+pub mod wordlist;
 
-pub use scanner::SubdomainScanner;
+pub use error::ScanError;
+#[cfg(not(target_family = "wasm"))]
+pub use scanner::{ScanResult, SubdomainScanner};
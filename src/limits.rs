@@ -0,0 +1,263 @@
+//! What the host can sustain, checked before a scan starts instead of
+//! surfacing mid-scan as "too many open files", an OOM kill, or timeouts
+//! from a full conntrack table. Descriptor and memory shortfalls lower the
+//! concurrency to what fits; conntrack only gets a warning, since how many
+//! entries a scan needs depends on how fast resolvers answer.
+
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::engine::EngineKind;
+use crate::net::{self, RESERVED_FDS};
+
+/// Below this many concurrent queries a scan is not worth starting.
+const MIN_CONCURRENCY: u32 = 16;
+/// Rough memory per in-flight query: hickory holds a socket, a task and an
+/// encoder each; the raw engine only a table entry and a waiting task.
+const HICKORY_QUERY_BYTES: u64 = 48 << 10;
+const RAW_QUERY_BYTES: u64 = 4 << 10;
+/// Share of the available memory in-flight queries may take.
+const MEMORY_SHARE: f64 = 0.5;
+/// Queries per second one concurrent query makes, at a 50ms round trip,
+/// when no --max-pps says otherwise.
+const QUERIES_PER_SLOT: u64 = 20;
+
+/// What a scan is about to ask of the host.
+#[derive(Debug, Clone)]
+pub struct Demand {
+    pub concurrency: u32,
+    pub engine: EngineKind,
+    /// Raw engine sockets per address family.
+    pub sockets: usize,
+    pub max_pps: Option<u32>,
+    /// At most this many queries in all, when known; small scans never
+    /// fill a table however fast they go.
+    pub queries: Option<u64>,
+    /// --socket-buffer; the kernel charges it to every hickory socket.
+    pub socket_buffer: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct HostLimits {
+    pub open_files: Option<u64>,
+    /// Bytes the scan may still allocate: MemAvailable, or less when a
+    /// cgroup caps the process.
+    pub memory_available: Option<u64>,
+    /// `None` when connection tracking is not loaded.
+    pub conntrack: Option<Conntrack>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Conntrack {
+    pub max: u64,
+    pub count: u64,
+    /// Seconds an unanswered UDP flow stays in the table.
+    pub udp_timeout: u64,
+}
+
+/// The concurrency to run at and what was adjusted or looks risky.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub concurrency: u32,
+    pub warnings: Vec<String>,
+}
+
+impl HostLimits {
+    /// Reads the current limits. The open file limit is raised first as
+    /// far as the hard limit allows for `demand`.
+    pub fn detect(demand: &Demand) -> Self {
+        let open_files = net::raise_fd_limit(fds_needed(demand, demand.concurrency)).or_else(net::fd_limit);
+        Self {
+            open_files,
+            memory_available: memory_available(),
+            conntrack: conntrack(),
+        }
+    }
+}
+
+fn fds_needed(demand: &Demand, concurrency: u32) -> u64 {
+    let raw_sockets = match demand.engine {
+        EngineKind::Raw => 2 * demand.sockets as u64,
+        EngineKind::Hickory => 0,
+    };
+    let per_query = match demand.engine {
+        // The raw engine still sends TCP retries and the verification pass
+        // through hickory, but those are few.
+        EngineKind::Raw => 0,
+        EngineKind::Hickory => concurrency as u64,
+    };
+    RESERVED_FDS + raw_sockets + per_query
+}
+
+fn query_bytes(demand: &Demand) -> u64 {
+    match demand.engine {
+        EngineKind::Raw => RAW_QUERY_BYTES,
+        EngineKind::Hickory => HICKORY_QUERY_BYTES + 2 * demand.socket_buffer.unwrap_or(0) as u64,
+    }
+}
+
+/// Fits `demand` into `limits`, or says why it cannot run at all.
+pub fn plan(demand: &Demand, limits: &HostLimits) -> Result<Plan, String> {
+    let mut concurrency = demand.concurrency;
+    let mut warnings = Vec::new();
+
+    if let Some(open_files) = limits.open_files {
+        let wanted = fds_needed(demand, concurrency);
+        if open_files < wanted {
+            let usable = open_files.saturating_sub(wanted - concurrency as u64) as u32;
+            if usable < MIN_CONCURRENCY {
+                let guidance = net::fd_limit_guidance("--thread", usable as u64, wanted);
+                return Err(format!("open file limit is {} but --thread {} needs about {}; {}", open_files, concurrency, wanted, guidance));
+            }
+            warnings.push(format!(
+                "open file limit is {}, lowering --thread from {} to {}; raise it with `ulimit -n {}` to run at {}",
+                open_files, concurrency, usable, wanted, concurrency
+            ));
+            concurrency = usable;
+        }
+    }
+
+    if let Some(available) = limits.memory_available {
+        let per_query = query_bytes(demand);
+        let budget = (available as f64 * MEMORY_SHARE) as u64;
+        if concurrency as u64 * per_query > budget {
+            let usable = (budget / per_query).min(u32::MAX as u64) as u32;
+            let guidance = match demand.engine {
+                EngineKind::Hickory => "use --engine raw, which needs far less per query, or free memory",
+                EngineKind::Raw => "free memory or raise the container's memory limit",
+            };
+            if usable < MIN_CONCURRENCY {
+                return Err(format!("only {} MiB of memory is available, too little for --thread {}; {}", available >> 20, concurrency, guidance));
+            }
+            warnings.push(format!(
+                "only {} MiB of memory is available, lowering --thread from {} to {}; {}",
+                available >> 20,
+                concurrency,
+                usable,
+                guidance
+            ));
+            concurrency = usable;
+        }
+    }
+
+    // Hickory sends every query from a new port, so each one is a flow of
+    // its own; the raw engine's few sockets make one flow per resolver.
+    if let Some(table) = limits.conntrack
+        && demand.engine == EngineKind::Hickory
+    {
+        let rate = demand.max_pps.map_or(concurrency as u64 * QUERIES_PER_SLOT, u64::from);
+        let needed = (rate * table.udp_timeout).min(demand.queries.unwrap_or(u64::MAX));
+        let free = table.max.saturating_sub(table.count);
+        if needed > free {
+            warnings.push(format!(
+                "the conntrack table has room for {} more entries (nf_conntrack_max {}, {} in use) but about {} queries/s from new ports \
+                 would add about {} within {}s, and the kernel drops packets once it is full, which shows up as timeouts; \
+                 use --engine raw, pass --max-pps {} or raise net.netfilter.nf_conntrack_max",
+                free,
+                table.max,
+                table.count,
+                rate,
+                needed,
+                table.udp_timeout,
+                (free / table.udp_timeout.max(1)).max(1)
+            ));
+        }
+    }
+
+    Ok(Plan { concurrency, warnings })
+}
+
+/// Detects the host's limits for `demand` and returns the concurrency to
+/// run at, logging every adjustment.
+pub fn prepare(demand: &Demand) -> Result<u32, String> {
+    let limits = HostLimits::detect(demand);
+    debug!("host limits: {:?}", limits);
+    let plan = plan(demand, &limits)?;
+    for warning in &plan.warnings {
+        warn!("{}", warning);
+    }
+    Ok(plan.concurrency)
+}
+
+#[cfg(target_os = "linux")]
+fn memory_available() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let available = meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemAvailable:"))
+        .and_then(|rest| rest.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map(|kib| kib << 10)?;
+    // cgroup v2: a container's limit is usually well below the host's memory.
+    let cgroup = read_number("/sys/fs/cgroup/memory.max")
+        .zip(read_number("/sys/fs/cgroup/memory.current"))
+        .map(|(max, current)| max.saturating_sub(current));
+    Some(cgroup.map_or(available, |cgroup| cgroup.min(available)))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn memory_available() -> Option<u64> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn conntrack() -> Option<Conntrack> {
+    Some(Conntrack {
+        max: read_number("/proc/sys/net/netfilter/nf_conntrack_max")?,
+        count: read_number("/proc/sys/net/netfilter/nf_conntrack_count").unwrap_or(0),
+        udp_timeout: read_number("/proc/sys/net/netfilter/nf_conntrack_udp_timeout").unwrap_or(30),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn conntrack() -> Option<Conntrack> {
+    None
+}
+
+/// A file holding one number; `None` for "max" and missing files.
+#[cfg(target_os = "linux")]
+fn read_number(path: &str) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn demand(engine: EngineKind) -> Demand {
+        Demand {
+            concurrency: 1000,
+            engine,
+            sockets: 4,
+            max_pps: None,
+            queries: None,
+            socket_buffer: None,
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let unlimited = HostLimits::default();
+        assert_eq!(plan(&demand(EngineKind::Hickory), &unlimited), Ok(Plan { concurrency: 1000, warnings: Vec::new() }));
+
+        let few_files = HostLimits { open_files: Some(564), ..Default::default() };
+        let adjusted = plan(&demand(EngineKind::Hickory), &few_files).unwrap();
+        assert_eq!(adjusted.concurrency, 500);
+        assert!(adjusted.warnings[0].contains("lowering --thread from 1000 to 500"));
+        assert_eq!(plan(&demand(EngineKind::Raw), &few_files).unwrap().concurrency, 1000);
+        let too_few = HostLimits { open_files: Some(70), ..Default::default() };
+        assert!(plan(&demand(EngineKind::Hickory), &too_few).unwrap_err().contains("ulimit -n"));
+
+        let little_memory = HostLimits { memory_available: Some(48 << 20), ..Default::default() };
+        assert_eq!(plan(&demand(EngineKind::Hickory), &little_memory).unwrap().concurrency, 512);
+
+        let table = Conntrack { max: 262144, count: 200000, udp_timeout: 30 };
+        let tracked = HostLimits { conntrack: Some(table), ..Default::default() };
+        let warned = plan(&demand(EngineKind::Hickory), &tracked).unwrap();
+        assert!(warned.warnings[0].contains("--max-pps 2071"), "{:?}", warned);
+        assert!(plan(&demand(EngineKind::Raw), &tracked).unwrap().warnings.is_empty());
+        let capped = Demand { max_pps: Some(1000), ..demand(EngineKind::Hickory) };
+        assert!(plan(&capped, &tracked).unwrap().warnings.is_empty());
+        let small = Demand { queries: Some(5000), ..demand(EngineKind::Hickory) };
+        assert!(plan(&small, &tracked).unwrap().warnings.is_empty());
+    }
+}
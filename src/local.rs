@@ -0,0 +1,402 @@
+//! Local network discovery for `subscan local`: mDNS (`.local`) and LLMNR
+//! queries sent to their multicast groups, a reverse (PTR) sweep of the
+//! local subnet and DNS-SD service enumeration. Whatever answers ends up in
+//! the same results layout as a scan, with `mdns`/`llmnr` as the source.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+
+use hickory_client::proto::op::{Message, MessageType, OpCode, Query};
+use hickory_client::proto::rr::{Name, RData, Record, RecordType};
+use serde::Serialize;
+use tokio::net::UdpSocket;
+use tokio::time::{Instant, timeout_at};
+use tracing::{debug, info, warn};
+
+use crate::passive::PassiveName;
+use crate::printer::Printer;
+use crate::scanner::Resolution;
+
+pub const MDNS_GROUP: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+pub const LLMNR_GROUP: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 252)), 5355);
+/// Asks every mDNS responder which service types it offers (RFC 6763 9).
+const SERVICES_NAME: &str = "_services._dns-sd._udp.local.";
+/// Largest subnet swept, so a typo does not send a /8 worth of queries.
+pub const MIN_PREFIX: u8 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum LocalProtocol {
+    Mdns,
+    Llmnr,
+}
+
+impl FromStr for LocalProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mdns" => Ok(LocalProtocol::Mdns),
+            "llmnr" => Ok(LocalProtocol::Llmnr),
+            _ => Err(format!("Unknown local protocol: {}", s)),
+        }
+    }
+}
+
+impl LocalProtocol {
+    pub fn label(&self) -> &'static str {
+        match self {
+            LocalProtocol::Mdns => "mdns",
+            LocalProtocol::Llmnr => "llmnr",
+        }
+    }
+
+    fn group(&self) -> SocketAddr {
+        match self {
+            LocalProtocol::Mdns => MDNS_GROUP,
+            LocalProtocol::Llmnr => LLMNR_GROUP,
+        }
+    }
+
+    /// The name asked for a wordlist entry: `printer.local` over mDNS,
+    /// the bare label over LLMNR.
+    fn query_name(&self, entry: &str) -> String {
+        let entry = entry.trim_end_matches('.');
+        match self {
+            LocalProtocol::Mdns if !entry.ends_with(".local") => format!("{}.local.", entry),
+            _ => format!("{}.", entry),
+        }
+    }
+}
+
+/// An IPv4 network written `192.168.1.0/24`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subnet {
+    pub network: Ipv4Addr,
+    pub prefix: u8,
+}
+
+impl FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = s.split_once('/').unwrap_or((s, "24"));
+        let address: Ipv4Addr = address.parse().map_err(|_| format!("Unknown subnet: {}", s))?;
+        let prefix: u8 = prefix.parse().ok().filter(|p| *p <= 32).ok_or_else(|| format!("Unknown subnet: {}", s))?;
+        if prefix < MIN_PREFIX {
+            return Err(format!("Subnet {} is larger than a /{}", s, MIN_PREFIX));
+        }
+        Ok(Self::new(address, prefix))
+    }
+}
+
+impl Subnet {
+    pub fn new(address: Ipv4Addr, prefix: u8) -> Self {
+        let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+        Self {
+            network: Ipv4Addr::from(u32::from(address) & mask),
+            prefix,
+        }
+    }
+
+    /// Host addresses, without the network and broadcast addresses of
+    /// subnets that have them.
+    pub fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let start = u32::from(self.network);
+        let size = 1u64 << (32 - self.prefix as u32);
+        let (first, last) = if size > 2 { (1, size - 2) } else { (0, size - 1) };
+        (first..=last).map(move |offset| Ipv4Addr::from(start + offset as u32))
+    }
+
+    /// The /24 around the address this host sends multicast from, if it
+    /// has a route for it.
+    pub fn detect() -> Option<Self> {
+        let socket = std::net::UdpSocket::bind("0.0.0.0:0").ok()?;
+        socket.connect(MDNS_GROUP).ok()?;
+        match socket.local_addr().ok()?.ip() {
+            IpAddr::V4(ip) if !ip.is_unspecified() && !ip.is_loopback() => Some(Self::new(ip, 24)),
+            _ => None,
+        }
+    }
+}
+
+pub fn reverse_name(ip: Ipv4Addr) -> String {
+    let [a, b, c, d] = ip.octets();
+    format!("{}.{}.{}.{}.in-addr.arpa.", d, c, b, a)
+}
+
+fn parse_reverse(name: &str) -> Option<Ipv4Addr> {
+    let labels: Vec<u8> = name
+        .trim_end_matches('.')
+        .strip_suffix(".in-addr.arpa")?
+        .split('.')
+        .map(|label| label.parse().ok())
+        .collect::<Option<_>>()?;
+    match labels[..] {
+        [d, c, b, a] => Some(Ipv4Addr::new(a, b, c, d)),
+        _ => None,
+    }
+}
+
+/// What to ask and how long to listen.
+#[derive(Debug, Clone)]
+pub struct LocalSweep {
+    pub protocols: Vec<LocalProtocol>,
+    /// Host names to ask for, from a wordlist.
+    pub names: Vec<String>,
+    /// Addresses to ask the name of; none skips the reverse sweep.
+    pub subnet: Option<Subnet>,
+    /// How long to wait for answers after the last query of a round.
+    pub listen: Duration,
+    /// Queries sent per second, across both groups.
+    pub rate: u32,
+}
+
+/// A host that answered, with every address and protocol it was seen by.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LocalHost {
+    pub addresses: BTreeSet<IpAddr>,
+    pub protocols: BTreeSet<LocalProtocol>,
+    /// Where the answers came from, which can differ from the addresses.
+    pub responders: BTreeSet<IpAddr>,
+    pub ttl: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LocalFindings {
+    pub hosts: BTreeMap<String, LocalHost>,
+    /// DNS-SD service types and the instances seen for each.
+    pub services: BTreeMap<String, BTreeSet<String>>,
+    pub queries_sent: u64,
+    pub responses: u64,
+}
+
+impl LocalFindings {
+    /// Takes in one response: addresses from A/AAAA records, names from
+    /// PTRs of reverse names, service types and instances from DNS-SD
+    /// PTRs, and SRV targets. Returns the host names seen for the first time.
+    pub fn add_response(&mut self, message: &Message, protocol: LocalProtocol, from: IpAddr) -> Vec<String> {
+        self.responses += 1;
+        let mut new = Vec::new();
+        for record in message.answers().iter().chain(message.additionals()) {
+            let owner = presentation(record.name());
+            match record.data() {
+                RData::A(a) => self.host(&owner, Some(IpAddr::V4(a.0)), record, protocol, from, &mut new),
+                RData::AAAA(aaaa) => self.host(&owner, Some(IpAddr::V6(aaaa.0)), record, protocol, from, &mut new),
+                RData::PTR(ptr) => {
+                    let target = presentation(&ptr.0);
+                    if let Some(ip) = parse_reverse(&owner) {
+                        self.host(&target, Some(IpAddr::V4(ip)), record, protocol, from, &mut new);
+                    } else if owner == SERVICES_NAME.trim_end_matches('.') {
+                        self.services.entry(target).or_default();
+                    } else if owner.starts_with('_') {
+                        self.services.entry(owner).or_default().insert(target);
+                    }
+                }
+                RData::SRV(srv) => self.host(&presentation(srv.target()), None, record, protocol, from, &mut new),
+                _ => {}
+            }
+        }
+        new
+    }
+
+    fn host(
+        &mut self,
+        name: &str,
+        address: Option<IpAddr>,
+        record: &Record,
+        protocol: LocalProtocol,
+        from: IpAddr,
+        new: &mut Vec<String>,
+    ) {
+        if name.is_empty() {
+            return;
+        }
+        if !self.hosts.contains_key(name) {
+            new.push(name.to_string());
+        }
+        let host = self.hosts.entry(name.to_string()).or_default();
+        host.addresses.extend(address);
+        host.protocols.insert(protocol);
+        host.responders.insert(from);
+        host.ttl = Some(host.ttl.map_or(record.ttl(), |ttl| ttl.min(record.ttl())));
+    }
+
+    /// The findings as scan results: names with their addresses, and the
+    /// protocols that found them as sources.
+    pub fn to_results(&self, started_at: String) -> serde_json::Value {
+        let mut records = BTreeMap::new();
+        let mut origins = BTreeMap::new();
+        for (name, host) in &self.hosts {
+            records.insert(
+                name.clone(),
+                Resolution {
+                    cname_chain: Vec::new(),
+                    addresses: host.addresses.iter().copied().collect(),
+                    ttl: host.ttl,
+                    ..Resolution::default()
+                },
+            );
+            let sources: Vec<PassiveName> = host.protocols.iter().map(|p| PassiveName::new(name.clone(), p.label())).collect();
+            origins.insert(name.clone(), sources);
+        }
+        serde_json::json!({
+            "target": "local",
+            "started_at": started_at,
+            "results": {
+                "subdomain": self.hosts.keys().collect::<Vec<_>>(),
+                "records": records,
+                "origins": origins,
+                "responders": self.hosts.iter().map(|(name, host)| (name.clone(), host.responders.clone())).collect::<BTreeMap<_, _>>(),
+                "services": self.services,
+                "total_scanned": self.queries_sent,
+                "queries_sent": self.queries_sent,
+                "responses": self.responses,
+            }
+        })
+    }
+}
+
+fn presentation(name: &Name) -> String {
+    name.to_utf8().trim_end_matches('.').to_lowercase()
+}
+
+fn query(name: &str, record_type: RecordType) -> Option<Vec<u8>> {
+    let name = Name::from_str(name).map_err(|e| debug!("skipping {}: {}", name, e)).ok()?;
+    let mut message = Message::new();
+    message
+        .set_id(rand::random())
+        .add_query(Query::query(name, record_type))
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(false);
+    message.to_vec().ok()
+}
+
+impl LocalSweep {
+    /// Sends every query, listens for answers, then asks each service type
+    /// found for its instances and listens again.
+    pub async fn run(&self, printer: &Printer) -> std::io::Result<LocalFindings> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        // mDNS queries go out with TTL 255 (RFC 6762 11); LLMNR's are meant
+        // to stay on the link, which the groups' scope already ensures.
+        socket.set_multicast_ttl_v4(255)?;
+        socket.set_multicast_loop_v4(true)?;
+
+        let mut findings = LocalFindings::default();
+        let mut round = Vec::new();
+        for protocol in &self.protocols {
+            if *protocol == LocalProtocol::Mdns {
+                round.push((*protocol, SERVICES_NAME.to_string(), RecordType::PTR));
+            }
+            for name in &self.names {
+                round.push((*protocol, protocol.query_name(name), RecordType::A));
+                round.push((*protocol, protocol.query_name(name), RecordType::AAAA));
+            }
+            for ip in self.subnet.iter().flat_map(Subnet::hosts) {
+                round.push((*protocol, reverse_name(ip), RecordType::PTR));
+            }
+        }
+        info!("sending {} local queries", round.len());
+        self.round(&socket, round, &mut findings, printer).await?;
+
+        if !findings.services.is_empty() {
+            let round = findings
+                .services
+                .keys()
+                .map(|service| (LocalProtocol::Mdns, format!("{}.", service), RecordType::PTR))
+                .collect();
+            self.round(&socket, round, &mut findings, printer).await?;
+        }
+        Ok(findings)
+    }
+
+    async fn round(
+        &self,
+        socket: &UdpSocket,
+        queries: Vec<(LocalProtocol, String, RecordType)>,
+        findings: &mut LocalFindings,
+        printer: &Printer,
+    ) -> std::io::Result<()> {
+        let gap = Duration::from_secs(1) / self.rate.max(1);
+        let mut next_send = Instant::now();
+        let mut queries = queries.into_iter().peekable();
+        let mut deadline = Instant::now() + self.listen;
+        let mut buf = vec![0u8; 9000];
+        loop {
+            let wake = if queries.peek().is_some() { next_send } else { deadline };
+            match timeout_at(wake, socket.recv_from(&mut buf)).await {
+                Ok(Ok((len, from))) => {
+                    let protocol = if from.port() == LLMNR_GROUP.port() { LocalProtocol::Llmnr } else { LocalProtocol::Mdns };
+                    match Message::from_vec(&buf[..len]) {
+                        Ok(message) if message.message_type() == MessageType::Response => {
+                            for name in findings.add_response(&message, protocol, from.ip()) {
+                                printer.outcome(&name, "found");
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => debug!("unparsable answer from {}: {}", from, e),
+                    }
+                }
+                Ok(Err(e)) => warn!("receiving local answers failed: {}", e),
+                Err(_) => match queries.next() {
+                    Some((protocol, name, record_type)) => {
+                        if let Some(bytes) = query(&name, record_type) {
+                            if let Err(e) = socket.send_to(&bytes, protocol.group()).await {
+                                warn!("could not send {} query for {}: {}", protocol.label(), name, e);
+                            }
+                            findings.queries_sent += 1;
+                        }
+                        next_send = Instant::now() + gap;
+                        deadline = Instant::now() + self.listen;
+                    }
+                    None => return Ok(()),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_client::proto::rr::rdata::{A, PTR, SRV};
+
+    #[test]
+    fn test_subnet() {
+        let subnet: Subnet = "192.168.1.77/24".parse().unwrap();
+        assert_eq!(subnet.network, Ipv4Addr::new(192, 168, 1, 0));
+        let hosts: Vec<Ipv4Addr> = subnet.hosts().collect();
+        assert_eq!(hosts.len(), 254);
+        assert_eq!(hosts[0], Ipv4Addr::new(192, 168, 1, 1));
+        assert_eq!("10.0.0.5/32".parse::<Subnet>().unwrap().hosts().count(), 1);
+        assert!("10.0.0.0/8".parse::<Subnet>().is_err());
+        assert_eq!(parse_reverse(&reverse_name(Ipv4Addr::new(10, 1, 2, 3))), Some(Ipv4Addr::new(10, 1, 2, 3)));
+    }
+
+    #[test]
+    fn test_add_response() {
+        let name = |s: &str| Name::from_str(s).unwrap();
+        let mut message = Message::new();
+        message.set_message_type(MessageType::Response);
+        message.add_answers([
+            Record::from_rdata(name("5.1.168.192.in-addr.arpa."), 120, RData::PTR(PTR(name("NAS.local.")))),
+            Record::from_rdata(name(SERVICES_NAME), 120, RData::PTR(PTR(name("_smb._tcp.local.")))),
+            Record::from_rdata(name("_smb._tcp.local."), 120, RData::PTR(PTR(name("nas._smb._tcp.local.")))),
+            Record::from_rdata(name("nas._smb._tcp.local."), 120, RData::SRV(SRV::new(0, 0, 445, name("nas.local.")))),
+        ]);
+        message.add_additional(Record::from_rdata(name("printer.local."), 60, RData::A(A::new(192, 168, 1, 9))));
+
+        let mut findings = LocalFindings::default();
+        let from = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 5));
+        assert_eq!(findings.add_response(&message, LocalProtocol::Mdns, from), ["nas.local", "printer.local"]);
+        assert_eq!(findings.hosts["nas.local"].addresses.iter().collect::<Vec<_>>(), [&from]);
+        assert_eq!(findings.hosts["printer.local"].ttl, Some(60));
+        assert_eq!(findings.services["_smb._tcp.local"].iter().collect::<Vec<_>>(), ["nas._smb._tcp.local"]);
+
+        let results = findings.to_results(String::new());
+        assert_eq!(results["results"]["subdomain"][1], "printer.local");
+        assert_eq!(results["results"]["origins"]["nas.local"][0]["source"], "mdns");
+    }
+}
@@ -1,47 +1,2258 @@
-use subscan::scanner::SubdomainScanner;
+use subscan::alerts::Alerts;
+use subscan::answers::{AnswerPolicy, IpVersion};
+use subscan::checkpoint::{self, ScanState, TargetState};
+use subscan::cluster;
+use subscan::consistency;
+use subscan::escalation;
+use subscan::geo;
+use subscan::doq;
+use subscan::domain::{self, SuffixList};
+use subscan::egress::{self, Bandwidth, EgressLimit, LimiterKind, Ramp};
+use subscan::engine::EngineKind;
+use subscan::exec::{ExecCommand, ExecHook};
+use subscan::exit::Exit;
+#[cfg(feature = "fault-injection")]
+use subscan::faults::{self, FaultPlan};
+use subscan::findings::{self, FindingsIndex};
+use subscan::health::HealthPolicy;
+use subscan::limits;
+use subscan::local::{LocalProtocol, LocalSweep, Subnet};
+use subscan::manifest::{InputDigest, ScanManifest};
+use subscan::monitor::Monitor;
+use subscan::net::{self, SocketTuning};
+use subscan::output::{self, ExistingOutput, OutputFormat, SortOrder};
+use subscan::packs::{self, Pack};
+use subscan::printer::{Printer, ShowMode};
+use subscan::project::{self, Project};
+use subscan::pin::PinRule;
+use subscan::names::Underscores;
+use subscan::negative::NegativeLog;
+use subscan::querylog::{self, QueryLog};
+use subscan::related;
+use subscan::replay;
+use subscan::resolvconf::{self, SearchList};
+use subscan::nameservers;
+use subscan::posture;
+use subscan::ptr;
+use subscan::scanner::{QueryFlags, RawEdnsOption, SubdomainScanner};
+use subscan::schedule::Scheduler;
+use subscan::screenshot::Screenshotter;
+use subscan::selftest::{self, SelftestConfig};
+use subscan::sources::{self, favicon, ApiClient, ApiKeys, PassiveDns, Rdap, ResponseCache};
+use subscan::filter::{Filter, View};
+use subscan::history::WordHistory;
+use subscan::reputation::{self, ReputationDb};
+use subscan::stats::{self, PhaseTimings};
+use subscan::unbound::UnboundControl;
+use subscan::targets::{self, TargetConfig};
+use subscan::template::OutputTemplate;
+use subscan::tokens;
+use subscan::traffic::Traffic;
+use subscan::typosquat;
+use subscan::validate;
+use std::collections::BTreeMap;
 use std::fs::File;
-use clap::Parser;
-use std::io::Write;
+use clap::{Args, Parser, Subcommand};
+use hickory_client::proto::rr::RecordType;
+use rand::seq::SliceRandom;
+use serde_json::{Value, json};
+use std::io::{IsTerminal, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
 
 #[derive(Parser, Debug)]
 #[command(name = "Subbrute", version="0.1", about = "It checks for package in npm public repo")]
+#[command(args_conflicts_with_subcommands = true)]
 struct ArgumentCli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    scan: ScanArgs,
+}
+
+/// Flags of a scan, the default command.
+#[derive(Args, Debug)]
+struct ScanArgs {
     /// list of dns resolvers
     #[arg(short, long, default_value = "")]
     resolvers: String,
+    /// ask the NetBIOS name service at this address (a WINS server, or a broadcast address like 192.168.1.255) for candidates one label below the target that DNS has no answer for; results say which names it answered
+    #[arg(long, value_name = "ADDRESS")]
+    netbios: Option<Ipv4Addr>,
+    /// for internal assessments: also try short candidates (fewer dots than its ndots, so one label by default) below each search domain of this resolv.conf (default /etc/resolv.conf), the way internal clients resolve short names; results say which search domain each such name was found under
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = resolvconf::DEFAULT_PATH)]
+    search_domains: Option<PathBuf>,
+    /// send candidates matching PATTERN only to the resolvers in FILE and the rest only to --resolvers, e.g. '*.internal.example.com=internal.txt'; repeatable, first match wins
+    #[arg(long, value_name = "PATTERN=FILE")]
+    pin: Vec<PinRule>,
     /// wordlist containing subdomains
     #[arg(short, long, default_value = "")]
     wordlist: String,
-    /// domain name
-    #[arg(short, long, default_value = "")]
-    domain: String,
+    /// target-specific words (product, team or brand names), queried on their own and combined with every one-label wordlist entry as token-word and word-token
+    #[arg(long, value_name = "TOKEN", value_delimiter = ',', value_parser = tokens::parse_token)]
+    tokens: Vec<String>,
+    /// also take up to 8 tokens from the title and meta tags of each target's website
+    #[arg(long)]
+    site_tokens: bool,
+    /// small, high-hit-rate wordlist scanned in full before --wordlist, so likely names are found first; entries already in it are skipped in the main list
+    #[arg(long, value_name = "FILE", conflicts_with = "passive_only")]
+    quick_wordlist: Option<String>,
+    /// target domain(s), repeatable or comma-separated; URLs and wildcards like https://www.example.com/ or *.example.com are reduced to the registrable domain
+    #[arg(short, long, value_delimiter = ',')]
+    domain: Vec<String>,
+    /// file with one target per line, optionally with overrides: `example.com wordlist=small.txt threads=200 resolvers=r.txt max-queries=N sources=crtsh,github|none`
+    #[arg(long, value_name = "FILE")]
+    targets: Option<String>,
+    /// keep a subdomain target (dev.example.com) instead of widening it to its registrable domain
+    #[arg(long)]
+    subdomain_scope: bool,
+    /// public suffix list file to use instead of the embedded copy
+    #[arg(long, value_name = "FILE")]
+    psl: Option<String>,
     /// output json
     #[arg(short, long, default_value = "")]
     output: String,
+    /// also write each target's findings to DIR/<target>/: results.jsonl (one line per name), report.html and manifest.json
+    #[arg(long, value_name = "DIR")]
+    output_dir: Option<PathBuf>,
+    /// keep this run under ~/.subscan/projects/NAME ($SUBSCAN_HOME/projects/NAME): results, manifest and a diff against the project's previous scan go to scans/<time>/ unless --output is given, and passive source responses are cached there
+    #[arg(long, value_name = "NAME")]
+    project: Option<String>,
+    /// query the words that produced findings most often in past scans first; their hit counts are read from and saved to --history
+    #[arg(long)]
+    prioritize_by_history: bool,
+    /// per-word hit counts kept across scans, updated after every scan that uses it (default: the project's word-history.json with --project, else ~/.subscan/word-history.json with --prioritize-by-history)
+    #[arg(long, value_name = "FILE")]
+    history: Option<PathBuf>,
+    /// per-resolver reliability kept across scans, used to send fewer queries to resolvers that failed before and updated after every scan (default: the project's resolver-reputation.json with --project)
+    #[arg(long, value_name = "FILE")]
+    reputation: Option<PathBuf>,
+    /// on Ctrl-C, save where the scan got to (wordlist position, names to ask again, resolver stats, findings so far) to FILE for `subscan resume FILE`
+    #[arg(long, value_name = "FILE", conflicts_with = "prioritize_by_history")]
+    state: Option<PathBuf>,
+    /// format of the output: json, tree (names indented by label), dot or graphml (infrastructure graph), asm (asset list for attack-surface platforms); non-json formats go to stdout when no --output is given
+    #[arg(long, default_value = "json", value_name = "FORMAT")]
+    output_format: OutputFormat,
+    /// write one line per found name in this format instead, one per address when it uses {{ip}}; fields are {{target}}, {{name}}, {{ip}}, {{ips}}, {{cname}}, {{source}} and {{ttl}}, and \t stands for a tab
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "output_format")]
+    output_template: Option<OutputTemplate>,
+    /// write what normalization did to wordlist entries (rejected and rewritten, by reason, with samples) to this json file
+    #[arg(long, value_name = "FILE")]
+    sanitization_report: Option<String>,
+    /// also write one file per record type (a.txt, aaaa.txt, cname.txt, ns.txt) of `name value` lines into this directory; leave out --output to get only these
+    #[arg(long, value_name = "DIR")]
+    split_output: Option<String>,
+    /// write only the found names matching this expression, e.g. 'type==A && !cdn && confidence>=medium' (fields: name, type, address, cname, cdn, ttl, addresses, depth, source, confidence, score, random)
+    #[arg(long, value_name = "EXPR")]
+    filter: Option<Filter>,
+    /// also write the names matching EXPR to FILE, in the --output-format; repeat for several views of one scan
+    #[arg(long, value_name = "FILE=EXPR")]
+    view: Vec<View>,
+    /// replace an output file that already holds a scan
+    #[arg(long, group = "existing")]
+    overwrite: bool,
+    /// keep the previous scan in the output file and add this one after it, even one of other targets or another wordlist
+    #[arg(long, group = "existing")]
+    append: bool,
+    /// fold this scan's findings into the previous scan of the same targets and wordlist in the output file
+    #[arg(long, group = "existing")]
+    merge: bool,
+    /// order of names in the output: name (DNS canonical), ip or score (most sources first); stdout stays in arrival order
+    #[arg(long, default_value = "name", value_name = "ORDER")]
+    sort: SortOrder,
         /// number of threads/concurrent tasks
     #[arg(short = 't', long = "thread", default_value_t = 1000)]
     thread: u32,
+    /// what to print on stdout: all (every candidate and outcome), found (names only) or none
+    #[arg(long, default_value = "found", value_name = "MODE")]
+    show: ShowMode,
+    /// start with low concurrency and ramp up while timeouts stay low (--thread becomes the ceiling)
+    #[arg(long)]
+    auto_tune: bool,
+    /// how queries are sent: hickory (a socket per query) or raw (pre-encoded queries over one shared socket)
+    #[arg(long, default_value = "hickory", value_name = "ENGINE")]
+    engine: EngineKind,
+    /// sockets --engine raw sends from per address family (at most 256), each served by its own IO thread and sharing one port through SO_REUSEPORT
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    sockets: u16,
+    /// give each resolver its own timeout, learned from its round-trip times, instead of the fixed 2s
+    #[arg(long)]
+    adaptive_timeout: bool,
+    /// with --adaptive-timeout, wait this many times a resolver's p99 round-trip time (between 100 ms and 10 s)
+    #[arg(long, value_name = "FACTOR", default_value_t = 3.0)]
+    timeout_factor: f64,
+    /// candidates with an underscore in a label (_dmarc, _sip._tcp): allow queries them, reject skips them as invalid hostnames
+    #[arg(long, value_name = "POLICY", default_value = "allow")]
+    underscores: Underscores,
+    /// evict a resolver from the rotation after this many consecutive failed queries (0 never evicts)
+    #[arg(long, value_name = "N", default_value_t = 50)]
+    evict_after: u32,
+    /// seconds an evicted resolver stays out before it is tried again
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    evict_cooldown: u64,
+    /// with every resolver evicted, pause this many seconds for one to answer again before writing what was found and stopping
+    #[arg(long, value_name = "SECS", default_value_t = 300)]
+    exhausted_grace: u64,
+    /// poll a self-hosted unbound's statistics with this command line (stats_noreset is appended), e.g. 'unbound-control -s 10.0.0.53@8953'; with --auto-tune, back off when it drops queries
+    #[arg(long, value_name = "COMMAND")]
+    unbound_control: Option<UnboundControl>,
+    /// with --unbound-control, stop adding concurrency while this many queries wait on upstream answers
+    #[arg(long, value_name = "N", default_value_t = 800, requires = "unbound_control")]
+    unbound_queue_limit: u64,
+    /// leave found names that look machine-generated (high entropy, hex, long consonant runs, mostly digits) out of the findings and list them under random_looking
+    #[arg(long)]
+    drop_random_looking: bool,
+    /// after the scan, ask K of the resolvers (spread over the list) for every found name again and report names they answer differently, as poisoning, geo-DNS or stale records do, with the consensus answer
+    #[arg(long, value_name = "K", value_parser = clap::value_parser!(u16).range(2..))]
+    consistency: Option<u16>,
+    /// do not ask found names whose role calls for more than addresses (mail*, _dmarc, DKIM, autodiscover, _service._tcp) for their MX, TXT and SRV records after the scan
+    #[arg(long)]
+    no_escalation: bool,
+    /// after the scan, ask the resolvers of each tag in the resolver file (`9.9.9.9 eu`) for every found name and report names whose answers differ by tag, such as geo-fenced hosts
+    #[arg(long)]
+    resolve_per_tag: bool,
+    /// comma-separated service packs (microsoft365, kubernetes): add their names to the candidates, ask them for the record types the service publishes, and report which of its services the target uses under packs
+    #[arg(long, value_name = "PACK", value_delimiter = ',', value_parser = packs::by_name)]
+    pack: Vec<&'static Pack>,
+    /// address records to query and report: 4 (A), 6 (AAAA) or both (one query of each per candidate)
+    #[arg(long, default_value = "4", value_name = "VERSION")]
+    ip_version: IpVersion,
+    /// keep RFC1918 and unique local answers of external names, which are left out as unreachable by default (names under .internal, .corp, .lan and similar always keep them)
+    #[arg(long)]
+    allow_private: bool,
+    /// send a query that failed at its resolver (timeout, refused, garbage answer) again to the next resolver, up to N times
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retries: u32,
+    /// with --ip-version both, send every record type of a name at once instead of the rest only once the first did not come back NXDOMAIN
+    #[arg(long)]
+    no_nxdomain_skip: bool,
+    /// comma-separated resolvers (IP or IP:port) never evicted and left out of --adaptive-timeout and --auto-tune, e.g. self-hosted unbound instances built for scanning
+    #[arg(long, value_name = "LIST", value_delimiter = ',', value_parser = parse_exempt)]
+    exempt_resolvers: Vec<SocketAddr>,
+    /// re-check every found name on a second resolver and drop names it has no answer for
+    #[arg(long)]
+    verify: bool,
+    /// number of concurrent --verify queries
+    #[arg(long, value_name = "N", default_value_t = 50, requires = "verify")]
+    verify_thread: usize,
+    /// comma-separated passive sources to collect candidates from (crtsh, wayback, commoncrawl, github)
+    #[arg(long, value_name = "LIST", value_delimiter = ',')]
+    sources: Vec<String>,
+    /// never query passive sources, even if some are configured
+    #[arg(long, conflicts_with_all = ["sources", "passive_only"])]
+    no_sources: bool,
+    /// make no HTTP requests at all (passive sources, passive DNS, downloads); only DNS queries leave the host
+    #[arg(long, conflicts_with_all = ["sources", "passive_only", "pdns_url"])]
+    offline: bool,
+    /// skip the wordlist and only verify names reported by --sources
+    #[arg(long, requires = "sources", conflicts_with = "wordlist")]
+    passive_only: bool,
+    /// stop waiting for passive sources after this many seconds (default 60 with --passive-only, unlimited otherwise)
+    #[arg(long, value_name = "SECS")]
+    sources_timeout: Option<u64>,
+    /// passive DNS (COF) endpoint to fetch record history for findings, e.g. https://www.circl.lu/pdns/query (auth via PDNS_API_KEY or PDNS_BASIC_AUTH)
+    #[arg(long, value_name = "URL")]
+    pdns_url: Option<String>,
+    /// fetch /favicon.ico from every found name (https, then http) and record its Shodan-style mmh3 hash, for pivoting to hosts with the same icon
+    #[arg(long, conflicts_with = "offline")]
+    favicon: bool,
+    /// screenshot every found name that serves HTTP(S) with headless Chromium into DIR/<target>/, with an index.html and a urls.txt for gowitness
+    #[arg(long, value_name = "DIR", conflicts_with = "offline")]
+    screenshots: Option<PathBuf>,
+    /// Chromium or Chrome binary for --screenshots
+    #[arg(long, value_name = "PATH", default_value = "chromium", requires = "screenshots")]
+    chromium: String,
+    /// after the scan, look up every distinct found address in reverse, once, and add its PTR names to the findings (records.<name>.ptr)
+    #[arg(long)]
+    ptr: bool,
+    /// look up the target's registrant and the netblock owner of every found address over RDAP, and flag names whose netblock belongs to another organization
+    #[arg(long, conflicts_with = "offline")]
+    rdap: bool,
+    /// RDAP bootstrap service that redirects to the right registry
+    #[arg(long, value_name = "URL", default_value = "https://rdap.org", requires = "rdap")]
+    rdap_url: String,
+    /// organization netblocks should belong to, for when the registrant is redacted or differs from the hosting org
+    #[arg(long, value_name = "NAME", requires = "rdap")]
+    rdap_org: Option<String>,
+    /// report passive DNS records that changed within this many days
+    #[arg(long, value_name = "DAYS", default_value_t = 30)]
+    pdns_recent_days: u64,
+    /// randomize the order of candidates
+    #[arg(long)]
+    shuffle: bool,
+    /// seed for --shuffle so the order is reproducible across runs and shards
+    #[arg(long, value_name = "N", requires = "shuffle")]
+    seed: Option<u64>,
+    /// stop after sending this many queries and write what was found so far
+    #[arg(long, value_name = "N")]
+    max_queries: Option<u64>,
+    /// keep candidates that got no answer (NXDOMAIN, empty answer, timeout, error) in the output under results.negative
+    #[arg(long)]
+    include_negative: bool,
+    /// write candidates that got no answer to this file as JSON lines flagged "found": false
+    #[arg(long, value_name = "FILE")]
+    negative_output: Option<String>,
+    /// log every query and response to this file in dnstap (Frame Streams) format
+    #[arg(long, value_name = "FILE")]
+    dnstap_file: Option<String>,
+    /// keep at most N found names per target in memory and in the output; the rest go to --spill-dir as JSON lines
+    #[arg(long, value_name = "N")]
+    spill_after: Option<usize>,
+    /// directory for --spill-after, one <target>-found.jsonl per target
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    spill_dir: String,
+    /// run this command for every confirmed finding, with {name}, {ip} (first address), {ips} and {cname} filled in; no shell is involved
+    #[arg(long, value_name = "CMD")]
+    exec: Option<String>,
+    /// run at most this many --exec commands at once
+    #[arg(long, value_name = "N", default_value_t = 8)]
+    exec_concurrency: usize,
+    /// send at most this many DNS packets per second, counting every query (verification, enrichment) across all targets
+    #[arg(long, value_name = "N")]
+    max_pps: Option<u32>,
+    /// cap outgoing DNS traffic, IP and UDP headers included, in bits per second with an optional k, M or G suffix (10M = 10 Mbit/s)
+    #[arg(long, value_name = "RATE")]
+    max_bandwidth: Option<Bandwidth>,
+    /// send at most this many queries per second to any one resolver
+    #[arg(long, value_name = "N")]
+    max_pps_per_resolver: Option<u32>,
+    /// send at most this many queries per second for names under any one registrable domain, sparing its authoritative nameservers
+    #[arg(long, value_name = "N")]
+    max_pps_per_zone: Option<u32>,
+    /// how the rate caps are enforced: token-bucket (smooth, short bursts) or sliding-window (at most the rate in any second)
+    #[arg(long, value_name = "KIND", default_value = "token-bucket")]
+    rate_limiter: LimiterKind,
+    /// start at a tenth of the rate caps and reach them over this many seconds
+    #[arg(long, value_name = "SECS")]
+    warm_up: Option<u64>,
+    /// ease off, down to a tenth of --max-pps, once the queries left would take this many seconds at the full rate
+    #[arg(long, value_name = "SECS", requires = "max_pps")]
+    cool_down: Option<u64>,
+    /// for engine development: drop, delay and corrupt queries, e.g. drop=5,delay=10-200,corrupt=1,seed=7 (percentages, milliseconds); the same seed meets the same faults
+    #[cfg(feature = "fault-injection")]
+    #[arg(long, value_name = "SPEC")]
+    inject_faults: Option<FaultPlan>,
+    /// UDP socket send/receive buffer size in bytes (OS default if unset)
+    #[arg(long, value_name = "BYTES")]
+    socket_buffer: Option<usize>,
+    /// clear the RD (recursion desired) bit, so resolvers answer only from cache or their own zones
+    #[arg(long)]
+    no_recursion: bool,
+    /// add an EDNS option to every query, as CODE or CODE:HEXDATA (e.g. 3 for NSID); repeatable
+    #[arg(long, value_name = "OPTION")]
+    edns_option: Vec<RawEdnsOption>,
+    /// check the target's CAA, MX and SPF records and report gaps (no CAA, MX without an address, SPF soft-fail)
+    #[arg(long)]
+    posture: bool,
+    /// group found names by the /24 (or /64) they resolve into and flag names outside the CDN that fronts their siblings
+    #[arg(long)]
+    clusters: bool,
+    /// suggest other registrable domains that share the target's addresses, nameservers or mail exchangers, found through reverse lookups at --pdns-url; listed apart, under related_domains
+    #[arg(long, requires = "pdns_url")]
+    related_domains: bool,
+    /// look up the target's nameservers and report who hosts its DNS (Route 53, Cloudflare, ...)
+    #[arg(long)]
+    nameservers: bool,
+    /// like --nameservers, and also ask each server for version.bind and hostname.bind (CHAOS class)
+    #[arg(long)]
+    chaos: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// re-issue the queries recorded with --dnstap-file
+    Replay(ReplayArgs),
+    /// keep re-resolving the findings of a previous scan as their TTLs expire
+    Monitor(MonitorArgs),
+    /// answer questions about a results json (findings by IP, by source, new since a date) without writing jq
+    Query(QueryArgs),
+    /// find hosts on the local network over mDNS (.local) and LLMNR: service discovery, a reverse sweep of the subnet and names from a wordlist
+    Local(LocalArgs),
+    /// resolve a file of fully qualified names as they are, instead of wordlist entries under a domain, through the scan engine
+    Resolve(ResolveArgs),
+    /// generate typo and confusable-character lookalikes of a domain and its key names, and report the registered ones with their owners
+    Typosquat(TyposquatArgs),
+    /// check a scan's flags, targets file, input files and API keys without scanning, reporting every problem at once
+    CheckConfig(Box<ScanArgs>),
+    /// continue a scan paused with --state, with the flags it was started with
+    Resume(ResumeArgs),
+    /// measure the query engine against a built-in mock server: the rate reached, how long findings take and peak memory
+    Selftest(SelftestArgs),
+}
+
+#[derive(Args, Debug)]
+struct ResumeArgs {
+    /// state file written when the scan was paused
+    #[arg(value_name = "FILE")]
+    state: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct SelftestArgs {
+    /// queries per second to reach; the run exits with 5 when it falls short
+    #[arg(long, value_name = "QPS", default_value_t = 50000)]
+    qps_target: u64,
+    /// names to query (five seconds' worth at the target by default)
+    #[arg(long, value_name = "N")]
+    queries: Option<usize>,
+    /// number of concurrent queries
+    #[arg(short = 't', long = "thread", default_value_t = 1000)]
+    thread: u32,
+    /// how queries are sent, as for a scan
+    #[arg(long, default_value = "hickory", value_name = "ENGINE")]
+    engine: EngineKind,
+    /// sockets --engine raw sends from per address family
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    sockets: u16,
+    /// also write the report as json
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct ReplayArgs {
+    /// dnstap file written by a previous scan
+    #[arg(long, value_name = "FILE")]
+    from: String,
+    /// send to these resolvers (round-robin) instead of the ones in the log
+    #[arg(short, long, value_name = "FILE")]
+    resolvers: Option<String>,
+    /// number of concurrent queries
+    #[arg(short = 't', long = "thread", default_value_t = 100)]
+    thread: usize,
+    /// seconds to wait for each answer
+    #[arg(long, value_name = "SECS", default_value_t = 2)]
+    timeout: u64,
+    /// write per-query results as json (only the summary is printed otherwise)
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct MonitorArgs {
+    /// results json written by a previous scan (the latest scan is used)
+    #[arg(long, value_name = "FILE", required_unless_present = "project")]
+    from: Option<String>,
+    /// monitor the latest scan of this project and append change events to its monitor.jsonl
+    #[arg(long, value_name = "NAME", conflicts_with = "from")]
+    project: Option<String>,
+    /// list of dns resolvers, used round-robin
+    #[arg(short, long, value_name = "FILE")]
+    resolvers: String,
+    /// seconds to wait for each answer
+    #[arg(long, value_name = "SECS", default_value_t = 2)]
+    timeout: u64,
+    /// never re-check a name sooner than this, however short its TTL
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    min_interval: u64,
+    /// re-check at least this often, and this often for names without a TTL
+    #[arg(long, value_name = "SECS", default_value_t = 3600)]
+    max_interval: u64,
+    /// also append change events to this file as json lines (with --project, its monitor.jsonl unless given)
+    #[arg(short, long)]
+    output: Option<String>,
+    /// rules file (alerts.yaml) saying which changes call a webhook, run a command or send mail; an smtp section there also mails high-severity alerts and digests
+    #[arg(long, value_name = "FILE")]
+    alerts: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct QueryArgs {
+    /// ask about every scan of this project instead of one file
+    #[arg(long, value_name = "NAME")]
+    project: Option<String>,
+    /// results json written by a previous scan; every scan kept with --append counts
+    #[arg(required_unless_present = "project")]
+    file: Option<String>,
+    /// one question to answer, e.g. `ip 192.0.2.1`; without one, questions are read from stdin until `quit`
+    question: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+struct ResolveArgs {
+    /// file with one fully qualified name per line; blank lines and # comments are skipped
+    #[arg(short, long, value_name = "FILE")]
+    domains: String,
+    /// list of dns resolvers
+    #[arg(short, long, value_name = "FILE")]
+    resolvers: String,
+    /// number of concurrent queries
+    #[arg(short = 't', long = "thread", default_value_t = 1000)]
+    thread: u32,
+    /// seconds to wait for each answer
+    #[arg(long, value_name = "SECS", default_value_t = 2)]
+    timeout: u64,
+    /// send a query that failed at its resolver again to the next resolver, up to N times
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retries: u32,
+    /// send every record type of a name at once instead of the rest only once the first did not come back NXDOMAIN
+    #[arg(long)]
+    no_nxdomain_skip: bool,
+    /// how queries are sent, as for a scan
+    #[arg(long, default_value = "hickory", value_name = "ENGINE")]
+    engine: EngineKind,
+    /// sockets --engine raw sends from per address family
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    sockets: u16,
+    /// address records to query and report, as for a scan
+    #[arg(long, default_value = "4", value_name = "VERSION")]
+    ip_version: IpVersion,
+    /// record types to query for every name instead, e.g. A,AAAA,MX,TXT
+    #[arg(long, value_name = "TYPES", value_delimiter = ',', value_parser = parse_record_type, conflicts_with = "ip_version")]
+    record_types: Vec<RecordType>,
+    /// send every query over TCP instead of UDP; needs --engine hickory
+    #[arg(long)]
+    tcp_only: bool,
+    /// resolve the names in random order rather than as listed
+    #[arg(long)]
+    shuffle: bool,
+    /// keep private answers of external names, as for a scan
+    #[arg(long)]
+    allow_private: bool,
+    /// send at most this many DNS packets per second
+    #[arg(long, value_name = "N")]
+    max_pps: Option<u32>,
+    /// cap outgoing DNS traffic in bits per second, as for a scan
+    #[arg(long, value_name = "RATE")]
+    max_bandwidth: Option<Bandwidth>,
+    /// send at most this many queries per second to any one resolver
+    #[arg(long, value_name = "N")]
+    max_pps_per_resolver: Option<u32>,
+    /// send at most this many queries per second for names under any one registrable domain
+    #[arg(long, value_name = "N")]
+    max_pps_per_zone: Option<u32>,
+    /// token-bucket or sliding-window, as for a scan
+    #[arg(long, value_name = "KIND", default_value = "token-bucket")]
+    rate_limiter: LimiterKind,
+    /// for engine development: drop, delay and corrupt queries, e.g. drop=5,delay=10-200,corrupt=1,seed=7 (percentages, milliseconds); the same seed meets the same faults
+    #[cfg(feature = "fault-injection")]
+    #[arg(long, value_name = "SPEC")]
+    inject_faults: Option<FaultPlan>,
+    /// re-check every answered name on a second resolver and drop names it has no answer for
+    #[arg(long)]
+    verify: bool,
+    /// what to print on stdout: all, found or none
+    #[arg(long, default_value = "found", value_name = "MODE")]
+    show: ShowMode,
+    /// output json
+    #[arg(short, long)]
+    output: Option<String>,
+    /// format of the output, as for a scan
+    #[arg(long, default_value = "json", value_name = "FORMAT")]
+    output_format: OutputFormat,
+    /// write one line per answered name in this format instead, as for a scan
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "output_format")]
+    output_template: Option<OutputTemplate>,
+    /// keep names that got no answer in the output under results.negative
+    #[arg(long)]
+    include_negative: bool,
+    /// log every query and response to this file in dnstap (Frame Streams) format
+    #[arg(long, value_name = "FILE")]
+    dnstap_file: Option<String>,
+    /// keep at most N answered names in memory and in the output; the rest go to --spill-file as JSON lines
+    #[arg(long, value_name = "N")]
+    spill_after: Option<usize>,
+    /// file for --spill-after
+    #[arg(long, value_name = "FILE", default_value = "found.jsonl")]
+    spill_file: String,
+}
+
+#[derive(Args, Debug)]
+struct TyposquatArgs {
+    /// domain to find lookalikes of, reduced to its registrable domain
+    #[arg(short, long)]
+    domain: String,
+    /// key subdomains to run into the domain as well (login makes loginexample.com and login-example.com), comma-separated
+    #[arg(long, value_delimiter = ',', value_name = "LIST")]
+    names: Vec<String>,
+    /// list of dns resolvers
+    #[arg(short, long, value_name = "FILE")]
+    resolvers: String,
+    /// number of concurrent checks
+    #[arg(short = 't', long = "thread", default_value_t = 50)]
+    thread: usize,
+    /// seconds to wait for each answer
+    #[arg(long, value_name = "SECS", default_value_t = 2)]
+    timeout: u64,
+    /// skip the RDAP lookups of who registered each lookalike
+    #[arg(long)]
+    no_rdap: bool,
+    /// RDAP bootstrap service for the owner lookups
+    #[arg(long, value_name = "URL", default_value = "https://rdap.org")]
+    rdap_url: String,
+    /// what to print on stdout: found (registered lookalikes as they are found) or none
+    #[arg(long, default_value = "found", value_name = "MODE")]
+    show: ShowMode,
+    /// output json
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct LocalArgs {
+    /// comma-separated protocols to use: mdns, llmnr
+    #[arg(long, value_delimiter = ',', default_value = "mdns,llmnr", value_name = "LIST")]
+    protocol: Vec<LocalProtocol>,
+    /// host names to ask for (printer becomes printer.local over mDNS)
+    #[arg(short, long, value_name = "FILE")]
+    wordlist: Option<String>,
+    /// IPv4 subnet to sweep with reverse lookups, e.g. 192.168.1.0/24 (default: the /24 of this host's multicast address)
+    #[arg(long, value_name = "CIDR", conflicts_with = "no_sweep")]
+    subnet: Option<Subnet>,
+    /// skip the reverse sweep
+    #[arg(long)]
+    no_sweep: bool,
+    /// seconds to keep listening after the last query
+    #[arg(long, value_name = "SECS", default_value_t = 3)]
+    listen: u64,
+    /// queries sent per second
+    #[arg(long, value_name = "N", default_value_t = 100)]
+    rate: u32,
+    /// what to print on stdout: found (names as they answer) or none
+    #[arg(long, default_value = "found", value_name = "MODE")]
+    show: ShowMode,
+    /// output json
+    #[arg(short, long)]
+    output: Option<String>,
+    /// format of the output, as for a scan
+    #[arg(long, default_value = "json", value_name = "FORMAT")]
+    output_format: OutputFormat,
+}
+
+async fn run_replay(args: &ReplayArgs) -> Result<Exit, Box<dyn std::error::Error>> {
+    let mut problems = Vec::new();
+    let resolvers = match &args.resolvers {
+        Some(path) => match validate::check_resolvers(path) {
+            Ok(_) => std::fs::read_to_string(path)?.lines().filter_map(subscan::scanner::parse_resolver).collect(),
+            Err(e) => {
+                problems.push(e);
+                Vec::new()
+            }
+        },
+        None => Vec::new(),
+    };
+    let queries = querylog::read_log(Path::new(&args.from)).unwrap_or_else(|e| {
+        problems.push(format!("could not read query log '{}': {}", args.from, e));
+        Vec::new()
+    });
+    if !problems.is_empty() {
+        exit_with_problems(&problems);
+    }
+
+    info!("replaying {} queries from {}", queries.len(), args.from);
+    let replayed = replay::replay(queries, &resolvers, args.thread, Duration::from_secs(args.timeout)).await;
+    let summary = replay::summarize(&replayed);
+    println!("{}", serde_json::to_string_pretty(&summary)?);
+    if let Some(output) = &args.output {
+        let json = json!({ "summary": summary, "queries": replayed });
+        std::fs::write(output, serde_json::to_string_pretty(&json)?)?;
+    }
+    Ok(if summary["answered"] == 0 && !replayed.is_empty() {
+        Exit::ResolversUnusable
+    } else {
+        Exit::Findings
+    })
+}
+
+async fn run_selftest(args: &SelftestArgs) -> Result<Exit, Box<dyn std::error::Error>> {
+    if let Some(e) = socket_problem(args.engine, args.sockets) {
+        exit_with_problems(&[e]);
+    }
+    let config = SelftestConfig {
+        queries: args.queries.unwrap_or((args.qps_target as usize).saturating_mul(5).max(1000)),
+        concurrency: args.thread,
+        engine: args.engine,
+        sockets: args.sockets as usize,
+        timeout: Duration::from_secs(2),
+        qps_target: args.qps_target,
+    };
+    info!("selftest: {} queries, {} concurrent, {:?} engine", config.queries, config.concurrency, config.engine);
+    let report = selftest::run(&config).await?;
+    let json = serde_json::to_string_pretty(&report)?;
+    println!("{}", json);
+    if let Some(output) = &args.output {
+        std::fs::write(output, &json)?;
+    }
+    let finding = &report.answer_to_finding;
+    info!(
+        "selftest: {} qps (target {}), answer to finding p50 {}us p99 {}us, startup {}ms, drain {}ms",
+        report.qps, report.qps_target, finding.p50_us, finding.p99_us, report.startup_ms, report.drain_ms
+    );
+    if report.met_target {
+        Ok(Exit::Findings)
+    } else {
+        warn!("selftest: {} qps is below the target of {}", report.qps, report.qps_target);
+        Ok(Exit::BelowTarget)
+    }
+}
+
+async fn run_monitor(args: &MonitorArgs) -> Result<Exit, Box<dyn std::error::Error>> {
+    let mut problems = Vec::new();
+    let resolvers: Vec<_> = match validate::check_resolvers(&args.resolvers) {
+        Ok(_) => std::fs::read_to_string(&args.resolvers)?
+            .lines()
+            .filter_map(subscan::scanner::parse_resolver)
+            .collect(),
+        Err(e) => {
+            problems.push(e);
+            Vec::new()
+        }
+    };
+    let project = args.project.as_deref().map(Project::open).transpose().unwrap_or_else(|e| exit_with_problems(&[e]));
+    let from = match (&args.from, &project) {
+        (Some(from), _) => from.clone(),
+        (None, Some(project)) => match project.latest_results() {
+            Some(path) => path.display().to_string(),
+            None => exit_with_problems(&[format!("project '{}' has no finished scan to monitor", project.name)]),
+        },
+        (None, None) => unreachable!("clap requires --from or --project"),
+    };
+    let previous = std::fs::read_to_string(&from)
+        .map_err(|e| e.to_string())
+        .and_then(|s| serde_json::from_str::<Value>(&s).map_err(|e| e.to_string()))
+        .unwrap_or_else(|e| {
+            problems.push(format!("could not read results '{}': {}", from, e));
+            Value::Null
+        });
+    if args.min_interval == 0 || args.min_interval > args.max_interval {
+        problems.push("--min-interval must be at least 1 and no more than --max-interval".to_string());
+    }
+    let alerts = args.alerts.as_deref().map(Alerts::load).transpose().unwrap_or_else(|e| {
+        problems.push(e);
+        None
+    });
+    if !problems.is_empty() {
+        exit_with_problems(&problems);
+    }
+
+    let mut monitor = Monitor::new(
+        resolvers,
+        Duration::from_secs(args.timeout),
+        Duration::from_secs(args.min_interval),
+        Duration::from_secs(args.max_interval),
+    );
+    let watched = monitor.watch_results(&output::latest_scans(&previous));
+    // A project's later scans may still bring names to watch.
+    if watched.is_empty() && project.is_none() {
+        warn!("no findings in {} to monitor", from);
+        return Ok(Exit::NoFindings);
+    }
+    if let Some(project) = &project {
+        monitor = monitor.with_project(project.clone());
+    }
+    let alerts = alerts.map(Arc::new);
+    let mut alert_tasks = tokio::task::JoinSet::new();
+    let stop_digests = Arc::new(AtomicBool::new(false));
+    if let Some(alerts) = &alerts {
+        alert_tasks.spawn(alerts.clone().send_digests(stop_digests.clone()));
+    }
+
+    let events = args.output.clone().map(PathBuf::from).or_else(|| project.as_ref().map(Project::monitor_log));
+    let mut sink = match &events {
+        Some(path) => Some(std::fs::OpenOptions::new().create(true).append(true).open(path)?),
+        None => None,
+    };
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let flag = interrupt.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            flag.store(true, Ordering::Relaxed);
+        }
+    });
+    let mut changes = 0;
+    monitor
+        .run(watched, interrupt, |event| {
+            changes += 1;
+            println!("{}", event);
+            if let Some(file) = &mut sink
+                && let Err(e) = writeln!(file, "{}", event)
+            {
+                warn!("could not write change event: {}", e);
+            }
+            if let Some(alerts) = &alerts {
+                alerts.dispatch(&event, &mut alert_tasks);
+            }
+            // Reap finished actions so a long watch does not pile them up.
+            while alert_tasks.try_join_next().is_some() {}
+        })
+        .await;
+    info!("stopped monitoring after {} changes", changes);
+    // Mails what the last digest has collected.
+    stop_digests.store(true, Ordering::Relaxed);
+    if !alert_tasks.is_empty() && tokio::time::timeout(Duration::from_secs(15), alert_tasks.join_all()).await.is_err() {
+        warn!("gave up waiting for alert actions to finish");
+    }
+    Ok(Exit::Interrupted)
+}
+
+async fn run_local(args: &LocalArgs) -> Result<Exit, Box<dyn std::error::Error>> {
+    let names = match &args.wordlist {
+        Some(path) => match validate::check_wordlist(path) {
+            Ok(_) => std::fs::read_to_string(path)?.lines().map(str::trim).filter(|l| !l.is_empty()).map(str::to_string).collect(),
+            Err(e) => exit_with_problems(&[e]),
+        },
+        None => Vec::new(),
+    };
+    let subnet = if args.no_sweep {
+        None
+    } else {
+        match args.subnet.or_else(Subnet::detect) {
+            Some(subnet) => Some(subnet),
+            None => {
+                warn!("no route for multicast to derive a subnet from, skipping the reverse sweep (pass --subnet)");
+                None
+            }
+        }
+    };
+    if let Some(subnet) = &subnet {
+        info!("sweeping {}/{}", subnet.network, subnet.prefix);
+    }
+
+    let sweep = LocalSweep {
+        protocols: args.protocol.clone(),
+        names,
+        subnet,
+        listen: Duration::from_secs(args.listen),
+        rate: args.rate,
+    };
+    let started_at = chrono::Utc::now().to_rfc3339();
+    let (printer, printer_task) = Printer::spawn(args.show);
+    let findings = sweep.run(&printer).await?;
+    drop(printer);
+    printer_task.finish().await;
+    info!(
+        "{} hosts and {} service types answered {} queries",
+        findings.hosts.len(),
+        findings.services.len(),
+        findings.queries_sent
+    );
+
+    let results = [findings.to_results(started_at)];
+    let rendered = match output::render(args.output_format, &results) {
+        Some(rendered) => rendered,
+        None => serde_json::to_string_pretty(&results[0])?,
+    };
+    match &args.output {
+        Some(path) => std::fs::write(path, rendered)?,
+        None if args.output_format != OutputFormat::Json => print!("{}", rendered),
+        None => {}
+    }
+    Ok(if findings.hosts.is_empty() { Exit::NoFindings } else { Exit::Findings })
 }
 
+async fn run_resolve(args: &ResolveArgs) -> Result<Exit, Box<dyn std::error::Error>> {
+    let mut problems = Vec::new();
+    let mut names = validate::read_name_list(&args.domains).unwrap_or_else(|e| {
+        problems.push(e);
+        Vec::new()
+    });
+    let resolvers: Vec<SocketAddr> = match validate::check_resolvers(&args.resolvers) {
+        Ok(_) => std::fs::read_to_string(&args.resolvers)?
+            .lines()
+            .filter_map(subscan::scanner::parse_resolver)
+            .collect(),
+        Err(e) => {
+            problems.push(e);
+            Vec::new()
+        }
+    };
+    problems.extend(socket_problem(args.engine, args.sockets));
+    if args.engine == EngineKind::Raw && doq::registered() > 0 {
+        problems.push(RAW_WITHOUT_DOQ.to_string());
+    }
+    if args.engine == EngineKind::Raw && args.tcp_only {
+        problems.push("--tcp-only needs --engine hickory; the raw engine only sends plain UDP".to_string());
+    }
+    if !problems.is_empty() {
+        exit_with_problems(&problems);
+    }
+    if args.shuffle {
+        names.shuffle(&mut rand::rng());
+    }
+
+    let limit = EgressLimit::with_kind(args.rate_limiter, args.max_pps, args.max_bandwidth)
+        .with_per_resolver(args.max_pps_per_resolver)
+        .with_per_zone(args.max_pps_per_zone);
+    if !limit.is_empty() {
+        egress::set_limit(limit);
+    }
+    #[cfg(feature = "fault-injection")]
+    inject_faults(&args.inject_faults);
+    let (query_log, query_log_task) = match &args.dnstap_file {
+        Some(path) => {
+            let (log, task) = QueryLog::create(Path::new(path))?;
+            (log, Some(task))
+        }
+        None => (QueryLog::default(), None),
+    };
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let flag = interrupt.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            warn!("interrupted, waiting for queries in flight and writing partial results");
+            flag.store(true, Ordering::Relaxed);
+        }
+    });
+
+    let record_types = if args.record_types.is_empty() { args.ip_version.record_types() } else { args.record_types.len() as u64 };
+    let demand = limits::Demand {
+        concurrency: args.thread,
+        engine: args.engine,
+        sockets: args.sockets as usize,
+        max_pps: args.max_pps,
+        queries: Some(names.len() as u64 * record_types * (1 + args.retries as u64)),
+        socket_buffer: None,
+    };
+    let thread = limits::prepare(&demand).unwrap_or_else(|e| exit_with_problems(&[e]));
+    let ip_version = asked_family(&args.record_types, args.ip_version);
+    let mut scanner = SubdomainScanner::for_names(resolvers, names, Duration::from_secs(args.timeout), thread)?
+        .with_engine(args.engine)
+        .with_socket_count(args.sockets as usize)
+        .with_retries(args.retries)
+        .with_answer_policy(AnswerPolicy { ip_version, allow_private: args.allow_private })
+        .with_record_types(args.record_types.clone())
+        .with_skip_after_nxdomain(!args.no_nxdomain_skip)
+        .with_query_flags(QueryFlags { tcp: args.tcp_only, ..QueryFlags::default() })
+        .with_verification(args.verify, 50)
+        .with_show(args.show)
+        .with_include_negative(args.include_negative)
+        .with_query_log(query_log.clone())
+        .with_interrupt(interrupt.clone());
+    if let Some(after) = args.spill_after {
+        scanner = scanner.with_spill(after, PathBuf::from(&args.spill_file));
+    }
+    if scanner.repeated_queries() > 0 {
+        info!("skipped {} repeated (name, type) queries", scanner.repeated_queries());
+    }
+    info!("resolving {}", scanner.estimate());
+    let result = scanner.scan().await;
+    drop(scanner);
+    drop(query_log);
+    if let Some(task) = query_log_task {
+        task.finish().await?;
+    }
+
+    let results = [serde_json::to_value(result)?];
+    let rendered = match &args.output_template {
+        Some(template) => Some(template.render(&results)),
+        None => output::render(args.output_format, &results),
+    };
+    let json_output = rendered.is_none();
+    let rendered = match rendered {
+        Some(rendered) => rendered,
+        None => serde_json::to_string_pretty(&results[0])?,
+    };
+    match &args.output {
+        Some(path) => std::fs::write(path, rendered)?,
+        None if !json_output => print!("{}", rendered),
+        None => {}
+    }
+    #[cfg(feature = "fault-injection")]
+    if let Some(counts) = faults::counts() {
+        info!("injected faults: {} dropped, {} delayed, {} corrupted", counts.dropped, counts.delayed, counts.corrupted);
+    }
+    Ok(Exit::from_results(&results, interrupt.load(Ordering::Relaxed)))
+}
+
+async fn run_typosquat(args: &TyposquatArgs) -> Result<Exit, Box<dyn std::error::Error>> {
+    let mut problems = Vec::new();
+    let domain = domain::normalize_target(&args.domain, false, &SuffixList::Embedded).unwrap_or_else(|e| {
+        problems.push(e);
+        String::new()
+    });
+    let mut names = Vec::new();
+    for name in &args.names {
+        let name = name.trim().to_lowercase();
+        let label = name.strip_suffix(&format!(".{}", domain)).unwrap_or(&name);
+        if label.is_empty() || label.contains('.') || !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            problems.push(format!("--names: '{}' is not one label like login or www", name));
+        } else {
+            names.push(label.to_string());
+        }
+    }
+    let resolvers: Vec<SocketAddr> = match validate::check_resolvers(&args.resolvers) {
+        Ok(_) => std::fs::read_to_string(&args.resolvers)?
+            .lines()
+            .filter_map(subscan::scanner::parse_resolver)
+            .collect(),
+        Err(e) => {
+            problems.push(e);
+            Vec::new()
+        }
+    };
+    if !problems.is_empty() {
+        exit_with_problems(&problems);
+    }
+
+    let variants = typosquat::variants(&domain, &names);
+    info!("checking {} lookalikes of {}", variants.len(), domain);
+    let (printer, printer_task) = Printer::spawn(args.show);
+    let report = typosquat::check_all(&resolvers, Duration::from_secs(args.timeout), args.thread, &domain, variants, &printer).await;
+    drop(printer);
+    printer_task.finish().await;
+    if report.unchecked > 0 {
+        warn!("{} lookalikes got no clear answer and were not checked", report.unchecked);
+    }
+    info!("{} of {} lookalikes of {} are registered", report.lookalikes.len(), report.variants, domain);
+
+    let mut results = serde_json::to_value(&report)?;
+    if !args.no_rdap && !report.lookalikes.is_empty() {
+        let client = ApiClient::new(Duration::from_secs(30))?;
+        let rdap = Rdap::new(&args.rdap_url);
+        let owner = match rdap.domain(&client, &domain).await {
+            Ok(registration) => registration.registrant_org,
+            Err(e) => {
+                warn!("rdap lookup for {} failed: {}", domain, e);
+                None
+            }
+        };
+        results["registrant_org"] = json!(owner);
+        for (lookalike, entry) in report.lookalikes.iter().zip(results["lookalikes"].as_array_mut().into_iter().flatten()) {
+            match rdap.domain(&client, &lookalike.variant.name).await {
+                Ok(registration) => {
+                    // Registered by the brand itself: a defensive registration, not a squat.
+                    if let (Some(owner), Some(org)) = (&owner, &registration.registrant_org) {
+                        entry["same_registrant"] = json!(sources::same_org(owner, org));
+                    }
+                    entry["registration"] = serde_json::to_value(registration)?;
+                }
+                Err(e) => warn!("rdap lookup for {} failed: {}", lookalike.variant.name, e),
+            }
+        }
+    }
+    let rendered = serde_json::to_string_pretty(&results)?;
+    if let Some(path) = &args.output {
+        std::fs::write(path, rendered)?;
+    }
+    Ok(if report.lookalikes.is_empty() { Exit::NoFindings } else { Exit::Findings })
+}
+
+fn parse_exempt(s: &str) -> Result<SocketAddr, String> {
+    subscan::scanner::parse_resolver(s).ok_or_else(|| format!("Unknown resolver address: {}", s))
+}
+
+fn parse_record_type(s: &str) -> Result<RecordType, String> {
+    RecordType::from_str(&s.trim().to_ascii_uppercase()).map_err(|_| format!("Unknown record type: {}", s))
+}
+
+/// The address family the policy keeps for `--record-types`: whichever of
+/// A and AAAA were asked, or `ip_version` when neither was.
+fn asked_family(record_types: &[RecordType], ip_version: IpVersion) -> IpVersion {
+    match (record_types.contains(&RecordType::A), record_types.contains(&RecordType::AAAA)) {
+        (true, true) => IpVersion::Both,
+        (true, false) => IpVersion::V4,
+        (false, true) => IpVersion::V6,
+        (false, false) => ip_version,
+    }
+}
+
+fn exit_with_problems(problems: &[String]) -> ! {
+    for problem in problems {
+        eprintln!("error: {}", problem);
+    }
+    std::process::exit(Exit::InputError.code());
+}
+
+async fn build_scanner(
+    args: &ScanArgs,
+    target: &TargetConfig,
+    client: &Arc<ApiClient>,
+    keys: &ApiKeys,
+    query_log: &QueryLog,
+    negative_log: &NegativeLog,
+    timings: &mut PhaseTimings,
+) -> Result<SubdomainScanner, Box<dyn std::error::Error>> {
+    let tuning = args.socket_buffer.map(SocketTuning::with_buffers).unwrap_or_default();
+
+    let domain = target.domain.as_str();
+    let mut scanner = SubdomainScanner::new(
+        &target.resolvers,
+       &target.wordlist,
+        domain,
+        2,
+    target.thread,
+    ).await?
+    .with_socket_tuning(tuning)
+    .with_auto_tune(args.auto_tune)
+    .with_verification(args.verify, args.verify_thread)
+    .with_engine(args.engine)
+    .with_socket_count(args.sockets as usize)
+    .with_adaptive_timeout(args.adaptive_timeout.then_some(args.timeout_factor))
+    .with_underscores(args.underscores)
+    .with_netbios(args.netbios)
+    .with_search_domains(args.search_domains.as_deref().map(SearchList::load).transpose()?)
+    .with_drop_random_looking(args.drop_random_looking)
+    .with_answer_policy(AnswerPolicy { ip_version: args.ip_version, allow_private: args.allow_private })
+    .with_unbound_control(args.unbound_control.clone().map(|control| control.with_queue_limit(args.unbound_queue_limit)))
+    .with_health_policy(HealthPolicy {
+        evict_after: args.evict_after,
+        cooldown: Duration::from_secs(args.evict_cooldown),
+        grace: Duration::from_secs(args.exhausted_grace),
+    })
+    .with_max_queries(target.max_queries)
+    .with_retries(args.retries)
+    .with_skip_after_nxdomain(!args.no_nxdomain_skip)
+    .with_show(args.show)
+    .with_query_log(query_log.clone())
+    .with_include_negative(args.include_negative)
+    .with_negative_log(negative_log.clone())
+    .with_query_flags(QueryFlags {
+        recursion_desired: !args.no_recursion,
+        edns_options: args.edns_option.clone(),
+        ..QueryFlags::default()
+    });
+
+    if let Some(quick) = &args.quick_wordlist {
+        scanner = scanner.with_quick_wordlist(quick)?;
+    }
+    let mut target_tokens = args.tokens.clone();
+    if args.site_tokens {
+        match tokens::from_site(client, domain).await {
+            Ok(found) => {
+                info!("{}: tokens from its website: {}", domain, found.join(", "));
+                for token in found {
+                    if !target_tokens.contains(&token) {
+                        target_tokens.push(token);
+                    }
+                }
+            }
+            Err(e) => warn!("{}: no tokens from its website: {}", domain, e),
+        }
+    }
+    scanner = scanner.with_tokens(target_tokens);
+    if let Some(after) = args.spill_after {
+        scanner = scanner.with_spill(after, spill_path(args, domain));
+    }
+    if !args.pin.is_empty() {
+        scanner = scanner.with_resolver_pins(&args.pin)?;
+    }
+    if !args.exempt_resolvers.is_empty() {
+        scanner = scanner.with_exempt_resolvers(args.exempt_resolvers.clone());
+    }
+
+    if !target.sources.is_empty() && !args.no_sources {
+        let timer = timings.start("passive_collection", Some(domain));
+        let mut selected = Vec::new();
+        for name in &target.sources {
+            selected.push(sources::by_name(name, keys)?);
+        }
+        let time_limit = args
+            .sources_timeout
+            .or(args.passive_only.then_some(60))
+            .map(Duration::from_secs);
+        let names = sources::collect(client.clone(), selected, domain, time_limit).await;
+        info!("passive sources reported {} names for {}", names.len(), domain);
+        timings.record(timer, target.sources.len() as u64, names.len() as u64);
+        scanner = scanner.with_passive_names(names);
+    }
+    for pack in &args.pack {
+        scanner = scanner.with_passive_names(pack.candidates(domain));
+    }
+
+    if args.shuffle {
+        scanner = scanner.with_shuffle(args.seed);
+        info!("shuffled candidates with seed {}", scanner.seed().unwrap_or_default());
+    }
+
+    let estimate = scanner.estimate();
+    info!("{}: scan will send {}", domain, estimate);
+    if let Some(limit) = target.max_queries.filter(|limit| *limit < estimate.total()) {
+        info!("--max-queries {} will stop the scan before it covers every candidate", limit);
+    }
+    Ok(scanner)
+}
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> ExitCode {
     let args = ArgumentCli::parse();
+    tracing_subscriber::fmt().with_writer(std::io::stderr).init();
+    let outcome = match &args.command {
+        Some(Command::Replay(replay_args)) => run_replay(replay_args).await,
+        Some(Command::Monitor(monitor_args)) => run_monitor(monitor_args).await,
+        Some(Command::Query(query_args)) => run_query(query_args),
+        Some(Command::Local(local_args)) => run_local(local_args).await,
+        Some(Command::Resolve(resolve_args)) => run_resolve(resolve_args).await,
+        Some(Command::Typosquat(typosquat_args)) => run_typosquat(typosquat_args).await,
+        Some(Command::CheckConfig(scan_args)) => run_check_config(scan_args),
+        Some(Command::Resume(resume_args)) => run_resume(resume_args).await,
+        Some(Command::Selftest(selftest_args)) => run_selftest(selftest_args).await,
+        None => run_scan(&args.scan, None).await,
+    };
+    match outcome {
+        Ok(exit) => exit.into(),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            Exit::InputError.into()
+        }
+    }
+}
 
-    let scanner = SubdomainScanner::new(
-        &args.resolvers,
-       &args.wordlist,
-        &args.domain,
-        2,
-    args.thread,
-    ).await?;
+fn run_query(args: &QueryArgs) -> Result<Exit, Box<dyn std::error::Error>> {
+    let read = |path: &str| {
+        std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| serde_json::from_str::<Value>(&s).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| exit_with_problems(&[format!("could not read results '{}': {}", path, e)]))
+    };
+    // With a project every positional word is part of the question.
+    let (document, question) = match &args.project {
+        Some(name) => {
+            let project = Project::open(name).unwrap_or_else(|e| exit_with_problems(&[e]));
+            let scans: Vec<Value> = project.scan_results().iter().map(|path| read(&path.display().to_string())).collect();
+            (json!({ "scans": scans }), args.file.iter().chain(&args.question).cloned().collect::<Vec<_>>())
+        }
+        None => (read(args.file.as_deref().unwrap_or_default()), args.question.clone()),
+    };
+    let index = FindingsIndex::new(&document);
+
+    if !question.is_empty() {
+        let lines = index.ask(&question.join(" "))?;
+        for line in &lines {
+            println!("{}", line);
+        }
+        return Ok(if lines.is_empty() { Exit::NoFindings } else { Exit::Findings });
+    }
+
+    let interactive = std::io::stdin().is_terminal();
+    if interactive {
+        eprintln!("{}", findings::HELP);
+    }
+    let mut stdout = std::io::stdout().lock();
+    let mut line = String::new();
+    loop {
+        if interactive {
+            eprint!("query> ");
+        }
+        line.clear();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            break;
+        }
+        match line.trim() {
+            "quit" | "exit" => break,
+            "" => continue,
+            question => match index.ask(question) {
+                Ok(answer) => {
+                    for answer in answer {
+                        writeln!(stdout, "{}", answer)?;
+                    }
+                }
+                Err(e) => eprintln!("{}", e),
+            },
+        }
+        stdout.flush()?;
+    }
+    Ok(Exit::Findings)
+}
+
+/// Why `--sockets` cannot be bound by the raw engine, checked before any
+/// socket is set up. Hickory opens its own sockets and ignores the count.
+fn socket_problem(engine: EngineKind, sockets: u16) -> Option<String> {
+    match engine {
+        EngineKind::Raw => net::check_socket_count(sockets as usize).err(),
+        EngineKind::Hickory => {
+            if sockets > 1 {
+                warn!("--sockets only applies to --engine raw, ignoring it");
+            }
+            None
+        }
+    }
+}
+
+const RAW_WITHOUT_DOQ: &str = "quic:// resolvers need --engine hickory; the raw engine only sends plain UDP";
+
+/// Targets from the flags and targets file, normalized, with their input
+/// files checked. Returns the usable targets, how many resolvers their
+/// files list and every problem found.
+fn check_targets(args: &ScanArgs, suffixes: &SuffixList) -> (Vec<TargetConfig>, usize, Vec<String>) {
+    let mut problems = Vec::new();
+    let defaults = TargetConfig {
+        domain: String::new(),
+        wordlist: if args.passive_only { String::new() } else { args.wordlist.clone() },
+        resolvers: args.resolvers.clone(),
+        thread: args.thread,
+        max_queries: args.max_queries,
+        sources: args.sources.clone(),
+    };
+    let mut requested: Vec<TargetConfig> = args
+        .domain
+        .iter()
+        .filter(|d| !d.trim().is_empty())
+        .map(|d| defaults.with_domain(d))
+        .collect();
+    if let Some(path) = &args.targets {
+        match targets::load(path, &defaults) {
+            Ok(loaded) => requested.extend(loaded),
+            Err(e) => problems.extend(e),
+        }
+    }
+    if requested.is_empty() && problems.is_empty() {
+        problems.extend(validate::check_domain("").err());
+    }
+
+    let mut targets: Vec<TargetConfig> = Vec::new();
+    for mut target in requested {
+        match domain::normalize_target(&target.domain, args.subdomain_scope, suffixes) {
+            Ok(normalized) => match validate::check_domain(&normalized) {
+                Ok(()) if targets.iter().any(|t| t.domain == normalized) => warn!("skipping duplicate target {}", normalized),
+                Ok(()) => {
+                    target.domain = normalized;
+                    if args.passive_only {
+                        target.wordlist.clear();
+                    }
+                    targets.push(target);
+                }
+                Err(e) => problems.push(e),
+            },
+            Err(e) => problems.push(e),
+        }
+    }
+
+    problems.extend(socket_problem(args.engine, args.sockets));
 
-    let results = scanner.scan().await;
-    let json = serde_json::to_string_pretty(&results)?;
+    let mut valid_resolvers = 0;
+    let mut checked: Vec<(&str, &str)> = Vec::new();
+    for target in &targets {
+        if !checked.contains(&("wordlist", &target.wordlist)) && !args.passive_only {
+            checked.push(("wordlist", &target.wordlist));
+            problems.extend(validate::check_wordlist(&target.wordlist).err());
+        }
+        if let Some(quick) = &args.quick_wordlist
+            && !checked.contains(&("quick_wordlist", quick))
+        {
+            checked.push(("quick_wordlist", quick));
+            problems.extend(validate::check_wordlist(quick).err());
+        }
+        if !checked.contains(&("resolvers", &target.resolvers)) {
+            checked.push(("resolvers", &target.resolvers));
+            match validate::check_resolvers(&target.resolvers) {
+                Ok(valid) => valid_resolvers += valid,
+                Err(e) => problems.push(e),
+            }
+        }
+    }
+    if args.engine == EngineKind::Raw && doq::registered() > 0 {
+        problems.push(RAW_WITHOUT_DOQ.to_string());
+    }
+    if let Some(command) = &args.exec {
+        problems.extend(ExecCommand::parse(command).err());
+    }
+    if let Some(path) = &args.search_domains {
+        problems.extend(SearchList::load(path).err());
+    }
+    let capped = args.max_pps.is_some() || args.max_bandwidth.is_some() || args.max_pps_per_resolver.is_some() || args.max_pps_per_zone.is_some();
+    if args.warm_up.is_some() && !capped {
+        problems.push("--warm-up needs a rate to ramp up to: --max-pps, --max-bandwidth, --max-pps-per-resolver or --max-pps-per-zone".to_string());
+    }
+    if args.state.is_some() && args.shuffle && args.seed.is_none() {
+        problems.push("--state with --shuffle needs --seed, so a resumed scan goes through the wordlist in the same order".to_string());
+    }
+    (targets, valid_resolvers, problems)
+}
 
-    if args.output != "" {
-        let mut file = File::create(&args.output)?;
-        file.write_all(json.as_bytes())?;
+/// Copies of `results` with only the names `filter` keeps.
+fn filtered(results: &[Value], filter: &Filter) -> Vec<Value> {
+    results
+        .iter()
+        .cloned()
+        .map(|mut result| {
+            filter.retain(&mut result);
+            result
+        })
+        .collect()
+}
+
+/// The output document: a single target's result as it is, several
+/// grouped by the registrable domain they belong to.
+fn results_document(results: &[Value], suffixes: &SuffixList, domains: &[String]) -> Value {
+    if results.len() == 1 {
+        return results[0].clone();
+    }
+    let mut grouped = serde_json::Map::new();
+    for (registrable, members) in suffixes.group_by_registrable(domains.iter().map(String::as_str)) {
+        let scans: Vec<Value> = results
+            .iter()
+            .filter(|r| r["target"].as_str().is_some_and(|t| members.iter().any(|m| m == t)))
+            .cloned()
+            .collect();
+        grouped.insert(registrable, scans.into());
+    }
+    json!({ "registrable_domains": grouped })
+}
+
+/// `results` in the output format, or the template when there is one.
+fn render_results(args: &ScanArgs, results: &[Value], suffixes: &SuffixList, domains: &[String]) -> Result<String, serde_json::Error> {
+    let rendered = match &args.output_template {
+        Some(template) => Some(template.render(results)),
+        None => output::render(args.output_format, results),
+    };
+    match rendered {
+        Some(rendered) => Ok(rendered),
+        None => serde_json::to_string_pretty(&results_document(results, suffixes, domains)),
+    }
+}
+
+/// A manifest of one target's scan alone, for `--output-dir`.
+fn target_manifest(args: &ScanArgs, target: &TargetConfig, scanner: &SubdomainScanner) -> std::io::Result<ScanManifest> {
+    let mut manifest = ScanManifest::new(scanner);
+    if !target.wordlist.is_empty() {
+        manifest = manifest.with_input("wordlist", &target.wordlist)?;
+    }
+    manifest = manifest.with_input("resolvers", &target.resolvers)?;
+    if let Some(quick) = &args.quick_wordlist {
+        manifest = manifest.with_input("quick_wordlist", quick)?;
+    }
+    for pin in &args.pin {
+        manifest = manifest.with_input("pinned_resolvers", &pin.resolvers)?;
+    }
+    Ok(manifest)
+}
+
+/// `results.jsonl`, `report.html` and `manifest.json` for one target. Names
+/// past `--spill-after` are copied over from the spill file as they are.
+fn write_target_dir(dir: &Path, result: &Value, manifest: Option<&mut ScanManifest>) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let mut lines = std::io::BufWriter::new(File::create(dir.join("results.jsonl"))?);
+    for line in output::name_lines(result) {
+        writeln!(lines, "{}", line)?;
+    }
+    if let Some(spilled) = result["results"]["spilled"]["path"].as_str() {
+        std::io::copy(&mut File::open(spilled)?, &mut lines)?;
+    }
+    lines.flush()?;
+    std::fs::write(dir.join("report.html"), output::render_html(result))?;
+    if let Some(manifest) = manifest {
+        manifest.finish();
+        std::fs::write(dir.join("manifest.json"), serde_json::to_string_pretty(manifest)?)?;
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+fn spill_path(args: &ScanArgs, domain: &str) -> PathBuf {
+    Path::new(&args.spill_dir).join(format!("{}-found.jsonl", domain))
+}
+
+/// What running `targets` side by side asks of the host.
+fn scan_demand(args: &ScanArgs, targets: &[TargetConfig]) -> limits::Demand {
+    // Every wordlist line takes at least two bytes; passive sources add
+    // names no file size bounds.
+    let wordlist_bound = targets.iter().all(|t| (args.no_sources || t.sources.is_empty()) && !t.wordlist.is_empty());
+    let bytes: u64 = targets.iter().filter_map(|t| std::fs::metadata(&t.wordlist).ok()).map(|m| m.len()).sum();
+    limits::Demand {
+        concurrency: targets.iter().map(|t| t.thread).max().unwrap_or(args.thread),
+        engine: args.engine,
+        sockets: args.sockets as usize,
+        max_pps: args.max_pps,
+        queries: wordlist_bound.then_some(bytes / 2 * args.ip_version.record_types() * (1 + args.retries as u64)),
+        socket_buffer: args.socket_buffer,
+    }
+}
+
+/// How `--append`, `--merge` and `--overwrite` treat a scan already in
+/// the output file.
+fn existing_mode(args: &ScanArgs, resumed: bool) -> Option<ExistingOutput> {
+    if args.append {
+        Some(ExistingOutput::Append)
+    } else if args.merge {
+        Some(ExistingOutput::Merge)
+    } else if args.overwrite || resumed {
+        // A resumed scan's findings include what the paused one wrote.
+        Some(ExistingOutput::Overwrite)
+    } else {
+        None
+    }
+}
+
+/// The fingerprint of a scan of `targets`, and the scan already in
+/// `output_path` that `mode` keeps, see [`output::existing_document`].
+fn existing_scan(targets: &[TargetConfig], output_path: &str, mode: Option<ExistingOutput>) -> (Value, Result<Option<Value>, String>) {
+    let fingerprint_parts: Vec<(String, Option<String>)> = targets.iter().map(|t| (t.domain.clone(), wordlist_sha256(t))).collect();
+    let fingerprint = output::fingerprint(&fingerprint_parts);
+    let previous = output::existing_document(output_path, &fingerprint, mode);
+    (fingerprint, previous)
+}
+
+/// The sha256 of `target`'s wordlist, if it has one that can be read.
+fn wordlist_sha256(target: &TargetConfig) -> Option<String> {
+    (!target.wordlist.is_empty())
+        .then(|| InputDigest::of_file("wordlist", &target.wordlist).ok())
+        .flatten()
+        .map(|digest| digest.sha256)
+}
+
+/// `check-config`: everything a scan with these flags would refuse to start
+/// over, or fail on partway through, reported together without sending a
+/// query.
+fn run_check_config(args: &ScanArgs) -> Result<Exit, Box<dyn std::error::Error>> {
+    let mut problems = Vec::new();
+    let mut warnings = Vec::new();
+
+    let project = args.project.as_deref().map(Project::open).transpose().unwrap_or_else(|e| {
+        problems.push(e);
+        None
+    });
+    let history_path = args
+        .history
+        .clone()
+        .or_else(|| project.as_ref().map(Project::word_history))
+        .or_else(|| args.prioritize_by_history.then(WordHistory::default_path).flatten());
+    if let Some(path) = &history_path
+        && let Err(e) = WordHistory::load(path)
+    {
+        problems.push(format!("could not read word history {}: {}, fix or delete it to start a new one", path.display(), e));
+    }
+    if let Some(path) = args.reputation.clone().or_else(|| project.as_ref().map(Project::resolver_reputation))
+        && let Err(e) = ReputationDb::load(&path)
+    {
+        problems.push(format!("could not read resolver reputation {}: {}, fix or delete it to start a new one", path.display(), e));
+    }
+    let suffixes = match &args.psl {
+        Some(path) => SuffixList::load(path).unwrap_or_else(|e| {
+            problems.push(e);
+            SuffixList::Embedded
+        }),
+        None => SuffixList::Embedded,
+    };
+
+    let (targets, valid_resolvers, target_problems) = check_targets(args, &suffixes);
+    problems.extend(target_problems);
+    for pin in &args.pin {
+        if let Err(e) = validate::check_resolvers(&pin.resolvers) {
+            problems.push(format!("--pin {}: {}", pin.pattern, e));
+        }
+    }
+
+    let keys = ApiKeys::from_env();
+    let mut checked: Vec<&str> = Vec::new();
+    for name in targets.iter().flat_map(|t| &t.sources) {
+        if checked.contains(&name.as_str()) {
+            continue;
+        }
+        checked.push(name);
+        if let Err(e) = sources::by_name(name, &keys) {
+            problems.push(match validate::closest(name, sources::AVAILABLE).filter(|_| !sources::AVAILABLE.contains(&name.as_str())) {
+                Some(meant) => format!("{}, did you mean {}?", e, meant),
+                None => e,
+            });
+        }
+    }
+    if args.no_sources && !checked.is_empty() {
+        warnings.push("--no-sources is set, so the sources named in the targets file are never queried".to_string());
+    }
+    if args.pdns_url.is_some() && keys.pdns_key.is_none() && keys.pdns_basic_auth.is_none() {
+        warnings.push("--pdns-url is set but neither PDNS_API_KEY nor PDNS_BASIC_AUTH is, and most passive DNS services refuse anonymous queries".to_string());
+    }
+
+    let outputs = [
+        ("output", "--output", (!args.output.is_empty()).then_some(&args.output)),
+        ("negative output", "--negative-output", args.negative_output.as_ref()),
+        ("dnstap file", "--dnstap-file", args.dnstap_file.as_ref()),
+        ("sanitization report", "--sanitization-report", args.sanitization_report.as_ref()),
+    ];
+    for (what, flag, path) in outputs {
+        if let Some(path) = path {
+            problems.extend(validate::check_output(what, flag, path).err());
+        }
+    }
+    if args.spill_after.is_some()
+        && let Some(target) = targets.first()
+    {
+        problems.extend(validate::check_output("spill file", "--spill-dir", &spill_path(args, &target.domain).to_string_lossy()).err());
+    }
+    let json_output = args.output_format == OutputFormat::Json && args.output_template.is_none();
+    if !args.output.is_empty() {
+        problems.extend(existing_scan(&targets, &args.output, existing_mode(args, false)).1.err());
+    }
+    if (args.append || args.merge) && !json_output {
+        problems.push("--append and --merge need --output-format json".to_string());
+    }
+    if args.show == ShowMode::None && args.output.is_empty() && args.output_dir.is_none() && project.is_none() && json_output {
+        warnings.push("--show none without --output discards all results".to_string());
+    }
+
+    if args.screenshots.is_some() && validate::find_program(&args.chromium).is_none() {
+        problems.push(format!("browser '{}' not found, install Chromium or point --chromium at a Chrome binary", args.chromium));
+    }
+    if let Some(control) = &args.unbound_control
+        && validate::find_program(&control.command[0]).is_none()
+    {
+        problems.push(format!("'{}' from --unbound-control not found, give its full path", control.command[0]));
+    }
+
+    if !targets.is_empty() {
+        let demand = scan_demand(args, &targets);
+        match limits::plan(&demand, &limits::HostLimits::detect(&demand)) {
+            Ok(plan) => warnings.extend(plan.warnings),
+            Err(e) => problems.push(e),
+        }
+    }
+
+    for warning in &warnings {
+        eprintln!("warning: {}", warning);
+    }
+    for problem in &problems {
+        eprintln!("error: {}", problem);
+    }
+    if !problems.is_empty() {
+        eprintln!("{} problem(s) found", problems.len());
+        return Ok(Exit::InputError);
+    }
+    println!("config ok: {} target(s), {} resolver(s)", targets.len(), valid_resolvers);
+    Ok(Exit::Findings)
+}
+
+/// Installs --inject-faults for the rest of the run.
+#[cfg(feature = "fault-injection")]
+fn inject_faults(plan: &Option<FaultPlan>) {
+    if let Some(plan) = plan {
+        warn!("injecting faults into every query ({:?}); the results say nothing about the target", plan);
+        faults::set_plan(plan.clone());
+    }
+}
+
+/// Runs the scan a state file was saved from, with its flags, from where
+/// it was paused.
+async fn run_resume(args: &ResumeArgs) -> Result<Exit, Box<dyn std::error::Error>> {
+    let state = ScanState::load(&args.state).unwrap_or_else(|e| exit_with_problems(&[format!("could not read scan state {}: {}", args.state.display(), e)]));
+    let mut cli = match ArgumentCli::try_parse_from(&state.command_line) {
+        Ok(cli) if cli.command.is_none() => cli,
+        Ok(_) => exit_with_problems(&[format!("{} was not saved by a scan", args.state.display())]),
+        Err(e) => exit_with_problems(&[format!("the paused scan's flags are no longer valid: {}", e)]),
+    };
+    // Pausing again saves to the file resumed from, wherever it was moved.
+    cli.scan.state = Some(args.state.clone());
+    info!("resuming the scan paused at {}, {} targets under way", state.paused_at, state.targets.len());
+    run_scan(&cli.scan, Some(state)).await
+}
+
+async fn run_scan(args: &ScanArgs, resume: Option<ScanState>) -> Result<Exit, Box<dyn std::error::Error>> {
+    let started = Instant::now();
+
+    let json_output = args.output_format == OutputFormat::Json && args.output_template.is_none();
+    let project = args.project.as_deref().map(Project::open).transpose().unwrap_or_else(|e| exit_with_problems(&[e]));
+    // An explicit --output wins over the project's scan directory, and
+    // non-json formats still go to stdout.
+    let scan_dir = project
+        .as_ref()
+        .filter(|_| args.output.is_empty() && json_output)
+        .map(|project| project.next_scan_dir(chrono::Utc::now()));
+    let output_path = match &scan_dir {
+        Some(dir) => dir.join(project::RESULTS_FILE).display().to_string(),
+        None => args.output.clone(),
+    };
+    let previous_scan = scan_dir.as_ref().and(project.as_ref()).and_then(Project::latest_results);
+
+    let history_path = args
+        .history
+        .clone()
+        .or_else(|| project.as_ref().map(Project::word_history))
+        .or_else(|| args.prioritize_by_history.then(WordHistory::default_path).flatten());
+    let history = match &history_path {
+        Some(path) => match WordHistory::load(path) {
+            Ok(history) => Some(Arc::new(Mutex::new(history))),
+            Err(e) => exit_with_problems(&[format!("could not read word history {}: {}", path.display(), e)]),
+        },
+        None => None,
+    };
+    let reputation_path = args.reputation.clone().or_else(|| project.as_ref().map(Project::resolver_reputation));
+    let mut reputation = match &reputation_path {
+        Some(path) => match ReputationDb::load(path) {
+            Ok(reputation) => Some(reputation),
+            Err(e) => exit_with_problems(&[format!("could not read resolver reputation {}: {}", path.display(), e)]),
+        },
+        None => None,
+    };
+
+    let suffixes = match &args.psl {
+        Some(path) => SuffixList::load(path).unwrap_or_else(|e| exit_with_problems(&[e])),
+        None => SuffixList::Embedded,
+    };
+
+    let mut timings = PhaseTimings::default();
+    let timer = timings.start("resolver_validation", None);
+    let (mut targets, valid_resolvers, problems) = check_targets(args, &suffixes);
+    timings.record(timer, 0, valid_resolvers as u64);
+    if !problems.is_empty() {
+        exit_with_problems(&problems);
+    }
+    let domains: Vec<String> = targets.iter().map(|t| t.domain.clone()).collect();
+    // A resumed scan always has --state, see run_resume.
+    let wordlists: BTreeMap<String, Option<String>> = match &args.state {
+        Some(_) => targets.iter().map(|t| (t.domain.clone(), wordlist_sha256(t))).collect(),
+        None => BTreeMap::new(),
+    };
+    if let Some(state) = &resume {
+        let problems = state.changed_wordlists(&wordlists);
+        if !problems.is_empty() {
+            exit_with_problems(&problems);
+        }
+    }
+
+    let existing_mode = existing_mode(args, resume.is_some());
+    let (fingerprint, previous) = existing_scan(&targets, &output_path, existing_mode);
+    let previous = previous.unwrap_or_else(|problem| exit_with_problems(&[problem]));
+    if matches!(existing_mode, Some(ExistingOutput::Append | ExistingOutput::Merge)) && !json_output {
+        exit_with_problems(&["--append and --merge need --output-format json".to_string()]);
+    }
+
+    let thread = limits::prepare(&scan_demand(args, &targets)).unwrap_or_else(|e| exit_with_problems(&[e]));
+    for target in &mut targets {
+        target.thread = target.thread.min(thread);
+    }
+
+    if args.show == ShowMode::None && output_path.is_empty() && args.output_dir.is_none() && json_output {
+        warn!("--show none without --output discards all results");
+    }
+
+    let keys = ApiKeys::from_env();
+    let client = Arc::new(
+        ApiClient::new(Duration::from_secs(30))?
+            .with_cache(match &project {
+                Some(project) => Some(ResponseCache::in_dir(project.http_cache_dir())),
+                None => ResponseCache::default_location(),
+            })
+            .with_offline(args.offline),
+    );
+
+    let limit = EgressLimit::with_kind(args.rate_limiter, args.max_pps, args.max_bandwidth)
+        .with_per_resolver(args.max_pps_per_resolver)
+        .with_per_zone(args.max_pps_per_zone)
+        .with_ramp(Ramp {
+            warm_up: Duration::from_secs(args.warm_up.unwrap_or(0)),
+            cool_down: Duration::from_secs(args.cool_down.unwrap_or(0)),
+        });
+    if !limit.is_empty() {
+        egress::set_limit(limit);
+    }
+    #[cfg(feature = "fault-injection")]
+    inject_faults(&args.inject_faults);
+    let (query_log, query_log_task) = match &args.dnstap_file {
+        Some(path) => {
+            let (log, task) = QueryLog::create(Path::new(path))?;
+            (log, Some(task))
+        }
+        None => (QueryLog::default(), None),
+    };
+    let (negative_log, negative_log_task) = match &args.negative_output {
+        Some(path) => {
+            let (log, task) = NegativeLog::create(Path::new(path))?;
+            (log, Some(task))
+        }
+        None => (NegativeLog::default(), None),
+    };
+
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let flag = interrupt.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            warn!("interrupted, waiting for queries in flight and writing partial results");
+            flag.store(true, Ordering::Relaxed);
+        }
+    });
+
+    // Several targets run side by side through one scheduler instead of
+    // one after another, sharing the --thread limit and the resolver pool.
+    let (scheduler, printer_task) = if targets.len() > 1 {
+        let (printer, task) = Printer::spawn(args.show);
+        (Some(Scheduler::new(args.thread.min(thread) as usize, printer)), Some(task))
+    } else {
+        (None, None)
+    };
+
+    let exec_hook = args
+        .exec
+        .as_deref()
+        .map(|command| ExecHook::start(ExecCommand::parse(command).unwrap_or_else(|e| exit_with_problems(&[e])), args.exec_concurrency));
+    let mut manifest = None;
+    let mut scanners = Vec::new();
+    let mut target_manifests = Vec::new();
+    for target in &targets {
+        if interrupt.load(Ordering::Relaxed) {
+            warn!("skipping {} after interrupt", target.domain);
+            continue;
+        }
+        let mut scanner = build_scanner(args, target, &client, &keys, &query_log, &negative_log, &mut timings)
+            .await?
+            .with_interrupt(interrupt.clone());
+        // After build_scanner's shuffle, which would undo the order.
+        if let Some(history) = &history {
+            scanner = scanner.with_history(history.clone(), args.prioritize_by_history);
+            if args.prioritize_by_history {
+                info!("{}: trying {} words that hit in past scans first", target.domain, scanner.prioritized_by_history());
+            }
+        }
+        if let Some(scheduler) = &scheduler {
+            scanner = scanner.with_scheduler(scheduler.clone());
+        }
+        let paused = resume.as_ref().and_then(|state| Some((state, state.targets.get(&target.domain)?)));
+        if args.state.is_some() {
+            scanner = scanner.with_resume(paused.map(|(_, paused)| paused.resume.clone()).unwrap_or_default());
+        }
+        // Without a reputation database the paused scan's own resolver
+        // stats steer the rotation; with one, they went into it on pausing.
+        let paused_reputation = paused.filter(|_| reputation.is_none()).map(|(state, paused)| {
+            let mut reputation = ReputationDb::default();
+            reputation.record(&paused.resolver_usage, &state.paused_at);
+            reputation
+        });
+        if let Some(reputation) = reputation.as_ref().or(paused_reputation.as_ref()) {
+            let weights = reputation.weights(scanner.resolvers());
+            let demoted = weights.iter().filter(|weight| **weight < reputation::MAX_WEIGHT / 2).count();
+            if demoted > 0 {
+                info!("{}: {} resolvers unreliable in past scans get fewer queries", target.domain, demoted);
+            }
+            scanner = scanner.with_resolver_weights(weights);
+        }
+        if let Some(hook) = &exec_hook {
+            scanner = scanner.with_found_sender(hook.sender());
+        }
+        if manifest.is_none() {
+            let mut m = ScanManifest::new(&scanner).with_targets(&domains);
+            let mut recorded: Vec<&str> = Vec::new();
+            for t in &targets {
+                if !t.wordlist.is_empty() && !recorded.contains(&t.wordlist.as_str()) {
+                    recorded.push(&t.wordlist);
+                    m = m.with_input("wordlist", &t.wordlist)?;
+                }
+                if !recorded.contains(&t.resolvers.as_str()) {
+                    recorded.push(&t.resolvers);
+                    m = m.with_input("resolvers", &t.resolvers)?;
+                }
+            }
+            if let Some(quick) = &args.quick_wordlist {
+                m = m.with_input("quick_wordlist", quick)?;
+            }
+            for pin in &args.pin {
+                m = m.with_input("pinned_resolvers", &pin.resolvers)?;
+            }
+            manifest = Some(m);
+        }
+        if args.output_dir.is_some() {
+            target_manifests.push(target_manifest(args, target, &scanner)?);
+        }
+        scanners.push(scanner);
+    }
+
+    let phase = if args.passive_only { "passive_verification" } else { "brute_force" };
+    let scans = scanners.iter().map(|scanner| {
+        let timer = timings.start(phase, Some(scanner.domain()));
+        async move { (timer, scanner.scan().await) }
+    });
+    let scanned = futures_util::future::join_all(scans).await;
+    let scan_resolvers: Vec<(Vec<SocketAddr>, Traffic)> = scanners.iter().map(|scanner| (scanner.resolvers().to_vec(), scanner.traffic().clone())).collect();
+    drop(scheduler);
+    drop(scanners);
+    if let Some(task) = printer_task {
+        task.finish().await;
+    }
+    if let Some(hook) = exec_hook {
+        let report = hook.finish().await;
+        info!("--exec ran {} commands, {} failed", report.ran, report.failed);
+    }
+
+    let mut all_results = Vec::new();
+    let mut paused_targets = BTreeMap::new();
+    for ((timer, scan), (resolvers, traffic)) in scanned.into_iter().zip(scan_resolvers) {
+        let resolver = resolvers[0];
+        let domain = scan.target.clone();
+        let found = scan.results.subdomain.clone();
+        let spilled = scan.results.spilled.as_ref().map_or(0, |spilled| spilled.names);
+        timings.record(timer, scan.results.queries_sent, found.len() as u64 + spilled);
+        if let Some(manifest) = &mut manifest {
+            manifest.record_phases(&domain, &scan.results.phases);
+        }
+        if let Some(manifest) = target_manifests.iter_mut().find(|manifest| manifest.targets == [domain.as_str()]) {
+            manifest.record_phases(&domain, &scan.results.phases);
+        }
+        let addresses = if args.ptr || !args.pack.is_empty() || args.rdap || args.favicon || args.screenshots.is_some() { scan.results.records.clone() } else { Default::default() };
+        if let Some(reputation) = &mut reputation {
+            reputation.record(&scan.results.resolver_usage, &scan.started_at);
+        }
+        let (resume_point, resolver_usage) = (scan.results.resume_point.clone(), scan.results.resolver_usage.clone());
+        let mut results = serde_json::to_value(scan)?;
+        if let Some(paused) = resume.as_ref().and_then(|state| state.targets.get(&domain)) {
+            results = checkpoint::merge(results, &paused.result);
+        }
+        if args.state.is_some() {
+            let wordlist_sha256 = wordlists.get(&domain).cloned().flatten();
+            paused_targets.insert(domain.clone(), TargetState { resume: resume_point, resolver_usage, result: results.clone(), wordlist_sha256 });
+        }
+        results["registrable_domain"] = suffixes.registrable_domain(&domain).map(Value::from).unwrap_or_default();
+
+        if let Some(count) = args.consistency {
+            let timer = timings.start("consistency_check", Some(&domain));
+            let picked = consistency::pick(&resolvers, count as usize);
+            if picked.len() < count as usize {
+                warn!("{}: --consistency {} but only {} resolvers to ask", domain, count, picked.len());
+            }
+            let report = consistency::check_all(&found, picked, Duration::from_secs(2), args.ip_version, &traffic).await;
+            timings.record(timer, report.queries, report.names.len() as u64);
+            let inconsistent: Vec<&str> = report.inconsistent().collect();
+            if !inconsistent.is_empty() {
+                warn!("{}: resolvers disagree about {} names: {}", domain, inconsistent.len(), inconsistent.join(", "));
+            }
+            if report.skipped > 0 {
+                warn!("{}: query budget spent, {} consistency queries not sent", domain, report.skipped);
+            }
+            results["results"]["consistency"] = serde_json::to_value(report)?;
+        }
+        if args.ptr {
+            let timer = timings.start("reverse_lookup", Some(&domain));
+            let unique = addresses.values().flat_map(|resolution| resolution.addresses.iter().copied()).collect();
+            let report = ptr::lookup_all(unique, &resolvers, Duration::from_secs(2), &traffic).await;
+            timings.record(timer, report.queries, report.resolved);
+            for (name, resolution) in &addresses {
+                let names = report.for_addresses(&resolution.addresses);
+                if !names.is_empty() {
+                    results["results"]["records"][name]["ptr"] = serde_json::to_value(names)?;
+                }
+            }
+            if report.failed > 0 {
+                warn!("{}: reverse lookups of {} of {} addresses failed", domain, report.failed, report.addresses);
+            }
+            if report.skipped > 0 {
+                warn!("{}: query budget spent, {} of {} addresses not looked up in reverse", domain, report.skipped, report.addresses);
+            }
+            results["results"]["reverse_lookup"] = serde_json::to_value(report)?;
+        }
+        let mut rules = if args.no_escalation { Vec::new() } else { escalation::RULES.to_vec() };
+        let mut asked = found.clone();
+        for pack in &args.pack {
+            rules.extend_from_slice(pack.rules);
+            // Candidates with only SRV or TXT records are never found.
+            asked.extend(pack.candidates(&domain).into_iter().map(|name| name.name).filter(|name| !found.contains(name)));
+        }
+        let mut escalated = BTreeMap::new();
+        if !rules.is_empty() {
+            let timer = timings.start("record_type_escalation", Some(&domain));
+            let report = escalation::escalate(&asked, &rules, resolver, Duration::from_secs(2), &traffic).await;
+            timings.record(timer, report.queries, report.names.len() as u64);
+            if !report.names.is_empty() {
+                info!("{}: {} names have MX, TXT or SRV records (see escalated)", domain, report.names.len());
+            }
+            results["results"]["escalated"] = serde_json::to_value(&report.names)?;
+            escalated = report.names;
+        }
+        for pack in &args.pack {
+            let report = pack.detect(&addresses, &escalated);
+            if !report.services.is_empty() {
+                info!("{}: {} pack detected {}", domain, pack.name, report.services.iter().copied().collect::<Vec<_>>().join(", "));
+            }
+            results["results"]["packs"][pack.name] = serde_json::to_value(report)?;
+        }
+        if args.resolve_per_tag
+            && let Some(target) = targets.iter().find(|target| target.domain == domain)
+        {
+            let tags = geo::read_tags(&target.resolvers)?;
+            if tags.is_empty() {
+                warn!("{}: --resolve-per-tag but no resolver in {} is tagged", domain, target.resolvers);
+            } else {
+                let timer = timings.start("per_tag_resolution", Some(&domain));
+                let report = geo::resolve_all(&found, tags, Duration::from_secs(2), args.ip_version, &traffic).await;
+                timings.record(timer, report.queries, report.names.len() as u64);
+                if !report.region_specific.is_empty() {
+                    warn!("{}: {} names resolve differently by tag: {}", domain, report.region_specific.len(), report.region_specific.join(", "));
+                }
+                if report.skipped > 0 {
+                    warn!("{}: query budget spent, {} per-tag queries not sent", domain, report.skipped);
+                }
+                results["results"]["per_tag"] = serde_json::to_value(report)?;
+            }
+        }
+        if let Some(url) = &args.pdns_url {
+            let timer = timings.start("enrichment", Some(&domain));
+            let pdns = PassiveDns::new(url, keys.pdns_key.as_deref(), keys.pdns_basic_auth.as_deref());
+            let history = pdns.report(&client, &found, args.pdns_recent_days).await;
+            timings.record(timer, found.len() as u64, history.len() as u64);
+            results["results"]["dns_history"] = history.into();
+        }
+        if args.favicon {
+            let timer = timings.start("favicon_probe", Some(&domain));
+            let hosts: Vec<(String, IpAddr)> = addresses
+                .iter()
+                .filter_map(|(name, resolution)| Some((name.clone(), *resolution.addresses.first()?)))
+                .collect();
+            let favicons = favicon::fetch_all(client.clone(), &hosts).await;
+            timings.record(timer, hosts.len() as u64, favicons.len() as u64);
+            let mut by_hash: BTreeMap<i32, Vec<&str>> = BTreeMap::new();
+            for (name, icon) in &favicons {
+                by_hash.entry(icon.mmh3).or_default().push(name);
+            }
+            for (hash, names) in by_hash.iter().filter(|(_, names)| names.len() > 1) {
+                info!("{}: {} names serve favicon {}: {}", domain, names.len(), hash, names.join(", "));
+            }
+            results["results"]["favicons"] = serde_json::to_value(&favicons)?;
+        }
+        if let Some(dir) = &args.screenshots {
+            let timer = timings.start("screenshots", Some(&domain));
+            let hosts: Vec<(String, IpAddr)> = addresses
+                .iter()
+                .filter_map(|(name, resolution)| Some((name.clone(), *resolution.addresses.first()?)))
+                .collect();
+            let screenshotter = Screenshotter::new(&args.chromium, dir, Duration::from_secs(30));
+            let report = screenshotter.capture_all(&domain, &hosts).await?;
+            timings.record(timer, report.urls as u64, report.screenshots.len() as u64);
+            match &report.browser_error {
+                Some(e) => warn!(
+                    "{}: could not start the browser ({}); {} live URLs are in {}",
+                    domain,
+                    e,
+                    report.urls,
+                    report.dir.join("urls.txt").display()
+                ),
+                None => info!("{}: {} screenshots in {}", domain, report.screenshots.len(), report.dir.join("index.html").display()),
+            }
+            results["results"]["screenshots"] = serde_json::to_value(report)?;
+        }
+        if args.rdap {
+            let timer = timings.start("enrichment", Some(&domain));
+            let found: Vec<(String, Vec<IpAddr>)> = addresses
+                .iter()
+                .map(|(name, resolution)| (name.clone(), resolution.addresses.clone()))
+                .collect();
+            let report = Rdap::new(&args.rdap_url).report(&client, &domain, &found, args.rdap_org.as_deref()).await;
+            timings.record(timer, 1 + report.netblocks.len() as u64, report.netblocks.len() as u64);
+            match &report.expected_org {
+                Some(org) if !report.mismatches.is_empty() => {
+                    warn!("{}: {} addresses sit in netblocks not held by {}", domain, report.mismatches.len(), org)
+                }
+                Some(_) => {}
+                None => warn!("{}: registrant is not published over RDAP, pass --rdap-org to compare netblock owners", domain),
+            }
+            results["results"]["rdap"] = serde_json::to_value(report)?;
+        }
+        if args.nameservers || args.chaos {
+            let timer = timings.start("nameserver_probe", Some(&domain));
+            let nameservers = nameservers::probe(resolver, Duration::from_secs(2), &domain, args.chaos, &traffic).await;
+            let per_server = if args.chaos { 3 } else { 1 };
+            timings.record(timer, 1 + per_server * nameservers.len() as u64, nameservers.len() as u64);
+            let report = nameservers::hosting_report(&nameservers);
+            for hosted in &report.providers {
+                info!("{}: DNS hosted by {}", domain, hosted.provider);
+            }
+            results["results"]["dns_hosting"] = serde_json::to_value(report)?;
+            results["results"]["nameservers"] = serde_json::to_value(nameservers)?;
+        }
+        if args.posture {
+            let timer = timings.start("posture_probe", Some(&domain));
+            let posture = posture::probe(resolver, Duration::from_secs(2), &domain, &traffic).await;
+            let exchangers = posture.mx.as_ref().map_or(0, Vec::len) as u64;
+            timings.record(timer, 3 + exchangers, posture.findings.len() as u64);
+            for finding in &posture.findings {
+                info!("{}: {}", domain, finding.detail);
+            }
+            results["results"]["posture"] = serde_json::to_value(posture)?;
+        }
+        if let (true, Some(url)) = (args.related_domains, &args.pdns_url) {
+            let timer = timings.start("related_domains", Some(&domain));
+            // Nameservers and mail exchangers are looked up for this when
+            // --nameservers and --posture did not, without adding them.
+            let infrastructure = if results["results"]["nameservers"].is_null() || results["results"]["posture"].is_null() {
+                let mut probed = json!({ "target": domain, "results": {
+                    "records": results["results"]["records"],
+                    "nameservers": results["results"]["nameservers"],
+                    "posture": results["results"]["posture"],
+                }});
+                if probed["results"]["nameservers"].is_null() {
+                    probed["results"]["nameservers"] = serde_json::to_value(nameservers::probe(resolver, Duration::from_secs(2), &domain, false, &traffic).await)?;
+                }
+                if probed["results"]["posture"].is_null() {
+                    probed["results"]["posture"] = serde_json::to_value(posture::probe(resolver, Duration::from_secs(2), &domain, &traffic).await)?;
+                }
+                related::infrastructure(&probed)
+            } else {
+                related::infrastructure(&results)
+            };
+            if infrastructure.len() > related::MAX_LOOKUPS {
+                warn!("{}: looking up {} of {} addresses and servers in reverse", domain, related::MAX_LOOKUPS, infrastructure.len());
+            }
+            let pdns = PassiveDns::new(url, keys.pdns_key.as_deref(), keys.pdns_basic_auth.as_deref());
+            let mut neighbours = Vec::new();
+            for (index, piece) in infrastructure.iter().enumerate().take(related::MAX_LOOKUPS) {
+                match pdns.reverse(&client, &piece.value, piece.record_types()).await {
+                    Ok(names) => neighbours.push((index, names)),
+                    Err(e) => warn!("pdns reverse lookup for {} failed: {}", piece.value, e),
+                }
+            }
+            let report = related::related(&domain, infrastructure, &neighbours, &suffixes);
+            timings.record(timer, neighbours.len() as u64, report.domains.len() as u64);
+            if !report.domains.is_empty() {
+                let top: Vec<&str> = report.domains.iter().take(5).map(|related| related.domain.as_str()).collect();
+                info!("{}: {} domains share infrastructure with it, e.g. {}", domain, report.domains.len(), top.join(", "));
+            }
+            results["related_domains"] = serde_json::to_value(report)?;
+        }
+        if args.clusters {
+            let report = cluster::cluster(&results);
+            for hint in &report.origin_hints {
+                info!(
+                    "{}: {} resolves outside {} unlike {}; it may be the origin behind it",
+                    domain,
+                    hint.name,
+                    hint.cdn,
+                    hint.protected_siblings.join(", ")
+                );
+            }
+            results["results"]["clusters"] = serde_json::to_value(report)?;
+        }
+        // Recounted so the truncated answers of the lookups above are in it.
+        results["results"]["truncation"] = serde_json::to_value(traffic.truncation.report())?;
+        // The lookups above spent budget too; a resumed scan must not get it back.
+        if let Some(paused) = paused_targets.get_mut(&domain) {
+            paused.resume.queries_sent = traffic.budget.sent();
+        }
+        output::sort_results(&mut results, args.sort);
+        all_results.push(results);
+    }
+
+    if let Some(path) = &args.state {
+        if interrupt.load(Ordering::Relaxed) {
+            let command_line = resume.as_ref().map_or_else(|| std::env::args().collect(), |state| state.command_line.clone());
+            let mut state = ScanState::new(command_line, &chrono::Utc::now().to_rfc3339());
+            state.targets = paused_targets;
+            match state.save(path) {
+                Ok(()) => info!("saved the paused scan to {}, continue it with: subscan resume {}", path.display(), path.display()),
+                Err(e) => warn!("could not save the paused scan to {}: {}", path.display(), e),
+            }
+        } else if resume.is_some() && std::fs::remove_file(path).is_ok() {
+            info!("scan complete, removed {}", path.display());
+        }
+    }
+    if let (Some(path), Some(reputation)) = (&reputation_path, &reputation) {
+        match reputation.save(path) {
+            Ok(()) => info!("updated resolver reputation in {} ({} resolvers)", path.display(), reputation.resolvers.len()),
+            Err(e) => warn!("could not save resolver reputation to {}: {}", path.display(), e),
+        }
+    }
+    if let (Some(path), Some(history)) = (&history_path, &history) {
+        let history = history.lock().unwrap();
+        match history.save(path) {
+            Ok(()) => info!("updated word history in {} ({} words)", path.display(), history.words.len()),
+            Err(e) => warn!("could not save word history to {}: {}", path.display(), e),
+        }
+    }
+    drop(query_log);
+    if let Some(task) = query_log_task {
+        task.finish().await?;
+        info!("wrote query log to {}", args.dnstap_file.as_deref().unwrap_or_default());
+    }
+    drop(negative_log);
+    if let Some(task) = negative_log_task {
+        task.finish().await?;
+        info!("wrote negative results to {}", args.negative_output.as_deref().unwrap_or_default());
+    }
+
+    // The filter only changes what is written; the exit code and summary
+    // still count every finding.
+    let shown = match &args.filter {
+        Some(filter) => filtered(&all_results, filter),
+        None => all_results.clone(),
+    };
+    let rendered = match &args.output_template {
+        Some(template) => Some(template.render(&shown)),
+        None => output::render(args.output_format, &shown),
+    };
+    let mut results = results_document(&shown, &suffixes, &domains);
+    timings.log_summary();
+    results["stats"] = serde_json::to_value(&timings)?;
+    results["fingerprint"] = fingerprint;
+    if let (Some(previous), Some(mode)) = (previous, existing_mode) {
+        results = output::combine(previous, results, mode);
+    }
+    let rendered = match rendered {
+        Some(rendered) => rendered,
+        None => serde_json::to_string_pretty(&results)?,
+    };
+
+    if output_path.is_empty() {
+        if !json_output {
+            print!("{}", rendered);
+        }
+    } else {
+        if let Some(dir) = &scan_dir {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = File::create(&output_path)?;
+        file.write_all(rendered.as_bytes())?;
+        if let Some(manifest) = &mut manifest {
+            manifest.finish();
+            let path = manifest.write_alongside(Path::new(&output_path))?;
+            info!("wrote scan manifest to {}", path.display());
+        }
+    }
+    if let Some(dir) = &scan_dir {
+        info!("stored scan in {}", dir.display());
+        if let Some(previous) = &previous_scan
+            && let Some(document) = std::fs::read_to_string(previous).ok().and_then(|s| serde_json::from_str::<Value>(&s).ok())
+        {
+            let label = previous.parent().and_then(Path::file_name).map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let diff = project::diff(&label, &document, &results);
+            info!(
+                "since scan {}: {} new, {} gone, {} changed",
+                label,
+                diff.added.len(),
+                diff.removed.len(),
+                diff.changed.len()
+            );
+            std::fs::write(dir.join(project::DIFF_FILE), serde_json::to_string_pretty(&diff)?)?;
+        }
+    }
+
+    if let Some(path) = &args.sanitization_report {
+        let report: serde_json::Map<String, Value> = all_results
+            .iter()
+            .map(|r| {
+                let target = r["target"].as_str().unwrap_or_default().to_string();
+                let wordlist = json!({
+                    "entries": r["results"]["total_scanned"],
+                    "invalid": r["results"]["invalid_candidates"],
+                });
+                let sanitization = r["results"].get("sanitization").cloned().unwrap_or_else(|| json!({}));
+                (target, json!({ "wordlist": wordlist, "sanitization": sanitization }))
+            })
+            .collect();
+        std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
+        info!("wrote sanitization report to {}", path);
+    }
+
+    if let Some(dir) = &args.split_output {
+        std::fs::create_dir_all(dir)?;
+        for (kind, lines) in output::split_by_type(&shown) {
+            let mut file = File::create(Path::new(dir).join(format!("{}.txt", kind)))?;
+            for line in lines {
+                writeln!(file, "{}", line)?;
+            }
+        }
+        info!("wrote per-record-type files to {}", dir);
+    }
+    if let Some(dir) = &args.output_dir {
+        for result in &shown {
+            let domain = result["target"].as_str().unwrap_or_default();
+            let manifest = target_manifests.iter_mut().find(|manifest| manifest.targets == [domain]);
+            write_target_dir(&dir.join(domain), result, manifest)?;
+        }
+        info!("wrote {} target directories to {}", shown.len(), dir.display());
+    }
+    for view in &args.view {
+        let results = filtered(&all_results, &view.filter);
+        let names: usize = results.iter().map(|r| r["results"]["subdomain"].as_array().map_or(0, Vec::len)).sum();
+        std::fs::write(&view.path, render_results(args, &results, &suffixes, &domains)?)?;
+        info!("wrote {} names to view {}", names, view.path);
+    }
+
+    let exit = Exit::from_results(&all_results, interrupt.load(Ordering::Relaxed));
+    if exit == Exit::ResolversUnusable {
+        warn!("every query failed at the resolvers, check --resolvers and connectivity");
+    }
+    #[cfg(feature = "fault-injection")]
+    if let Some(counts) = faults::counts() {
+        info!("injected faults: {} dropped, {} delayed, {} corrupted", counts.dropped, counts.delayed, counts.corrupted);
+    }
+    eprintln!("{}", stats::run_summary(&all_results, started.elapsed(), exit.code()));
+    Ok(exit)
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::scanner::{PhaseReport, SubdomainScanner};
+
+pub const MANIFEST_FILE: &str = "scan-manifest.json";
+
+/// Everything needed to rerun a scan exactly and to audit where its results
+/// came from.
+#[derive(Debug, Serialize)]
+pub struct ScanManifest {
+    pub crate_version: &'static str,
+    pub command_line: Vec<String>,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub config: Value,
+    pub targets: Vec<String>,
+    pub inputs: Vec<InputDigest>,
+    pub resolver_list_sha256: String,
+    pub seed: Option<u64>,
+    /// Wordlist phases per target, with `--quick-wordlist`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub phases: BTreeMap<String, Vec<PhaseReport>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InputDigest {
+    pub role: &'static str,
+    pub path: String,
+    pub sha256: String,
+    pub bytes: u64,
+}
+
+impl ScanManifest {
+    pub fn new(scanner: &SubdomainScanner) -> Self {
+        let mut hasher = Sha256::new();
+        for resolver in scanner.resolvers() {
+            hasher.update(resolver.to_string().as_bytes());
+            hasher.update(b"\n");
+        }
+
+        Self {
+            crate_version: env!("CARGO_PKG_VERSION"),
+            command_line: std::env::args().collect(),
+            started_at: Utc::now().to_rfc3339(),
+            finished_at: None,
+            config: serde_json::to_value(scanner).unwrap_or(Value::Null),
+            targets: vec![scanner.domain().to_string()],
+            inputs: Vec::new(),
+            resolver_list_sha256: format!("{:x}", hasher.finalize()),
+            seed: scanner.seed(),
+            phases: BTreeMap::new(),
+        }
+    }
+
+    /// Records every target of a multi-target run; `config` only describes
+    /// the first one.
+    pub fn with_targets(mut self, targets: &[String]) -> Self {
+        self.targets = targets.to_vec();
+        self
+    }
+
+    pub fn with_input(mut self, role: &'static str, path: &str) -> io::Result<Self> {
+        self.inputs.push(InputDigest::of_file(role, path)?);
+        Ok(self)
+    }
+
+    pub fn record_phases(&mut self, target: &str, phases: &[PhaseReport]) {
+        if !phases.is_empty() {
+            self.phases.insert(target.to_string(), phases.to_vec());
+        }
+    }
+
+    pub fn finish(&mut self) {
+        self.finished_at = Some(Utc::now().to_rfc3339());
+    }
+
+    /// Writes the manifest next to `output` and returns where it went.
+    pub fn write_alongside(&self, output: &Path) -> io::Result<PathBuf> {
+        let path = output
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(MANIFEST_FILE);
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(&path, json)?;
+        Ok(path)
+    }
+}
+
+impl InputDigest {
+    pub fn of_file(role: &'static str, path: &str) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::new();
+        let mut buf = [0u8; 64 * 1024];
+        let mut bytes = 0u64;
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            bytes += n as u64;
+        }
+
+        Ok(Self {
+            role,
+            path: path.to_string(),
+            sha256: format!("{:x}", hasher.finalize()),
+            bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_input_digest() {
+        let path = std::env::temp_dir().join(format!("subscan-digest-{}", std::process::id()));
+        File::create(&path).unwrap().write_all(b"abc").unwrap();
+        let digest = InputDigest::of_file("wordlist", path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(digest.bytes, 3);
+        assert_eq!(
+            digest.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use serde_json::{Value, json};
+use tokio::time::{Instant, sleep_until};
+use tracing::{debug, info};
+
+use crate::net::{SocketTuning, TunedRuntimeProvider};
+use crate::output;
+use crate::project::Project;
+use crate::scanner::{QueryFlags, QueryOutcome, Resolution, SubdomainScanner};
+use crate::traffic::Traffic;
+
+/// How often a watched project is checked for a newer scan.
+const PROJECT_POLL: Duration = Duration::from_secs(60);
+
+/// A finding being watched and when it is next due for a check.
+#[derive(Debug, Clone)]
+pub struct Watched {
+    pub name: String,
+    pub last: Option<Resolution>,
+    pub due: Instant,
+}
+
+/// Re-checks found names when their recorded TTL expires rather than on a
+/// fixed interval; `min`/`max` clamp names with tiny or huge TTLs.
+pub struct Monitor {
+    resolvers: Vec<SocketAddr>,
+    timeout: Duration,
+    min: Duration,
+    max: Duration,
+    next_resolver: usize,
+    /// Counts the truncated answers of the rechecks.
+    traffic: Traffic,
+    /// A project whose newer scans add names, and the scan last read.
+    project: Option<(Project, Option<PathBuf>)>,
+}
+
+impl Monitor {
+    pub fn new(resolvers: Vec<SocketAddr>, timeout: Duration, min: Duration, max: Duration) -> Self {
+        Self {
+            resolvers,
+            timeout,
+            min,
+            max,
+            next_resolver: 0,
+            traffic: Traffic::default(),
+            project: None,
+        }
+    }
+
+    /// Also picks up names that later scans of `project` find, reporting
+    /// each as `new` and watching it from then on.
+    pub fn with_project(mut self, project: Project) -> Self {
+        let latest = project.latest_results();
+        self.project = Some((project, latest));
+        self
+    }
+
+    /// Findings of the latest scan in a results document, each due when the
+    /// TTL recorded at scan time runs out.
+    pub fn watch_results(&self, scans: &[&Value]) -> Vec<Watched> {
+        let now = Instant::now();
+        let wall_now = chrono::Utc::now();
+        let mut watched = Vec::new();
+        for scan in scans {
+            let started = scan["started_at"]
+                .as_str()
+                .and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok())
+                .map(|at| at.with_timezone(&chrono::Utc))
+                .unwrap_or(wall_now);
+            let names = scan["results"]["subdomain"].as_array().cloned().unwrap_or_default();
+            for name in names.iter().filter_map(Value::as_str) {
+                let last: Option<Resolution> = serde_json::from_value(scan["results"]["records"][name].clone()).ok();
+                let expires = started + chrono::Duration::from_std(self.interval(last.as_ref())).unwrap_or_default();
+                let remaining = (expires - wall_now).to_std().unwrap_or_default();
+                watched.push(Watched {
+                    name: name.to_string(),
+                    last,
+                    due: now + remaining,
+                });
+            }
+        }
+        watched
+    }
+
+    fn interval(&self, resolution: Option<&Resolution>) -> Duration {
+        next_check(resolution.and_then(|r| r.ttl), self.min, self.max)
+    }
+
+    /// Names in the project's latest scan that are not watched yet, if
+    /// that scan is newer than the one last read.
+    fn new_from_project(&mut self, watched: &[Watched]) -> Vec<Watched> {
+        let Some((project, seen)) = &self.project else {
+            return Vec::new();
+        };
+        let Some(latest) = project.latest_results().filter(|latest| seen.as_ref() != Some(latest)) else {
+            return Vec::new();
+        };
+        let document = match std::fs::read_to_string(&latest).map_err(|e| e.to_string()).and_then(|s| serde_json::from_str::<Value>(&s).map_err(|e| e.to_string())) {
+            Ok(document) => document,
+            Err(e) => {
+                // Possibly still being written; read it at the next poll.
+                debug!("could not read {}: {}", latest.display(), e);
+                return Vec::new();
+            }
+        };
+        info!("picking up new names from {}", latest.display());
+        if let Some((_, seen)) = &mut self.project {
+            *seen = Some(latest);
+        }
+        let known: HashSet<&str> = watched.iter().map(|w| w.name.as_str()).collect();
+        self.watch_results(&output::latest_scans(&document))
+            .into_iter()
+            .filter(|w| !known.contains(w.name.as_str()))
+            .collect()
+    }
+
+    /// Runs until `interrupt` is set, calling `on_change` with a JSON event
+    /// whenever a name's answer differs from the previous check, or a newer
+    /// scan of the project found a name.
+    pub async fn run(&mut self, mut watched: Vec<Watched>, interrupt: Arc<AtomicBool>, mut on_change: impl FnMut(Value)) {
+        info!("monitoring {} names", watched.len());
+        let mut next_poll = Instant::now() + PROJECT_POLL;
+        while !interrupt.load(Ordering::Relaxed) && (!watched.is_empty() || self.project.is_some()) {
+            let due = watched.iter().map(|w| w.due).min().unwrap_or(next_poll);
+            // Wake up periodically so an interrupt is noticed promptly.
+            sleep_until(due.min(Instant::now() + Duration::from_secs(1))).await;
+            let now = Instant::now();
+            if self.project.is_some() && now >= next_poll {
+                next_poll = now + PROJECT_POLL;
+                for entry in self.new_from_project(&watched) {
+                    on_change(json!({
+                        "name": entry.name,
+                        "change": "new",
+                        "before": null,
+                        "after": entry.last,
+                        "at": chrono::Utc::now().to_rfc3339(),
+                    }));
+                    watched.push(entry);
+                }
+            }
+            for entry in watched.iter_mut().filter(|w| w.due <= now) {
+                let resolver = self.resolvers[self.next_resolver % self.resolvers.len()];
+                self.next_resolver += 1;
+                let outcome = SubdomainScanner::try_resolve_once(
+                    resolver,
+                    self.timeout,
+                    TunedRuntimeProvider::new(SocketTuning::default()),
+                    entry.name.clone(),
+                    &QueryFlags::default(),
+                    &self.traffic,
+                )
+                .await;
+                let current = match outcome {
+                    QueryOutcome::Found(_, resolution) => Some(resolution),
+                    QueryOutcome::NotFound | QueryOutcome::NxDomain => None,
+                    QueryOutcome::Failed(failure, detail) => {
+                        // Inconclusive; try again at the floor interval.
+                        debug!("recheck of {} failed ({}): {}", entry.name, failure.label(), detail);
+                        entry.due = now + self.min;
+                        continue;
+                    }
+                };
+                if let Some(change) = diff(entry.last.as_ref(), current.as_ref()) {
+                    on_change(json!({
+                        "name": entry.name,
+                        "change": change,
+                        "before": entry.last,
+                        "after": current,
+                        "at": chrono::Utc::now().to_rfc3339(),
+                    }));
+                }
+                entry.due = now + next_check(current.as_ref().and_then(|r| r.ttl), self.min, self.max);
+                entry.last = current;
+            }
+        }
+    }
+}
+
+/// When to check a name again after an answer with `ttl`.
+pub fn next_check(ttl: Option<u32>, min: Duration, max: Duration) -> Duration {
+    ttl.map_or(max, |ttl| Duration::from_secs(u64::from(ttl))).clamp(min, max)
+}
+
+/// How a name's answer changed between two checks, if it did. TTL counting
+/// down is not a change.
+pub fn diff(before: Option<&Resolution>, after: Option<&Resolution>) -> Option<&'static str> {
+    match (before, after) {
+        (Some(_), None) => Some("gone"),
+        (None, Some(_)) => Some("back"),
+        (Some(a), Some(b)) if a.cname_chain != b.cname_chain => Some("cname_changed"),
+        (Some(a), Some(b)) => {
+            let mut a = a.addresses.clone();
+            let mut b = b.addresses.clone();
+            a.sort();
+            b.sort();
+            (a != b).then_some("addresses_changed")
+        }
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_check() {
+        let (min, max) = (Duration::from_secs(30), Duration::from_secs(3600));
+        assert_eq!(next_check(Some(5), min, max), min);
+        assert_eq!(next_check(Some(300), min, max), Duration::from_secs(300));
+        assert_eq!(next_check(Some(86400), min, max), max);
+        assert_eq!(next_check(None, min, max), max);
+    }
+
+    #[test]
+    fn test_diff() {
+        let at = |ips: &[&str], ttl| Resolution {
+            cname_chain: vec![],
+            addresses: ips.iter().map(|ip| ip.parse().unwrap()).collect(),
+            ttl: Some(ttl),
+            ..Resolution::default()
+        };
+        assert_eq!(diff(Some(&at(&["1.1.1.1", "2.2.2.2"], 60)), Some(&at(&["2.2.2.2", "1.1.1.1"], 10))), None);
+        assert_eq!(diff(Some(&at(&["1.1.1.1"], 60)), Some(&at(&["3.3.3.3"], 60))), Some("addresses_changed"));
+        assert_eq!(diff(Some(&at(&["1.1.1.1"], 60)), None), Some("gone"));
+        assert_eq!(diff(None, Some(&at(&["1.1.1.1"], 60))), Some("back"));
+    }
+}
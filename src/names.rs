@@ -0,0 +1,266 @@
+//! DNS name validation and normalization shared by every input path:
+//! targets, wordlist candidates and names from passive sources.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+/// Longest name in presentation form, without the trailing dot (255 octets
+/// on the wire).
+pub const MAX_NAME_LEN: usize = 253;
+pub const MAX_LABEL_LEN: usize = 63;
+
+/// Whether `_` may appear in a label. Hostnames (RFC 1123) cannot have it,
+/// but service labels like `_dmarc` and plenty of real records do.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum Underscores {
+    #[default]
+    Allow,
+    Reject,
+}
+
+impl FromStr for Underscores {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "allow" => Ok(Underscores::Allow),
+            "reject" => Ok(Underscores::Reject),
+            _ => Err(format!("Unknown underscore policy: {}", s)),
+        }
+    }
+}
+
+/// Why a name was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rejection {
+    Empty,
+    InvalidCharacter,
+    Underscore,
+    NameTooLong,
+    EmptyLabel,
+    LabelTooLong,
+}
+
+impl Rejection {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Rejection::Empty => "empty",
+            Rejection::InvalidCharacter => "invalid_character",
+            Rejection::Underscore => "underscore",
+            Rejection::NameTooLong => "name_too_long",
+            Rejection::EmptyLabel => "empty_label",
+            Rejection::LabelTooLong => "label_too_long",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidName {
+    pub reason: Rejection,
+    pub detail: String,
+}
+
+impl fmt::Display for InvalidName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.detail)
+    }
+}
+
+/// A change [`sanitize`] made to get a valid name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rewrite {
+    Whitespace,
+    TrailingDot,
+    Lowercased,
+}
+
+impl Rewrite {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Rewrite::Whitespace => "whitespace_trimmed",
+            Rewrite::TrailingDot => "trailing_dot_removed",
+            Rewrite::Lowercased => "lowercased",
+        }
+    }
+}
+
+/// Lowercases `name` and drops surrounding whitespace and one trailing dot,
+/// then checks it: letters, digits, `-` and (per `underscores`) `_` only,
+/// no empty label, labels of at most 63 and names of at most 253
+/// characters.
+pub fn normalize(name: &str, underscores: Underscores) -> Result<String, InvalidName> {
+    sanitize(name, underscores).map(|(name, _)| name)
+}
+
+/// [`normalize`], also saying what had to change.
+pub fn sanitize(name: &str, underscores: Underscores) -> Result<(String, Vec<Rewrite>), InvalidName> {
+    let (name, rewrites) = rewrite(name);
+    check(&name, underscores)?;
+    Ok((name, rewrites))
+}
+
+/// The name a wordlist `entry` stands for under `domain`, sanitized like
+/// [`sanitize`]; rewrites apply to the entry, checks to the whole name.
+pub fn candidate(entry: &str, domain: &str, underscores: Underscores) -> Result<(String, Vec<Rewrite>), InvalidName> {
+    let (label, rewrites) = rewrite(entry);
+    // Under the root the entry is the whole name.
+    let name = if domain.is_empty() { label } else { format!("{}.{}", label, domain) };
+    check(&name, underscores)?;
+    Ok((name, rewrites))
+}
+
+fn rewrite(name: &str) -> (String, Vec<Rewrite>) {
+    let mut rewrites = Vec::new();
+    let trimmed = name.trim();
+    if trimmed.len() != name.len() {
+        rewrites.push(Rewrite::Whitespace);
+    }
+    let bare = match trimmed.strip_suffix('.') {
+        Some(bare) => {
+            rewrites.push(Rewrite::TrailingDot);
+            bare
+        }
+        None => trimmed,
+    };
+    if bare.bytes().any(|b| b.is_ascii_uppercase()) {
+        rewrites.push(Rewrite::Lowercased);
+    }
+    (bare.to_ascii_lowercase(), rewrites)
+}
+
+/// Checks an already normalized name; see [`normalize`].
+pub fn check(name: &str, underscores: Underscores) -> Result<(), InvalidName> {
+    let invalid = |reason, detail| Err(InvalidName { reason, detail });
+    if name.is_empty() {
+        return invalid(Rejection::Empty, "name is empty".to_string());
+    }
+    if let Some(c) = name.chars().find(|&c| !(c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_')) {
+        return invalid(Rejection::InvalidCharacter, format!("'{}' contains invalid character '{}'", name, c));
+    }
+    if underscores == Underscores::Reject && name.contains('_') {
+        return invalid(Rejection::Underscore, format!("'{}' contains an underscore", name));
+    }
+    if name.len() > MAX_NAME_LEN {
+        return invalid(Rejection::NameTooLong, format!("'{}' is longer than {} characters", name, MAX_NAME_LEN));
+    }
+    if let Some(label) = name.split('.').find(|l| l.is_empty() || l.len() > MAX_LABEL_LEN) {
+        return if label.is_empty() {
+            invalid(Rejection::EmptyLabel, format!("'{}' has an empty label", name))
+        } else {
+            invalid(Rejection::LabelTooLong, format!("'{}' has a label longer than {} characters", name, MAX_LABEL_LEN))
+        };
+    }
+    Ok(())
+}
+
+// Example entries kept per reason in a report.
+const REPORT_SAMPLES: usize = 5;
+
+/// What normalization did to the candidates of a scan: how many were
+/// rejected or rewritten, by reason, with a few of the entries as they
+/// were. Explains why the names queried differ from the wordlist lines.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SanitizationReport {
+    pub rejected: BTreeMap<&'static str, ReportEntry>,
+    pub rewritten: BTreeMap<&'static str, ReportEntry>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReportEntry {
+    pub count: u64,
+    pub samples: Vec<String>,
+}
+
+impl ReportEntry {
+    fn add(&mut self, sample: &str) {
+        self.count += 1;
+        if self.samples.len() < REPORT_SAMPLES {
+            self.samples.push(sample.to_string());
+        }
+    }
+}
+
+impl SanitizationReport {
+    pub fn is_empty(&self) -> bool {
+        self.rejected.is_empty() && self.rewritten.is_empty()
+    }
+
+    pub fn rejected_total(&self) -> u64 {
+        self.rejected.values().map(|e| e.count).sum()
+    }
+
+    pub fn reject(&mut self, entry: &str, reason: Rejection) {
+        self.rejected.entry(reason.label()).or_default().add(entry);
+    }
+
+    pub fn rewrite(&mut self, entry: &str, rewrites: &[Rewrite]) {
+        for rewrite in rewrites {
+            self.rewritten.entry(rewrite.label()).or_default().add(entry);
+        }
+    }
+
+    /// `count reason` pairs for a log line, e.g. `3 empty_label, 1 underscore`.
+    pub fn summary(section: &BTreeMap<&'static str, ReportEntry>) -> String {
+        section.iter().map(|(reason, e)| format!("{} {}", e.count, reason)).collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any run of
+/// characters, dots included, and `?` for one.
+pub fn glob(pattern: &str, name: &str) -> bool {
+    glob_bytes(pattern.as_bytes(), name.as_bytes())
+}
+
+fn glob_bytes(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| glob_bytes(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && glob_bytes(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob_bytes(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("WWW.Example.com.", Underscores::Allow).unwrap(), "www.example.com");
+        assert_eq!(normalize("_dmarc.example.com", Underscores::Allow).unwrap(), "_dmarc.example.com");
+        assert!(normalize("_dmarc.example.com", Underscores::Reject).unwrap_err().detail.contains("underscore"));
+        assert!(normalize("a..example.com", Underscores::Allow).unwrap_err().detail.contains("empty label"));
+        assert!(normalize("example.com..", Underscores::Allow).unwrap_err().detail.contains("empty label"));
+        assert!(normalize(".example.com", Underscores::Allow).is_err());
+        assert!(normalize("", Underscores::Allow).is_err());
+        assert!(normalize("ex ample.com", Underscores::Allow).unwrap_err().detail.contains("invalid character ' '"));
+
+        let label = "a".repeat(64);
+        assert!(normalize(&format!("{}.com", label), Underscores::Allow).unwrap_err().detail.contains("label longer"));
+        let long = vec!["a".repeat(63); 4].join(".");
+        assert_eq!(long.len(), 255);
+        assert!(normalize(&long, Underscores::Allow).unwrap_err().detail.contains("longer than 253"));
+        assert!(normalize(&long[2..], Underscores::Allow).is_ok());
+    }
+
+    #[test]
+    fn test_sanitization_report() {
+        let mut report = SanitizationReport::default();
+        for entry in [" Www.", "a.", "a..", "b..", "ok"] {
+            match candidate(entry, "example.com", Underscores::Allow) {
+                Ok((_, rewrites)) => report.rewrite(entry, &rewrites),
+                Err(e) => report.reject(entry, e.reason),
+            }
+        }
+        assert_eq!(report.rejected_total(), 2);
+        assert_eq!(report.rejected["empty_label"].samples, ["a..", "b.."]);
+        assert_eq!(
+            SanitizationReport::summary(&report.rewritten),
+            "1 lowercased, 2 trailing_dot_removed, 1 whitespace_trimmed"
+        );
+        assert_eq!(candidate("WWW.Example.org.", "", Underscores::Allow).unwrap().0, "www.example.org");
+    }
+}
@@ -0,0 +1,315 @@
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+
+use hickory_client::proto::rr::{DNSClass, Name, RData, RecordType};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::net::{SocketTuning, TunedRuntimeProvider};
+use crate::scanner::{self, QueryFlags, QueryOutcome, SubdomainScanner};
+use crate::traffic::Traffic;
+
+/// One of the target's authoritative servers and what it says about itself
+/// when asked in the CHAOS class. Most servers have these answers disabled
+/// or faked; an answer is a lead, not proof.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Nameserver {
+    pub name: String,
+    pub addresses: Vec<IpAddr>,
+    pub version_bind: Option<String>,
+    pub hostname_bind: Option<String>,
+    /// DNS hosting provider, recognized from the server's name.
+    pub provider: Option<&'static str>,
+    /// Server software, recognized from `version_bind`.
+    pub software: Option<&'static str>,
+}
+
+/// A DNS hosting provider, recognized by markers in its nameserver names,
+/// and what it means for enumeration. Markers ending in a dot are domain
+/// suffixes; others may sit anywhere (`.awsdns-` catches `ns-1.awsdns-01.org`).
+pub struct Provider {
+    pub name: &'static str,
+    markers: &'static [&'static str],
+    pub notes: &'static [&'static str],
+}
+
+const PROVIDERS: &[Provider] = &[
+    Provider {
+        name: "Amazon Route 53",
+        markers: &[".awsdns-"],
+        notes: &["alias records answer with the target's addresses directly, so no CNAME reveals the AWS service behind a name"],
+    },
+    Provider {
+        name: "Cloudflare",
+        markers: &[".ns.cloudflare.com."],
+        notes: &[
+            "proxied records resolve to Cloudflare edge addresses, not the origin",
+            "CNAMEs are flattened, so chains to third-party services are usually hidden",
+        ],
+    },
+    Provider {
+        name: "NS1",
+        markers: &[".nsone.net."],
+        notes: &["filter chains can answer differently per resolver location"],
+    },
+    Provider {
+        name: "Azure DNS",
+        markers: &[".azure-dns.com.", ".azure-dns.net.", ".azure-dns.org.", ".azure-dns.info."],
+        notes: &["dangling CNAMEs to *.azurewebsites.net or *.cloudapp.net are common takeover candidates"],
+    },
+    Provider {
+        name: "Google Cloud DNS",
+        markers: &[".googledomains.com."],
+        notes: &[],
+    },
+    Provider {
+        name: "Akamai Edge DNS",
+        markers: &[".akam.net."],
+        notes: &["names often CNAME into edgekey.net / edgesuite.net; the origin sits behind them"],
+    },
+    Provider {
+        name: "UltraDNS",
+        markers: &[".ultradns.com.", ".ultradns.net.", ".ultradns.org.", ".ultradns.biz."],
+        notes: &[],
+    },
+    Provider {
+        name: "Dyn",
+        markers: &[".dynect.net."],
+        notes: &[],
+    },
+    Provider {
+        name: "DNS Made Easy",
+        markers: &[".dnsmadeeasy.com."],
+        notes: &[],
+    },
+    Provider {
+        name: "GoDaddy",
+        markers: &[".domaincontrol.com."],
+        notes: &["parked domains usually carry a wildcard record, so every candidate resolves"],
+    },
+    Provider {
+        name: "Namecheap",
+        markers: &[".registrar-servers.com."],
+        notes: &["parked domains usually carry a wildcard record, so every candidate resolves"],
+    },
+    Provider {
+        name: "DigitalOcean",
+        markers: &[".digitalocean.com."],
+        notes: &[],
+    },
+    Provider {
+        name: "Hetzner",
+        markers: &[".hetzner.com.", ".hetzner.de.", ".your-server.de."],
+        notes: &[],
+    },
+    Provider {
+        name: "OVHcloud",
+        markers: &[".ovh.net."],
+        notes: &[],
+    },
+];
+
+/// The provider operating the nameserver `name`, if it is a known one.
+pub fn identify_provider(name: &str) -> Option<&'static Provider> {
+    let name = format!(".{}.", name.trim_end_matches('.').to_lowercase());
+    PROVIDERS
+        .iter()
+        .find(|provider| {
+            provider.markers.iter().any(|marker| {
+                if marker.ends_with('.') { name.ends_with(marker) } else { name.contains(marker) }
+            })
+        })
+}
+
+/// The server software a `version.bind` answer points at. Bare BIND
+/// versions (`9.18.1`) are the most common answer.
+pub fn identify_software(version: &str) -> Option<&'static str> {
+    let version = version.to_lowercase();
+    let known = [
+        ("powerdns", "PowerDNS"),
+        ("knot", "Knot DNS"),
+        ("nsd", "NSD"),
+        ("unbound", "Unbound"),
+        ("microsoft", "Microsoft DNS"),
+        ("dnsmasq", "dnsmasq"),
+        ("bind", "BIND"),
+    ];
+    known
+        .iter()
+        .find(|(marker, _)| version.contains(marker))
+        .map(|(_, software)| *software)
+        .or_else(|| version.starts_with("9.").then_some("BIND"))
+}
+
+/// Who hosts a target's DNS, summarized from its nameservers.
+#[derive(Debug, Default, Serialize)]
+pub struct HostingReport {
+    pub providers: Vec<HostedBy>,
+    /// Nameservers that match no known provider: self-hosted or a smaller one.
+    pub unidentified: Vec<String>,
+    pub software: Vec<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HostedBy {
+    pub provider: &'static str,
+    pub nameservers: Vec<String>,
+    pub notes: &'static [&'static str],
+}
+
+pub fn hosting_report(nameservers: &[Nameserver]) -> HostingReport {
+    let mut report = HostingReport::default();
+    for ns in nameservers {
+        match ns.provider.and_then(|name| PROVIDERS.iter().find(|p| p.name == name)) {
+            Some(provider) => match report.providers.iter_mut().find(|h| h.provider == provider.name) {
+                Some(hosted) => hosted.nameservers.push(ns.name.clone()),
+                None => report.providers.push(HostedBy {
+                    provider: provider.name,
+                    nameservers: vec![ns.name.clone()],
+                    notes: provider.notes,
+                }),
+            },
+            None => report.unidentified.push(ns.name.clone()),
+        }
+        if let Some(software) = ns.software
+            && !report.software.contains(&software)
+        {
+            report.software.push(software);
+        }
+    }
+    report
+}
+
+/// Looks up the NS records of `domain` through `resolver`, resolves each
+/// server and, with `chaos`, asks it for `version.bind` and `hostname.bind`.
+pub async fn probe(resolver: SocketAddr, timeout: Duration, domain: &str, chaos: bool, traffic: &Traffic) -> Vec<Nameserver> {
+    let mut nameservers = Vec::new();
+    for name in lookup_ns(resolver, timeout, domain, traffic).await {
+        let addresses = match SubdomainScanner::try_resolve_once(
+            resolver,
+            timeout,
+            provider(),
+            name.clone(),
+            &QueryFlags::default(),
+            traffic,
+        )
+        .await
+        {
+            QueryOutcome::Found(_, resolution) => resolution.addresses,
+            _ => Vec::new(),
+        };
+        let mut nameserver = Nameserver {
+            provider: identify_provider(&name).map(|provider| provider.name),
+            name,
+            addresses,
+            version_bind: None,
+            hostname_bind: None,
+            software: None,
+        };
+        if chaos && let Some(address) = nameserver.addresses.first() {
+            let server = SocketAddr::new(*address, 53);
+            nameserver.version_bind = chaos_txt(server, timeout, "version.bind", traffic).await;
+            nameserver.hostname_bind = chaos_txt(server, timeout, "hostname.bind", traffic).await;
+            nameserver.software = nameserver.version_bind.as_deref().and_then(identify_software);
+        }
+        nameservers.push(nameserver);
+    }
+    nameservers
+}
+
+async fn lookup_ns(resolver: SocketAddr, timeout: Duration, domain: &str, traffic: &Traffic) -> Vec<String> {
+    let Ok(name) = Name::from_str(&format!("{}.", domain)) else {
+        return Vec::new();
+    };
+    let message = scanner::build_query(name, RecordType::NS, &QueryFlags::default());
+    match scanner::exchange(resolver, timeout, provider(), message, traffic).await {
+        Ok(response) => {
+            let mut names: Vec<String> = response
+                .answers()
+                .iter()
+                .filter_map(|record| match record.data() {
+                    RData::NS(ns) => Some(ns.0.to_utf8().trim_end_matches('.').to_lowercase()),
+                    _ => None,
+                })
+                .collect();
+            names.sort();
+            names.dedup();
+            names
+        }
+        Err((_, e)) => {
+            debug!("NS lookup for {} failed: {}", domain, e);
+            Vec::new()
+        }
+    }
+}
+
+/// Asks `server` directly (no recursion) for a CHAOS-class TXT record.
+pub async fn chaos_txt(server: SocketAddr, timeout: Duration, name: &str, traffic: &Traffic) -> Option<String> {
+    let flags = QueryFlags {
+        recursion_desired: false,
+        ..QueryFlags::default()
+    };
+    let mut message = scanner::build_query(Name::from_str(name).ok()?, RecordType::TXT, &flags);
+    message.queries_mut()[0].set_query_class(DNSClass::CH);
+    match scanner::exchange(server, timeout, provider(), message, traffic).await {
+        Ok(response) => response.answers().iter().find_map(|record| match record.data() {
+            RData::TXT(txt) => Some(
+                txt.txt_data()
+                    .iter()
+                    .map(|part| String::from_utf8_lossy(part))
+                    .collect::<Vec<_>>()
+                    .join(""),
+            ),
+            _ => None,
+        }),
+        Err((_, e)) => {
+            debug!("{} query to {} failed: {}", name, server, e);
+            None
+        }
+    }
+}
+
+fn provider() -> TunedRuntimeProvider {
+    TunedRuntimeProvider::new(SocketTuning::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nameserver(name: &str, version: Option<&str>) -> Nameserver {
+        Nameserver {
+            name: name.to_string(),
+            addresses: vec![],
+            version_bind: version.map(str::to_string),
+            hostname_bind: None,
+            provider: identify_provider(name).map(|provider| provider.name),
+            software: version.and_then(identify_software),
+        }
+    }
+
+    #[test]
+    fn test_identify() {
+        assert_eq!(identify_provider("ns-1536.awsdns-00.co.uk").unwrap().name, "Amazon Route 53");
+        assert_eq!(identify_provider("Kim.NS.Cloudflare.com.").unwrap().name, "Cloudflare");
+        assert!(identify_provider("ns.cloudflare.com.evil.example").is_none());
+        assert!(identify_provider("ns1.example.com").is_none());
+        assert_eq!(identify_software("9.18.1-1ubuntu1"), Some("BIND"));
+        assert_eq!(identify_software("PowerDNS Authoritative Server 4.8.3"), Some("PowerDNS"));
+        assert_eq!(identify_software("none of your business"), None);
+    }
+
+    #[test]
+    fn test_hosting_report() {
+        let report = hosting_report(&[
+            nameserver("ns-1.awsdns-01.org", None),
+            nameserver("ns-2.awsdns-02.net", None),
+            nameserver("ns1.example.com", Some("9.16.1")),
+        ]);
+        assert_eq!(report.providers.len(), 1);
+        assert_eq!(report.providers[0].nameservers.len(), 2);
+        assert_eq!(report.unidentified, vec!["ns1.example.com"]);
+        assert_eq!(report.software, vec!["BIND"]);
+    }
+}
@@ -0,0 +1,79 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Why a candidate did not resolve, and which resolver said so. `outcome`
+/// is `notfound` for NXDOMAIN or an empty answer, otherwise the failure
+/// category (`timeout`, `connect_error`, ...).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Negative {
+    pub outcome: &'static str,
+    pub resolver: SocketAddr,
+}
+
+/// Streams negative results to a file as JSON lines, one per candidate,
+/// flagged `"found": false` so they can be concatenated with other
+/// per-name output. A single blocking task owns the file, like
+/// [`crate::querylog::QueryLog`].
+#[derive(Clone, Default)]
+pub struct NegativeLog {
+    tx: Option<mpsc::UnboundedSender<String>>,
+}
+
+pub struct NegativeLogTask(Option<JoinHandle<io::Result<()>>>);
+
+#[derive(Serialize)]
+struct Line<'a> {
+    name: &'a str,
+    found: bool,
+    #[serde(flatten)]
+    negative: &'a Negative,
+}
+
+impl NegativeLog {
+    pub fn create(path: &Path) -> io::Result<(Self, NegativeLogTask)> {
+        let out = BufWriter::new(File::create(path)?);
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = tokio::task::spawn_blocking(move || write_lines(out, rx));
+        Ok((Self { tx: Some(tx) }, NegativeLogTask(Some(handle))))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    pub fn record(&self, name: &str, negative: &Negative) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        if let Ok(line) = serde_json::to_string(&Line {
+            name,
+            found: false,
+            negative,
+        }) {
+            let _ = tx.send(line);
+        }
+    }
+}
+
+impl NegativeLogTask {
+    /// Flushes the file. All [`NegativeLog`] clones must be dropped first.
+    pub async fn finish(self) -> io::Result<()> {
+        match self.0 {
+            Some(handle) => handle.await.map_err(io::Error::other)?,
+            None => Ok(()),
+        }
+    }
+}
+
+fn write_lines(mut out: BufWriter<File>, mut rx: mpsc::UnboundedReceiver<String>) -> io::Result<()> {
+    while let Some(line) = rx.blocking_recv() {
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()
+}
@@ -0,0 +1,242 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::time::Duration;
+
+use hickory_client::proto::runtime::iocompat::AsyncIoTokioAsStd;
+use hickory_client::proto::runtime::{RuntimeProvider, TokioHandle, TokioRuntimeProvider, TokioTime};
+use serde::Serialize;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpStream, UdpSocket};
+use tracing::debug;
+
+// Descriptors kept free for stdin/stdout/stderr, the wordlist, the output file
+// and whatever the runtime opens on its own.
+pub(crate) const RESERVED_FDS: u64 = 64;
+/// Most raw engine sockets per address family. Each socket gets an equal
+/// share of the 16-bit query ID space and a thread of its own; past this
+/// there are too few IDs per socket to keep a fast scan's queries apart.
+pub const MAX_SOCKETS: usize = 256;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SocketTuning {
+    pub reuse_port: bool,
+    pub recv_buffer: Option<usize>,
+    pub send_buffer: Option<usize>,
+}
+
+impl SocketTuning {
+    pub fn with_buffers(size: usize) -> Self {
+        Self {
+            recv_buffer: Some(size),
+            send_buffer: Some(size),
+            ..Self::default()
+        }
+    }
+}
+
+/// Binds a UDP socket with the platform options in `tuning` applied. Options the
+/// OS doesn't support are skipped rather than treated as errors.
+pub fn bind_udp(local_addr: SocketAddr, tuning: &SocketTuning) -> io::Result<std::net::UdpSocket> {
+    let socket = Socket::new(Domain::for_address(local_addr), Type::DGRAM, Some(Protocol::UDP))?;
+
+    if tuning.reuse_port {
+        set_reuse_port(&socket)?;
+    }
+    if let Some(size) = tuning.recv_buffer
+        && let Err(e) = socket.set_recv_buffer_size(size)
+    {
+        debug!("could not set SO_RCVBUF to {}: {}", size, e);
+    }
+    if let Some(size) = tuning.send_buffer
+        && let Err(e) = socket.set_send_buffer_size(size)
+    {
+        debug!("could not set SO_SNDBUF to {}: {}", size, e);
+    }
+
+    socket.set_nonblocking(true)?;
+    socket.bind(&local_addr.into())?;
+    Ok(socket.into())
+}
+
+#[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin"))))]
+fn set_reuse_port(socket: &Socket) -> io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+#[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos", target_os = "cygwin")))))]
+fn set_reuse_port(socket: &Socket) -> io::Result<()> {
+    // Windows has no SO_REUSEPORT; SO_REUSEADDR is the closest equivalent for UDP.
+    socket.set_reuse_address(true)
+}
+
+/// Current soft limit on open file descriptors, or `None` where the OS has no
+/// meaningful per-process cap (Windows).
+// rlim_t is not u64 on every unix, hence the casts below.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+pub fn fd_limit() -> Option<u64> {
+    let mut rlim = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlim) } != 0 {
+        return None;
+    }
+    Some(rlim.rlim_cur as u64)
+}
+
+#[cfg(not(unix))]
+pub fn fd_limit() -> Option<u64> {
+    None
+}
+
+/// Raises the soft descriptor limit towards `wanted`, bounded by the hard limit
+/// (and OPEN_MAX on macOS). Returns the limit in effect afterwards.
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+pub fn raise_fd_limit(wanted: u64) -> Option<u64> {
+    let mut rlim = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut rlim) } != 0 {
+        return None;
+    }
+    let current = rlim.rlim_cur as u64;
+    if current >= wanted {
+        return Some(current);
+    }
+
+    let mut ceiling = rlim.rlim_max as u64;
+    if cfg!(target_os = "macos") {
+        // setrlimit rejects anything above OPEN_MAX even when the hard limit is unlimited.
+        ceiling = ceiling.min(10240);
+    }
+    let target = wanted.min(ceiling);
+    if target <= current {
+        return Some(current);
+    }
+
+    rlim.rlim_cur = target as libc::rlim_t;
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &rlim) } != 0 {
+        debug!("setrlimit(RLIMIT_NOFILE, {}) failed: {}", target, io::Error::last_os_error());
+        return Some(current);
+    }
+    debug!("raised open file limit from {} to {}", current, target);
+    Some(target)
+}
+
+#[cfg(not(unix))]
+pub fn raise_fd_limit(_wanted: u64) -> Option<u64> {
+    None
+}
+
+/// Checks that `sockets` raw engine sockets per address family can be
+/// bound: within [`MAX_SOCKETS`], and within the open file limit (raised
+/// if need be) for both families.
+pub fn check_socket_count(sockets: usize) -> Result<(), String> {
+    if sockets == 0 || sockets > MAX_SOCKETS {
+        return Err(format!("--sockets must be between 1 and {}, got {}", MAX_SOCKETS, sockets));
+    }
+    let wanted = 2 * sockets as u64 + RESERVED_FDS;
+    match raise_fd_limit(wanted) {
+        Some(limit) if limit < wanted => {
+            let usable = (limit.saturating_sub(RESERVED_FDS) / 2).max(1);
+            Err(format!(
+                "--sockets {} needs about {} open files but the limit is {}; {}",
+                sockets,
+                wanted,
+                limit,
+                fd_limit_guidance("--sockets", usable, wanted)
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn fd_limit_guidance(flag: &str, usable: u64, wanted: u64) -> String {
+    if cfg!(target_os = "macos") {
+        format!(
+            "lower {} to {} or raise the limit with `ulimit -n {}` (and `sudo launchctl limit maxfiles {} unlimited` if the hard limit is lower)",
+            flag, usable, wanted, wanted
+        )
+    } else if cfg!(target_os = "linux") {
+        format!(
+            "lower {} to {} or raise the limit with `ulimit -n {}` (persist it via `nofile` in /etc/security/limits.conf or LimitNOFILE= for systemd units)",
+            flag, usable, wanted
+        )
+    } else {
+        format!("lower {} to {} or raise the open file limit to {}", flag, usable, wanted)
+    }
+}
+
+/// Tokio runtime provider that binds its UDP sockets through [`bind_udp`] so the
+/// hickory client picks up the configured socket options.
+#[derive(Clone, Default)]
+pub struct TunedRuntimeProvider {
+    inner: TokioRuntimeProvider,
+    tuning: SocketTuning,
+}
+
+impl TunedRuntimeProvider {
+    pub fn new(tuning: SocketTuning) -> Self {
+        Self {
+            inner: TokioRuntimeProvider::default(),
+            tuning,
+        }
+    }
+}
+
+impl RuntimeProvider for TunedRuntimeProvider {
+    type Handle = TokioHandle;
+    type Timer = TokioTime;
+    type Udp = UdpSocket;
+    type Tcp = AsyncIoTokioAsStd<TcpStream>;
+
+    fn create_handle(&self) -> Self::Handle {
+        self.inner.create_handle()
+    }
+
+    fn connect_tcp(
+        &self,
+        server_addr: SocketAddr,
+        bind_addr: Option<SocketAddr>,
+        timeout: Option<Duration>,
+    ) -> Pin<Box<dyn Send + Future<Output = io::Result<Self::Tcp>>>> {
+        self.inner.connect_tcp(server_addr, bind_addr, timeout)
+    }
+
+    fn bind_udp(
+        &self,
+        local_addr: SocketAddr,
+        _server_addr: SocketAddr,
+    ) -> Pin<Box<dyn Send + Future<Output = io::Result<Self::Udp>>>> {
+        let socket = bind_udp(local_addr, &self.tuning);
+        Box::pin(async move { UdpSocket::from_std(socket?) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind_udp_with_tuning() {
+        let tuning = SocketTuning {
+            reuse_port: true,
+            ..SocketTuning::with_buffers(1 << 20)
+        };
+        let socket = bind_udp("127.0.0.1:0".parse().unwrap(), &tuning).unwrap();
+        assert_ne!(socket.local_addr().unwrap().port(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_raise_fd_limit_never_lowers() {
+        let before = fd_limit().unwrap();
+        assert!(raise_fd_limit(1).unwrap() >= before);
+    }
+
+    #[test]
+    fn test_check_socket_count() {
+        assert!(check_socket_count(4).is_ok());
+        assert!(check_socket_count(0).unwrap_err().contains("between 1 and 256"));
+        assert!(check_socket_count(MAX_SOCKETS + 1).unwrap_err().contains("got 257"));
+    }
+}
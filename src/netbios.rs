@@ -0,0 +1,143 @@
+//! NetBIOS name service (RFC 1002) lookups, tried for candidates DNS has no
+//! answer for when scanning an internal network: Windows hosts often
+//! register their names with a WINS server or answer broadcasts without
+//! ever being in DNS.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+use crate::scanner::Resolution;
+
+pub const NBNS_PORT: u16 = 137;
+/// NetBIOS names are 15 characters plus a one-byte suffix.
+pub const MAX_NAME_LEN: usize = 15;
+/// The suffix every Windows host registers its workstation name with.
+const WORKSTATION: u8 = 0x00;
+const TYPE_NB: u16 = 0x0020;
+const CLASS_IN: u16 = 0x0001;
+
+/// Where name queries go: a WINS server, or a broadcast address.
+#[derive(Debug, Clone, Serialize)]
+pub struct NetbiosFallback {
+    pub server: Ipv4Addr,
+    pub timeout: Duration,
+    /// Only candidates one label below this domain are asked for.
+    pub domain: String,
+}
+
+impl NetbiosFallback {
+    /// Asks for `name`'s first label when it sits directly below the
+    /// domain. `None` when the name does not fit NetBIOS or nobody answered.
+    pub async fn resolve(&self, name: &str) -> Option<Resolution> {
+        let label = name.strip_suffix(&self.domain)?.strip_suffix('.')?;
+        if label.contains('.') {
+            return None;
+        }
+        let packet = name_query(label, rand::random(), self.is_broadcast())?;
+        let socket = UdpSocket::bind("0.0.0.0:0").await.ok()?;
+        socket.set_broadcast(self.is_broadcast()).ok()?;
+        socket.send_to(&packet, SocketAddr::new(IpAddr::V4(self.server), NBNS_PORT)).await.ok()?;
+        let mut buf = [0u8; 576];
+        loop {
+            let (len, _) = timeout(self.timeout, socket.recv_from(&mut buf)).await.ok()?.ok()?;
+            if let Some(resolution) = parse_response(&buf[..len], &packet[..2]) {
+                return Some(resolution);
+            }
+        }
+    }
+
+    fn is_broadcast(&self) -> bool {
+        self.server.is_broadcast() || self.server.octets()[3] == 255
+    }
+}
+
+/// A name query for `name` with the workstation suffix, first-level
+/// encoded (each nibble as a letter from `A`).
+pub fn name_query(name: &str, id: u16, broadcast: bool) -> Option<Vec<u8>> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN || !name.is_ascii() {
+        return None;
+    }
+    let flags: u16 = if broadcast { 0x0110 } else { 0x0100 };
+    let mut packet = Vec::with_capacity(50);
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&flags.to_be_bytes());
+    packet.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    packet.push(32);
+    let mut padded = [b' '; 16];
+    padded[..name.len()].copy_from_slice(name.to_ascii_uppercase().as_bytes());
+    padded[15] = WORKSTATION;
+    for byte in padded {
+        packet.push(b'A' + (byte >> 4));
+        packet.push(b'A' + (byte & 0x0f));
+    }
+    packet.push(0);
+    packet.extend_from_slice(&TYPE_NB.to_be_bytes());
+    packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Some(packet)
+}
+
+/// The addresses of a positive name query response to the query with `id`.
+pub fn parse_response(packet: &[u8], id: &[u8]) -> Option<Resolution> {
+    let header = packet.get(..12)?;
+    // A response (bit 15) with no error code (low four bits) and an answer.
+    if &header[..2] != id || header[2] & 0x80 == 0 || header[3] & 0x0f != 0 || header[6..8] == [0, 0] {
+        return None;
+    }
+    let mut at = 12;
+    // The answer repeats the encoded name: length-prefixed labels.
+    while *packet.get(at)? != 0 {
+        at += 1 + *packet.get(at)? as usize;
+    }
+    at += 1;
+    let fixed = packet.get(at..at + 10)?;
+    if u16::from_be_bytes([fixed[0], fixed[1]]) != TYPE_NB {
+        return None;
+    }
+    let ttl = u32::from_be_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+    let length = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+    let data = packet.get(at + 10..at + 10 + length)?;
+    // Each entry is two bytes of flags and an IPv4 address.
+    let addresses: Vec<IpAddr> = data
+        .chunks_exact(6)
+        .map(|entry| IpAddr::V4(Ipv4Addr::new(entry[2], entry[3], entry[4], entry[5])))
+        .collect();
+    if addresses.is_empty() {
+        return None;
+    }
+    Some(Resolution {
+        cname_chain: Vec::new(),
+        addresses,
+        ttl: Some(ttl),
+        ..Resolution::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_query_round_trip() {
+        let query = name_query("fileserver", 0x1234, true).unwrap();
+        assert_eq!(&query[..4], &[0x12, 0x34, 0x01, 0x10]);
+        // FILESERVER padded with spaces, then the 0x00 suffix.
+        assert_eq!(&query[13..17], b"EGEJ");
+        assert_eq!(&query[13 + 30..13 + 32], b"AA");
+        assert!(name_query("a-name-longer-than-15", 1, false).is_none());
+
+        let mut response = query.clone();
+        response[2] = 0x85;
+        response[3] = 0x00;
+        response[4..8].copy_from_slice(&[0, 0, 0, 1]);
+        response.truncate(12 + 34);
+        response.extend_from_slice(&[0x00, 0x20, 0x00, 0x01, 0, 0, 0x0e, 0x10, 0, 6, 0, 0, 10, 0, 0, 7]);
+        let resolution = parse_response(&response, &[0x12, 0x34]).unwrap();
+        assert_eq!(resolution.addresses, [IpAddr::V4(Ipv4Addr::new(10, 0, 0, 7))]);
+        assert_eq!(resolution.ttl, Some(3600));
+        assert!(parse_response(&response, &[0, 0]).is_none());
+    }
+}
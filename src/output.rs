@@ -0,0 +1,807 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_json::{Value, json};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    /// Found names indented by label hierarchy under each target.
+    Tree,
+    /// Graphviz graph of targets, names, CNAME chains and addresses.
+    Dot,
+    GraphMl,
+    /// Flat asset list (type, value, first_seen, source) for attack-surface
+    /// management platforms.
+    Asm,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(OutputFormat::Json),
+            "tree" => Ok(OutputFormat::Tree),
+            "dot" => Ok(OutputFormat::Dot),
+            "graphml" => Ok(OutputFormat::GraphMl),
+            "asm" => Ok(OutputFormat::Asm),
+            _ => Err(format!("Unknown output format: {}", s)),
+        }
+    }
+}
+
+/// Order of found names in file output. Streamed stdout lines stay in the
+/// order answers arrive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    /// DNS canonical order: compared label by label from the right, so a
+    /// name sorts right after its parent.
+    #[default]
+    Name,
+    /// By first resolved address, then name.
+    Ip,
+    /// Most corroborated first: names produced by more sources, brute force
+    /// included, lead.
+    Score,
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(SortOrder::Name),
+            "ip" => Ok(SortOrder::Ip),
+            "score" => Ok(SortOrder::Score),
+            _ => Err(format!("Unknown sort order: {}", s)),
+        }
+    }
+}
+
+fn canonical_key(name: &str) -> Vec<String> {
+    name.trim_end_matches('.').rsplit('.').map(str::to_lowercase).collect()
+}
+
+/// Sorts the found names of one scan result in place.
+pub fn sort_results(result: &mut Value, order: SortOrder) {
+    let Some(mut names) = result["results"]["subdomain"].as_array().cloned() else {
+        return;
+    };
+    let first_ip = |name: &str| {
+        result["results"]["records"][name]["addresses"]
+            .as_array()
+            .and_then(|addresses| addresses.iter().filter_map(|a| a.as_str()?.parse::<IpAddr>().ok()).min())
+    };
+    let score = |name: &str| result["results"]["attribution"][name]["sources"].as_array().map_or(0, Vec::len);
+    names.sort_by_cached_key(|name| {
+        let name = name.as_str().unwrap_or_default();
+        let key = canonical_key(name);
+        match order {
+            SortOrder::Name => (false, None, Reverse(0), key),
+            // Names without an address go last.
+            SortOrder::Ip => {
+                let ip = first_ip(name);
+                (ip.is_none(), ip, Reverse(0), key)
+            }
+            SortOrder::Score => (false, None, Reverse(score(name)), key),
+        }
+    });
+    result["results"]["subdomain"] = Value::Array(names);
+}
+
+/// What to do when the output file already holds a scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExistingOutput {
+    Overwrite,
+    /// Keep previous runs and add this one: `{"scans": [previous..., new]}`.
+    Append,
+    /// Fold this run's findings into the previous result.
+    Merge,
+}
+
+/// Identifies a scan by what determines its results: each target and the
+/// digest of the wordlist it was scanned with.
+pub fn fingerprint(targets: &[(String, Option<String>)]) -> Value {
+    let targets: Vec<Value> = targets
+        .iter()
+        .map(|(domain, wordlist_sha256)| json!({ "domain": domain, "wordlist_sha256": wordlist_sha256 }))
+        .collect();
+    json!({ "targets": targets })
+}
+
+/// The fingerprint of the latest scan stored in an output document.
+pub fn stored_fingerprint(document: &Value) -> Option<&Value> {
+    match document["scans"].as_array() {
+        Some(scans) => scans.last()?.get("fingerprint"),
+        None => document.get("fingerprint"),
+    }
+}
+
+/// The per-target scan results of the latest scan stored in an output
+/// document, whichever shape it was written in.
+pub fn latest_scans(document: &Value) -> Vec<&Value> {
+    match document["scans"].as_array() {
+        Some(scans) => scans.last().map(scans_of).unwrap_or_default(),
+        None => scans_of(document),
+    }
+}
+
+/// The per-target scan results of every scan stored in an output document,
+/// oldest first.
+pub fn all_scans(document: &Value) -> Vec<&Value> {
+    match document["scans"].as_array() {
+        Some(scans) => scans.iter().flat_map(scans_of).collect(),
+        None => scans_of(document),
+    }
+}
+
+/// The scan document already at `path` that a scan with `fingerprint`
+/// keeps under `mode`, or why it may not be written there: without a mode
+/// an existing scan is never replaced, and one of a different scan is only
+/// kept beside this one (`--append`) or replaced (`--overwrite`).
+pub fn existing_document(path: &str, fingerprint: &Value, mode: Option<ExistingOutput>) -> Result<Option<Value>, String> {
+    let Some(previous) = std::fs::read_to_string(path).ok().and_then(|existing| serde_json::from_str::<Value>(&existing).ok()) else {
+        return Ok(None);
+    };
+    let same = stored_fingerprint(&previous) == Some(fingerprint);
+    match mode {
+        None if same => Err(format!("'{}' already holds a scan of the same targets and wordlist, pass --append, --merge or --overwrite", path)),
+        None | Some(ExistingOutput::Merge) if !same => Err(format!(
+            "'{}' holds a scan of other targets or another wordlist, pass --append to keep both or --overwrite to replace it",
+            path
+        )),
+        _ => Ok(Some(previous)),
+    }
+}
+
+/// Combines a previous output document with a new one, of the same scan
+/// unless appending.
+pub fn combine(previous: Value, new: Value, mode: ExistingOutput) -> Value {
+    match mode {
+        ExistingOutput::Overwrite => new,
+        ExistingOutput::Append => {
+            let mut scans = match previous {
+                Value::Object(mut map) if map.contains_key("scans") => match map.remove("scans") {
+                    Some(Value::Array(scans)) => scans,
+                    _ => Vec::new(),
+                },
+                other => vec![other],
+            };
+            scans.push(new);
+            json!({ "scans": scans })
+        }
+        ExistingOutput::Merge if previous["scans"].is_array() => {
+            // Appended runs keep their history; fold into the latest one.
+            let mut previous = previous;
+            if let Some(scans) = previous["scans"].as_array_mut()
+                && let Some(last) = scans.pop()
+            {
+                scans.push(combine(last, new, ExistingOutput::Merge));
+            }
+            previous
+        }
+        ExistingOutput::Merge => {
+            let mut merged = new;
+            let previous_scans = scans_of(&previous);
+            for scan in scans_of_mut(&mut merged) {
+                if let Some(old) = previous_scans.iter().find(|old| old["target"] == scan["target"]) {
+                    merge_scan(scan, old);
+                }
+            }
+            merged
+        }
+    }
+}
+
+fn scans_of(document: &Value) -> Vec<&Value> {
+    match document["registrable_domains"].as_object() {
+        Some(groups) => groups.values().filter_map(Value::as_array).flatten().collect(),
+        None => vec![document],
+    }
+}
+
+fn scans_of_mut(document: &mut Value) -> Vec<&mut Value> {
+    if document.get("registrable_domains").is_some() {
+        return document["registrable_domains"]
+            .as_object_mut()
+            .into_iter()
+            .flat_map(|groups| groups.values_mut())
+            .filter_map(Value::as_array_mut)
+            .flatten()
+            .collect();
+    }
+    vec![document]
+}
+
+/// Adds names, records and origins only `old` has to `scan`.
+fn merge_scan(scan: &mut Value, old: &Value) {
+    let old_names = old["results"]["subdomain"].as_array().cloned().unwrap_or_default();
+    for name in old_names {
+        let Some(key) = name.as_str() else {
+            continue;
+        };
+        let names = &mut scan["results"]["subdomain"];
+        if names.as_array().is_some_and(|names| names.contains(&name)) {
+            continue;
+        }
+        if let Some(names) = names.as_array_mut() {
+            names.push(name.clone());
+        }
+        for section in ["records", "origins", "attribution", "dns_history"] {
+            let value = &old["results"][section][key];
+            if !value.is_null() && scan["results"][section].is_object() {
+                scan["results"][section][key] = value.clone();
+            }
+        }
+    }
+    // Keyed by name, but a list, and kept for names the findings left out.
+    for disclosure in old["results"]["internal_ip_disclosure"].as_array().into_iter().flatten() {
+        let list = &mut scan["results"]["internal_ip_disclosure"];
+        if !list.is_array() {
+            *list = json!([]);
+        }
+        if let Some(list) = list.as_array_mut()
+            && !list.iter().any(|known| known["name"] == disclosure["name"])
+        {
+            list.push(disclosure.clone());
+        }
+    }
+}
+
+/// Renders scan results in a non-JSON `format`; `None` for JSON, which is
+/// serialized from the full result model instead.
+pub fn render(format: OutputFormat, results: &[Value]) -> Option<String> {
+    match format {
+        OutputFormat::Json => None,
+        OutputFormat::Tree => Some(render_trees(results)),
+        OutputFormat::Dot => Some(Graph::from_results(results).to_dot()),
+        OutputFormat::GraphMl => Some(Graph::from_results(results).to_graphml()),
+        OutputFormat::Asm => serde_json::to_string_pretty(&json!({ "assets": assets(results) })).ok(),
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    found: bool,
+    children: BTreeMap<String, Node>,
+}
+
+/// Renders `names` under `root` as an indented tree, one level per label:
+/// `v1.api.example.com` sits below `api`. Levels that were not themselves
+/// found are marked `(implied)`.
+pub fn render_tree(root: &str, names: &[String]) -> String {
+    let suffix = format!(".{}", root);
+    let mut tree = Node::default();
+    for name in names {
+        let Some(relative) = name.strip_suffix(&suffix) else {
+            continue;
+        };
+        let mut node = &mut tree;
+        let labels: Vec<&str> = relative.split('.').collect();
+        for depth in (0..labels.len()).rev() {
+            node = node.children.entry(labels[depth..].join(".")).or_default();
+        }
+        node.found = true;
+    }
+
+    let mut out = format!("{}\n", root);
+    write_children(&tree, "", &mut out);
+    out
+}
+
+fn write_children(node: &Node, prefix: &str, out: &mut String) {
+    let count = node.children.len();
+    for (i, (label, child)) in node.children.iter().enumerate() {
+        let last = i + 1 == count;
+        let _ = writeln!(
+            out,
+            "{}{}{}{}",
+            prefix,
+            if last { "└── " } else { "├── " },
+            label,
+            if child.found { "" } else { " (implied)" }
+        );
+        write_children(child, &format!("{}{}", prefix, if last { "    " } else { "│   " }), out);
+    }
+}
+
+/// Trees for every scan result in `results`, one per target.
+pub fn render_trees<'a>(results: impl IntoIterator<Item = &'a Value>) -> String {
+    let mut out = String::new();
+    for result in results {
+        let root = result["target"].as_str().unwrap_or_default();
+        let names: Vec<String> = result["results"]["subdomain"]
+            .as_array()
+            .map(|names| names.iter().filter_map(|n| n.as_str().map(str::to_string)).collect())
+            .unwrap_or_default();
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&render_tree(root, &names));
+    }
+    out
+}
+
+/// Infrastructure graph: targets own names, names alias (CNAME) other names
+/// and resolve to addresses.
+#[derive(Debug, Default)]
+pub struct Graph {
+    /// Node id to kind (`target`, `name`, `address`), in insertion order.
+    nodes: Vec<(String, &'static str)>,
+    edges: Vec<(String, String, &'static str)>,
+}
+
+impl Graph {
+    pub fn from_results<'a>(results: impl IntoIterator<Item = &'a Value>) -> Self {
+        let mut graph = Graph::default();
+        for result in results {
+            let target = result["target"].as_str().unwrap_or_default();
+            graph.node(target, "target");
+            let names = result["results"]["subdomain"].as_array().cloned().unwrap_or_default();
+            for name in names.iter().filter_map(Value::as_str) {
+                graph.node(name, "name");
+                graph.edge(target, name, "subdomain");
+
+                let record = &result["results"]["records"][name];
+                let mut last = name.to_string();
+                for cname in record["cname_chain"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                    graph.node(cname, "name");
+                    graph.edge(&last, cname, "cname");
+                    last = cname.to_string();
+                }
+                for address in record["addresses"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                    graph.node(address, "address");
+                    graph.edge(&last, address, "resolves_to");
+                }
+            }
+        }
+        graph
+    }
+
+    fn node(&mut self, id: &str, kind: &'static str) {
+        if !self.nodes.iter().any(|(n, _)| n == id) {
+            self.nodes.push((id.to_string(), kind));
+        }
+    }
+
+    fn edge(&mut self, from: &str, to: &str, kind: &'static str) {
+        let edge = (from.to_string(), to.to_string(), kind);
+        if !self.edges.contains(&edge) {
+            self.edges.push(edge);
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph subscan {\n    rankdir=LR;\n");
+        for (id, kind) in &self.nodes {
+            let shape = match *kind {
+                "target" => "doubleoctagon",
+                "address" => "box",
+                _ => "ellipse",
+            };
+            let _ = writeln!(out, "    \"{}\" [kind={}, shape={}];", id, kind, shape);
+        }
+        for (from, to, kind) in &self.edges {
+            let _ = writeln!(out, "    \"{}\" -> \"{}\" [label={}];", from, to, kind);
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"kind\" for=\"all\" attr.name=\"kind\" attr.type=\"string\"/>\n",
+            "  <graph id=\"subscan\" edgedefault=\"directed\">\n",
+        ));
+        for (id, kind) in &self.nodes {
+            let _ = writeln!(
+                out,
+                "    <node id=\"{}\"><data key=\"kind\">{}</data></node>",
+                xml_escape(id),
+                kind
+            );
+        }
+        for (from, to, kind) in &self.edges {
+            let _ = writeln!(
+                out,
+                "    <edge source=\"{}\" target=\"{}\"><data key=\"kind\">{}</data></edge>",
+                xml_escape(from),
+                xml_escape(to),
+                kind
+            );
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Asset {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub value: String,
+    pub first_seen: String,
+    pub source: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+}
+
+/// Every source that produced `name` in one target's results. Results
+/// written before attribution was recorded only know the passive sources,
+/// and `dns_bruteforce` when none reported it.
+pub fn sources(result: &Value, name: &str) -> Vec<String> {
+    if let Some(sources) = result["results"]["attribution"][name]["sources"].as_array() {
+        return sources.iter().filter_map(|source| source.as_str().map(str::to_string)).collect();
+    }
+    let mut sources: Vec<String> = result["results"]["origins"][name]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|origin| origin["source"].as_str().map(str::to_string))
+        .collect();
+    sources.dedup();
+    if sources.is_empty() {
+        sources.push("dns_bruteforce".to_string());
+    }
+    sources
+}
+
+/// One asset per target, found name and address. `first_seen` is the scan
+/// start unless passive DNS history knows an earlier sighting; `source` lists
+/// the passive sources that reported a name, or `dns_bruteforce`.
+pub fn assets(results: &[Value]) -> Vec<Asset> {
+    let mut assets: Vec<Asset> = Vec::new();
+    for result in results {
+        let target = result["target"].as_str().unwrap_or_default();
+        let scanned_at = result["started_at"].as_str().unwrap_or_default().to_string();
+        assets.push(Asset {
+            kind: "domain",
+            value: target.to_string(),
+            first_seen: scanned_at.clone(),
+            source: vec!["input".to_string()],
+            parent: None,
+        });
+
+        let names = result["results"]["subdomain"].as_array().cloned().unwrap_or_default();
+        for name in names.iter().filter_map(Value::as_str) {
+            let source = sources(result, name);
+            let first_seen = result["results"]["dns_history"][name]["records"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|record| record["time_first"].as_i64())
+                .min()
+                .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+                .map(|at| at.to_rfc3339())
+                .unwrap_or_else(|| scanned_at.clone());
+            assets.push(Asset {
+                kind: "subdomain",
+                value: name.to_string(),
+                first_seen: first_seen.clone(),
+                source,
+                parent: Some(target.to_string()),
+            });
+
+            let addresses = result["results"]["records"][name]["addresses"].as_array().cloned().unwrap_or_default();
+            for address in addresses.iter().filter_map(Value::as_str) {
+                if let Some(existing) = assets.iter_mut().find(|a| a.kind == "ip" && a.value == address) {
+                    existing.first_seen = existing.first_seen.clone().min(first_seen.clone());
+                    continue;
+                }
+                assets.push(Asset {
+                    kind: "ip",
+                    value: address.to_string(),
+                    first_seen: first_seen.clone(),
+                    source: vec!["dns_resolution".to_string()],
+                    parent: Some(name.to_string()),
+                });
+            }
+        }
+    }
+    assets
+}
+
+/// Findings as `name value` lines per record type, for `--split-output`:
+/// `a` and `aaaa` map each found name to its addresses, `cname` lists each
+/// hop of a chain, `ns` the nameservers of probed targets. `a`, `aaaa`
+/// and `cname` are always present, even when empty.
+pub fn split_by_type(results: &[Value]) -> BTreeMap<&'static str, Vec<String>> {
+    let mut split: BTreeMap<&'static str, Vec<String>> = ["a", "aaaa", "cname"].into_iter().map(|t| (t, Vec::new())).collect();
+    for result in results {
+        let names = result["results"]["subdomain"].as_array().cloned().unwrap_or_default();
+        for name in names.iter().filter_map(Value::as_str) {
+            let record = &result["results"]["records"][name];
+            let mut last = name;
+            for cname in record["cname_chain"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                split.entry("cname").or_default().push(format!("{} {}", last, cname));
+                last = cname;
+            }
+            for address in record["addresses"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                let kind = if address.contains(':') { "aaaa" } else { "a" };
+                split.entry(kind).or_default().push(format!("{} {}", name, address));
+            }
+        }
+        let target = result["target"].as_str().unwrap_or_default();
+        for ns in result["results"]["nameservers"].as_array().into_iter().flatten() {
+            if let Some(ns) = ns["name"].as_str() {
+                split.entry("ns").or_default().push(format!("{} {}", target, ns));
+            }
+        }
+    }
+    split
+}
+
+/// One scan result's found names as JSON lines, laid out like the lines
+/// of a `--spill-after` file so the two can be concatenated.
+pub fn name_lines(result: &Value) -> Vec<Value> {
+    let findings = &result["results"];
+    findings["subdomain"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(|name| {
+            let mut line = json!({
+                "name": name,
+                "found": true,
+                "resolution": findings["records"][name],
+                "attribution": findings["attribution"][name],
+                "name_score": findings["name_scores"][name],
+            });
+            for (field, map) in [("origins", "origins"), ("transport", "transport")] {
+                if let Some(value) = findings[map].get(name) {
+                    line[field] = value.clone();
+                }
+            }
+            line
+        })
+        .collect()
+}
+
+/// A page for one scan result that opens without a server: what was
+/// scanned, how the scan went and a table of the found names.
+pub fn render_html(result: &Value) -> String {
+    let target = xml_escape(result["target"].as_str().unwrap_or_default());
+    let findings = &result["results"];
+    let names: Vec<&str> = findings["subdomain"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+    let mut out = String::new();
+    let _ = writeln!(out, "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>subscan: {}</title>", target);
+    out.push_str(
+        "<style>body{font-family:sans-serif;margin:2em}table{border-collapse:collapse}td,th{border:1px solid #ccc;padding:4px 8px;text-align:left;vertical-align:top}th{background:#eee}</style>\n",
+    );
+    let _ = writeln!(out, "</head><body>\n<h1>{}</h1>", target);
+    let _ = writeln!(
+        out,
+        "<p>Started {}. {} names found, {} candidates, {} queries, {} errors.</p>",
+        xml_escape(result["started_at"].as_str().unwrap_or_default()),
+        names.len() as u64 + findings["spilled"]["names"].as_u64().unwrap_or(0),
+        findings["total_scanned"],
+        findings["queries_sent"],
+        findings["errors"]["total"],
+    );
+    if let Some(truncated) = findings["truncation"]["truncated"].as_u64().filter(|truncated| *truncated > 0) {
+        let _ = writeln!(
+            out,
+            "<p>{} responses were truncated: {} answered in full over TCP, {} may be missing records.</p>",
+            truncated, findings["truncation"]["recovered"], findings["truncation"]["lost"],
+        );
+    }
+    out.push_str("<table>\n<tr><th>Name</th><th>CNAME chain</th><th>Addresses</th><th>TTL</th><th>Sources</th><th>Confidence</th></tr>\n");
+    let list = |value: &Value| -> String {
+        let items: Vec<String> = value.as_array().into_iter().flatten().filter_map(Value::as_str).map(xml_escape).collect();
+        items.join("<br>")
+    };
+    for name in &names {
+        let record = &findings["records"][*name];
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            xml_escape(name),
+            list(&record["cname_chain"]),
+            list(&record["addresses"]),
+            record["ttl"].as_u64().map(|ttl| ttl.to_string()).unwrap_or_default(),
+            sources(result, name).iter().map(|source| xml_escape(source)).collect::<Vec<_>>().join(", "),
+            xml_escape(findings["attribution"][*name]["confidence"].as_str().unwrap_or_default()),
+        );
+    }
+    out.push_str("</table>\n");
+    if let Some(disclosures) = findings["internal_ip_disclosure"].as_array().filter(|list| !list.is_empty()) {
+        out.push_str("<h2>Internal IP disclosure</h2>\n<table>\n<tr><th>Name</th><th>Private addresses</th><th>Severity</th></tr>\n");
+        for disclosure in disclosures {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                xml_escape(disclosure["name"].as_str().unwrap_or_default()),
+                list(&disclosure["addresses"]),
+                xml_escape(disclosure["severity"].as_str().unwrap_or_default()),
+            );
+        }
+        out.push_str("</table>\n");
+    }
+    out.push_str("</body></html>\n");
+    out
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_tree() {
+        let names: Vec<String> = ["www.example.com", "v1.api.example.com", "api.example.com", "a.b.c.example.com"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(
+            render_tree("example.com", &names),
+            "example.com\n\
+             ├── api\n\
+             │   └── v1.api\n\
+             ├── c (implied)\n\
+             │   └── b.c (implied)\n\
+             │       └── a.b.c\n\
+             └── www\n"
+        );
+    }
+
+    fn sample() -> Value {
+        serde_json::json!({
+            "target": "example.com",
+            "results": {
+                "subdomain": ["www.example.com", "api.example.com"],
+                "records": {
+                    "www.example.com": { "cname_chain": ["cdn.example.net"], "addresses": ["1.1.1.1"] },
+                    "api.example.com": { "cname_chain": [], "addresses": ["1.1.1.1"] }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_sort_results() {
+        let mut result = sample();
+        result["results"]["subdomain"] = json!(["www.example.com", "b.api.example.com", "api.example.com", "mail.example.com"]);
+        result["results"]["records"]["mail.example.com"] = json!({ "cname_chain": [], "addresses": ["1.0.0.9"] });
+        result["results"]["attribution"] = json!({
+            "www.example.com": { "sources": ["dns_bruteforce", "crtsh", "wayback"] },
+            "mail.example.com": { "sources": ["crtsh"] },
+            "api.example.com": { "sources": ["dns_bruteforce"] }
+        });
+        let sorted = |order| {
+            let mut result = result.clone();
+            sort_results(&mut result, order);
+            result["results"]["subdomain"].clone()
+        };
+
+        assert_eq!(sorted(SortOrder::Name), json!(["api.example.com", "b.api.example.com", "mail.example.com", "www.example.com"]));
+        assert_eq!(sorted(SortOrder::Ip), json!(["mail.example.com", "api.example.com", "www.example.com", "b.api.example.com"]));
+        assert_eq!(sorted(SortOrder::Score), json!(["www.example.com", "api.example.com", "mail.example.com", "b.api.example.com"]));
+    }
+
+    #[test]
+    fn test_combine_existing() {
+        let mut previous = sample();
+        previous["fingerprint"] = fingerprint(&[("example.com".to_string(), Some("abc".to_string()))]);
+        previous["results"]["subdomain"] = json!(["old.example.com", "www.example.com"]);
+        previous["results"]["records"]["old.example.com"] = json!({ "cname_chain": [], "addresses": ["9.9.9.9"] });
+        let mut new = sample();
+        new["fingerprint"] = previous["fingerprint"].clone();
+
+        let merged = combine(previous.clone(), new.clone(), ExistingOutput::Merge);
+        assert_eq!(merged["results"]["subdomain"], json!(["www.example.com", "api.example.com", "old.example.com"]));
+        assert_eq!(merged["results"]["records"]["old.example.com"]["addresses"][0], "9.9.9.9");
+
+        let appended = combine(previous, new.clone(), ExistingOutput::Append);
+        let appended = combine(appended, new, ExistingOutput::Append);
+        assert_eq!(appended["scans"].as_array().unwrap().len(), 3);
+        assert_eq!(stored_fingerprint(&appended).unwrap()["targets"][0]["wordlist_sha256"], "abc");
+        let merged = combine(appended, sample(), ExistingOutput::Merge);
+        assert_eq!(merged["scans"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_different_scan_in_output() {
+        let path = std::env::temp_dir().join(format!("subscan-output-{}.json", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        let scan = |wordlist: &str| {
+            let mut scan = sample();
+            scan["fingerprint"] = fingerprint(&[("example.com".to_string(), Some(wordlist.to_string()))]);
+            scan
+        };
+        let (first, second) = (scan("abc"), scan("def"));
+        assert_eq!(existing_document(path_str, &first["fingerprint"], None), Ok(None));
+        std::fs::write(&path, first.to_string()).unwrap();
+
+        assert!(existing_document(path_str, &second["fingerprint"], None).is_err());
+        assert!(existing_document(path_str, &second["fingerprint"], Some(ExistingOutput::Merge)).is_err());
+        assert!(existing_document(path_str, &first["fingerprint"], None).is_err());
+        let previous = existing_document(path_str, &second["fingerprint"], Some(ExistingOutput::Append)).unwrap().unwrap();
+        std::fs::write(&path, combine(previous, second.clone(), ExistingOutput::Append).to_string()).unwrap();
+
+        let written: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["scans"], json!([first, second]));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_assets() {
+        let mut result = sample();
+        result["started_at"] = json!("2026-01-01T00:00:00+00:00");
+        result["results"]["origins"] = json!({ "api.example.com": [{ "name": "api.example.com", "source": "crtsh", "tags": [] }] });
+        result["results"]["dns_history"] = json!({ "www.example.com": { "records": [{ "time_first": 0 }] } });
+
+        let assets = assets(&[result]);
+        let kinds: Vec<_> = assets.iter().map(|a| (a.kind, a.value.as_str())).collect();
+        assert_eq!(
+            kinds,
+            vec![("domain", "example.com"), ("subdomain", "www.example.com"), ("ip", "1.1.1.1"), ("subdomain", "api.example.com")]
+        );
+        assert_eq!(assets[1].first_seen, "1970-01-01T00:00:00+00:00");
+        assert_eq!(assets[1].source, vec!["dns_bruteforce"]);
+        assert_eq!(assets[3].source, vec!["crtsh"]);
+        assert_eq!(assets[3].first_seen, "2026-01-01T00:00:00+00:00");
+
+        let mut result = sample();
+        result["results"]["attribution"] = json!({ "www.example.com": { "sources": ["dns_bruteforce", "crtsh"], "confidence": "high" } });
+        assert_eq!(sources(&result, "www.example.com"), ["dns_bruteforce", "crtsh"]);
+    }
+
+    #[test]
+    fn test_split_by_type() {
+        let mut result = sample();
+        result["results"]["records"]["api.example.com"]["addresses"] = json!(["1.1.1.1", "2001:db8::1"]);
+        result["results"]["nameservers"] = json!([{ "name": "ns1.example.com" }]);
+        let split = split_by_type(&[result]);
+        assert_eq!(split["a"], ["www.example.com 1.1.1.1", "api.example.com 1.1.1.1"]);
+        assert_eq!(split["aaaa"], ["api.example.com 2001:db8::1"]);
+        assert_eq!(split["cname"], ["www.example.com cdn.example.net"]);
+        assert_eq!(split["ns"], ["example.com ns1.example.com"]);
+        assert!(split_by_type(&[])["a"].is_empty());
+    }
+
+    #[test]
+    fn test_target_files() {
+        let mut result = sample();
+        result["results"]["attribution"] = json!({ "www.example.com": { "sources": ["crtsh"], "confidence": "high" } });
+        let lines = name_lines(&result);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["name"], "www.example.com");
+        assert_eq!(lines[0]["resolution"]["cname_chain"], json!(["cdn.example.net"]));
+        assert_eq!(lines[0]["attribution"]["confidence"], "high");
+
+        result["results"]["subdomain"][1] = json!("<script>.example.com");
+        let html = render_html(&result);
+        assert!(html.contains("<td>www.example.com</td><td>cdn.example.net</td><td>1.1.1.1</td>"));
+        assert!(html.contains("&lt;script&gt;.example.com"));
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("Internal IP disclosure"));
+        result["results"]["internal_ip_disclosure"] = json!([{ "name": "db.example.com", "addresses": ["10.0.0.5"], "severity": "medium" }]);
+        assert!(render_html(&result).contains("<tr><td>db.example.com</td><td>10.0.0.5</td><td>medium</td></tr>"));
+    }
+
+    #[test]
+    fn test_graph_from_results() {
+        let graph = Graph::from_results([&sample()]);
+        assert_eq!(graph.nodes.len(), 5);
+        assert!(graph.edges.contains(&("www.example.com".to_string(), "cdn.example.net".to_string(), "cname")));
+        assert!(graph.edges.contains(&("cdn.example.net".to_string(), "1.1.1.1".to_string(), "resolves_to")));
+        assert!(graph.edges.contains(&("api.example.com".to_string(), "1.1.1.1".to_string(), "resolves_to")));
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph subscan {"));
+        assert!(dot.contains("\"example.com\" -> \"www.example.com\" [label=subdomain];"));
+        assert!(graph.to_graphml().contains("<node id=\"1.1.1.1\"><data key=\"kind\">address</data></node>"));
+    }
+}
@@ -0,0 +1,62 @@
+//! Kubernetes: clusters' API servers, ingress and dashboards, managed
+//! control planes, and in-cluster DNS leaking into the public zone. A
+//! `*.cluster.local` name only means something inside a cluster, so one
+//! turning up in public answers gives away service and namespace names.
+
+use hickory_client::proto::rr::RecordType;
+
+use super::{Match, Pack, Signature};
+use crate::escalation::{Owner, Rule};
+
+pub const PACK: Pack = Pack {
+    name: "kubernetes",
+    source: "pack:kubernetes",
+    candidates: &[
+        "k8s",
+        "kube",
+        "kubernetes",
+        "api.k8s",
+        "k8s-api",
+        "kube-api",
+        "kubeapi",
+        "ingress",
+        "ingress-nginx",
+        "traefik",
+        "argocd",
+        "argo",
+        "rancher",
+        "dashboard.k8s",
+        "k8s-dashboard",
+        "kubernetes-dashboard",
+        "harbor",
+        "registry",
+        "kiali",
+        "etcd",
+        "_etcd-server-ssl._tcp",
+        "_etcd-client-ssl._tcp",
+        "kubernetes.default.svc.cluster.local",
+    ],
+    rules: &[Rule { pattern: "_etcd-*._tcp.*", queries: &[(RecordType::SRV, Owner::Name)] }],
+    signatures: &[
+        Signature { service: "cluster_local_leak", matches: Match::Cname("*.cluster.local") },
+        Signature { service: "cluster_local_leak", matches: Match::Record(RecordType::SRV, "*.cluster.local") },
+        // Resolves only where a search list or wildcard completes in-cluster
+        // names with the public zone.
+        Signature { service: "cluster_local_name", matches: Match::Name("*.cluster.local.*") },
+        Signature { service: "api_server", matches: Match::Name("api.k8s.*") },
+        Signature { service: "api_server", matches: Match::Name("k8s-api.*") },
+        Signature { service: "api_server", matches: Match::Name("kube*api.*") },
+        Signature { service: "eks", matches: Match::Cname("*.eks.amazonaws.com") },
+        Signature { service: "aks", matches: Match::Cname("*.azmk8s.io") },
+        Signature { service: "doks", matches: Match::Cname("*.k8s.ondigitalocean.com") },
+        Signature { service: "argo_cd", matches: Match::Name("argocd.*") },
+        Signature { service: "rancher", matches: Match::Name("rancher.*") },
+        Signature { service: "dashboard", matches: Match::Name("kubernetes-dashboard.*") },
+        Signature { service: "dashboard", matches: Match::Name("k8s-dashboard.*") },
+        Signature { service: "dashboard", matches: Match::Name("dashboard.k8s.*") },
+        // etcd's discovery records, which only exist for clusters that
+        // bootstrap through DNS.
+        Signature { service: "etcd", matches: Match::Record(RecordType::SRV, "* 2379 *") },
+        Signature { service: "etcd", matches: Match::Record(RecordType::SRV, "* 2380 *") },
+    ],
+};
@@ -0,0 +1,46 @@
+//! Microsoft 365: Exchange Online, Teams (Skype for Business Online),
+//! Entra ID and Intune. Tenants point their own names at Microsoft's with
+//! CNAMEs, and the mail records name Exchange Online Protection.
+
+use hickory_client::proto::rr::RecordType;
+
+use super::{Match, Pack, Signature};
+use crate::escalation::{Owner, Rule};
+
+pub const PACK: Pack = Pack {
+    name: "microsoft365",
+    source: "pack:microsoft365",
+    candidates: &[
+        "autodiscover",
+        "lyncdiscover",
+        "sip",
+        "msoid",
+        "enterpriseregistration",
+        "enterpriseenrollment",
+        "selector1._domainkey",
+        "selector2._domainkey",
+        "_sip._tls",
+        "_sipfederationtls._tcp",
+        "adfs",
+        "sts",
+    ],
+    rules: &[
+        // The zone's own mail records show whether Exchange Online takes
+        // its mail.
+        Rule { pattern: "autodiscover.*", queries: &[(RecordType::MX, Owner::Parent("")), (RecordType::TXT, Owner::Parent(""))] },
+        Rule { pattern: "_sip._tls.*", queries: &[(RecordType::SRV, Owner::Name)] },
+        Rule { pattern: "_sipfederationtls._tcp.*", queries: &[(RecordType::SRV, Owner::Name)] },
+    ],
+    signatures: &[
+        Signature { service: "exchange_online", matches: Match::Cname("autodiscover.outlook.com") },
+        Signature { service: "exchange_online_protection", matches: Match::Record(RecordType::MX, "*.mail.protection.outlook.com") },
+        Signature { service: "exchange_online_protection", matches: Match::Record(RecordType::TXT, "*include:spf.protection.outlook.com*") },
+        Signature { service: "exchange_online_dkim", matches: Match::Cname("*._domainkey.*.onmicrosoft.com") },
+        Signature { service: "teams", matches: Match::Cname("*.online.lync.com") },
+        Signature { service: "teams", matches: Match::Record(RecordType::SRV, "*.online.lync.com") },
+        Signature { service: "entra_id", matches: Match::Cname("clientconfig.microsoftonline-p.net") },
+        Signature { service: "entra_device_registration", matches: Match::Cname("enterpriseregistration.windows.net") },
+        Signature { service: "intune", matches: Match::Cname("enterpriseenrollment*.manage.microsoft.com") },
+        Signature { service: "adfs", matches: Match::Name("adfs.*") },
+    ],
+};
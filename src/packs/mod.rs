@@ -0,0 +1,149 @@
+//! Service packs (`--pack microsoft365`): what to look for when a target
+//! runs a given ecosystem. Each pack is plain data in a module of its own,
+//! contributing candidate names to the scan, extra record types to ask
+//! for (as [`crate::escalation`] rules do, also of its candidates that
+//! have no address, like SRV-only names), and signatures that tell from
+//! the answers which of its services the target uses.
+
+mod kubernetes;
+mod microsoft365;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use hickory_client::proto::rr::RecordType;
+use serde::Serialize;
+
+use crate::escalation::{Escalated, Rule};
+use crate::names;
+use crate::passive::PassiveName;
+use crate::scanner::Resolution;
+
+/// Every pack, for `--pack`.
+pub const PACKS: &[&Pack] = &[&kubernetes::PACK, &microsoft365::PACK];
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Pack {
+    pub name: &'static str,
+    /// The source its candidates are attributed to.
+    pub source: &'static str,
+    /// Labels tried below the target, on top of the wordlist.
+    pub candidates: &'static [&'static str],
+    pub rules: &'static [Rule],
+    pub signatures: &'static [Signature],
+}
+
+/// What in a found name's answers points at one service. Patterns are
+/// [`names::glob`]s over lowercase names without the trailing dot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Match {
+    /// The found name itself.
+    Name(&'static str),
+    /// A name in its CNAME chain.
+    Cname(&'static str),
+    /// A record of the given type that escalation found for it.
+    Record(RecordType, &'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    pub service: &'static str,
+    pub matches: Match,
+}
+
+/// One signature that matched.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Detection {
+    pub name: String,
+    pub service: &'static str,
+    /// The name, CNAME target or record that matched.
+    pub evidence: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PackReport {
+    /// Services detected, each once.
+    pub services: BTreeSet<&'static str>,
+    pub detections: Vec<Detection>,
+}
+
+/// The pack called `name`.
+pub fn by_name(name: &str) -> Result<&'static Pack, String> {
+    PACKS.iter().copied().find(|pack| pack.name == name.to_lowercase()).ok_or_else(|| {
+        let available: Vec<&str> = PACKS.iter().map(|pack| pack.name).collect();
+        format!("unknown pack '{}' (available: {})", name, available.join(", "))
+    })
+}
+
+impl Pack {
+    /// The pack's candidates below `domain`, as names from its source.
+    pub fn candidates(&self, domain: &str) -> Vec<PassiveName> {
+        self.candidates.iter().map(|label| PassiveName::new(format!("{}.{}", label, domain), self.source)).collect()
+    }
+
+    /// Matches the pack's signatures against found names, their answers
+    /// and the records escalation found for them.
+    pub fn detect(&self, records: &BTreeMap<String, Resolution>, escalated: &BTreeMap<String, Vec<Escalated>>) -> PackReport {
+        let mut report = PackReport::default();
+        let found: BTreeSet<&String> = records.keys().chain(escalated.keys()).collect();
+        for name in found {
+            let cnames = records.get(name).map_or(&[][..], |resolution| resolution.cname_chain.as_slice());
+            let extra = escalated.get(name).map_or(&[][..], Vec::as_slice);
+            for signature in self.signatures {
+                if let Some(evidence) = signature.matches.evidence(name, cnames, extra) {
+                    report.services.insert(signature.service);
+                    report.detections.push(Detection { name: name.clone(), service: signature.service, evidence });
+                }
+            }
+        }
+        report
+    }
+}
+
+impl Match {
+    /// What matched, if anything did.
+    fn evidence(&self, name: &str, cnames: &[String], escalated: &[Escalated]) -> Option<String> {
+        let normalize = |value: &str| value.trim_end_matches('.').to_lowercase();
+        match *self {
+            Match::Name(pattern) => Some(normalize(name)).filter(|name| names::glob(pattern, name)),
+            Match::Cname(pattern) => cnames.iter().map(|cname| normalize(cname)).find(|cname| names::glob(pattern, cname)),
+            Match::Record(record_type, pattern) => escalated
+                .iter()
+                .filter(|found| found.record_type == record_type.to_string())
+                .flat_map(|found| found.records.iter().map(|record| normalize(record)))
+                .find(|record| names::glob(pattern, record)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let microsoft = by_name("Microsoft365").unwrap();
+        assert!(by_name("openshift").unwrap_err().contains("kubernetes, microsoft365"));
+        assert!(microsoft.candidates("example.com").iter().any(|name| name.name == "lyncdiscover.example.com"));
+
+        let resolution = |cnames: &[&str]| Resolution { cname_chain: cnames.iter().map(|c| c.to_string()).collect(), ..Default::default() };
+        let records = BTreeMap::from([
+            ("autodiscover.example.com".to_string(), resolution(&["autodiscover.outlook.com"])),
+            ("www.example.com".to_string(), resolution(&["example.azureedge.net"])),
+        ]);
+        let mx = Escalated {
+            owner: "example.com".to_string(),
+            record_type: "MX".to_string(),
+            records: vec!["0 example-com.mail.protection.outlook.com.".to_string()],
+        };
+        let escalated = BTreeMap::from([("autodiscover.example.com".to_string(), vec![mx])]);
+        let report = microsoft.detect(&records, &escalated);
+        assert_eq!(report.services, BTreeSet::from(["exchange_online", "exchange_online_protection"]));
+        assert!(report.detections.iter().all(|detection| detection.name == "autodiscover.example.com"));
+
+        let kubernetes = by_name("kubernetes").unwrap();
+        let leaked = BTreeMap::from([("grafana.example.com".to_string(), resolution(&["grafana.monitoring.svc.cluster.local."]))]);
+        let report = kubernetes.detect(&leaked, &BTreeMap::new());
+        assert_eq!(report.detections[0].evidence, "grafana.monitoring.svc.cluster.local");
+        assert_eq!(report.services, BTreeSet::from(["cluster_local_leak"]));
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+/// A hostname reported by a passive source, before it has been verified by DNS.
+///
+/// Lives outside [`crate::sources`] so the engine can carry origins in builds
+/// without the `sources` feature and its HTTP client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PassiveName {
+    pub name: String,
+    pub source: &'static str,
+    pub tags: Vec<&'static str>,
+}
+
+impl PassiveName {
+    pub fn new(name: impl Into<String>, source: &'static str) -> Self {
+        Self {
+            name: name.into(),
+            source,
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn tagged(mut self, tag: &'static str) -> Self {
+        self.tags.push(tag);
+        self
+    }
+}
+
+/// Names from any number of sources, merged as they arrive: one entry per
+/// name and source, with the tags of every report of it.
+#[derive(Debug, Default)]
+pub struct PassiveSet {
+    index: HashMap<(String, &'static str), usize>,
+    names: Vec<PassiveName>,
+}
+
+impl PassiveSet {
+    pub fn add(&mut self, name: PassiveName) {
+        match self.index.get(&(name.name.clone(), name.source)) {
+            Some(&i) => {
+                let existing = &mut self.names[i];
+                for tag in name.tags {
+                    if !existing.tags.contains(&tag) {
+                        existing.tags.push(tag);
+                    }
+                }
+            }
+            None => {
+                self.index.insert((name.name.clone(), name.source), self.names.len());
+                self.names.push(name);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// In the order they were first reported.
+    pub fn into_names(self) -> Vec<PassiveName> {
+        self.names
+    }
+}
+
+impl Extend<PassiveName> for PassiveSet {
+    fn extend<I: IntoIterator<Item = PassiveName>>(&mut self, names: I) {
+        for name in names {
+            self.add(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passive_set_merges_tags() {
+        let mut set = PassiveSet::default();
+        set.extend([
+            PassiveName::new("a.example.com", "crtsh").tagged("expired"),
+            PassiveName::new("a.example.com", "wayback"),
+            PassiveName::new("a.example.com", "crtsh").tagged("wildcard").tagged("expired"),
+        ]);
+        let names = set.into_names();
+        assert_eq!(names.len(), 2);
+        assert_eq!(names[0].tags, ["expired", "wildcard"]);
+        assert_eq!(names[1].source, "wayback");
+    }
+}
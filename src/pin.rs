@@ -0,0 +1,111 @@
+//! Pinning candidates to resolvers: names matching a pattern are only sent
+//! to the resolvers listed for it, e.g. `*.internal.example.com` to the
+//! internal resolvers of a hybrid network, and everything else only to the
+//! main resolver list.
+
+use std::ops::Range;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+/// `PATTERN=FILE`: candidates matching `PATTERN` go to the resolvers in
+/// `FILE`. `*.internal.example.com` matches every name below
+/// internal.example.com, anything else only that exact name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PinRule {
+    pub pattern: String,
+    pub resolvers: String,
+}
+
+impl FromStr for PinRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, resolvers) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Unknown resolver pin: {} (expected PATTERN=FILE)", s))?;
+        let pattern = pattern.trim().trim_end_matches('.').to_lowercase();
+        let bare = pattern.strip_prefix("*.").unwrap_or(&pattern);
+        if bare.is_empty() || bare.contains('*') {
+            return Err(format!("Unknown resolver pin pattern: {} (a name, or *. and a name)", pattern));
+        }
+        if resolvers.is_empty() {
+            return Err(format!("Resolver pin {} has no resolver file", pattern));
+        }
+        Ok(Self {
+            pattern,
+            resolvers: resolvers.to_string(),
+        })
+    }
+}
+
+impl PinRule {
+    pub fn matches(&self, name: &str) -> bool {
+        match self.pattern.strip_prefix("*.") {
+            Some(parent) => name.strip_suffix(parent).is_some_and(|rest| rest.len() > 1 && rest.ends_with('.')),
+            None => name == self.pattern,
+        }
+    }
+}
+
+/// Which slots of the scan's resolver list each candidate may use. The
+/// main list comes first; each rule's resolvers follow in a block of
+/// their own.
+#[derive(Debug, Clone)]
+pub struct ResolverPins {
+    default: Range<usize>,
+    rules: Vec<(PinRule, Range<usize>)>,
+}
+
+impl ResolverPins {
+    /// No pins: every candidate may use all `resolvers`.
+    pub fn none(resolvers: usize) -> Self {
+        Self {
+            default: 0..resolvers,
+            rules: Vec::new(),
+        }
+    }
+
+    /// Adds a rule whose resolvers fill `slots`; the first matching rule wins.
+    pub fn with_rule(mut self, rule: PinRule, slots: Range<usize>) -> Self {
+        self.rules.push((rule, slots));
+        self
+    }
+
+    /// The resolver slots for `name`.
+    pub fn slots(&self, name: &str) -> Range<usize> {
+        self.rules
+            .iter()
+            .find(|(rule, _)| rule.matches(name))
+            .map_or(self.default.clone(), |(_, slots)| slots.clone())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pins() {
+        let internal: PinRule = "*.Internal.example.com.=internal.txt".parse().unwrap();
+        assert_eq!(internal.pattern, "*.internal.example.com");
+        assert!(internal.matches("db.internal.example.com"));
+        assert!(internal.matches("a.b.internal.example.com"));
+        assert!(!internal.matches("internal.example.com"));
+        assert!(!internal.matches("xinternal.example.com"));
+        assert!("*.=r.txt".parse::<PinRule>().is_err());
+        assert!("a.*.example.com=r.txt".parse::<PinRule>().is_err());
+        assert!("vpn.example.com".parse::<PinRule>().is_err());
+
+        let pins = ResolverPins::none(3)
+            .with_rule(internal, 3..5)
+            .with_rule("vpn.example.com=vpn.txt".parse().unwrap(), 5..6);
+        assert_eq!(pins.slots("www.example.com"), 0..3);
+        assert_eq!(pins.slots("db.internal.example.com"), 3..5);
+        assert_eq!(pins.slots("vpn.example.com"), 5..6);
+    }
+}
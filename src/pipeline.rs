@@ -0,0 +1,466 @@
+//! The stages of a scan: generate → resolve → verify → enrich → output.
+//!
+//! Each stage runs its own fixed set of workers and hands work on through a
+//! bounded channel, so a slow stage holds the ones before it back instead of
+//! letting names pile up in memory. Generation and output run inside
+//! [`SubdomainScanner::scan`](crate::SubdomainScanner::scan); the stages in
+//! between live here.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use hickory_client::proto::rr::RecordType;
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::JoinSet;
+
+use crate::engine::RawEngine;
+use crate::health::{ResolverPool, UsageTally};
+use crate::negative::Negative;
+use crate::netbios::{self, NetbiosFallback};
+use crate::net::{SocketTuning, TunedRuntimeProvider};
+use crate::passive::PassiveName;
+use crate::pin::ResolverPins;
+use crate::printer::Printer;
+use crate::rtt::AdaptiveTimeout;
+use crate::scanner::{QueryFailure, QueryFlags, QueryOutcome, Resolution, SubdomainScanner};
+use crate::schedule::Scheduler;
+use crate::traffic::Traffic;
+use crate::tune::AutoTuner;
+use crate::wire::QueryTemplate;
+
+/// A name that resolved, and the resolver that said so.
+pub(crate) struct Found {
+    pub name: String,
+    pub resolution: Resolution,
+    pub resolver: SocketAddr,
+    /// Set when the answer came from a fallback like NetBIOS, not DNS.
+    pub fallback: Option<&'static str>,
+}
+
+pub(crate) enum Verified {
+    Confirmed(Found),
+    /// A second resolver had no answer for the name.
+    Unconfirmed(String),
+}
+
+/// A confirmed name with the passive sources that reported it.
+pub(crate) struct Enriched {
+    pub name: String,
+    pub resolution: Resolution,
+    pub fallback: Option<&'static str>,
+    pub origins: Option<Vec<PassiveName>>,
+}
+
+/// What a query needs besides the name, shared by every worker sending
+/// queries.
+#[derive(Clone)]
+pub(crate) struct QueryContext {
+    pub resolvers: Arc<Vec<SocketAddr>>,
+    pub timeout: Duration,
+    pub tuning: SocketTuning,
+    pub flags: QueryFlags,
+    pub traffic: Traffic,
+    /// Which resolvers each candidate may be sent to.
+    pub pins: Arc<ResolverPins>,
+    /// Record types asked for each name, all at once unless
+    /// `skip_after_nxdomain`.
+    pub record_types: Arc<[RecordType]>,
+    /// Asks the first record type alone and the rest only if the name
+    /// exists.
+    pub skip_after_nxdomain: bool,
+    /// Queries that failed at their resolver (timeout, refused socket,
+    /// garbage answer), one per record type and attempt, where a scan's
+    /// errors count each name once.
+    pub failed: Arc<AtomicU64>,
+    /// Set when queries go through the raw engine instead of hickory, with
+    /// a template per entry of `record_types`.
+    pub raw: Option<(Arc<RawEngine>, Arc<Vec<QueryTemplate>>)>,
+}
+
+impl QueryContext {
+    /// Asks `resolver` about `name` for every record type, the budget for
+    /// which the caller has reserved. Types not sent because the first came
+    /// back NXDOMAIN are given back to the budget.
+    async fn query(&self, resolver: SocketAddr, name: String, timeout: Duration) -> QueryOutcome {
+        let mut first = 0;
+        let mut outcome = None;
+        if self.skip_after_nxdomain && self.record_types.len() > 1 {
+            let asked = self.query_type(resolver, name.clone(), timeout, 0).await;
+            if matches!(asked, QueryOutcome::NxDomain) {
+                self.traffic.budget.refund(self.record_types.len() as u64 - 1);
+                return asked;
+            }
+            (first, outcome) = (1, Some(asked));
+        }
+        let asked = (first..self.record_types.len()).map(|index| self.query_type(resolver, name.clone(), timeout, index));
+        let outcomes = futures_util::future::join_all(asked).await;
+        outcome.into_iter().chain(outcomes).reduce(QueryOutcome::merge).unwrap_or(QueryOutcome::NotFound)
+    }
+
+    /// Sends the query for the `index`th of the record types asked. One
+    /// for a name that is not valid DNS never leaves the host, so it is
+    /// given back to the budget.
+    async fn query_type(&self, resolver: SocketAddr, name: String, timeout: Duration, index: usize) -> QueryOutcome {
+        let outcome = match &self.raw {
+            Some((engine, templates)) => engine.resolve(&templates[index], resolver, name, timeout, &self.traffic).await,
+            None => {
+                let provider = TunedRuntimeProvider::new(self.tuning.clone());
+                SubdomainScanner::try_resolve_type(resolver, timeout, provider, name, self.record_types[index], &self.flags, &self.traffic).await
+            }
+        };
+        match &outcome {
+            QueryOutcome::Failed(QueryFailure::Parse, _) => self.traffic.budget.refund(1),
+            QueryOutcome::Failed(..) => {
+                self.failed.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+        outcome
+    }
+}
+
+/// Sends one query per candidate, spread round-robin over the resolvers
+/// the [`ResolverPool`] has in rotation. The worker count caps
+/// concurrency; an [`AutoTuner`] or a shared [`Scheduler`] can hold it
+/// lower.
+pub(crate) struct ResolveStage {
+    pub context: QueryContext,
+    pub pool: Arc<ResolverPool>,
+    /// This scan's share of the pool's queries, which other targets may
+    /// share.
+    pub usage: Arc<UsageTally>,
+    pub tuner: Option<Arc<AutoTuner>>,
+    pub scheduler: Option<Scheduler>,
+    pub printer: Printer,
+    pub errors: mpsc::UnboundedSender<(String, QueryFailure, String)>,
+    /// Where candidates that got no answer go, if anywhere.
+    pub negatives: Option<mpsc::UnboundedSender<(String, Negative)>>,
+    /// Per-resolver timeouts, when they adapt to round-trip times.
+    pub adaptive: Option<Arc<AdaptiveTimeout>>,
+    /// Where names DNS has no answer for are asked next, if anywhere.
+    pub netbios: Option<NetbiosFallback>,
+    /// Where candidates the query budget left unsent go, if anywhere.
+    pub unsent: Option<mpsc::UnboundedSender<String>>,
+    /// Times a query that failed at its resolver is sent again, each time
+    /// to the next resolver in rotation.
+    pub retries: u32,
+    cursor: AtomicUsize,
+}
+
+impl ResolveStage {
+    pub fn new(
+        context: QueryContext,
+        pool: Arc<ResolverPool>,
+        tuner: Option<Arc<AutoTuner>>,
+        scheduler: Option<Scheduler>,
+        printer: Printer,
+        errors: mpsc::UnboundedSender<(String, QueryFailure, String)>,
+    ) -> Self {
+        Self {
+            context,
+            usage: Arc::new(UsageTally::new(pool.resolvers().len())),
+            pool,
+            tuner,
+            scheduler,
+            printer,
+            errors,
+            negatives: None,
+            adaptive: None,
+            netbios: None,
+            unsent: None,
+            retries: 0,
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn with_negatives(mut self, negatives: Option<mpsc::UnboundedSender<(String, Negative)>>) -> Self {
+        self.negatives = negatives;
+        self
+    }
+
+    pub fn with_adaptive_timeout(mut self, adaptive: Option<Arc<AdaptiveTimeout>>) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    pub fn with_netbios(mut self, netbios: Option<NetbiosFallback>) -> Self {
+        self.netbios = netbios;
+        self
+    }
+
+    pub fn with_unsent(mut self, unsent: Option<mpsc::UnboundedSender<String>>) -> Self {
+        self.unsent = unsent;
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn spawn(self, workers: usize, candidates: mpsc::Receiver<String>, found: mpsc::Sender<Found>) -> JoinSet<()> {
+        let stage = Arc::new(self);
+        let candidates = Arc::new(Mutex::new(candidates));
+        let mut set = JoinSet::new();
+        for _ in 0..workers.max(1) {
+            let stage = stage.clone();
+            let candidates = candidates.clone();
+            let found = found.clone();
+            set.spawn(async move {
+                loop {
+                    let Some(name) = candidates.lock().await.recv().await else {
+                        break;
+                    };
+                    if stage.resolve(name, &found).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        set
+    }
+
+    async fn resolve(&self, name: String, found: &mpsc::Sender<Found>) -> Result<(), ()> {
+        let permit = match &self.tuner {
+            Some(tuner) => Some(tuner.semaphore().acquire_owned().await.unwrap()),
+            None => None,
+        };
+        let shared_permit = match &self.scheduler {
+            Some(scheduler) => Some(scheduler.acquire().await),
+            None => None,
+        };
+        let index = match &self.scheduler {
+            Some(scheduler) => scheduler.next_resolver(),
+            None => self.cursor.fetch_add(1, Ordering::Relaxed),
+        };
+        let mut attempt = 0;
+        let (resolver, outcome) = loop {
+            // With every resolver evicted this waits; once the pool is given
+            // up on, the worker stops and the scan finalizes.
+            let Some((slot, resolver)) = self.pool.pick(index + attempt, self.context.pins.slots(&name)).await else {
+                return Err(());
+            };
+            // Candidates queued before the budget ran out are drained unsent,
+            // as are retries it no longer covers; both are asked again on
+            // resume.
+            if !self.context.traffic.budget.try_spend_many(self.context.record_types.len() as u64) {
+                if let Some(unsent) = &self.unsent {
+                    let _ = unsent.send(name);
+                }
+                return Ok(());
+            }
+            // Exempt resolvers keep the fixed timeout and do not steer the tuner.
+            let exempt = self.pool.is_exempt(slot);
+            let adaptive = self.adaptive.as_ref().filter(|_| !exempt);
+            let timeout = match adaptive {
+                Some(adaptive) => adaptive.timeout(slot),
+                None => self.context.timeout,
+            };
+            let sent_at = Instant::now();
+            let outcome = self.context.query(resolver, name.clone(), timeout).await;
+            if let Some(adaptive) = adaptive {
+                match &outcome {
+                    QueryOutcome::Found(..) | QueryOutcome::NotFound | QueryOutcome::NxDomain => adaptive.record(slot, sent_at.elapsed()),
+                    QueryOutcome::Failed(QueryFailure::Timeout, _) => adaptive.record(slot, timeout),
+                    QueryOutcome::Failed(..) => {}
+                }
+            }
+            let resolver_failed = matches!(outcome, QueryOutcome::Failed(QueryFailure::Timeout | QueryFailure::Connect | QueryFailure::Protocol, _));
+            let evicted = self.pool.record(slot, resolver_failed);
+            self.usage.record(slot, resolver_failed, evicted);
+            if let Some(tuner) = &self.tuner
+                && !exempt
+            {
+                tuner.record(matches!(outcome, QueryOutcome::Failed(QueryFailure::Timeout, _)));
+            }
+            if !resolver_failed || attempt >= self.retries as usize {
+                break (resolver, outcome);
+            }
+            attempt += 1;
+        };
+        // Free the slot before handing on: a full next stage should stall
+        // this worker, not every other query in flight.
+        drop(permit);
+        drop(shared_permit);
+        match outcome {
+            QueryOutcome::Found(name, resolution) => found
+                .send(Found {
+                    name,
+                    resolution,
+                    resolver,
+                    fallback: None,
+                })
+                .await
+                .map_err(drop),
+            QueryOutcome::Failed(failure, detail) => {
+                self.printer.outcome(&name, failure.label());
+                self.negative(name.clone(), failure.label(), resolver);
+                let _ = self.errors.send((name, failure, detail));
+                Ok(())
+            }
+            QueryOutcome::NotFound | QueryOutcome::NxDomain => {
+                if let Some(netbios) = &self.netbios
+                    && let Some(resolution) = netbios.resolve(&name).await
+                {
+                    return found
+                        .send(Found {
+                            name,
+                            resolution,
+                            resolver: SocketAddr::new(netbios.server.into(), netbios::NBNS_PORT),
+                            fallback: Some("netbios"),
+                        })
+                        .await
+                        .map_err(drop);
+                }
+                self.printer.outcome(&name, "notfound");
+                self.negative(name, "notfound", resolver);
+                Ok(())
+            }
+        }
+    }
+
+    fn negative(&self, name: String, outcome: &'static str, resolver: SocketAddr) {
+        if let Some(negatives) = &self.negatives {
+            let _ = negatives.send((name, Negative { outcome, resolver }));
+        }
+    }
+}
+
+/// With `enabled`, asks a second resolver about every found name and drops
+/// the ones it has no answer for, which weeds out resolvers that invent
+/// answers. Otherwise names pass straight through.
+pub(crate) struct VerifyStage {
+    pub context: QueryContext,
+    pub enabled: bool,
+    pub printer: Printer,
+    cursor: AtomicUsize,
+}
+
+impl VerifyStage {
+    pub fn new(context: QueryContext, enabled: bool, printer: Printer) -> Self {
+        Self {
+            enabled: enabled && context.resolvers.len() > 1,
+            context,
+            printer,
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn spawn(self, workers: usize, found: mpsc::Receiver<Found>, verified: mpsc::Sender<Verified>) -> JoinSet<()> {
+        let workers = if self.enabled { workers.max(1) } else { 1 };
+        let stage = Arc::new(self);
+        let found = Arc::new(Mutex::new(found));
+        let mut set = JoinSet::new();
+        for _ in 0..workers {
+            let stage = stage.clone();
+            let found = found.clone();
+            let verified = verified.clone();
+            set.spawn(async move {
+                loop {
+                    let Some(candidate) = found.lock().await.recv().await else {
+                        break;
+                    };
+                    if verified.send(stage.verify(candidate).await).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        set
+    }
+
+    async fn verify(&self, found: Found) -> Verified {
+        // Other resolvers cannot check a NetBIOS answer.
+        if !self.enabled || found.fallback.is_some() {
+            return Verified::Confirmed(found);
+        }
+        // A name pinned to a single resolver has nobody to ask twice.
+        let Some(resolver) = self.second_resolver(found.resolver, self.context.pins.slots(&found.name)) else {
+            return Verified::Confirmed(found);
+        };
+        if !self.context.traffic.budget.try_spend_many(self.context.record_types.len() as u64) {
+            return Verified::Confirmed(found);
+        }
+        match self.context.query(resolver, found.name.clone(), self.context.timeout).await {
+            QueryOutcome::NotFound | QueryOutcome::NxDomain => {
+                self.printer.outcome(&found.name, "unconfirmed");
+                Verified::Unconfirmed(found.name)
+            }
+            // A failed re-check says nothing against the first answer.
+            QueryOutcome::Found(..) | QueryOutcome::Failed(..) => Verified::Confirmed(found),
+        }
+    }
+
+    fn second_resolver(&self, first: SocketAddr, slots: Range<usize>) -> Option<SocketAddr> {
+        let resolvers = &self.context.resolvers[slots];
+        if resolvers.len() < 2 {
+            return None;
+        }
+        let index = self.cursor.fetch_add(1, Ordering::Relaxed);
+        let resolver = resolvers[index % resolvers.len()];
+        Some(if resolver == first { resolvers[(index + 1) % resolvers.len()] } else { resolver })
+    }
+}
+
+/// Attaches what passive sources reported about each confirmed name.
+pub(crate) async fn enrich(
+    mut verified: mpsc::Receiver<Verified>,
+    enriched: mpsc::Sender<Result<Enriched, String>>,
+    origins: Arc<HashMap<String, Vec<PassiveName>>>,
+) {
+    while let Some(next) = verified.recv().await {
+        let next = match next {
+            Verified::Confirmed(found) => Ok(Enriched {
+                origins: origins.get(&found.name).cloned(),
+                fallback: found.fallback,
+                name: found.name,
+                resolution: found.resolution,
+            }),
+            Verified::Unconfirmed(name) => Err(name),
+        };
+        if enriched.send(next).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::ShowMode;
+
+    #[tokio::test]
+    async fn test_second_resolver_differs() {
+        let resolvers: Vec<SocketAddr> = vec!["127.0.0.1:53".parse().unwrap(), "127.0.0.2:53".parse().unwrap()];
+        let context = QueryContext {
+            resolvers: Arc::new(resolvers.clone()),
+            timeout: Duration::from_secs(1),
+            tuning: SocketTuning::default(),
+            flags: QueryFlags::default(),
+            traffic: Traffic::default(),
+            pins: Arc::new(ResolverPins::none(2)),
+            record_types: Arc::new([RecordType::A]),
+            skip_after_nxdomain: true,
+            failed: Arc::default(),
+            raw: None,
+        };
+        let (printer, task) = Printer::spawn(ShowMode::None);
+        let stage = VerifyStage::new(context.clone(), true, printer.clone());
+        assert!(stage.enabled);
+        for _ in 0..4 {
+            assert_eq!(stage.second_resolver(resolvers[0], 0..2), Some(resolvers[1]));
+        }
+        assert_eq!(stage.second_resolver(resolvers[0], 1..2), None);
+
+        let single = QueryContext {
+            resolvers: Arc::new(vec![resolvers[0]]),
+            ..context
+        };
+        assert!(!VerifyStage::new(single, true, printer).enabled);
+        task.finish().await;
+    }
+}
@@ -0,0 +1,263 @@
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+
+use hickory_client::proto::op::ResponseCode;
+use hickory_client::proto::rr::{Name, RData, RecordType};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::net::{SocketTuning, TunedRuntimeProvider};
+use crate::scanner::{self, QueryFlags, QueryOutcome, SubdomainScanner};
+use crate::traffic::Traffic;
+
+/// The CAA, MX and SPF records of a target and what stands out about them.
+/// A lookup that failed leaves its field `None` and raises no finding, so
+/// a flaky resolver is not mistaken for a missing record.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Posture {
+    pub caa: Option<Vec<String>>,
+    pub mx: Option<Vec<MailExchanger>>,
+    /// TXT records starting with `v=spf1`.
+    pub spf: Option<Vec<String>>,
+    /// The domain does not exist. Found on the first lookup, it saves the
+    /// others: a name missing for one record type is missing for all.
+    pub nxdomain: bool,
+    pub findings: Vec<Finding>,
+}
+
+/// The answer to one lookup.
+pub(crate) enum Lookup {
+    Records(Vec<RData>),
+    NxDomain,
+    Failed,
+}
+
+impl Lookup {
+    pub(crate) fn records(self) -> Option<Vec<RData>> {
+        match self {
+            Lookup::Records(records) => Some(records),
+            Lookup::NxDomain => Some(Vec::new()),
+            Lookup::Failed => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MailExchanger {
+    pub preference: u16,
+    pub exchange: String,
+    pub addresses: Vec<IpAddr>,
+    /// The exchange has no address: the name does not exist or has no
+    /// A/AAAA record. A lookup that failed does not count.
+    pub dead: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub detail: String,
+}
+
+/// Looks up the CAA, MX and TXT records of `domain` through `resolver`,
+/// resolves every mail exchanger and assesses the result.
+pub async fn probe(resolver: SocketAddr, timeout: Duration, domain: &str, traffic: &Traffic) -> Posture {
+    let caa = lookup(resolver, timeout, domain, RecordType::CAA, traffic).await;
+    if matches!(caa, Lookup::NxDomain) {
+        return Posture {
+            nxdomain: true,
+            ..Posture::default()
+        };
+    }
+    let caa = caa.records().map(|records| {
+        records
+            .iter()
+            .filter_map(|record| match record {
+                RData::CAA(caa) => Some(caa.to_string()),
+                _ => None,
+            })
+            .collect()
+    });
+    let spf = lookup(resolver, timeout, domain, RecordType::TXT, traffic).await.records().map(|records| {
+        records
+            .iter()
+            .filter_map(|record| match record {
+                RData::TXT(txt) => Some(
+                    txt.txt_data()
+                        .iter()
+                        .map(|part| String::from_utf8_lossy(part))
+                        .collect::<Vec<_>>()
+                        .join(""),
+                ),
+                _ => None,
+            })
+            .filter(|txt| txt.to_lowercase().starts_with("v=spf1"))
+            .collect()
+    });
+    let mx = match lookup(resolver, timeout, domain, RecordType::MX, traffic).await.records() {
+        Some(records) => {
+            let mut exchangers = Vec::new();
+            for record in records {
+                if let RData::MX(mx) = record {
+                    let exchange = mx.exchange().to_utf8().trim_end_matches('.').to_lowercase();
+                    exchangers.push(resolve_exchange(resolver, timeout, mx.preference(), exchange, traffic).await);
+                }
+            }
+            exchangers.sort_by(|a, b| (a.preference, &a.exchange).cmp(&(b.preference, &b.exchange)));
+            Some(exchangers)
+        }
+        None => None,
+    };
+
+    let mut posture = Posture {
+        caa,
+        mx,
+        spf,
+        nxdomain: false,
+        findings: Vec::new(),
+    };
+    posture.findings = assess(domain, &posture);
+    posture
+}
+
+async fn resolve_exchange(resolver: SocketAddr, timeout: Duration, preference: u16, exchange: String, traffic: &Traffic) -> MailExchanger {
+    // A null MX (`0 .`, RFC 7505) says the domain takes no mail at all.
+    if exchange.is_empty() {
+        return MailExchanger {
+            preference,
+            exchange,
+            addresses: Vec::new(),
+            dead: false,
+        };
+    }
+    let provider = TunedRuntimeProvider::new(SocketTuning::default());
+    let outcome = SubdomainScanner::try_resolve_once(
+        resolver,
+        timeout,
+        provider,
+        exchange.clone(),
+        &QueryFlags::default(),
+        traffic,
+    )
+    .await;
+    let (addresses, dead) = match outcome {
+        QueryOutcome::Found(_, resolution) => (resolution.addresses, false),
+        QueryOutcome::NotFound | QueryOutcome::NxDomain => (Vec::new(), true),
+        QueryOutcome::Failed(..) => (Vec::new(), false),
+    };
+    MailExchanger {
+        preference,
+        exchange,
+        addresses,
+        dead,
+    }
+}
+
+/// The findings for what was looked up about `domain`.
+pub fn assess(domain: &str, posture: &Posture) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut add = |check, detail: String| findings.push(Finding { check, detail });
+
+    if posture.caa.as_ref().is_some_and(Vec::is_empty) {
+        add("no_caa", format!("{} has no CAA record, so any certificate authority may issue for it", domain));
+    }
+
+    let accepts_mail = posture.mx.as_ref().is_some_and(|mx| mx.iter().any(|mx| !mx.exchange.is_empty()));
+    for mx in posture.mx.iter().flatten().filter(|mx| mx.dead) {
+        add("mx_dead", format!("MX {} has no address; mail for {} may bounce or be taken over", mx.exchange, domain));
+    }
+
+    match posture.spf.as_deref() {
+        Some([]) if accepts_mail => add("no_spf", format!("{} takes mail but publishes no SPF record", domain)),
+        Some([]) | None => {}
+        Some([spf]) => {
+            let all = spf.split_whitespace().map(str::to_lowercase).find(|term| term.trim_start_matches(['+', '-', '~', '?']) == "all");
+            match all.as_deref() {
+                Some("~all") => add("spf_softfail", format!("SPF for {} ends in ~all: failing mail is only marked, not rejected", domain)),
+                Some("?all") => add("spf_neutral", format!("SPF for {} ends in ?all: failing mail is treated as unknown", domain)),
+                Some("all") | Some("+all") => add("spf_pass_all", format!("SPF for {} ends in +all: any host may send as it", domain)),
+                Some(_) => {}
+                None if !spf.to_lowercase().contains("redirect=") => {
+                    add("spf_no_all", format!("SPF for {} has no all term, so unlisted hosts are neutral", domain))
+                }
+                None => {}
+            }
+        }
+        Some(several) => add(
+            "spf_multiple",
+            format!("{} publishes {} SPF records; receivers treat that as an error", domain, several.len()),
+        ),
+    }
+    findings
+}
+
+/// Asks `resolver` for the `record_type` records of `domain`.
+pub(crate) async fn lookup(resolver: SocketAddr, timeout: Duration, domain: &str, record_type: RecordType, traffic: &Traffic) -> Lookup {
+    let Ok(name) = Name::from_str(&format!("{}.", domain)) else {
+        return Lookup::Failed;
+    };
+    let message = scanner::build_query(name, record_type, &QueryFlags::default());
+    let provider = TunedRuntimeProvider::new(SocketTuning::default());
+    match scanner::exchange(resolver, timeout, provider, message, traffic).await {
+        Ok(response) if response.response_code() == ResponseCode::NXDomain => Lookup::NxDomain,
+        Ok(response) => Lookup::Records(
+            response
+                .answers()
+                .iter()
+                .filter(|record| record.record_type() == record_type)
+                .map(|record| record.data().clone())
+                .collect(),
+        ),
+        Err((_, e)) => {
+            debug!("{} lookup for {} failed: {}", record_type, domain, e);
+            Lookup::Failed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checks(posture: &Posture) -> Vec<&'static str> {
+        assess("example.com", posture).iter().map(|f| f.check).collect()
+    }
+
+    fn mx(exchange: &str, dead: bool) -> MailExchanger {
+        MailExchanger {
+            preference: 10,
+            exchange: exchange.to_string(),
+            addresses: vec![],
+            dead,
+        }
+    }
+
+    #[test]
+    fn test_assess() {
+        let mut posture = Posture {
+            caa: Some(vec![]),
+            mx: Some(vec![mx("mx1.example.com", false), mx("old.example.net", true)]),
+            spf: Some(vec!["v=spf1 include:_spf.example.net ~all".to_string()]),
+            nxdomain: false,
+            findings: vec![],
+        };
+        assert_eq!(checks(&posture), ["no_caa", "mx_dead", "spf_softfail"]);
+
+        posture.caa = None;
+        posture.spf = Some(vec![]);
+        assert_eq!(checks(&posture), ["mx_dead", "no_spf"]);
+
+        posture.mx = Some(vec![mx("", false)]);
+        assert!(checks(&posture).is_empty());
+
+        posture.caa = Some(vec!["0 issue \"letsencrypt.org\"".to_string()]);
+        posture.spf = Some(vec!["v=spf1 -all".to_string()]);
+        assert!(checks(&posture).is_empty());
+        posture.spf = Some(vec!["v=spf1 +all".to_string()]);
+        assert_eq!(checks(&posture), ["spf_pass_all"]);
+        posture.spf = Some(vec!["v=spf1 redirect=_spf.example.net".to_string()]);
+        assert!(checks(&posture).is_empty());
+        posture.spf = Some(vec!["v=spf1 -all".to_string(), "v=spf1 ~all".to_string()]);
+        assert_eq!(checks(&posture), ["spf_multiple"]);
+    }
+}
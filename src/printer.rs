@@ -0,0 +1,119 @@
+use std::io::{self, BufWriter, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, timeout_at};
+
+// Upper bound on how long a printed line can sit in the buffer.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShowMode {
+    /// Every candidate with its outcome, tab separated.
+    All,
+    /// Found names only, one per line.
+    #[default]
+    Found,
+    /// Nothing on stdout; results only go to the output file.
+    None,
+}
+
+impl FromStr for ShowMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "all" => Ok(ShowMode::All),
+            "found" => Ok(ShowMode::Found),
+            "none" => Ok(ShowMode::None),
+            _ => Err(format!("Unknown show mode: {}", s)),
+        }
+    }
+}
+
+/// Writes scan progress to stdout from a single task so query tasks never
+/// contend on the stdout lock, batching writes and flushing at most every
+/// [`FLUSH_INTERVAL`].
+#[derive(Clone)]
+pub struct Printer {
+    mode: ShowMode,
+    tx: Option<mpsc::UnboundedSender<String>>,
+}
+
+pub struct PrinterTask(Option<JoinHandle<()>>);
+
+impl Printer {
+    pub fn spawn(mode: ShowMode) -> (Self, PrinterTask) {
+        if mode == ShowMode::None {
+            return (Self { mode, tx: None }, PrinterTask(None));
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = tokio::task::spawn_blocking(move || write_lines(rx));
+        (Self { mode, tx: Some(tx) }, PrinterTask(Some(handle)))
+    }
+
+    pub fn outcome(&self, name: &str, outcome: &str) {
+        let line = match self.mode {
+            ShowMode::All => format!("{}\t{}", name, outcome),
+            ShowMode::Found if outcome == "found" => name.to_string(),
+            _ => return,
+        };
+        if let Some(tx) = &self.tx {
+            let _ = tx.send(line);
+        }
+    }
+
+    /// Marks a phase boundary in `--show all` output with a `#` line.
+    pub fn phase(&self, message: &str) {
+        if self.mode == ShowMode::All
+            && let Some(tx) = &self.tx
+        {
+            let _ = tx.send(format!("# {}", message));
+        }
+    }
+}
+
+impl PrinterTask {
+    /// Waits until everything sent so far has been written. All [`Printer`]
+    /// clones must be dropped first.
+    pub async fn finish(self) {
+        if let Some(handle) = self.0 {
+            let _ = handle.await;
+        }
+    }
+}
+
+fn write_lines(mut rx: mpsc::UnboundedReceiver<String>) {
+    let runtime = tokio::runtime::Handle::current();
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let mut deadline = None;
+
+    loop {
+        let next = match deadline {
+            Some(at) => runtime.block_on(async { timeout_at(at, rx.recv()).await }),
+            None => Ok(rx.blocking_recv()),
+        };
+        match next {
+            Ok(Some(line)) => {
+                // A closed pipe (e.g. `| head`) just means nobody is reading anymore.
+                if writeln!(out, "{}", line).is_err() {
+                    return;
+                }
+                let at = *deadline.get_or_insert_with(|| Instant::now() + FLUSH_INTERVAL);
+                if Instant::now() >= at {
+                    let _ = out.flush();
+                    deadline = None;
+                }
+            }
+            Ok(None) => break,
+            Err(_) => {
+                let _ = out.flush();
+                deadline = None;
+            }
+        }
+    }
+    let _ = out.flush();
+}
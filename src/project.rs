@@ -0,0 +1,216 @@
+//! Named projects: one directory per engagement that holds every scan's
+//! results, what changed since the scan before, monitor events and the
+//! HTTP cache, so repeated runs need no hand-picked paths.
+//!
+//! ```text
+//! ~/.subscan/projects/acme/
+//!     scans/20261014T154522Z/results.json, scan-manifest.json, diff.json
+//!     monitor.jsonl
+//!     word-history.json
+//!     resolver-reputation.json
+//!     cache/http/
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::history;
+use crate::output;
+
+pub const RESULTS_FILE: &str = "results.json";
+pub const DIFF_FILE: &str = "diff.json";
+
+#[derive(Debug, Clone)]
+pub struct Project {
+    pub name: String,
+    dir: PathBuf,
+}
+
+impl Project {
+    /// The project called `name` under `$SUBSCAN_HOME/projects`, by default
+    /// `~/.subscan/projects`, created if needed.
+    pub fn open(name: &str) -> Result<Self, String> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(format!("invalid project name '{}': use letters, digits, '-', '_' and '.'", name));
+        }
+        let base = std::env::var_os("SUBSCAN_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".subscan")))
+            .ok_or("neither SUBSCAN_HOME nor HOME is set, so projects have no home")?;
+        let project = Self {
+            name: name.to_string(),
+            dir: base.join("projects").join(name),
+        };
+        std::fs::create_dir_all(project.dir.join("scans"))
+            .map_err(|e| format!("could not create project directory {}: {}", project.dir.display(), e))?;
+        Ok(project)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Where a scan started at `started` goes; not created until the scan
+    /// has something to write.
+    pub fn next_scan_dir(&self, started: DateTime<Utc>) -> PathBuf {
+        let stamp = started.format("%Y%m%dT%H%M%SZ").to_string();
+        let mut dir = self.dir.join("scans").join(&stamp);
+        // Two scans in the same second get a suffix.
+        let mut n = 1;
+        while dir.exists() {
+            n += 1;
+            dir = self.dir.join("scans").join(format!("{}-{}", stamp, n));
+        }
+        dir
+    }
+
+    /// The results file of every finished scan, oldest first.
+    pub fn scan_results(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = std::fs::read_dir(self.dir.join("scans"))
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path().join(RESULTS_FILE))
+            .filter(|path| path.is_file())
+            .collect();
+        dirs.sort();
+        dirs
+    }
+
+    pub fn latest_results(&self) -> Option<PathBuf> {
+        self.scan_results().pop()
+    }
+
+    pub fn monitor_log(&self) -> PathBuf {
+        self.dir.join("monitor.jsonl")
+    }
+
+    /// Which words hit in the project's past scans.
+    pub fn word_history(&self) -> PathBuf {
+        self.dir.join(history::HISTORY_FILE)
+    }
+
+    /// How reliable each resolver was in the project's past scans.
+    pub fn resolver_reputation(&self) -> PathBuf {
+        self.dir.join("resolver-reputation.json")
+    }
+
+    pub fn http_cache_dir(&self) -> PathBuf {
+        self.dir.join("cache").join("http")
+    }
+}
+
+/// What changed between two scans: names that appeared or disappeared,
+/// and names whose addresses or CNAMEs differ.
+#[derive(Debug, Default, Serialize)]
+pub struct ScanDiff {
+    pub previous: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: BTreeMap<String, Change>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Change {
+    pub before: Value,
+    pub after: Value,
+}
+
+impl ScanDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares the latest scan in `current` with the latest in `previous`,
+/// both output documents. `previous_label` says which scan it was.
+pub fn diff(previous_label: &str, previous: &Value, current: &Value) -> ScanDiff {
+    let before = records(previous);
+    let after = records(current);
+    let mut diff = ScanDiff {
+        previous: previous_label.to_string(),
+        ..ScanDiff::default()
+    };
+    for (name, record) in &after {
+        match before.get(name) {
+            None => diff.added.push(name.clone()),
+            Some(old) if strip_ttl(old) != strip_ttl(record) => {
+                diff.changed.insert(
+                    name.clone(),
+                    Change {
+                        before: (*old).clone(),
+                        after: (*record).clone(),
+                    },
+                );
+            }
+            Some(_) => {}
+        }
+    }
+    let names: BTreeSet<&String> = after.keys().collect();
+    diff.removed = before.keys().filter(|name| !names.contains(name)).cloned().collect();
+    diff
+}
+
+fn records(document: &Value) -> BTreeMap<String, &Value> {
+    let mut records = BTreeMap::new();
+    for scan in output::latest_scans(document) {
+        let results = &scan["results"];
+        for name in results["subdomain"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+            records.insert(name.to_string(), &results["records"][name]);
+        }
+    }
+    records
+}
+
+// A TTL counting down between scans is not a change.
+fn strip_ttl(record: &Value) -> Value {
+    let mut record = record.clone();
+    if let Some(map) = record.as_object_mut() {
+        map.remove("ttl");
+    }
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn scan(records: Value) -> Value {
+        let names: Vec<String> = records.as_object().unwrap().keys().cloned().collect();
+        json!({ "target": "example.com", "results": { "subdomain": names, "records": records } })
+    }
+
+    #[test]
+    fn test_diff() {
+        let previous = scan(json!({
+            "www.example.com": { "cname_chain": [], "addresses": ["192.0.2.1"], "ttl": 300 },
+            "old.example.com": { "cname_chain": [], "addresses": ["192.0.2.2"], "ttl": 300 },
+            "api.example.com": { "cname_chain": [], "addresses": ["192.0.2.3"], "ttl": 300 },
+        }));
+        let current = scan(json!({
+            "www.example.com": { "cname_chain": [], "addresses": ["192.0.2.1"], "ttl": 12 },
+            "api.example.com": { "cname_chain": [], "addresses": ["192.0.2.30"], "ttl": 300 },
+            "new.example.com": { "cname_chain": [], "addresses": ["192.0.2.4"], "ttl": 300 },
+        }));
+        let diff = diff("20260101T000000Z", &previous, &current);
+        assert_eq!(diff.added, ["new.example.com"]);
+        assert_eq!(diff.removed, ["old.example.com"]);
+        assert_eq!(diff.changed.keys().collect::<Vec<_>>(), ["api.example.com"]);
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn test_project_name() {
+        assert!(Project::open("../etc").is_err());
+        assert!(Project::open("").is_err());
+        assert!(Project::open("a/b").is_err());
+    }
+}
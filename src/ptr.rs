@@ -0,0 +1,137 @@
+//! Reverse lookups of the addresses a scan found (`--ptr`), done once the
+//! scan is over: each distinct address is asked for its PTR records once,
+//! however many names point at it, and the hot loop sends nothing extra.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use hickory_client::proto::rr::{Name, RData, RecordType};
+use serde::Serialize;
+use tokio::task::JoinSet;
+
+use crate::posture;
+use crate::traffic::Traffic;
+
+/// Lookups sent at once.
+pub const CONCURRENCY: usize = 100;
+
+/// Resolvers asked for an address before it counts as failed.
+pub const ATTEMPTS: usize = 2;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PtrReport {
+    /// Distinct addresses looked up.
+    pub addresses: u64,
+    /// Of those, with at least one PTR record.
+    pub resolved: u64,
+    /// Of those, whose lookups all failed.
+    pub failed: u64,
+    /// Of those, left unanswered because the query budget was spent
+    /// before a lookup could be sent.
+    pub skipped: u64,
+    pub queries: u64,
+    /// PTR names of each address that has any. Serialized with the
+    /// findings' records instead, see [`PtrReport::for_addresses`].
+    #[serde(skip)]
+    pub names: BTreeMap<IpAddr, Vec<String>>,
+}
+
+impl PtrReport {
+    /// The PTR names of those of `addresses` that have any.
+    pub fn for_addresses(&self, addresses: &[IpAddr]) -> BTreeMap<IpAddr, Vec<String>> {
+        addresses.iter().filter_map(|address| Some((*address, self.names.get(address)?.clone()))).collect()
+    }
+}
+
+/// The `in-addr.arpa` or `ip6.arpa` name of `address`, without the
+/// trailing dot.
+pub fn reverse_name(address: IpAddr) -> String {
+    Name::from(address).to_utf8().trim_end_matches('.').to_string()
+}
+
+/// Looks up every address in `addresses` in reverse, spreading the
+/// lookups over `resolvers` and moving to the next one when a lookup
+/// fails. Each lookup counts against the query budget.
+pub async fn lookup_all(addresses: BTreeSet<IpAddr>, resolvers: &[SocketAddr], timeout: Duration, traffic: &Traffic) -> PtrReport {
+    let mut report = PtrReport { addresses: addresses.len() as u64, ..Default::default() };
+    if resolvers.is_empty() {
+        return report;
+    }
+    let mut set = JoinSet::new();
+    let mut pending = addresses.into_iter().enumerate();
+    loop {
+        while set.len() < CONCURRENCY
+            && let Some((i, address)) = pending.next()
+        {
+            let asked: Vec<SocketAddr> = (0..ATTEMPTS.min(resolvers.len())).map(|attempt| resolvers[(i + attempt) % resolvers.len()]).collect();
+            let traffic = traffic.clone();
+            set.spawn(async move {
+                let name = reverse_name(address);
+                let (mut queries, mut skipped) = (0, false);
+                for resolver in asked {
+                    if !traffic.budget.try_spend() {
+                        skipped = true;
+                        break;
+                    }
+                    queries += 1;
+                    if let Some(records) = posture::lookup(resolver, timeout, &name, RecordType::PTR, &traffic).await.records() {
+                        return (address, Some(ptr_names(&records)), queries, false);
+                    }
+                }
+                (address, None, queries, skipped)
+            });
+        }
+        let Some(joined) = set.join_next().await else {
+            break;
+        };
+        let Ok((address, names, queries, skipped)) = joined else {
+            continue;
+        };
+        report.queries += queries;
+        match names {
+            Some(names) if !names.is_empty() => {
+                report.resolved += 1;
+                report.names.insert(address, names);
+            }
+            Some(_) => {}
+            None if skipped => report.skipped += 1,
+            None => report.failed += 1,
+        }
+    }
+    report
+}
+
+fn ptr_names(records: &[RData]) -> Vec<String> {
+    let mut names: Vec<String> = records
+        .iter()
+        .filter_map(|record| match record {
+            RData::PTR(ptr) => Some(ptr.0.to_utf8().trim_end_matches('.').to_lowercase()),
+            _ => None,
+        })
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_client::proto::rr::rdata::PTR;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_reverse_names() {
+        assert_eq!(reverse_name("192.0.2.10".parse().unwrap()), "10.2.0.192.in-addr.arpa");
+        assert!(reverse_name("2001:db8::1".parse().unwrap()).ends_with(".8.b.d.0.1.0.0.2.ip6.arpa"));
+
+        let ptr = |name: &str| RData::PTR(PTR(Name::from_str(name).unwrap()));
+        assert_eq!(ptr_names(&[ptr("WWW.example.com."), ptr("host-10.example.net."), ptr("www.example.com.")]), ["host-10.example.net", "www.example.com"]);
+
+        let address: IpAddr = "192.0.2.10".parse().unwrap();
+        let report = PtrReport { names: BTreeMap::from([(address, vec!["www.example.com".to_string()])]), ..Default::default() };
+        let other: IpAddr = "192.0.2.11".parse().unwrap();
+        assert_eq!(report.for_addresses(&[address, other]), BTreeMap::from([(address, vec!["www.example.com".to_string()])]));
+    }
+}
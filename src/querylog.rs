@@ -0,0 +1,365 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/// Frame Streams content type for dnstap payloads.
+pub const CONTENT_TYPE: &[u8] = b"protobuf:dnstap.Dnstap";
+
+const CONTROL_START: u32 = 0x02;
+const CONTROL_STOP: u32 = 0x03;
+const CONTROL_FIELD_CONTENT_TYPE: u32 = 0x01;
+
+// dnstap.proto enum values.
+const DNSTAP_TYPE_MESSAGE: u64 = 1;
+pub const RESOLVER_QUERY: u64 = 3;
+pub const RESOLVER_RESPONSE: u64 = 4;
+const FAMILY_INET: u64 = 1;
+const FAMILY_INET6: u64 = 2;
+const PROTOCOL_UDP: u64 = 1;
+
+/// Records every query and response of a scan as a dnstap file (Frame
+/// Streams, readable by `dnstap-read`, `fstrm_capture` tooling and
+/// `subscan replay`). Encoding happens on the query tasks; a single blocking
+/// task owns the file, the same way [`crate::printer::Printer`] owns stdout.
+#[derive(Clone, Default)]
+pub struct QueryLog {
+    tx: Option<mpsc::UnboundedSender<Vec<u8>>>,
+}
+
+pub struct QueryLogTask(Option<JoinHandle<io::Result<()>>>);
+
+impl QueryLog {
+    pub fn create(path: &Path) -> io::Result<(Self, QueryLogTask)> {
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&control_frame(CONTROL_START, Some(CONTENT_TYPE)))?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = tokio::task::spawn_blocking(move || write_frames(out, rx));
+        Ok((Self { tx: Some(tx) }, QueryLogTask(Some(handle))))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.tx.is_some()
+    }
+
+    /// Logs one exchange with `resolver`. `query` is the wire-format message
+    /// as sent; `response` the raw answer, if one arrived.
+    pub fn exchange(&self, resolver: SocketAddr, query: &[u8], sent: SystemTime, response: Option<(&[u8], SystemTime)>) {
+        let Some(tx) = &self.tx else {
+            return;
+        };
+        let _ = tx.send(data_frame(&encode_dnstap(RESOLVER_QUERY, resolver, query, sent, None)));
+        if let Some((response, received)) = response {
+            let _ = tx.send(data_frame(&encode_dnstap(
+                RESOLVER_RESPONSE,
+                resolver,
+                query,
+                sent,
+                Some((response, received)),
+            )));
+        }
+    }
+}
+
+impl QueryLogTask {
+    /// Flushes the log and writes the closing STOP frame. All [`QueryLog`]
+    /// clones must be dropped first.
+    pub async fn finish(self) -> io::Result<()> {
+        match self.0 {
+            Some(handle) => handle.await.map_err(io::Error::other)?,
+            None => Ok(()),
+        }
+    }
+}
+
+fn write_frames(mut out: BufWriter<File>, mut rx: mpsc::UnboundedReceiver<Vec<u8>>) -> io::Result<()> {
+    while let Some(frame) = rx.blocking_recv() {
+        out.write_all(&frame)?;
+    }
+    out.write_all(&control_frame(CONTROL_STOP, None))?;
+    out.flush()
+}
+
+/// A query read back from a dnstap log, with the response it got if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoggedQuery {
+    pub resolver: SocketAddr,
+    pub query: Vec<u8>,
+    pub response: Option<Vec<u8>>,
+}
+
+/// Reads the resolver queries in a dnstap file, pairing each with its logged
+/// response. Messages of other types are skipped.
+pub fn read_log(path: &Path) -> io::Result<Vec<LoggedQuery>> {
+    let mut input = BufReader::new(File::open(path)?);
+    let mut queries: Vec<LoggedQuery> = Vec::new();
+    loop {
+        let mut len = [0u8; 4];
+        match input.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let len = u32::from_be_bytes(len);
+        if len == 0 {
+            // Control frame: skip its body, stop at STOP.
+            let mut control_len = [0u8; 4];
+            input.read_exact(&mut control_len)?;
+            let mut body = vec![0u8; u32::from_be_bytes(control_len) as usize];
+            input.read_exact(&mut body)?;
+            if body.get(..4) == Some(&CONTROL_STOP.to_be_bytes()[..]) {
+                break;
+            }
+            continue;
+        }
+        let mut payload = vec![0u8; len as usize];
+        input.read_exact(&mut payload)?;
+        let Some(message) = decode_dnstap(&payload) else {
+            continue;
+        };
+        match message.kind {
+            RESOLVER_QUERY => queries.push(LoggedQuery {
+                resolver: message.resolver,
+                query: message.query,
+                response: None,
+            }),
+            RESOLVER_RESPONSE => {
+                if let Some(query) = queries
+                    .iter_mut()
+                    .rev()
+                    .find(|q| q.response.is_none() && q.resolver == message.resolver && q.query == message.query)
+                {
+                    query.response = message.response;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(queries)
+}
+
+struct DecodedMessage {
+    kind: u64,
+    resolver: SocketAddr,
+    query: Vec<u8>,
+    response: Option<Vec<u8>>,
+}
+
+fn decode_dnstap(payload: &[u8]) -> Option<DecodedMessage> {
+    let message = fields(payload)?.into_iter().find_map(|(field, value)| match (field, value) {
+        (14, Field::Bytes(message)) => Some(message),
+        _ => None,
+    })?;
+
+    let mut kind = 0;
+    let mut address = None;
+    let mut port = 53;
+    let mut query = Vec::new();
+    let mut response = None;
+    for (field, value) in fields(message)? {
+        match (field, value) {
+            (1, Field::Varint(v)) => kind = v,
+            (5, Field::Bytes(b)) => address = ip_from_bytes(b),
+            (7, Field::Varint(v)) => port = u16::try_from(v).ok()?,
+            (10, Field::Bytes(b)) => query = b.to_vec(),
+            (14, Field::Bytes(b)) => response = Some(b.to_vec()),
+            _ => {}
+        }
+    }
+    Some(DecodedMessage {
+        kind,
+        resolver: SocketAddr::new(address?, port),
+        query,
+        response,
+    })
+}
+
+fn ip_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?)),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?)),
+        _ => None,
+    }
+}
+
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Splits a protobuf message into its top-level fields.
+fn fields(mut buf: &[u8]) -> Option<Vec<(u64, Field<'_>)>> {
+    let mut out = Vec::new();
+    while !buf.is_empty() {
+        let key = take_varint(&mut buf)?;
+        let value = match key & 7 {
+            0 => Field::Varint(take_varint(&mut buf)?),
+            1 => {
+                buf = buf.get(8..)?;
+                Field::Fixed
+            }
+            2 => {
+                let len = usize::try_from(take_varint(&mut buf)?).ok()?;
+                let bytes = buf.get(..len)?;
+                buf = &buf[len..];
+                Field::Bytes(bytes)
+            }
+            5 => {
+                buf = buf.get(4..)?;
+                Field::Fixed
+            }
+            _ => return None,
+        };
+        out.push((key >> 3, value));
+    }
+    Some(out)
+}
+
+fn take_varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first()?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn control_frame(kind: u32, content_type: Option<&[u8]>) -> Vec<u8> {
+    let mut body = kind.to_be_bytes().to_vec();
+    if let Some(content_type) = content_type {
+        body.extend_from_slice(&CONTROL_FIELD_CONTENT_TYPE.to_be_bytes());
+        body.extend_from_slice(&(content_type.len() as u32).to_be_bytes());
+        body.extend_from_slice(content_type);
+    }
+    // A zero length marks a control frame.
+    let mut frame = 0u32.to_be_bytes().to_vec();
+    frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&body);
+    frame
+}
+
+fn data_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = (payload.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn encode_dnstap(
+    kind: u64,
+    resolver: SocketAddr,
+    query: &[u8],
+    sent: SystemTime,
+    response: Option<(&[u8], SystemTime)>,
+) -> Vec<u8> {
+    let mut message = Vec::new();
+    put_varint_field(&mut message, 1, kind);
+    let (family, address) = match resolver.ip() {
+        IpAddr::V4(ip) => (FAMILY_INET, ip.octets().to_vec()),
+        IpAddr::V6(ip) => (FAMILY_INET6, ip.octets().to_vec()),
+    };
+    put_varint_field(&mut message, 2, family);
+    put_varint_field(&mut message, 3, PROTOCOL_UDP);
+    put_bytes_field(&mut message, 5, &address);
+    put_varint_field(&mut message, 7, u64::from(resolver.port()));
+    let (secs, nanos) = split_time(sent);
+    put_varint_field(&mut message, 8, secs);
+    put_fixed32_field(&mut message, 9, nanos);
+    put_bytes_field(&mut message, 10, query);
+    if let Some((response, received)) = response {
+        let (secs, nanos) = split_time(received);
+        put_varint_field(&mut message, 12, secs);
+        put_fixed32_field(&mut message, 13, nanos);
+        put_bytes_field(&mut message, 14, response);
+    }
+
+    let mut dnstap = Vec::new();
+    put_bytes_field(&mut dnstap, 1, b"subscan");
+    put_bytes_field(&mut dnstap, 2, env!("CARGO_PKG_VERSION").as_bytes());
+    put_bytes_field(&mut dnstap, 14, &message);
+    put_varint_field(&mut dnstap, 15, DNSTAP_TYPE_MESSAGE);
+    dnstap
+}
+
+fn split_time(at: SystemTime) -> (u64, u32) {
+    let since = at.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (since.as_secs(), since.subsec_nanos())
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_varint_field(out: &mut Vec<u8>, field: u64, value: u64) {
+    put_varint(out, field << 3);
+    put_varint(out, value);
+}
+
+fn put_fixed32_field(out: &mut Vec<u8>, field: u64, value: u32) {
+    put_varint(out, (field << 3) | 5);
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_bytes_field(out: &mut Vec<u8>, field: u64, value: &[u8]) {
+    put_varint(out, (field << 3) | 2);
+    put_varint(out, value.len() as u64);
+    out.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_and_encoding() {
+        let start = control_frame(CONTROL_START, Some(CONTENT_TYPE));
+        assert_eq!(&start[..4], &[0, 0, 0, 0]);
+        assert_eq!(u32::from_be_bytes(start[4..8].try_into().unwrap()) as usize, start.len() - 8);
+        assert_eq!(&start[start.len() - CONTENT_TYPE.len()..], CONTENT_TYPE);
+
+        let mut out = Vec::new();
+        put_varint(&mut out, 300);
+        assert_eq!(out, vec![0xac, 0x02]);
+
+        let resolver: SocketAddr = "192.0.2.1:53".parse().unwrap();
+        let payload = encode_dnstap(RESOLVER_QUERY, resolver, b"\x12\x34", UNIX_EPOCH, None);
+        // identity, then the nested message starting with type=RESOLVER_QUERY.
+        assert_eq!(&payload[..9], b"\x0a\x07subscan");
+        assert!(payload.windows(2).any(|w| w == [0x08, RESOLVER_QUERY as u8]));
+        assert!(payload.ends_with(&[0x78, 0x01]));
+    }
+
+    #[tokio::test]
+    async fn test_log_round_trip() {
+        let path = std::env::temp_dir().join(format!("subscan-dnstap-{}", std::process::id()));
+        let (log, task) = QueryLog::create(&path).unwrap();
+        let v4: SocketAddr = "192.0.2.1:53".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:5353".parse().unwrap();
+        log.exchange(v4, b"query-a", SystemTime::now(), Some((b"answer-a", SystemTime::now())));
+        log.exchange(v6, b"query-b", SystemTime::now(), None);
+        drop(log);
+        task.finish().await.unwrap();
+
+        let queries = read_log(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            queries,
+            vec![
+                LoggedQuery { resolver: v4, query: b"query-a".to_vec(), response: Some(b"answer-a".to_vec()) },
+                LoggedQuery { resolver: v6, query: b"query-b".to_vec(), response: None },
+            ]
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! Other registrable domains that share infrastructure with the target:
+//! the addresses its names resolve to, its nameservers and its mail
+//! exchangers, looked up in reverse through passive DNS. A domain on the
+//! same dedicated server or nameservers is often the same organization's,
+//! and out of a scan's reach otherwise.
+//!
+//! Suggestions, not findings: they are listed apart from the results and
+//! only with `--related-domains`. Infrastructure that many unrelated
+//! domains share (CDN edges, hosting providers' nameservers) says nothing
+//! and is left out.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::cluster;
+use crate::domain::SuffixList;
+
+/// More registrable domains than this on one address or server makes it
+/// shared hosting rather than a link.
+pub const MAX_SHARED: usize = 20;
+/// Reverse lookups per target at most.
+pub const MAX_LOOKUPS: usize = 50;
+/// Example names kept per related domain.
+const MAX_EXAMPLES: usize = 5;
+
+/// One address, nameserver or mail exchanger of the target.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Infrastructure {
+    /// `ip`, `ns` or `mx`.
+    pub kind: &'static str,
+    pub value: String,
+    /// The target's names using it.
+    pub names: Vec<String>,
+}
+
+impl Infrastructure {
+    /// The record types whose data names it, for the reverse lookup.
+    pub fn record_types(&self) -> &'static [&'static str] {
+        match self.kind {
+            "ns" => &["NS"],
+            "mx" => &["MX"],
+            _ => &["A", "AAAA"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RelatedDomain {
+    pub domain: String,
+    /// What it shares with the target, as `kind value`.
+    pub shared: Vec<String>,
+    /// Some of its names that passive DNS has pointing there.
+    pub names: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RelatedReport {
+    pub infrastructure: Vec<Infrastructure>,
+    /// Most shared infrastructure first.
+    pub domains: Vec<RelatedDomain>,
+    /// Infrastructure left out as shared by more than [`MAX_SHARED`] domains.
+    pub shared_hosting: Vec<String>,
+}
+
+/// The addresses, nameservers and mail exchangers in one scan result.
+/// Private addresses and CDN edges are left out; nobody else's names on
+/// them mean anything.
+pub fn infrastructure(result: &Value) -> Vec<Infrastructure> {
+    let target = result["target"].as_str().unwrap_or_default().to_string();
+    let mut by_address: BTreeMap<IpAddr, BTreeSet<String>> = BTreeMap::new();
+    if let Some(records) = result["results"]["records"].as_object() {
+        for (name, record) in records {
+            let chain: Vec<String> = record["cname_chain"]
+                .as_array()
+                .map(|chain| chain.iter().filter_map(|c| c.as_str().map(str::to_string)).collect())
+                .unwrap_or_default();
+            let addresses: Vec<IpAddr> = record["addresses"]
+                .as_array()
+                .map(|addresses| addresses.iter().filter_map(|a| a.as_str()?.parse().ok()).collect())
+                .unwrap_or_default();
+            if cluster::identify_cdn(&chain, &addresses).is_some() {
+                continue;
+            }
+            for address in addresses.into_iter().filter(|a| is_public(*a)) {
+                by_address.entry(address).or_default().insert(name.clone());
+            }
+        }
+    }
+    let mut infrastructure: Vec<Infrastructure> = by_address
+        .into_iter()
+        .map(|(address, names)| Infrastructure { kind: "ip", value: address.to_string(), names: names.into_iter().collect() })
+        .collect();
+    let servers = [("ns", &result["results"]["nameservers"], "name"), ("mx", &result["results"]["posture"]["mx"], "exchange")];
+    for (kind, list, field) in servers {
+        let values: BTreeSet<String> = list
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|server| server[field].as_str())
+            .map(|value| value.trim_end_matches('.').to_lowercase())
+            .collect();
+        infrastructure.extend(values.into_iter().map(|value| Infrastructure { kind, value, names: vec![target.clone()] }));
+    }
+    infrastructure
+}
+
+/// Groups the names passive DNS has on each piece of `infrastructure`
+/// (`neighbours`, by index) by registrable domain, leaving out the
+/// target's own and anything on shared hosting.
+pub fn related(target: &str, infrastructure: Vec<Infrastructure>, neighbours: &[(usize, Vec<String>)], suffixes: &SuffixList) -> RelatedReport {
+    let own = suffixes.registrable_domain(target).unwrap_or_else(|| target.to_string());
+    let mut domains: BTreeMap<String, (BTreeSet<String>, BTreeSet<String>)> = BTreeMap::new();
+    let mut shared_hosting = Vec::new();
+    for (index, names) in neighbours {
+        let Some(piece) = infrastructure.get(*index) else {
+            continue;
+        };
+        let label = format!("{} {}", piece.kind, piece.value);
+        let mut by_domain: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for name in names {
+            let name = name.trim_end_matches('.').to_lowercase();
+            if let Some(domain) = suffixes.registrable_domain(&name)
+                && domain != own
+            {
+                by_domain.entry(domain).or_default().insert(name);
+            }
+        }
+        if by_domain.len() > MAX_SHARED {
+            shared_hosting.push(label);
+            continue;
+        }
+        for (domain, names) in by_domain {
+            let entry = domains.entry(domain).or_default();
+            entry.0.insert(label.clone());
+            entry.1.extend(names);
+        }
+    }
+    let mut domains: Vec<RelatedDomain> = domains
+        .into_iter()
+        .map(|(domain, (shared, names))| RelatedDomain {
+            domain,
+            shared: shared.into_iter().collect(),
+            names: names.into_iter().take(MAX_EXAMPLES).collect(),
+        })
+        .collect();
+    domains.sort_by(|a, b| b.shared.len().cmp(&a.shared.len()).then(a.domain.cmp(&b.domain)));
+    RelatedReport { infrastructure, domains, shared_hosting }
+}
+
+fn is_public(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(a) => !(a.is_private() || a.is_loopback() || a.is_link_local() || a.is_unspecified() || a.is_documentation() || a.is_broadcast()),
+        IpAddr::V6(a) => !(a.is_loopback() || a.is_unspecified() || (a.segments()[0] & 0xfe00) == 0xfc00 || (a.segments()[0] & 0xffc0) == 0xfe80),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_related() {
+        let result = json!({
+            "target": "example.com",
+            "results": {
+                "records": {
+                    "www.example.com": {"cname_chain": [], "addresses": ["93.184.216.34"]},
+                    "api.example.com": {"cname_chain": [], "addresses": ["93.184.216.34", "10.0.0.1"]},
+                    "cdn.example.com": {"cname_chain": ["example.com.cdn.cloudflare.net"], "addresses": ["104.16.1.1"]}
+                },
+                "nameservers": [{"name": "ns1.example-dns.net."}],
+                "posture": {"mx": [{"exchange": "mx.example-mail.net"}]}
+            }
+        });
+        let infrastructure = infrastructure(&result);
+        assert_eq!(infrastructure.len(), 3);
+        assert_eq!(infrastructure[0], Infrastructure { kind: "ip", value: "93.184.216.34".to_string(), names: vec!["api.example.com".to_string(), "www.example.com".to_string()] });
+        assert_eq!(infrastructure[1].value, "ns1.example-dns.net");
+        assert_eq!(infrastructure[2].record_types(), ["MX"]);
+
+        let crowded: Vec<String> = (0..=MAX_SHARED).map(|i| format!("www.tenant{}.com", i)).collect();
+        let neighbours = vec![
+            (0, vec!["shop.example.org.".to_string(), "www.example.com".to_string(), "example.org".to_string()]),
+            (1, vec!["example.org".to_string(), "example.net".to_string()]),
+            (2, crowded),
+        ];
+        let report = related("example.com", infrastructure, &neighbours, &SuffixList::Embedded);
+        assert_eq!(report.domains[0].domain, "example.org");
+        assert_eq!(report.domains[0].shared, ["ip 93.184.216.34", "ns ns1.example-dns.net"]);
+        assert_eq!(report.domains[0].names, ["example.org", "shop.example.org"]);
+        assert_eq!(report.domains[1].domain, "example.net");
+        assert_eq!(report.domains.len(), 2);
+        assert_eq!(report.shared_hosting, ["mx mx.example-mail.net"]);
+    }
+}
@@ -0,0 +1,153 @@
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hickory_client::proto::op::Message;
+use serde::Serialize;
+use serde_json::{Value, json};
+use tokio::net::UdpSocket;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::querylog::LoggedQuery;
+
+/// Outcome of re-sending one logged query.
+#[derive(Debug, Clone, Serialize)]
+pub struct Replayed {
+    pub name: String,
+    pub resolver: SocketAddr,
+    /// `None` when no answer arrived in time.
+    pub rcode: Option<String>,
+    pub answers: usize,
+    pub latency_ms: Option<u128>,
+    pub original_rcode: Option<String>,
+    pub original_answers: Option<usize>,
+    /// The replayed answer differs from the logged one in rcode or answer count.
+    pub changed: bool,
+}
+
+/// Re-issues `queries` byte for byte, to their original resolvers or spread
+/// round-robin over `resolvers` when given.
+pub async fn replay(queries: Vec<LoggedQuery>, resolvers: &[SocketAddr], concurrency: usize, timeout: Duration) -> Vec<Replayed> {
+    let semaphore = Arc::new(Semaphore::new(concurrency.max(1)));
+    let mut tasks = JoinSet::new();
+    for (i, logged) in queries.into_iter().enumerate() {
+        let resolver = if resolvers.is_empty() {
+            logged.resolver
+        } else {
+            resolvers[i % resolvers.len()]
+        };
+        let permit = semaphore.clone().acquire_owned().await.unwrap();
+        tasks.spawn(async move {
+            let replayed = replay_one(&logged, resolver, timeout).await;
+            drop(permit);
+            (i, replayed)
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok(result) = joined {
+            results.push(result);
+        }
+    }
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, replayed)| replayed).collect()
+}
+
+async fn replay_one(logged: &LoggedQuery, resolver: SocketAddr, timeout: Duration) -> Replayed {
+    let query = Message::from_vec(&logged.query).ok();
+    let name = query
+        .as_ref()
+        .and_then(|m| m.queries().first())
+        .map(|q| format!("{} {}", q.name(), q.query_type()))
+        .unwrap_or_default();
+    let original = logged.response.as_deref().and_then(|r| Message::from_vec(r).ok());
+
+    let started = Instant::now();
+    let response = exchange(&logged.query, resolver, timeout)
+        .await
+        .and_then(|bytes| Message::from_vec(&bytes).ok());
+    let latency_ms = response.as_ref().map(|_| started.elapsed().as_millis());
+
+    let summary = |m: &Message| (m.response_code().to_string(), m.answers().len());
+    let now = response.as_ref().map(summary);
+    let before = original.as_ref().map(summary);
+    Replayed {
+        name,
+        resolver,
+        changed: before.is_some() && now != before,
+        rcode: now.as_ref().map(|(rcode, _)| rcode.clone()),
+        answers: now.as_ref().map_or(0, |(_, answers)| *answers),
+        latency_ms,
+        original_rcode: before.as_ref().map(|(rcode, _)| rcode.clone()),
+        original_answers: before.map(|(_, answers)| answers),
+    }
+}
+
+async fn exchange(query: &[u8], resolver: SocketAddr, timeout: Duration) -> Option<Vec<u8>> {
+    let local: SocketAddr = if resolver.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local).await.ok()?;
+    socket.connect(resolver).await.ok()?;
+    socket.send(query).await.ok()?;
+    let id = query.get(..2)?;
+    let mut buf = vec![0u8; 4096];
+    tokio::time::timeout(timeout, async {
+        loop {
+            let n = socket.recv(&mut buf).await.ok()?;
+            if buf.get(..2) == Some(id) {
+                return Some(buf[..n].to_vec());
+            }
+        }
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+pub fn summarize(replayed: &[Replayed]) -> Value {
+    let answered: Vec<&Replayed> = replayed.iter().filter(|r| r.rcode.is_some()).collect();
+    let latencies: Vec<u128> = answered.iter().filter_map(|r| r.latency_ms).collect();
+    let avg = (!latencies.is_empty()).then(|| latencies.iter().sum::<u128>() / latencies.len() as u128);
+    json!({
+        "queries": replayed.len(),
+        "answered": answered.len(),
+        "timeouts": replayed.len() - answered.len(),
+        "changed": replayed.iter().filter(|r| r.changed).count(),
+        "avg_latency_ms": avg,
+        "max_latency_ms": latencies.iter().max(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        let entry = |rcode: Option<&str>, latency: Option<u128>, changed| Replayed {
+            name: "www.example.com. A".to_string(),
+            resolver: "192.0.2.1:53".parse().unwrap(),
+            rcode: rcode.map(str::to_string),
+            answers: 1,
+            latency_ms: latency,
+            original_rcode: Some("No Error".to_string()),
+            original_answers: Some(1),
+            changed,
+        };
+        let summary = summarize(&[
+            entry(Some("No Error"), Some(10), false),
+            entry(Some("Non-Existent Domain"), Some(30), true),
+            entry(None, None, true),
+        ]);
+        assert_eq!(summary["answered"], 2);
+        assert_eq!(summary["timeouts"], 1);
+        assert_eq!(summary["changed"], 2);
+        assert_eq!(summary["avg_latency_ms"], 20);
+        assert_eq!(summary["max_latency_ms"], 30);
+    }
+}
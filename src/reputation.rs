@@ -0,0 +1,116 @@
+//! How reliable each resolver has been in past scans, kept in the project
+//! directory so a scan starts out trying the resolvers known to answer and
+//! rarely the ones known to time out, instead of finding out again one
+//! eviction at a time.
+//!
+//! Counts from older scans fade: once a resolver has more than
+//! [`MAX_QUERIES`] queries on record they are all halved, so one that got
+//! fixed (or broke) is judged mostly by its recent scans.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::health::ResolverUsage;
+use crate::jsonfile;
+
+/// Queries on record per resolver before the old ones count for half.
+pub const MAX_QUERIES: u64 = 100_000;
+/// The weight of a resolver that always answers; unknown ones get it too.
+pub const MAX_WEIGHT: u32 = 8;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReputationDb {
+    #[serde(default)]
+    pub resolvers: BTreeMap<SocketAddr, Reputation>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Reputation {
+    pub scans: u64,
+    pub queries: u64,
+    /// Queries that timed out or got an unusable answer.
+    pub failures: u64,
+    pub evictions: u64,
+    pub last_scan: String,
+}
+
+impl Reputation {
+    /// The share of queries answered, pulled toward a half while there
+    /// are few to go on.
+    pub fn reliability(&self) -> f64 {
+        (self.queries.saturating_sub(self.failures) as f64 + 1.0) / (self.queries as f64 + 2.0)
+    }
+
+    /// Its share of the rotation: a resolver failing one query in two
+    /// costs a retry for every other candidate sent to it, so weight falls
+    /// with the square of reliability.
+    pub fn weight(&self) -> u32 {
+        ((MAX_WEIGHT as f64 * self.reliability().powi(2)).round() as u32).max(1)
+    }
+}
+
+impl ReputationDb {
+    /// An empty database when the file does not exist yet.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        jsonfile::load_or_default(path)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        jsonfile::save(path, self)
+    }
+
+    /// Counts one scan's use of its resolvers, at `at` (RFC 3339).
+    /// Resolvers it sent nothing to are left as they were.
+    pub fn record(&mut self, usage: &[ResolverUsage], at: &str) {
+        for used in usage.iter().filter(|used| used.queries > 0) {
+            let reputation = self.resolvers.entry(used.resolver).or_default();
+            reputation.scans += 1;
+            reputation.queries += used.queries;
+            reputation.failures += used.failures;
+            reputation.evictions += used.evictions;
+            reputation.last_scan = at.to_string();
+            while reputation.queries > MAX_QUERIES {
+                reputation.queries /= 2;
+                reputation.failures /= 2;
+                reputation.evictions /= 2;
+            }
+        }
+    }
+
+    /// Rotation weights for `resolvers`, in order.
+    pub fn weights(&self, resolvers: &[SocketAddr]) -> Vec<u32> {
+        resolvers
+            .iter()
+            .map(|resolver| self.resolvers.get(resolver).map_or(MAX_WEIGHT, Reputation::weight))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_weights() {
+        let good: SocketAddr = "192.0.2.1:53".parse().unwrap();
+        let bad: SocketAddr = "192.0.2.2:53".parse().unwrap();
+        let unknown: SocketAddr = "192.0.2.3:53".parse().unwrap();
+        let usage = |resolver, queries, failures| ResolverUsage { resolver, queries, failures, evictions: 4 };
+        let mut db = ReputationDb::default();
+        db.record(&[usage(good, 1000, 10), usage(bad, 1000, 600), usage(unknown, 0, 0)], "2026-01-01T00:00:00Z");
+        db.record(&[usage(good, 1000, 0)], "2026-01-02T00:00:00Z");
+        assert_eq!(db.resolvers[&good].scans, 2);
+        assert_eq!(db.resolvers[&good].last_scan, "2026-01-02T00:00:00Z");
+        assert!(!db.resolvers.contains_key(&unknown));
+        assert_eq!(db.weights(&[good, bad, unknown]), [8, 1, MAX_WEIGHT]);
+
+        db.record(&[usage(bad, 2 * MAX_QUERIES, 0)], "2026-01-03T00:00:00Z");
+        assert!(db.resolvers[&bad].queries <= MAX_QUERIES);
+        assert_eq!(db.resolvers[&bad].failures, 600 / 4);
+        assert_eq!(db.resolvers[&bad].evictions, (4 + 4) / 4);
+    }
+}
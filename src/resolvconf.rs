@@ -0,0 +1,110 @@
+//! The search list of a resolv.conf, for `--search-domains`: a short
+//! candidate like `jira` is also tried as `jira.corp.example.com` for each
+//! search domain, the way an internal client typing `jira` resolves it.
+//!
+//! Follows the resolver's own rules: `search` and `domain` replace each
+//! other and the last one wins, and only words with fewer dots than
+//! `options ndots:N` (default 1) are expanded.
+
+use std::path::Path;
+
+use serde::Serialize;
+
+pub const DEFAULT_PATH: &str = "/etc/resolv.conf";
+/// The most `ndots` the resolver honours.
+const MAX_NDOTS: usize = 15;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchList {
+    pub domains: Vec<String>,
+    pub ndots: usize,
+}
+
+impl SearchList {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let list = Self::parse(&text);
+        if list.domains.is_empty() {
+            return Err(format!("{} has no search or domain line", path.display()));
+        }
+        Ok(list)
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut list = Self { domains: Vec::new(), ndots: 1 };
+        for line in text.lines() {
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("search" | "domain") => {
+                    list.domains.clear();
+                    for domain in words {
+                        let domain = domain.trim_end_matches('.').to_lowercase();
+                        if !domain.is_empty() && !list.domains.contains(&domain) {
+                            list.domains.push(domain);
+                        }
+                    }
+                }
+                Some("options") => {
+                    for option in words {
+                        if let Some(ndots) = option.strip_prefix("ndots:").and_then(|n| n.parse::<usize>().ok()) {
+                            list.ndots = ndots.min(MAX_NDOTS);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        list
+    }
+
+    /// The names to also try for `word`, the part of a candidate below the
+    /// target: one per search domain other than the target itself, none
+    /// when the word has `ndots` dots or more.
+    pub fn expand<'a>(&'a self, word: &'a str, target: &'a str) -> impl Iterator<Item = String> + 'a {
+        let short = word.matches('.').count() < self.ndots;
+        self.domains
+            .iter()
+            .filter(move |domain| short && domain.as_str() != target)
+            .map(move |domain| format!("{}.{}", word, domain))
+    }
+
+    /// The search domain `name` is an expansion of, if it is one.
+    pub fn suffix_of(&self, name: &str, target: &str) -> Option<&str> {
+        self.domains.iter().map(String::as_str).find(|domain| {
+            *domain != target
+                && name
+                    .strip_suffix(domain)
+                    .and_then(|word| word.strip_suffix('.'))
+                    .is_some_and(|word| !word.is_empty() && word.matches('.').count() < self.ndots)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_list() {
+        let list = SearchList::parse(
+            "nameserver 10.0.0.2\ndomain old.example.com\nsearch Corp.example.com. lab.example.net corp.example.com # comment\noptions ndots:2 timeout:1\n",
+        );
+        assert_eq!(list.domains, ["corp.example.com", "lab.example.net"]);
+        assert_eq!(list.ndots, 2);
+        assert_eq!(
+            list.expand("jira", "example.com").collect::<Vec<_>>(),
+            ["jira.corp.example.com", "jira.lab.example.net"]
+        );
+        assert_eq!(list.expand("a.jira", "example.com").count(), 2);
+        assert_eq!(list.expand("a.b.jira", "example.com").count(), 0);
+        assert_eq!(list.expand("jira", "corp.example.com").collect::<Vec<_>>(), ["jira.lab.example.net"]);
+
+        assert_eq!(list.suffix_of("jira.lab.example.net", "example.com"), Some("lab.example.net"));
+        assert_eq!(list.suffix_of("jira.corp.example.com", "corp.example.com"), None);
+        assert_eq!(list.suffix_of("lab.example.net", "example.com"), None);
+
+        assert_eq!(SearchList::parse("search a.example\ndomain b.example\n").domains, ["b.example"]);
+        assert_eq!(SearchList::parse("nameserver 10.0.0.2\n").domains, Vec::<String>::new());
+    }
+}
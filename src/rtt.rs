@@ -0,0 +1,133 @@
+//! Per-resolver timeouts learned from round-trip times. Once a resolver
+//! has answered often enough, its queries wait for its p99 RTT times a
+//! factor instead of the scan's fixed timeout: a fast resolver's misses
+//! are given up on sooner and a slow one is given the time it needs.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Round trips kept per resolver.
+const WINDOW: usize = 256;
+/// Answers needed before a resolver's own timeout applies.
+const MIN_SAMPLES: usize = 32;
+/// The timeout is recomputed after this many new samples.
+const UPDATE_EVERY: usize = 32;
+pub const MIN_TIMEOUT: Duration = Duration::from_millis(100);
+pub const MAX_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct AdaptiveTimeout {
+    default: Duration,
+    factor: f64,
+    resolvers: Vec<ResolverRtt>,
+}
+
+struct ResolverRtt {
+    /// Learned timeout in microseconds, 0 until there are enough samples.
+    timeout: AtomicU64,
+    window: Mutex<Window>,
+}
+
+#[derive(Default)]
+struct Window {
+    samples: Vec<u64>,
+    next: usize,
+    since_update: usize,
+}
+
+impl AdaptiveTimeout {
+    /// Timeouts for `resolvers` resolvers, starting at `default` and
+    /// settling on p99 × `factor` (at least 1).
+    pub fn new(resolvers: usize, default: Duration, factor: f64) -> Self {
+        Self {
+            default,
+            factor: factor.max(1.0),
+            resolvers: (0..resolvers)
+                .map(|_| ResolverRtt {
+                    timeout: AtomicU64::new(0),
+                    window: Mutex::default(),
+                })
+                .collect(),
+        }
+    }
+
+    /// How long to wait for the resolver at `index`.
+    pub fn timeout(&self, index: usize) -> Duration {
+        self.learned(index).unwrap_or(self.default)
+    }
+
+    /// The resolver's own timeout, once it has one.
+    pub fn learned(&self, index: usize) -> Option<Duration> {
+        match self.resolvers[index].timeout.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
+        }
+    }
+
+    /// Records how long the resolver at `index` took to answer. A query
+    /// that timed out is recorded with the time waited, which pushes the
+    /// percentile (and so the timeout) up when a resolver slows down.
+    pub fn record(&self, index: usize, rtt: Duration) {
+        let resolver = &self.resolvers[index];
+        let mut window = resolver.window.lock().unwrap();
+        let sample = rtt.as_micros() as u64;
+        if window.samples.len() < WINDOW {
+            window.samples.push(sample);
+        } else {
+            let next = window.next;
+            window.samples[next] = sample;
+        }
+        window.next = (window.next + 1) % WINDOW;
+        window.since_update += 1;
+        if window.samples.len() >= MIN_SAMPLES && window.since_update >= UPDATE_EVERY {
+            window.since_update = 0;
+            let timeout = self.timeout_for(p99(&window.samples));
+            resolver.timeout.store(timeout.as_micros() as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn timeout_for(&self, p99: Duration) -> Duration {
+        p99.mul_f64(self.factor).clamp(MIN_TIMEOUT, MAX_TIMEOUT)
+    }
+}
+
+fn p99(samples: &[u64]) -> Duration {
+    let mut sorted = samples.to_vec();
+    let rank = (sorted.len() * 99).div_ceil(100).saturating_sub(1);
+    let (_, p99, _) = sorted.select_nth_unstable(rank);
+    Duration::from_micros(*p99)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_p99() {
+        let samples: Vec<u64> = (1..=100).collect();
+        assert_eq!(p99(&samples), Duration::from_micros(99));
+        assert_eq!(p99(&[7]), Duration::from_micros(7));
+    }
+
+    #[test]
+    fn test_learned_timeout() {
+        let adaptive = AdaptiveTimeout::new(2, Duration::from_secs(2), 3.0);
+        for _ in 0..MIN_SAMPLES - 1 {
+            adaptive.record(0, Duration::from_millis(200));
+        }
+        assert_eq!(adaptive.timeout(0), Duration::from_secs(2));
+        adaptive.record(0, Duration::from_millis(200));
+        assert_eq!(adaptive.timeout(0), Duration::from_millis(600));
+        assert_eq!(adaptive.timeout(1), Duration::from_secs(2));
+
+        // Fast answers are held to the floor, timeouts push it back up.
+        for _ in 0..WINDOW {
+            adaptive.record(1, Duration::from_millis(1));
+        }
+        assert_eq!(adaptive.timeout(1), MIN_TIMEOUT);
+        for _ in 0..UPDATE_EVERY {
+            adaptive.record(1, adaptive.timeout(1));
+        }
+        assert_eq!(adaptive.timeout(1), MIN_TIMEOUT * 3);
+    }
+}
@@ -1,28 +1,481 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufRead, BufReader, Write, stdout};
-use std::net::SocketAddr;
+use std::io::{BufRead, BufReader};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
 use std::str::FromStr;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
-use serde::Serialize;
-use serde_json::{json, Value};
-use hickory_client::client::ClientHandle;
-use tokio::sync::{mpsc, Semaphore};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
 use tokio::task;
+use tracing::{debug, info, warn};
 use hickory_client::client::Client;
-use hickory_client::proto::rr::{DNSClass, Name, RecordType};
-use hickory_client::proto::runtime::TokioRuntimeProvider;
+use futures_util::StreamExt;
+use hickory_client::proto::{ProtoError, ProtoErrorKind};
+use hickory_client::proto::op::{Edns, Message, MessageType, OpCode, Query, ResponseCode};
+use hickory_client::proto::rr::rdata::opt::EdnsOption;
+use hickory_client::proto::rr::{Name, RData, Record, RecordType};
+use hickory_client::proto::xfer::{DnsHandle, DnsRequest, DnsRequestOptions, DnsResponse};
+use hickory_client::proto::tcp::TcpClientStream;
 use hickory_client::proto::udp::UdpClientStream;
 
+use crate::answers::{self, AnswerPolicy, AnswerReport, Disclosure, IpVersion};
+use crate::attribution::{self, Attribution, Evidence, SourceYield};
+use crate::engine::{EngineKind, RawEngine};
+use crate::egress;
+use crate::entropy::{self, NameScore};
+use crate::error::ScanError;
+use crate::history::WordHistory;
+use crate::health::{HealthPolicy, ResolverPool, ResolverUsage};
+use crate::budget::{QueryBudget, QueryEstimate};
+use crate::checkpoint::ResumePoint;
+use crate::net::{self, SocketTuning, TunedRuntimeProvider};
+use crate::printer::{Printer, ShowMode};
+use crate::names::{self, SanitizationReport, Underscores};
+use crate::negative::{Negative, NegativeLog};
+use crate::netbios::NetbiosFallback;
+use crate::resolvconf::SearchList;
+use crate::rtt::AdaptiveTimeout;
+use crate::schedule::Scheduler;
+use crate::spill::{SpillFile, SpillReport, SpilledName};
+use crate::passive::PassiveName;
+use crate::pin::{PinRule, ResolverPins};
+use crate::pipeline::{self, QueryContext, ResolveStage, VerifyStage};
+use crate::tokens;
+use crate::querylog::QueryLog;
+use crate::traffic::Traffic;
+use crate::truncation::TruncationReport;
+use crate::tune::AutoTuner;
+use crate::unbound::{UnboundControl, UnboundMonitor, UnboundSummary};
+use crate::wire::QueryTemplate;
+use crate::wordlist::Wordlist;
+
 
 #[derive(Serialize, Clone)]
 pub struct SubdomainScanner {
+    #[serde(skip)]
     resolvers: Vec<SocketAddr>,
     domain: String,
-    subdomains: Vec<String>,
+    #[serde(skip)]
+    subdomains: Wordlist,
+    quick_wordlist: Option<String>,
+    #[serde(skip)]
+    quick: Wordlist,
+    /// Wordlist entries moved to the front because they hit in past scans.
+    prioritized_by_history: usize,
+    #[serde(skip)]
+    history: Option<Arc<Mutex<WordHistory>>>,
+    /// Rotation weights per resolver, from their reputation.
+    #[serde(skip)]
+    resolver_weights: Vec<u32>,
     timeout: Duration,
     concurrency_limit: u32,
+    socket_tuning: SocketTuning,
+    auto_tune: bool,
+    max_queries: Option<u64>,
+    #[serde(skip)]
+    seed: Option<u64>,
+    #[serde(skip)]
+    origins: HashMap<String, Vec<PassiveName>>,
+    /// Names that are candidates only because a passive source reported them.
+    #[serde(skip)]
+    passive_only: HashSet<String>,
+    #[serde(skip)]
+    show: ShowMode,
+    #[serde(skip)]
+    traffic: Traffic,
+    #[serde(skip)]
+    interrupt: Arc<AtomicBool>,
+    #[serde(skip)]
+    scheduler: Option<Scheduler>,
+    query_flags: QueryFlags,
+    verify: bool,
+    verify_workers: usize,
+    engine: EngineKind,
+    socket_count: usize,
+    health: HealthPolicy,
+    resolver_pins: Vec<PinRule>,
+    netbios: Option<NetbiosFallback>,
+    search_domains: Option<SearchList>,
+    tokens: Vec<String>,
+    retries: u32,
+    #[serde(skip)]
+    pins: ResolverPins,
+    adaptive_timeout: Option<f64>,
+    exempt_resolvers: Vec<SocketAddr>,
+    drop_random_looking: bool,
+    answer_policy: AnswerPolicy,
+    #[serde(skip)]
+    record_types: Vec<RecordType>,
+    /// Names and record types given more than once to [`Self::for_names`]
+    /// and [`Self::with_record_types`], queried once each.
+    #[serde(skip)]
+    repeated: (usize, usize),
+    skip_after_nxdomain: bool,
+    unbound: Option<UnboundControl>,
+    #[serde(skip)]
+    found_sender: Option<mpsc::UnboundedSender<(String, Resolution)>>,
+    underscores: Underscores,
+    include_negative: bool,
+    #[serde(skip)]
+    negative_log: NegativeLog,
+    /// Found names kept in memory, and the file the rest go to.
+    spill: Option<(usize, PathBuf)>,
+    /// Where a paused scan left off, with `--state`.
+    #[serde(skip)]
+    resume: Option<ResumePoint>,
+}
+
+/// What the collecting end of the pipeline gathered.
+#[derive(Default)]
+struct Collected {
+    found_domains: Vec<String>,
+    found: u64,
+    quick_found: u64,
+    /// Words of found names, for the history.
+    hits: HashSet<String>,
+    records: BTreeMap<String, Resolution>,
+    origins: BTreeMap<String, Vec<PassiveName>>,
+    attribution: BTreeMap<String, Attribution>,
+    source_yield: BTreeMap<String, SourceYield>,
+    transport: BTreeMap<String, &'static str>,
+    search_domains: BTreeMap<String, String>,
+    unconfirmed: Vec<String>,
+    name_scores: BTreeMap<String, NameScore>,
+    random_looking: Vec<String>,
+    answers: AnswerReport,
+    disclosures: Vec<Disclosure>,
+    spilled: Option<SpillReport>,
+}
+
+/// Header bits and EDNS options put on every query, and how it is sent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QueryFlags {
+    pub recursion_desired: bool,
+    pub edns_options: Vec<RawEdnsOption>,
+    /// Over TCP instead of UDP; hickory engine only.
+    pub tcp: bool,
+}
+
+impl Default for QueryFlags {
+    fn default() -> Self {
+        Self {
+            recursion_desired: true,
+            edns_options: Vec::new(),
+            tcp: false,
+        }
+    }
+}
+
+/// An EDNS option sent as-is, written `CODE` or `CODE:HEXDATA` (`3` asks
+/// for the server's NSID, `10:0123456789abcdef` sends a client cookie).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RawEdnsOption {
+    pub code: u16,
+    pub data: Vec<u8>,
+}
+
+impl FromStr for RawEdnsOption {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (code, hex) = s.split_once(':').unwrap_or((s, ""));
+        let code = code
+            .parse()
+            .map_err(|_| format!("Unknown EDNS option code: {}", code))?;
+        if hex.len() % 2 != 0 {
+            return Err(format!("EDNS option data must be an even number of hex digits: {}", hex));
+        }
+        let data = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("EDNS option data is not hex: {}", hex))?;
+        Ok(Self { code, data })
+    }
+}
+
+pub(crate) enum QueryOutcome {
+    Found(String, Resolution),
+    NotFound,
+    /// Not found, and the resolver said the name does not exist at all, so
+    /// no other record type will find it either.
+    NxDomain,
+    Failed(QueryFailure, String),
+}
+
+impl QueryOutcome {
+    /// One outcome for the queries of a name's record types: found with
+    /// all answers if either found it, else a failure if either failed.
+    pub(crate) fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (QueryOutcome::Found(name, mut first), QueryOutcome::Found(_, second)) => {
+                if first.cname_chain.is_empty() {
+                    first.cname_chain = second.cname_chain;
+                }
+                first.addresses.extend(second.addresses);
+                first.ttl = first.ttl.into_iter().chain(second.ttl).min();
+                for (record_type, values) in second.records {
+                    first.records.entry(record_type).or_default().extend(values);
+                }
+                QueryOutcome::Found(name, first)
+            }
+            (found @ QueryOutcome::Found(..), _) | (_, found @ QueryOutcome::Found(..)) => found,
+            (failed @ QueryOutcome::Failed(..), _) | (_, failed @ QueryOutcome::Failed(..)) => failed,
+            (QueryOutcome::NxDomain, _) | (_, QueryOutcome::NxDomain) => QueryOutcome::NxDomain,
+            (QueryOutcome::NotFound, QueryOutcome::NotFound) => QueryOutcome::NotFound,
+        }
+    }
+}
+
+/// What a found name resolved to: the CNAMEs followed, in order, and the
+/// addresses at the end of the chain, plus the lowest TTL among the answers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Resolution {
+    pub cname_chain: Vec<String>,
+    pub addresses: Vec<IpAddr>,
+    #[serde(default)]
+    pub ttl: Option<u32>,
+    /// Any other answers, such as MX or TXT, by record type in
+    /// presentation format.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub records: BTreeMap<String, Vec<String>>,
+}
+
+impl Resolution {
+    pub(crate) fn from_answers(answers: &[Record]) -> Self {
+        let mut resolution = Resolution::default();
+        for record in answers {
+            resolution.ttl = Some(resolution.ttl.map_or(record.ttl(), |ttl| ttl.min(record.ttl())));
+            match record.data() {
+                RData::CNAME(cname) => resolution.cname_chain.push(cname.0.to_utf8().trim_end_matches('.').to_string()),
+                RData::A(a) => resolution.addresses.push(IpAddr::V4(a.0)),
+                RData::AAAA(aaaa) => resolution.addresses.push(IpAddr::V6(aaaa.0)),
+                data => resolution.records.entry(record.record_type().to_string()).or_default().push(data.to_string()),
+            }
+        }
+        resolution
+    }
+}
+
+/// Why a query produced no answer, so a scan that found little can be told
+/// apart from a target that has little.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum QueryFailure {
+    /// The candidate is not a valid DNS name.
+    Parse,
+    /// The UDP socket could not be set up.
+    Connect,
+    Timeout,
+    /// The resolver answered with something unusable.
+    Protocol,
+}
+
+impl QueryFailure {
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            QueryFailure::Parse => "parse_error",
+            QueryFailure::Connect => "connect_error",
+            QueryFailure::Timeout => "timeout",
+            QueryFailure::Protocol => "protocol_error",
+        }
+    }
+}
+
+// Examples kept per failure category for the output.
+const ERROR_SAMPLES: usize = 5;
+
+#[derive(Default)]
+struct ErrorSummary {
+    counts: BTreeMap<QueryFailure, u64>,
+    samples: BTreeMap<QueryFailure, Vec<String>>,
+}
+
+impl ErrorSummary {
+    fn add(&mut self, failure: QueryFailure, detail: String) {
+        *self.counts.entry(failure).or_default() += 1;
+        let samples = self.samples.entry(failure).or_default();
+        if samples.len() < ERROR_SAMPLES {
+            samples.push(detail);
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    fn report(&self) -> ErrorReport {
+        let by_category = self
+            .counts
+            .iter()
+            .map(|(failure, count)| {
+                let category = ErrorCategory {
+                    count: *count,
+                    samples: self.samples[failure].clone(),
+                };
+                (failure.label(), category)
+            })
+            .collect();
+        ErrorReport {
+            total: self.total(),
+            by_category,
+        }
+    }
+}
+
+/// The outcome of one target's scan, laid out as in the output file.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanResult {
+    pub target: String,
+    pub started_at: String,
+    pub results: ScanFindings,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanFindings {
+    /// Found names, in the order their answers arrived.
+    pub subdomain: Vec<String>,
+    pub records: BTreeMap<String, Resolution>,
+    /// The passive sources that reported each found name, if any did.
+    pub origins: BTreeMap<String, Vec<PassiveName>>,
+    /// Every source that produced each found name, and how far to trust it.
+    pub attribution: BTreeMap<String, Attribution>,
+    /// Found names per source, to tell which sources pay off.
+    pub source_yield: BTreeMap<String, SourceYield>,
+    /// Names dropped because a second resolver had no answer for them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unconfirmed: Vec<String>,
+    /// How random each found name looks, and why if it seems generated.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub name_scores: BTreeMap<String, NameScore>,
+    /// Names dropped for looking generated, with `--drop-random-looking`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub random_looking: Vec<String>,
+    /// Address answers left out for their family or for pointing nowhere
+    /// (0.0.0.0, loopback, private space of an external zone).
+    #[serde(skip_serializing_if = "AnswerReport::is_empty")]
+    pub filtered_answers: AnswerReport,
+    /// External names answering with private addresses, listed whether or
+    /// not `--allow-private` kept them among the findings.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub internal_ip_disclosure: Vec<Disclosure>,
+    /// Candidates that got no answer, kept with `--include-negative`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub negative: BTreeMap<String, Negative>,
+    /// Found names past `--spill-after`, which are in this file instead of
+    /// the lists and maps above.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spilled: Option<SpillReport>,
+    pub total_scanned: usize,
+    /// Candidates skipped without a query because they are not valid names.
+    pub invalid_candidates: u64,
+    /// Wordlist entries rejected or rewritten on the way to a candidate.
+    #[serde(skip_serializing_if = "SanitizationReport::is_empty")]
+    pub sanitization: SanitizationReport,
+    /// Names answered by a fallback instead of DNS, and which one.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub transport: BTreeMap<String, &'static str>,
+    /// Names found by expanding a short candidate with a resolv.conf search
+    /// domain, and which domain it was.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub search_domains: BTreeMap<String, String>,
+    /// Candidates per wordlist phase, with `--quick-wordlist`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub phases: Vec<PhaseReport>,
+    /// The self-hosted resolver's own statistics, with `--unbound-control`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unbound: Option<UnboundSummary>,
+    pub resolvers_used: usize,
+    /// Connections to DoQ resolvers, per resolver.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub connections: Vec<ConnectionReport>,
+    /// Queries and failures per resolver, for the reputation database;
+    /// left out of the output.
+    #[serde(skip)]
+    pub resolver_usage: Vec<ResolverUsage>,
+    /// Where this scan stopped, for `--state`; left out of the output.
+    #[serde(skip)]
+    pub resume_point: ResumePoint,
+    pub queries_sent: u64,
+    /// Of those, the ones that failed at their resolver (timeout, refused
+    /// socket, garbage answer), counted per attempt and record type.
+    pub queries_failed: u64,
+    pub budget_exhausted: bool,
+    /// Every resolver was evicted and none recovered within the grace
+    /// period, so the scan stopped early.
+    pub resolvers_exhausted: bool,
+    pub interrupted: bool,
+    pub errors: ErrorReport,
+    /// Responses truncated over UDP, and how many the retry over TCP
+    /// answered in full.
+    pub truncation: TruncationReport,
+}
+
+/// How a connection-oriented resolver (DoQ) was reached.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectionReport {
+    pub resolver: SocketAddr,
+    pub server_name: String,
+    pub transport: &'static str,
+    /// The address family of the last connection that was opened.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub connected_to: Option<SocketAddr>,
+    pub connects: u64,
+    /// Connections that went to the second family because the first did
+    /// not connect in time.
+    pub fallbacks: u64,
+    pub failed_connects: u64,
+}
+
+/// One wordlist phase of a scan: the quick list, then the full one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PhaseReport {
+    pub phase: String,
+    /// Milliseconds into the scan when its first candidate was queued.
+    pub started_after_ms: u64,
+    pub candidates: u64,
+    /// Entries skipped because an earlier phase already queried the name.
+    pub duplicates: u64,
+    pub found: u64,
+}
+
+/// Failed queries by category (`timeout`, `parse_error`, ...), with a few
+/// examples of each.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ErrorReport {
+    pub total: u64,
+    pub by_category: BTreeMap<&'static str, ErrorCategory>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorCategory {
+    pub count: u64,
+    pub samples: Vec<String>,
+}
+
+impl ErrorReport {
+    pub fn count(&self, category: &str) -> u64 {
+        self.by_category.get(category).map_or(0, |c| c.count)
+    }
+}
+
+impl ScanResult {
+    /// Fails with [`ScanError::ResolversExhausted`] when nothing was found
+    /// and every query that left the host failed at the resolvers.
+    pub fn check(self) -> Result<Self, ScanError> {
+        let (sent, failed) = (self.results.queries_sent, self.results.queries_failed);
+        if self.results.subdomain.is_empty() && sent > 0 && failed >= sent {
+            return Err(ScanError::ResolversExhausted { sent, failed });
+        }
+        Ok(self)
+    }
 }
 
 impl SubdomainScanner {
@@ -32,95 +485,1367 @@ impl SubdomainScanner {
         domain: &str,
         timeout_secs: u64,
         concurrency_limit: u32,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        let resolvers = read_lines(resolvers_file)?
+    ) -> Result<Self, ScanError> {
+        let resolvers = read_lines(resolvers_file)
+            .map_err(|e| ScanError::io(resolvers_file, e))?
             .filter_map(|line| line.ok())
-            .filter_map(|line| {
-                if line.contains(':') {
-                    SocketAddr::from_str(&line).ok()
-                } else {
-                    SocketAddr::from_str(&format!("{}:53", line.trim())).ok()
-                }
-            })
+            .filter_map(|line| parse_resolver(&line))
             .collect::<Vec<_>>();
 
-        let subdomains = read_lines(subdomains_file)?
-            .filter_map(|line| line.ok())
-            .filter(|line| !line.trim().is_empty())
-            .collect::<Vec<_>>();
+        // No wordlist means only passive names are verified.
+        let subdomains = if subdomains_file.is_empty() {
+            Wordlist::default()
+        } else {
+            Wordlist::open(subdomains_file).map_err(|e| ScanError::io(subdomains_file, e))?
+        };
+
+        if resolvers.is_empty() {
+            return Err(ScanError::Config(format!("no valid resolvers in {}", resolvers_file)));
+        }
+        Ok(Self::from_parts(resolvers, subdomains, domain, Duration::from_secs(timeout_secs), concurrency_limit))
+    }
 
+    /// Resolves `names` as they are rather than as labels under a target,
+    /// for bulk resolution of arbitrary name lists through the same
+    /// pipeline, engines and limits as a scan. Repeated names are queried
+    /// once; [`Self::repeated_queries`] tells how many queries that saved.
+    pub fn for_names(resolvers: Vec<SocketAddr>, names: Vec<String>, timeout: Duration, concurrency_limit: u32) -> Result<Self, ScanError> {
         if resolvers.is_empty() {
-            return Err("No valid resolvers found".into());
+            return Err(ScanError::Config("no resolvers given".to_string()));
         }
+        let given = names.len();
+        let mut list = Wordlist::default();
+        list.extend_missing(names);
+        let mut scanner = Self::from_parts(resolvers, list, "", timeout, concurrency_limit);
+        scanner.repeated.0 = given - scanner.subdomains.len();
+        Ok(scanner)
+    }
 
-        Ok(Self {
+    fn from_parts(resolvers: Vec<SocketAddr>, subdomains: Wordlist, domain: &str, timeout: Duration, concurrency_limit: u32) -> Self {
+        Self {
+            pins: ResolverPins::none(resolvers.len()),
             resolvers,
             domain: domain.to_string(),
             subdomains,
-            timeout: Duration::from_secs(timeout_secs),
+            quick_wordlist: None,
+            quick: Wordlist::default(),
+            prioritized_by_history: 0,
+            history: None,
+            timeout,
             concurrency_limit,
-        })
+            socket_tuning: SocketTuning::default(),
+            auto_tune: false,
+            max_queries: None,
+            seed: None,
+            origins: HashMap::new(),
+            passive_only: HashSet::new(),
+            show: ShowMode::default(),
+            traffic: Traffic::default(),
+            interrupt: Arc::default(),
+            scheduler: None,
+            query_flags: QueryFlags::default(),
+            verify: false,
+            verify_workers: 1,
+            engine: EngineKind::default(),
+            socket_count: 1,
+            health: HealthPolicy::default(),
+            resolver_pins: Vec::new(),
+            netbios: None,
+            search_domains: None,
+            tokens: Vec::new(),
+            retries: 0,
+            adaptive_timeout: None,
+            exempt_resolvers: Vec::new(),
+            resolver_weights: Vec::new(),
+            drop_random_looking: false,
+            answer_policy: AnswerPolicy::default(),
+            record_types: Vec::new(),
+            repeated: (0, 0),
+            skip_after_nxdomain: true,
+            unbound: None,
+            found_sender: None,
+            underscores: Underscores::default(),
+            include_negative: false,
+            negative_log: NegativeLog::default(),
+            spill: None,
+            resume: None,
+        }
     }
 
-    async fn try_resolve_once(resolver: SocketAddr, timeout: Duration, full_domain: String) -> Option<String> {
-        let name = Name::from_str(&format!("{}.", full_domain)).ok()?;
-        let conn = UdpClientStream::builder(resolver, TokioRuntimeProvider::default())
-            .with_timeout(Some(timeout))
-            .build();
-        let (mut client, bg) = Client::connect(conn).await.ok()?;
-        tokio::spawn(bg);
-        let resp = client.query(name, DNSClass::IN, RecordType::A).await.ok()?;
-        if !resp.answers().is_empty() {
-            Some(full_domain)
-        } else {
-            None
+    pub fn with_socket_tuning(mut self, tuning: SocketTuning) -> Self {
+        self.socket_tuning = tuning;
+        self
+    }
+
+    /// Treats the concurrency limit as a ceiling and lets an [`AutoTuner`] find
+    /// the sustainable level below it.
+    pub fn with_auto_tune(mut self, enabled: bool) -> Self {
+        self.auto_tune = enabled;
+        self
+    }
+
+    /// Stops dispatching once `limit` queries have been sent; results found so
+    /// far are still returned.
+    pub fn with_max_queries(mut self, limit: Option<u64>) -> Self {
+        self.max_queries = limit;
+        let budget = self.budget();
+        self.traffic = self.traffic.with_budget(budget);
+        self
+    }
+
+    /// The `--max-queries` budget, less what a resumed scan already sent.
+    fn budget(&self) -> QueryBudget {
+        QueryBudget::resumed(self.max_queries, self.resume.as_ref().map_or(0, |resume| resume.queries_sent))
+    }
+
+    /// Sends a query that failed at its resolver (timeout, refused socket,
+    /// garbage answer) again to the next resolver, up to `retries` times.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn with_show(mut self, show: ShowMode) -> Self {
+        self.show = show;
+        self
+    }
+
+    pub fn with_query_log(mut self, log: QueryLog) -> Self {
+        self.traffic = self.traffic.with_log(log);
+        self
+    }
+
+    /// Keeps the candidates that did not resolve, with why, in
+    /// [`ScanFindings::negative`].
+    pub fn with_include_negative(mut self, include: bool) -> Self {
+        self.include_negative = include;
+        self
+    }
+
+    /// Streams the candidates that did not resolve to `log`.
+    pub fn with_negative_log(mut self, log: NegativeLog) -> Self {
+        self.negative_log = log;
+        self
+    }
+
+    /// Dispatch stops once `flag` is set; queries in flight still complete and
+    /// the result is marked `interrupted`.
+    pub fn with_interrupt(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.interrupt = flag;
+        self
+    }
+
+    /// Runs this scan under a [`Scheduler`] shared with other targets; its
+    /// printer replaces the scan's own and `with_show` no longer applies.
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    pub fn with_query_flags(mut self, flags: QueryFlags) -> Self {
+        self.query_flags = flags;
+        self
+    }
+
+    pub fn with_engine(mut self, engine: EngineKind) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Sockets, each with its own IO thread, that the raw engine spreads
+    /// queries over per address family.
+    pub fn with_socket_count(mut self, sockets: usize) -> Self {
+        self.socket_count = sockets.clamp(1, net::MAX_SOCKETS);
+        self
+    }
+
+    /// When resolvers are evicted for failing and how long the scan waits
+    /// for one to recover once all of them are.
+    pub fn with_health_policy(mut self, policy: HealthPolicy) -> Self {
+        self.health = policy;
+        self
+    }
+
+    /// Resolvers never evicted and left out of adaptive timeouts and
+    /// auto-tuning, for self-hosted resolvers the heuristics tuned for
+    /// public ones would misjudge. Addresses not in the scan's resolver
+    /// list are ignored.
+    pub fn with_exempt_resolvers(mut self, exempt: Vec<SocketAddr>) -> Self {
+        for resolver in exempt.iter().filter(|resolver| !self.resolvers.contains(resolver)) {
+            warn!("exempt resolver {} is not one of the scan's resolvers", resolver);
         }
+        self.exempt_resolvers = exempt;
+        self
     }
 
-    pub async fn scan(&self) -> Value {
-        let (tx, mut rx) = mpsc::channel(self.concurrency_limit as usize);
-        let semaphore = Arc::new(Semaphore::new(self.concurrency_limit as usize));
+    /// Gives each resolver, in the order of [`Self::resolvers`], a share of
+    /// the rotation in proportion to its weight.
+    pub fn with_resolver_weights(mut self, weights: Vec<u32>) -> Self {
+        self.resolver_weights = weights;
+        self
+    }
 
-        for (i, subdomain) in self.subdomains.clone().into_iter().enumerate() {
-            let permit = semaphore.clone().acquire_owned().await.unwrap();
-            let tx = tx.clone();
-            let resolver = self.resolvers[i % self.resolvers.len()];
-            let domain = self.domain.clone();
-            let timeout = self.timeout;
+    /// Skips the wordlist entries a paused scan already queried and asks
+    /// its unanswered names again first. Also keeps this scan's unanswered
+    /// names in [`ScanFindings::resume_point`], for pausing it again.
+    pub fn with_resume(mut self, resume: ResumePoint) -> Self {
+        self.resume = Some(resume);
+        let budget = self.budget();
+        self.traffic = self.traffic.with_budget(budget);
+        self
+    }
 
-            task::spawn(async move {
-                let _permit = permit;
-                let full_domain = format!("{}.{}", subdomain, domain);
-                if let Some(found) = SubdomainScanner::try_resolve_once(resolver, timeout, full_domain).await {
-                    println!("{}", found); // print immediately
-   stdout().flush().unwrap(); // force flush for real-time output
-                    let _ = tx.send(found).await;
-                }
-            });
+    /// Leaves names that look machine-generated (DGA output, hashes,
+    /// per-session hosts) out of the findings; they are listed apart.
+    pub fn with_drop_random_looking(mut self, enabled: bool) -> Self {
+        self.drop_random_looking = enabled;
+        self
+    }
+
+    /// Which address families to query and keep, and whether private
+    /// answers count for external names; 0.0.0.0 and loopback answers are
+    /// always left out.
+    pub fn with_answer_policy(mut self, policy: AnswerPolicy) -> Self {
+        self.answer_policy = policy;
+        self
+    }
+
+    /// Queries these record types for every name instead of the address
+    /// types of the answer policy. Answers other than addresses and CNAMEs
+    /// are kept in [`Resolution::records`].
+    pub fn with_record_types(mut self, record_types: Vec<RecordType>) -> Self {
+        let given = record_types.len();
+        let mut seen = HashSet::new();
+        self.record_types = record_types.into_iter().filter(|record_type| seen.insert(*record_type)).collect();
+        self.repeated.1 = given - self.record_types.len();
+        self
+    }
+
+    /// With more than one record type per name, asks the first alone and
+    /// the rest only if it did not come back NXDOMAIN. On by default.
+    pub fn with_skip_after_nxdomain(mut self, enabled: bool) -> Self {
+        self.skip_after_nxdomain = enabled;
+        self
+    }
+
+    /// The (name, record type) queries left out because the name or the
+    /// type was given more than once.
+    pub fn repeated_queries(&self) -> u64 {
+        let (names, types) = (self.subdomains.len(), self.query_types().len());
+        let (repeated_names, repeated_types) = self.repeated;
+        ((names + repeated_names) * (types + repeated_types) - names * types) as u64
+    }
+
+    /// The record types queried for each name.
+    fn query_types(&self) -> Vec<RecordType> {
+        match self.record_types.is_empty() {
+            true => address_types(self.answer_policy.ip_version).to_vec(),
+            false => self.record_types.clone(),
         }
+    }
+
+    /// Also sends every finding here as it arrives, for embedders that
+    /// want results before the scan ends.
+    pub fn with_found_sender(mut self, sender: mpsc::UnboundedSender<(String, Resolution)>) -> Self {
+        self.found_sender = Some(sender);
+        self
+    }
+
+    /// Polls a self-hosted unbound's statistics during the scan and, with
+    /// auto-tuning, backs off when it drops queries.
+    pub fn with_unbound_control(mut self, control: Option<UnboundControl>) -> Self {
+        self.unbound = control;
+        self
+    }
 
-        drop(tx);
+    /// Gives each resolver its own timeout, p99 round-trip time × `factor`,
+    /// once it has answered enough queries. Until then the fixed timeout
+    /// applies.
+    pub fn with_adaptive_timeout(mut self, factor: Option<f64>) -> Self {
+        self.adaptive_timeout = factor;
+        self
+    }
 
-        let mut found_domains = Vec::new();
+    /// Whether candidates with `_` in a label are queried or skipped as
+    /// invalid.
+    pub fn with_underscores(mut self, underscores: Underscores) -> Self {
+        self.underscores = underscores;
+        self
+    }
+
+    /// Scans the small, high-hit-rate wordlist at `path` before the main
+    /// one, so likely names are found first; main wordlist entries it
+    /// already covered are not queried again.
+    pub fn with_quick_wordlist(mut self, path: &str) -> Result<Self, ScanError> {
+        self.quick = Wordlist::open(path).map_err(|e| ScanError::io(path, e))?;
+        self.quick_wordlist = Some(path.to_string());
+        Ok(self)
+    }
 
-        while let Some(found) = rx.recv().await {
-            // print!("{}\n", found);
-            // stdout().flush().unwrap();
-            found_domains.push(found);
+    /// Sends candidates matching a rule only to that rule's resolvers, and
+    /// the rest only to the main list. The first matching rule wins.
+    pub fn with_resolver_pins(mut self, rules: &[PinRule]) -> Result<Self, ScanError> {
+        for rule in rules {
+            let resolvers: Vec<SocketAddr> = read_lines(&rule.resolvers)
+                .map_err(|e| ScanError::io(&rule.resolvers, e))?
+                .filter_map(|line| line.ok())
+                .filter_map(|line| parse_resolver(&line))
+                .collect();
+            if resolvers.is_empty() {
+                return Err(ScanError::Config(format!("no valid resolvers in {}", rule.resolvers)));
+            }
+            let parent = rule.pattern.trim_start_matches("*.");
+            if !self.domain.is_empty() && parent != self.domain && !parent.ends_with(&format!(".{}", self.domain)) {
+                warn!("resolver pin {} is outside {} and matches no candidate", rule.pattern, self.domain);
+            }
+            let slots = self.resolvers.len()..self.resolvers.len() + resolvers.len();
+            self.resolvers.extend(resolvers);
+            self.pins = self.pins.clone().with_rule(rule.clone(), slots);
+            self.resolver_pins.push(rule.clone());
         }
+        Ok(self)
+    }
+
+    /// Asks the NetBIOS name service at `server` (a WINS server or a
+    /// broadcast address) for candidates DNS has no answer for.
+    pub fn with_netbios(mut self, server: Option<Ipv4Addr>) -> Self {
+        self.netbios = server.map(|server| NetbiosFallback {
+            server,
+            timeout: self.timeout,
+            domain: self.domain.clone(),
+        });
+        self
+    }
 
-        json!({
-            "target": self.domain,
-            "results": {
-                "subdomain": found_domains,
-                "total_scanned": self.subdomains.len(),
-                "resolvers_used": self.resolvers.len()
+    /// Also tries short candidates below each of `list`'s search domains,
+    /// as an internal client would resolve them.
+    pub fn with_search_domains(mut self, list: Option<SearchList>) -> Self {
+        self.search_domains = list;
+        self
+    }
+
+    /// Queries each of `tokens` on its own and combined with every
+    /// one-label wordlist entry, as `token-word` and `word-token`.
+    pub fn with_tokens(mut self, tokens: Vec<String>) -> Self {
+        self.tokens = tokens;
+        self
+    }
+
+    /// Re-checks every found name on a second resolver, `workers` at a
+    /// time, and drops names it has no answer for. Needs two resolvers.
+    pub fn with_verification(mut self, enabled: bool, workers: usize) -> Self {
+        self.verify = enabled;
+        self.verify_workers = workers;
+        self
+    }
+
+    /// Keeps the first `after` found names for the result and writes the
+    /// rest to `path` as JSON lines, so memory stays bounded however many
+    /// names the scan finds.
+    pub fn with_spill(mut self, after: usize, path: PathBuf) -> Self {
+        self.spill = Some((after, path));
+        self
+    }
+
+    /// Adds names reported by passive sources to the candidates so they are
+    /// verified like wordlist entries; their origins are kept for the output.
+    pub fn with_passive_names(mut self, names: Vec<PassiveName>) -> Self {
+        let suffix = format!(".{}", self.domain);
+        let mut labels = Vec::new();
+        for mut name in names {
+            let Ok(normalized) = names::normalize(&name.name, Underscores::Allow) else {
+                continue;
+            };
+            name.name = normalized;
+            let Some(label) = name.name.strip_suffix(&suffix) else {
+                continue;
+            };
+            let full_domain = name.name.clone();
+            let origins = self.origins.entry(full_domain).or_default();
+            if origins.is_empty() {
+                labels.push(label.to_string());
             }
+            origins.push(name);
+        }
+        let added = self.subdomains.extend_missing(labels);
+        self.passive_only.extend(added.into_iter().map(|label| format!("{}{}", label, suffix)));
+        self
+    }
+
+    /// Shuffles the candidate order with a seeded RNG so reruns and shards see
+    /// the same order; a random seed is picked when none is given.
+    pub fn with_shuffle(mut self, seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(rand::random);
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        self.subdomains.shuffle(&mut rng);
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Counts which words hit into `history` once the scan is done and,
+    /// with `prioritize`, queries the words that hit most often in past
+    /// scans first. Apply after shuffling, which would undo the order.
+    pub fn with_history(mut self, history: Arc<Mutex<WordHistory>>, prioritize: bool) -> Self {
+        if prioritize {
+            let ranked = history.lock().unwrap().ranked();
+            self.prioritized_by_history = self.subdomains.promote(&ranked);
+        }
+        self.history = Some(history);
+        self
+    }
+
+    pub fn prioritized_by_history(&self) -> usize {
+        self.prioritized_by_history
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    pub fn resolvers(&self) -> &[SocketAddr] {
+        &self.resolvers
+    }
+
+    /// The log, budget and truncation tally the scan's queries go through,
+    /// for lookups made after it.
+    pub fn traffic(&self) -> &Traffic {
+        &self.traffic
+    }
+
+    pub fn subdomains(&self) -> &Wordlist {
+        &self.subdomains
+    }
+
+    pub fn estimate(&self) -> QueryEstimate {
+        QueryEstimate {
+            // At most: only words short enough under ndots are expanded, and
+            // only one-label words combined with tokens.
+            candidates: (self.quick.len() + self.subdomains.len()) as u64
+                * (1 + 2 * self.tokens.len() as u64)
+                * (1 + self.search_domains.as_ref().map_or(0, |list| list.domains.len()) as u64)
+                + self.tokens.len() as u64,
+            record_types: self.query_types().len() as u64,
+            attempts_per_query: 1 + self.retries as u64,
+        }
+    }
+
+    /// The candidates `word` makes besides its own: token combinations,
+    /// then it and those below each search domain.
+    fn derived_candidates(&self, word: &str) -> Vec<String> {
+        let combined: Vec<String> = tokens::combine(&self.tokens, word).collect();
+        let mut derived: Vec<String> = combined.iter().map(|combined| format!("{}.{}", combined, self.domain)).collect();
+        if let Some(list) = &self.search_domains {
+            for word in std::iter::once(word).chain(combined.iter().map(String::as_str)) {
+                derived.extend(list.expand(word, &self.domain));
+            }
+        }
+        derived
+    }
+
+    pub(crate) async fn try_resolve_once(
+        resolver: SocketAddr,
+        timeout: Duration,
+        provider: TunedRuntimeProvider,
+        full_domain: String,
+        flags: &QueryFlags,
+        traffic: &Traffic,
+    ) -> QueryOutcome {
+        Self::try_resolve_type(resolver, timeout, provider, full_domain, RecordType::A, flags, traffic).await
+    }
+
+    pub(crate) async fn try_resolve_type(
+        resolver: SocketAddr,
+        timeout: Duration,
+        provider: TunedRuntimeProvider,
+        full_domain: String,
+        record_type: RecordType,
+        flags: &QueryFlags,
+        traffic: &Traffic,
+    ) -> QueryOutcome {
+        let name = match Name::from_str(&format!("{}.", full_domain)) {
+            Ok(name) => name,
+            Err(e) => return QueryOutcome::Failed(QueryFailure::Parse, format!("{}: {}", full_domain, e)),
+        };
+        let message = build_query(name, record_type, flags);
+        let response = match flags.tcp {
+            true => exchange_tcp(resolver, timeout, provider, message).await,
+            false => exchange(resolver, timeout, provider, message, traffic).await,
+        };
+        match response {
+            Ok(resp) if !resp.answers().is_empty() => {
+                let resolution = Resolution::from_answers(resp.answers());
+                QueryOutcome::Found(full_domain, resolution)
+            }
+            Ok(resp) if resp.response_code() == ResponseCode::NXDomain => QueryOutcome::NxDomain,
+            Ok(_) => QueryOutcome::NotFound,
+            Err((failure, e)) => QueryOutcome::Failed(failure, format!("{} via {}: {}", full_domain, resolver, e)),
+        }
+    }
+
+    /// Which sources produced a found name and how far to trust it.
+    fn attribute(&self, name: &str, origins: Option<&[PassiveName]>, fallback: bool, score: &NameScore, quick_names: &HashSet<String>) -> Attribution {
+        let mut sources = Vec::new();
+        if !self.passive_only.contains(name) || quick_names.contains(name) {
+            sources.push(attribution::BRUTEFORCE.to_string());
+        }
+        for origin in origins.into_iter().flatten() {
+            if !sources.iter().any(|source| source == origin.source) {
+                sources.push(origin.source.to_string());
+            }
+        }
+        Attribution::new(Evidence {
+            sources,
+            verified: self.verify,
+            fallback,
+            random_looking: score.is_random_looking(),
         })
     }
+
+    fn raw_engine(&self) -> Option<(Arc<RawEngine>, Arc<Vec<QueryTemplate>>)> {
+        if self.query_flags.tcp {
+            warn!("the raw engine only sends plain UDP, using hickory for TCP");
+            return None;
+        }
+        let engine = RawEngine::bind(&self.resolvers, &self.socket_tuning, self.socket_count)
+            .map_err(|e| warn!("could not bind raw engine sockets, using hickory: {}", e))
+            .ok()?;
+        let templates = self
+            .query_types()
+            .into_iter()
+            .map(|record_type| QueryTemplate::new(&self.domain, record_type, &self.query_flags))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| warn!("could not encode queries for {}, using hickory: {}", self.domain, e))
+            .ok()?;
+        Some((Arc::new(engine), Arc::new(templates)))
+    }
+
+    pub async fn scan(&self) -> ScanResult {
+        let started_at = chrono::Utc::now().to_rfc3339();
+        // Each stage may run this far ahead of the next one.
+        let depth = self.concurrency_limit as usize;
+        let tuner = self.auto_tune.then(|| AutoTuner::new(self.concurrency_limit as usize));
+        let tuning_task = tuner.clone().map(|tuner| task::spawn(tuner.run()));
+        let unbound = self.unbound.clone().map(UnboundMonitor::new);
+        let unbound_task = unbound.clone().map(|monitor| task::spawn(monitor.run(tuner.clone())));
+        let budget = self.traffic.budget.clone();
+        let query_types = self.query_types().len() as u64;
+        let (printer, printer_task) = match &self.scheduler {
+            Some(scheduler) => (scheduler.printer(), None),
+            None => {
+                let (printer, task) = Printer::spawn(self.show);
+                (printer, Some(task))
+            }
+        };
+        let (err_tx, mut err_rx) = mpsc::unbounded_channel();
+        let keep_unanswered = self.resume.is_some();
+        let error_task = task::spawn(async move {
+            let mut summary = ErrorSummary::default();
+            let mut unanswered = Vec::new();
+            while let Some((name, failure, detail)) = err_rx.recv().await {
+                summary.add(failure, detail);
+                if keep_unanswered && failure != QueryFailure::Parse {
+                    unanswered.push(name);
+                }
+            }
+            (summary, unanswered)
+        });
+        let (unsent_tx, unsent_task) = if keep_unanswered {
+            let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+            let task = task::spawn(async move {
+                let mut unsent = Vec::new();
+                while let Some(name) = rx.recv().await {
+                    unsent.push(name);
+                }
+                unsent
+            });
+            (Some(tx), Some(task))
+        } else {
+            (None, None)
+        };
+        let (negative_tx, negative_task) = if self.include_negative || self.negative_log.is_enabled() {
+            let (tx, mut rx) = mpsc::unbounded_channel::<(String, Negative)>();
+            let include = self.include_negative;
+            let log = self.negative_log.clone();
+            let task = task::spawn(async move {
+                let mut kept = BTreeMap::new();
+                while let Some((name, negative)) = rx.recv().await {
+                    log.record(&name, &negative);
+                    if include {
+                        kept.insert(name, negative);
+                    }
+                }
+                kept
+            });
+            (Some(tx), Some(task))
+        } else {
+            (None, None)
+        };
+
+        let context = QueryContext {
+            resolvers: Arc::new(self.resolvers.clone()),
+            timeout: self.timeout,
+            tuning: self.socket_tuning.clone(),
+            flags: self.query_flags.clone(),
+            traffic: self.traffic.clone(),
+            pins: Arc::new(self.pins.clone()),
+            record_types: self.query_types().into(),
+            skip_after_nxdomain: self.skip_after_nxdomain,
+            failed: Arc::default(),
+            raw: match self.engine {
+                EngineKind::Raw => self.raw_engine(),
+                EngineKind::Hickory => None,
+            },
+        };
+        let (candidate_tx, candidate_rx) = mpsc::channel(depth);
+        let (found_tx, found_rx) = mpsc::channel(depth);
+        let (verified_tx, verified_rx) = mpsc::channel(depth);
+        let (enriched_tx, mut enriched_rx) = mpsc::channel(depth);
+        let build_pool = || {
+            ResolverPool::new(self.resolvers.clone(), self.health)
+                .with_exempt(&self.exempt_resolvers)
+                .with_weights(&self.resolver_weights)
+        };
+        let pool = match &self.scheduler {
+            Some(scheduler) => scheduler.pool(&self.resolvers, build_pool),
+            None => Arc::new(build_pool()),
+        };
+        let adaptive = self
+            .adaptive_timeout
+            .map(|factor| Arc::new(AdaptiveTimeout::new(self.resolvers.len(), self.timeout, factor)));
+        let resolve = ResolveStage::new(context.clone(), pool.clone(), tuner, self.scheduler.clone(), printer.clone(), err_tx);
+        let usage = resolve.usage.clone();
+        let resolve = resolve
+            .with_negatives(negative_tx)
+            .with_adaptive_timeout(adaptive.clone())
+            .with_netbios(self.netbios.clone())
+            .with_unsent(unsent_tx)
+            .with_retries(self.retries)
+            .spawn(depth, candidate_rx, found_tx);
+        let failed = context.failed.clone();
+        let verify = VerifyStage::new(context, self.verify, printer.clone())
+            .spawn(self.verify_workers, found_rx, verified_tx);
+        let enrich = task::spawn(pipeline::enrich(verified_rx, enriched_tx, Arc::new(self.origins.clone())));
+
+        let mut sanitization = SanitizationReport::default();
+        let mut phases = Vec::new();
+        // Names the quick phase queues, so the full phase skips them and
+        // findings can be counted per phase.
+        let quick_names: HashSet<String> = self
+            .quick
+            .iter()
+            .filter_map(|entry| names::candidate(entry, &self.domain, self.underscores).ok())
+            .map(|(name, _)| name)
+            .collect();
+        // Words the history already knows that this scan queried; the
+        // words that hit come from the findings.
+        let known: HashSet<String> = match &self.history {
+            Some(history) => history.lock().unwrap().words.keys().cloned().collect(),
+            None => HashSet::new(),
+        };
+        let suffix = format!(".{}", self.domain);
+        let mut tried = HashSet::new();
+        let scan_start = Instant::now();
+        let resume = self.resume.clone().unwrap_or_default();
+        let mut seen = 0;
+        let generate = async {
+            // Without a quick wordlist there is one unnamed phase and no report.
+            let lists: Vec<(&'static str, &Wordlist)> = match &self.quick_wordlist {
+                Some(_) => vec![("quick", &self.quick), ("full", &self.subdomains)],
+                None => vec![("", &self.subdomains)],
+            };
+            let (estimate, entries) = (self.estimate().candidates, (self.quick.len() + self.subdomains.len()).max(1) as u64);
+            egress::expect(estimate - estimate * resume.position.min(entries) / entries + resume.retry.len() as u64);
+            for name in &resume.retry {
+                if candidate_tx.send(name.clone()).await.is_err() {
+                    break;
+                }
+            }
+            // A resumed scan sent its tokens before it was paused.
+            let tokens = if resume.position == 0 { self.tokens.as_slice() } else { &[] };
+            for token in tokens {
+                if candidate_tx.send(format!("{}.{}", token, self.domain)).await.is_err() {
+                    break;
+                }
+            }
+            'phases: for (phase, list) in lists {
+                // Phases only order the queue: the quick list's last queries
+                // may still be in flight when the full list starts.
+                if !phase.is_empty() {
+                    let message = format!("{} phase: {} entries", phase, list.len());
+                    info!("{}", message);
+                    printer.phase(&message);
+                    // A resumed scan carries on the paused one's counts.
+                    let paused = resume.phases.iter().find(|paused| paused.phase == phase);
+                    phases.push(PhaseReport {
+                        phase: phase.to_string(),
+                        started_after_ms: scan_start.elapsed().as_millis() as u64,
+                        candidates: paused.map_or(0, |paused| paused.candidates),
+                        duplicates: paused.map_or(0, |paused| paused.duplicates),
+                        found: paused.map_or(0, |paused| paused.found),
+                    });
+                }
+                for subdomain in list.iter() {
+                    // Each candidate takes a query per record type at once.
+                    if budget.remaining().is_some_and(|remaining| remaining < query_types) {
+                        warn!("query budget of {} reached after {} candidates, finalizing", budget.sent(), seen);
+                        break 'phases;
+                    }
+                    if self.interrupt.load(Ordering::Relaxed) {
+                        warn!("interrupted after {} candidates, finalizing", seen);
+                        break 'phases;
+                    }
+                    if pool.is_exhausted() {
+                        break 'phases;
+                    }
+                    seen += 1;
+                    if seen <= resume.position {
+                        continue;
+                    }
+                    let candidate = match names::candidate(subdomain, &self.domain, self.underscores) {
+                        Ok((candidate, rewrites)) => {
+                            sanitization.rewrite(subdomain, &rewrites);
+                            candidate
+                        }
+                        Err(e) => {
+                            debug!("skipping candidate: {}", e);
+                            sanitization.reject(subdomain, e.reason);
+                            continue;
+                        }
+                    };
+                    if let Some(report) = phases.last_mut() {
+                        if phase == "full" && quick_names.contains(&candidate) {
+                            report.duplicates += 1;
+                            continue;
+                        }
+                        report.candidates += 1;
+                    }
+                    if let Some(word) = candidate.strip_suffix(&suffix)
+                        && known.contains(word)
+                    {
+                        tried.insert(word.to_string());
+                    }
+                    let expanded = candidate.strip_suffix(&suffix).map(|word| self.derived_candidates(word)).unwrap_or_default();
+                    if candidate_tx.send(candidate).await.is_err() {
+                        break 'phases;
+                    }
+                    for name in expanded {
+                        if candidate_tx.send(name).await.is_err() {
+                            break 'phases;
+                        }
+                    }
+                }
+            }
+            drop(candidate_tx);
+        };
+        let collect = async {
+            let mut collected = Collected::default();
+            let mut spill_file: Option<SpillFile> = None;
+            while let Some(next) = enriched_rx.recv().await {
+                match next {
+                    Ok(mut found) => {
+                        // NetBIOS answers come from the local network and are private by nature.
+                        if found.fallback.is_none() {
+                            collected.disclosures.extend(answers::disclosure(&found.name, &found.resolution.addresses));
+                            if !self.answer_policy.apply(&found.name, &mut found.resolution.addresses, &mut collected.answers) {
+                                printer.outcome(&found.name, "bogus");
+                                continue;
+                            }
+                        }
+                        let score = entropy::score(&found.name, &self.domain);
+                        if self.drop_random_looking && score.is_random_looking() {
+                            printer.outcome(&found.name, "random");
+                            collected.random_looking.push(found.name);
+                            continue;
+                        }
+                        printer.outcome(&found.name, "found");
+                        collected.found += 1;
+                        if quick_names.contains(&found.name) {
+                            collected.quick_found += 1;
+                        }
+                        if self.history.is_some()
+                            && let Some(word) = found.name.strip_suffix(&suffix)
+                        {
+                            collected.hits.insert(word.to_string());
+                        }
+                        if let Some(sender) = &self.found_sender {
+                            let _ = sender.send((found.name.clone(), found.resolution.clone()));
+                        }
+                        let attribution = self.attribute(&found.name, found.origins.as_deref(), found.fallback.is_some(), &score, &quick_names);
+                        attribution::count(&mut collected.source_yield, &attribution);
+
+                        if let Some((after, path)) = &self.spill
+                            && collected.found_domains.len() >= *after
+                        {
+                            if spill_file.is_none() {
+                                match SpillFile::create(path) {
+                                    Ok(file) => {
+                                        info!("kept {} found names in memory, writing further ones to {}", after, path.display());
+                                        spill_file = Some(file);
+                                    }
+                                    Err(e) => warn!("could not create {}, keeping every found name in memory: {}", path.display(), e),
+                                }
+                            }
+                            if let Some(file) = &mut spill_file {
+                                let spilled = SpilledName {
+                                    name: found.name,
+                                    found: true,
+                                    resolution: found.resolution,
+                                    origins: found.origins,
+                                    attribution,
+                                    name_score: score,
+                                    transport: found.fallback,
+                                };
+                                file.write(&spilled).await;
+                                continue;
+                            }
+                        }
+                        collected.name_scores.insert(found.name.clone(), score);
+                        collected.attribution.insert(found.name.clone(), attribution);
+                        collected.records.insert(found.name.clone(), found.resolution);
+                        if let Some(o) = found.origins {
+                            collected.origins.insert(found.name.clone(), o);
+                        }
+                        if let Some(fallback) = found.fallback {
+                            collected.transport.insert(found.name.clone(), fallback);
+                        }
+                        if let Some(domain) = self.search_domains.as_ref().and_then(|list| list.suffix_of(&found.name, &self.domain)) {
+                            collected.search_domains.insert(found.name.clone(), domain.to_string());
+                        }
+                        collected.found_domains.push(found.name);
+                    }
+                    Err(name) => collected.unconfirmed.push(name),
+                }
+            }
+            if let Some(file) = spill_file {
+                match file.finish().await {
+                    Ok(report) => collected.spilled = Some(report),
+                    Err(e) => warn!("could not write found names to disk: {}", e),
+                }
+            }
+            collected
+        };
+        let ((), collected) = tokio::join!(generate, collect);
+        for report in &mut phases {
+            report.found += match report.phase.as_str() {
+                "quick" => collected.quick_found,
+                _ => collected.found - collected.quick_found,
+            };
+        }
+        if let Some(history) = &self.history {
+            history.lock().unwrap().record(&tried, &collected.hits);
+        }
+        resolve.join_all().await;
+        verify.join_all().await;
+        let _ = enrich.await;
+        drop(printer);
+
+        if let Some(task) = tuning_task {
+            task.abort();
+        }
+        if let Some(task) = unbound_task {
+            task.abort();
+        }
+        let unbound = match unbound {
+            Some(monitor) => {
+                monitor.sample(None).await;
+                monitor.summary()
+            }
+            None => None,
+        };
+        if let Some(summary) = &unbound {
+            info!(
+                "unbound answered {} queries, {:.0}% from cache, peak queue {}, {} dropped",
+                summary.queries,
+                summary.cache_hit_rate * 100.0,
+                summary.peak_queue,
+                summary.dropped
+            );
+        }
+        if let Some(task) = printer_task {
+            task.finish().await;
+        }
+        let (errors, mut unanswered) = error_task.await.unwrap_or_default();
+        if let Some(task) = unsent_task {
+            unanswered.extend(task.await.unwrap_or_default());
+        }
+        let negative = match negative_task {
+            Some(task) => task.await.unwrap_or_default(),
+            None => BTreeMap::new(),
+        };
+        if errors.total() > 0 {
+            let counts: Vec<String> = errors
+                .counts
+                .iter()
+                .map(|(failure, count)| format!("{} {}", count, failure.label()))
+                .collect();
+            warn!("{} queries failed: {}", errors.total(), counts.join(", "));
+        }
+        if let Some(adaptive) = &adaptive {
+            let learned: Vec<Duration> = (0..self.resolvers.len()).filter_map(|i| adaptive.learned(i)).collect();
+            if let (Some(min), Some(max)) = (learned.iter().min(), learned.iter().max()) {
+                info!(
+                    "adaptive timeouts for {} of {} resolvers, {} ms to {} ms",
+                    learned.len(),
+                    self.resolvers.len(),
+                    min.as_millis(),
+                    max.as_millis()
+                );
+            }
+        }
+        if !sanitization.rejected.is_empty() {
+            warn!(
+                "skipped {} wordlist entries that are not valid names: {}",
+                sanitization.rejected_total(),
+                SanitizationReport::summary(&sanitization.rejected)
+            );
+        }
+        if !sanitization.rewritten.is_empty() {
+            info!("rewrote wordlist entries: {}", SanitizationReport::summary(&sanitization.rewritten));
+        }
+        if !phases.is_empty() {
+            let summary: Vec<String> = phases
+                .iter()
+                .map(|p| format!("{} {} of {} candidates", p.phase, p.found, p.candidates))
+                .collect();
+            info!("found per phase: {}", summary.join(", "));
+        }
+        let Collected {
+            found_domains,
+            records,
+            origins,
+            attribution,
+            source_yield,
+            transport,
+            search_domains,
+            unconfirmed,
+            name_scores,
+            random_looking,
+            answers,
+            disclosures,
+            spilled,
+            ..
+        } = collected;
+        if source_yield.len() > 1 {
+            let summary: Vec<String> = source_yield
+                .iter()
+                .map(|(source, found)| format!("{} {} ({} only)", source, found.found, found.unique))
+                .collect();
+            info!("found per source: {}", summary.join(", "));
+        }
+        if !transport.is_empty() {
+            info!("{} names had no DNS answer but answered over NetBIOS", transport.len());
+        }
+        if !search_domains.is_empty() {
+            let mut per_domain: BTreeMap<&str, usize> = BTreeMap::new();
+            for domain in search_domains.values() {
+                *per_domain.entry(domain).or_default() += 1;
+            }
+            let summary: Vec<String> = per_domain.iter().map(|(domain, found)| format!("{} {}", domain, found)).collect();
+            info!("found below search domains: {}", summary.join(", "));
+        }
+        if !unconfirmed.is_empty() {
+            warn!("dropped {} names a second resolver could not confirm", unconfirmed.len());
+        }
+        if !answers.is_empty() {
+            let counts: Vec<String> = answers.filtered.iter().map(|(reason, count)| format!("{} {}", count, reason)).collect();
+            info!("left out address answers: {}; dropped {} names with none left", counts.join(", "), answers.dropped.len());
+        }
+        if !disclosures.is_empty() {
+            warn!("{} names disclose internal addresses (see internal_ip_disclosure)", disclosures.len());
+        }
+        let truncation = self.traffic.truncation.report();
+        if truncation.lost > 0 {
+            warn!("{} of {} truncated responses got no answer over TCP, their records may be incomplete (see truncation)", truncation.lost, truncation.truncated);
+        } else if truncation.truncated > 0 {
+            info!("{} truncated responses, all answered in full over TCP", truncation.truncated);
+        }
+        if !random_looking.is_empty() {
+            info!("dropped {} random-looking names", random_looking.len());
+        } else {
+            let flagged = name_scores.values().filter(|score| score.is_random_looking()).count();
+            if flagged > 0 {
+                info!("{} found names look machine-generated (see name_scores)", flagged);
+            }
+        }
+
+        let phases_so_far = phases.clone();
+        ScanResult {
+            target: self.domain.clone(),
+            started_at,
+            results: ScanFindings {
+                subdomain: found_domains,
+                records,
+                origins,
+                attribution,
+                source_yield,
+                unconfirmed,
+                name_scores,
+                random_looking,
+                filtered_answers: answers,
+                internal_ip_disclosure: disclosures,
+                negative,
+                spilled,
+                total_scanned: self.quick.len() + self.subdomains.len(),
+                invalid_candidates: sanitization.rejected_total(),
+                sanitization,
+                transport,
+                search_domains,
+                phases,
+                unbound,
+                resolvers_used: self.resolvers.len(),
+                connections: connection_reports(&self.resolvers),
+                resolver_usage: usage.usage(&self.resolvers),
+                resume_point: ResumePoint { position: seen, retry: unanswered, queries_sent: budget.sent(), phases: phases_so_far },
+                // This run's own; the saved result holds the paused run's.
+                queries_sent: budget.sent() - resume.queries_sent,
+                queries_failed: failed.load(Ordering::Relaxed),
+                budget_exhausted: budget.remaining().is_some_and(|remaining| remaining < query_types),
+                resolvers_exhausted: pool.is_exhausted(),
+                interrupted: self.interrupt.load(Ordering::Relaxed),
+                errors: errors.report(),
+                truncation,
+            },
+        }
+    }
+}
+
+#[cfg(feature = "doq")]
+fn connection_reports(resolvers: &[SocketAddr]) -> Vec<ConnectionReport> {
+    crate::doq::reports(resolvers)
+}
+
+#[cfg(not(feature = "doq"))]
+fn connection_reports(_: &[SocketAddr]) -> Vec<ConnectionReport> {
+    Vec::new()
+}
+
+/// Parses a resolver line as `IP:port`, or a bare IP on port 53. Tags
+/// after the address are ignored; see [`parse_tagged_resolver`].
+pub fn parse_resolver(line: &str) -> Option<SocketAddr> {
+    parse_tagged_resolver(line).map(|(resolver, _)| resolver)
+}
+
+/// Parses a resolver line with the tags that follow its address, separated
+/// by whitespace or commas, as in `9.9.9.9 eu,de`. A `#` word starts a
+/// comment.
+pub fn parse_tagged_resolver(line: &str) -> Option<(SocketAddr, Vec<String>)> {
+    let mut words = line.split_whitespace().take_while(|word| !word.starts_with('#'));
+    let resolver = parse_address(words.next()?)?;
+    let tags = words.flat_map(|word| word.split(',')).filter(|tag| !tag.is_empty()).map(str::to_lowercase).collect();
+    Some((resolver, tags))
+}
+
+fn parse_address(line: &str) -> Option<SocketAddr> {
+    #[cfg(feature = "doq")]
+    if let Some(spec) = line.strip_prefix(crate::doq::SCHEME) {
+        return crate::doq::register(spec);
+    }
+    if line.contains(':') {
+        SocketAddr::from_str(line).ok()
+    } else {
+        SocketAddr::from_str(&format!("{}:53", line)).ok()
+    }
+}
+
+/// Sends `message` to `resolver` over UDP, or its QUIC connection for a
+/// DoQ resolver, and waits for the response, logging the exchange. Errors
+/// carry the failure category and a reason. The caller has counted the
+/// query against the budget; a retry over TCP counts itself.
+pub(crate) async fn exchange(
+    resolver: SocketAddr,
+    timeout: Duration,
+    provider: TunedRuntimeProvider,
+    mut message: Message,
+    traffic: &Traffic,
+) -> Result<DnsResponse, (QueryFailure, String)> {
+    let log = &traffic.log;
+    let client = connect(resolver, timeout, provider.clone()).await?;
+    admit(resolver, &message).await;
+    #[cfg(feature = "fault-injection")]
+    let (timeout, corrupt_at) = match message.queries().first() {
+        Some(query) => crate::faults::before_send(&query.name().to_utf8(), timeout).await.map_err(|e| (QueryFailure::Timeout, e))?,
+        None => (timeout, None),
+    };
+
+    let sent = SystemTime::now();
+    // The UDP stream times out by itself; a QUIC stream waits as long as
+    // the connection lives.
+    let response = tokio::time::timeout(timeout, client.send(DnsRequest::new(message.clone(), DnsRequestOptions::default())).next())
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| Err(ProtoErrorKind::Timeout.into()));
+    #[cfg(feature = "fault-injection")]
+    let response = match (corrupt_at, response) {
+        (Some(at), Ok(response)) => DnsResponse::from_buffer(crate::faults::corrupt(response.as_buffer().to_vec(), at)),
+        (_, response) => response,
+    };
+    #[cfg(feature = "doq")]
+    if let Err(e) = &response
+        && !is_timeout(e)
+        && let Some(quic) = crate::doq::lookup(resolver)
+    {
+        quic.reset().await;
+    }
+
+    if log.is_enabled() {
+        // The stream picks the message id itself; it's only known once a
+        // response echoes it back.
+        let received = SystemTime::now();
+        if let Ok(resp) = &response {
+            message.set_id(resp.id());
+        }
+        if let Ok(query) = message.to_vec() {
+            log.exchange(resolver, &query, sent, response.as_ref().ok().map(|r| (r.as_buffer(), received)));
+        }
+    }
+
+    let response = response.map_err(|e| {
+        let failure = if is_timeout(&e) { QueryFailure::Timeout } else { QueryFailure::Protocol };
+        (failure, e.to_string())
+    })?;
+    // A QUIC stream is not limited to a datagram's payload.
+    if response.truncated() && !is_quic(resolver) {
+        return Ok(retry_truncated(resolver, timeout, provider, message, traffic).await.unwrap_or(response));
+    }
+    Ok(response)
+}
+
+/// Waits until the egress caps let `message` go to `resolver`.
+async fn admit(resolver: SocketAddr, message: &Message) {
+    if egress::is_limited() {
+        if let Some(query) = message.queries().first() {
+            egress::admit_query(resolver, &query.name().to_utf8()).await;
+        }
+        let len = message.to_vec().map_or(0, |bytes| bytes.len());
+        egress::admit(resolver, 1, len).await;
+    }
+}
+
+/// Sends a query whose UDP answer came back truncated again over TCP and
+/// counts the outcome in the tally. The retry is one more query, counted
+/// against the budget and held to the egress caps like any other. `None`
+/// when TCP got no answer either, or the budget is spent, so the truncated
+/// one has to do.
+pub(crate) async fn retry_truncated(
+    resolver: SocketAddr,
+    timeout: Duration,
+    provider: TunedRuntimeProvider,
+    message: Message,
+    traffic: &Traffic,
+) -> Option<DnsResponse> {
+    let (name, record_type) = message.queries().first().map(|query| (query.name().to_utf8(), query.query_type()))?;
+    if !traffic.budget.try_spend() {
+        debug!("{} via {}: truncated over UDP and no budget left to ask over TCP", name, resolver);
+        traffic.truncation.record(&name, record_type, false);
+        return None;
+    }
+    let response = send_tcp(resolver, timeout, provider, message).await;
+    if let Err(e) = &response {
+        debug!("{} via {}: truncated over UDP and no answer over TCP: {}", name, resolver, e);
+    }
+    traffic.truncation.record(&name, record_type, response.is_ok());
+    response.ok()
+}
+
+/// Sends `message` to `resolver` over TCP only, for `--tcp-only`. The
+/// caller has counted the query against the budget.
+pub(crate) async fn exchange_tcp(
+    resolver: SocketAddr,
+    timeout: Duration,
+    provider: TunedRuntimeProvider,
+    message: Message,
+) -> Result<DnsResponse, (QueryFailure, String)> {
+    send_tcp(resolver, timeout, provider, message).await.map_err(|e| {
+        let failure = if is_timeout(&e) { QueryFailure::Timeout } else { QueryFailure::Protocol };
+        (failure, e.to_string())
+    })
+}
+
+/// One query over a TCP connection of its own, held to the egress caps.
+async fn send_tcp(resolver: SocketAddr, timeout: Duration, provider: TunedRuntimeProvider, message: Message) -> Result<DnsResponse, ProtoError> {
+    admit(resolver, &message).await;
+    let (stream, handle) = TcpClientStream::new(resolver, None, Some(timeout), provider);
+    let sent = async {
+        let (client, bg) = Client::with_timeout(stream, handle, timeout, None).await?;
+        tokio::spawn(bg);
+        client.send(DnsRequest::new(message, DnsRequestOptions::default())).next().await.unwrap_or_else(|| Err(ProtoErrorKind::Timeout.into()))
+    };
+    tokio::time::timeout(timeout, sent).await.unwrap_or_else(|_| Err(ProtoErrorKind::Timeout.into()))
+}
+
+#[cfg(feature = "doq")]
+fn is_quic(resolver: SocketAddr) -> bool {
+    crate::doq::lookup(resolver).is_some()
+}
+
+#[cfg(not(feature = "doq"))]
+fn is_quic(_: SocketAddr) -> bool {
+    false
+}
+
+async fn connect(resolver: SocketAddr, timeout: Duration, provider: TunedRuntimeProvider) -> Result<Client, (QueryFailure, String)> {
+    #[cfg(feature = "doq")]
+    if let Some(quic) = crate::doq::lookup(resolver) {
+        return quic.client().await.map_err(|e| (QueryFailure::Connect, e));
+    }
+    let conn = UdpClientStream::builder(resolver, provider)
+        .with_timeout(Some(timeout))
+        .build();
+    let (client, bg) = Client::connect(conn).await.map_err(|e| (QueryFailure::Connect, e.to_string()))?;
+    tokio::spawn(bg);
+    Ok(client)
+}
+
+/// The address record types queried for each candidate.
+pub(crate) fn address_types(ip_version: IpVersion) -> &'static [RecordType] {
+    match ip_version {
+        IpVersion::V4 => &[RecordType::A],
+        IpVersion::V6 => &[RecordType::AAAA],
+        IpVersion::Both => &[RecordType::A, RecordType::AAAA],
+    }
+}
+
+/// A query for `name` as hickory's client would send it, EDNS0 with a
+/// 1232-byte payload, plus whatever `flags` ask for.
+pub(crate) fn build_query(name: Name, record_type: RecordType, flags: &QueryFlags) -> Message {
+    let mut message = Message::new();
+    message
+        .add_query(Query::query(name, record_type))
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .set_recursion_desired(flags.recursion_desired);
+    let edns = message.extensions_mut().get_or_insert_with(Edns::new);
+    edns.set_max_payload(1232).set_version(0);
+    for option in &flags.edns_options {
+        edns.options_mut()
+            .insert(EdnsOption::Unknown(option.code, option.data.clone()));
+    }
+    message
+}
+
+fn is_timeout(error: &ProtoError) -> bool {
+    matches!(error.kind(), ProtoErrorKind::Timeout)
 }
 
 fn read_lines(path: &str) -> std::io::Result<impl Iterator<Item = std::io::Result<String>>> {
     let file = File::open(path)?;
     Ok(BufReader::new(file).lines())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_summary() {
+        let mut summary = ErrorSummary::default();
+        for i in 0..7 {
+            summary.add(QueryFailure::Timeout, format!("t{}", i));
+        }
+        summary.add(QueryFailure::Parse, "bad..name".to_string());
+
+        let report = summary.report();
+        assert_eq!(report.total, 8);
+        assert_eq!(report.count("timeout"), 7);
+        assert_eq!(report.by_category["timeout"].samples.len(), ERROR_SAMPLES);
+        assert_eq!(report.by_category["parse_error"].samples[0], "bad..name");
+    }
+
+    #[test]
+    fn test_query_flags() {
+        assert_eq!("3".parse::<RawEdnsOption>().unwrap(), RawEdnsOption { code: 3, data: vec![] });
+        assert_eq!("10:0aFF".parse::<RawEdnsOption>().unwrap().data, vec![0x0a, 0xff]);
+        assert!("10:abc".parse::<RawEdnsOption>().is_err());
+        assert!("nsid".parse::<RawEdnsOption>().is_err());
+
+        let flags = QueryFlags {
+            recursion_desired: false,
+            edns_options: vec!["3".parse().unwrap()],
+            ..QueryFlags::default()
+        };
+        let message = build_query(Name::from_str("www.example.com.").unwrap(), RecordType::A, &flags);
+        assert!(!message.recursion_desired());
+        assert_eq!(message.extensions().as_ref().unwrap().options().as_ref().len(), 1);
+    }
+
+    #[test]
+    fn test_other_record_types() {
+        let name = Name::from_str("example.com.").unwrap();
+        let mx = Record::from_rdata(name.clone(), 300, RData::MX(hickory_client::proto::rr::rdata::MX::new(10, Name::from_str("mail.example.com.").unwrap())));
+        let a = Record::from_rdata(name, 60, RData::A(std::net::Ipv4Addr::new(192, 0, 2, 1).into()));
+        let found = |records: &[Record]| QueryOutcome::Found("example.com".to_string(), Resolution::from_answers(records));
+        let QueryOutcome::Found(_, resolution) = found(&[a]).merge(found(&[mx])) else {
+            panic!("either query found the name");
+        };
+        assert_eq!(resolution.addresses.len(), 1);
+        assert_eq!(resolution.records["MX"], ["10 mail.example.com."]);
+        assert_eq!(resolution.ttl, Some(60));
+    }
+
+    #[test]
+    fn test_repeated_queries() {
+        let names = ["example.com", "www.example.com", "example.com"].map(String::from).to_vec();
+        let scanner = SubdomainScanner::for_names(vec!["192.0.2.53:53".parse().unwrap()], names, Duration::from_secs(1), 1)
+            .unwrap()
+            .with_record_types(vec![RecordType::A, RecordType::MX, RecordType::A]);
+        assert_eq!(scanner.subdomains.iter().collect::<Vec<_>>(), ["example.com", "www.example.com"]);
+        assert_eq!(scanner.query_types(), [RecordType::A, RecordType::MX]);
+        assert_eq!(scanner.repeated_queries(), 9 - 4);
+    }
+
+    #[test]
+    fn test_check_resolvers_exhausted() {
+        let mut summary = ErrorSummary::default();
+        for _ in 0..3 {
+            summary.add(QueryFailure::Timeout, "t".to_string());
+        }
+        let result = |found: Vec<String>| ScanResult {
+            target: "example.com".to_string(),
+            started_at: String::new(),
+            results: ScanFindings {
+                subdomain: found,
+                records: BTreeMap::new(),
+                origins: BTreeMap::new(),
+                attribution: BTreeMap::new(),
+                source_yield: BTreeMap::new(),
+                unconfirmed: vec![],
+                name_scores: BTreeMap::new(),
+                random_looking: vec![],
+                filtered_answers: AnswerReport::default(),
+                internal_ip_disclosure: Vec::new(),
+                negative: BTreeMap::new(),
+                spilled: None,
+                total_scanned: 3,
+                invalid_candidates: 0,
+                sanitization: SanitizationReport::default(),
+                transport: BTreeMap::new(),
+                search_domains: BTreeMap::new(),
+                phases: vec![],
+                unbound: None,
+                resolvers_used: 1,
+                connections: Vec::new(),
+                resolver_usage: Vec::new(),
+                resume_point: ResumePoint::default(),
+                // Three names, each sent twice (--retries 1) and failing
+                // both times, but counted once among the errors.
+                queries_sent: 6,
+                queries_failed: 6,
+                budget_exhausted: false,
+                resolvers_exhausted: false,
+                interrupted: false,
+                errors: summary.report(),
+                truncation: TruncationReport::default(),
+            },
+        };
+        assert!(matches!(
+            result(vec![]).check(),
+            Err(ScanError::ResolversExhausted { sent: 6, failed: 6 })
+        ));
+        assert!(result(vec!["www.example.com".to_string()]).check().is_ok());
+    }
+}
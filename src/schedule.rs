@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::health::ResolverPool;
+use crate::printer::Printer;
+
+/// State shared by the scans of a multi-target run so they can run at the
+/// same time: one global concurrency limit, one rotation over the resolver
+/// pool, the pool itself (so a resolver evicted or a pause taken for one
+/// target holds for all) and one stdout printer. Each scanner still applies
+/// its own limits underneath.
+#[derive(Clone)]
+pub struct Scheduler {
+    permits: Arc<Semaphore>,
+    cursor: Arc<AtomicUsize>,
+    /// One pool per resolver list; targets with their own resolvers get
+    /// their own.
+    pools: Arc<Mutex<HashMap<Vec<SocketAddr>, Arc<ResolverPool>>>>,
+    printer: Printer,
+}
+
+impl Scheduler {
+    pub fn new(concurrency: usize, printer: Printer) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(concurrency.max(1))),
+            cursor: Arc::default(),
+            pools: Arc::default(),
+            printer,
+        }
+    }
+
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.permits.clone().acquire_owned().await.unwrap()
+    }
+
+    /// Position in the resolver pool for the next query, shared by all
+    /// targets so load stays even however the targets interleave.
+    pub fn next_resolver(&self) -> usize {
+        self.cursor.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// The pool of `resolvers` shared by every target using them, built
+    /// with `build` by the first.
+    pub fn pool(&self, resolvers: &[SocketAddr], build: impl FnOnce() -> ResolverPool) -> Arc<ResolverPool> {
+        self.pools.lock().unwrap().entry(resolvers.to_vec()).or_insert_with(|| Arc::new(build())).clone()
+    }
+
+    pub fn printer(&self) -> Printer {
+        self.printer.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::HealthPolicy;
+    use crate::printer::ShowMode;
+
+    #[tokio::test]
+    async fn test_shared_limits() {
+        let (printer, task) = Printer::spawn(ShowMode::None);
+        let scheduler = Scheduler::new(2, printer);
+        let other = scheduler.clone();
+        let _a = scheduler.acquire().await;
+        let _b = other.acquire().await;
+        assert_eq!(scheduler.permits.available_permits(), 0);
+        assert_eq!([scheduler.next_resolver(), other.next_resolver(), scheduler.next_resolver()], [0, 1, 2]);
+
+        let resolvers: Vec<SocketAddr> = vec!["192.0.2.1:53".parse().unwrap()];
+        let build = || ResolverPool::new(resolvers.clone(), HealthPolicy::default());
+        let pool = scheduler.pool(&resolvers, build);
+        assert!(Arc::ptr_eq(&pool, &other.pool(&resolvers, build)));
+        let own = ["192.0.2.2:53".parse().unwrap()];
+        assert!(!Arc::ptr_eq(&pool, &other.pool(&own, || ResolverPool::new(own.to_vec(), HealthPolicy::default()))));
+        drop((scheduler, other));
+        task.finish().await;
+    }
+}
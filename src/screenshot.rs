@@ -0,0 +1,209 @@
+//! `--screenshots`: a headless Chromium screenshot of every found name that
+//! accepts HTTP(S) connections, with an `index.html` linking them and a
+//! `urls.txt` for handing the same hosts to gowitness or aquatone instead.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use tracing::debug;
+
+/// Browsers running at once; each one is a few hundred MB.
+const CONCURRENCY: usize = 4;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Screenshot {
+    pub url: String,
+    /// Relative to the target's screenshot directory.
+    pub file: String,
+}
+
+/// What one target's capture left behind.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScreenshotReport {
+    pub dir: PathBuf,
+    /// Names with a live HTTP(S) port, listed in `urls.txt`.
+    pub urls: usize,
+    pub screenshots: BTreeMap<String, Screenshot>,
+    /// Set when the browser could not be started; `urls.txt` is still written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub browser_error: Option<String>,
+}
+
+pub struct Screenshotter {
+    browser: String,
+    dir: PathBuf,
+    timeout: Duration,
+}
+
+impl Screenshotter {
+    /// Captures into a directory per target below `dir`, with `browser`
+    /// (a Chromium or Chrome binary) given `timeout` per page.
+    pub fn new(browser: &str, dir: &Path, timeout: Duration) -> Self {
+        Self {
+            browser: browser.to_string(),
+            dir: dir.to_path_buf(),
+            timeout,
+        }
+    }
+
+    /// Screenshots every name in `hosts` (name and the address the scan
+    /// resolved it to) that accepts a connection on 443 or 80.
+    pub async fn capture_all(&self, target: &str, hosts: &[(String, IpAddr)]) -> io::Result<ScreenshotReport> {
+        let dir = self.dir.join(file_name(target));
+        tokio::fs::create_dir_all(&dir).await?;
+
+        let mut live = Vec::new();
+        let mut set = JoinSet::new();
+        for (name, address) in hosts.iter().cloned() {
+            set.spawn(async move { live_url(&name, address).await.map(|url| (name, address, url)) });
+        }
+        while let Some(joined) = set.join_next().await {
+            if let Ok(Some(host)) = joined {
+                live.push(host);
+            }
+        }
+        live.sort();
+        let urls: String = live.iter().map(|(_, _, url)| format!("{}\n", url)).collect();
+        tokio::fs::write(dir.join("urls.txt"), urls).await?;
+
+        let mut report = ScreenshotReport {
+            dir: dir.clone(),
+            urls: live.len(),
+            ..Default::default()
+        };
+        let mut pending = live.into_iter();
+        let mut set = JoinSet::new();
+        loop {
+            while report.browser_error.is_none()
+                && set.len() < CONCURRENCY
+                && let Some((name, address, url)) = pending.next()
+            {
+                let file = format!("{}.png", file_name(&name));
+                let path = dir.join(&file);
+                let command = self.command(&name, address, &url, &path);
+                let limit = self.timeout;
+                set.spawn(async move {
+                    let result = run(command, limit, &path).await;
+                    (name, Screenshot { url, file }, result)
+                });
+            }
+            match set.join_next().await {
+                Some(Ok((name, screenshot, Ok(())))) => {
+                    report.screenshots.insert(name, screenshot);
+                }
+                Some(Ok((_, _, Err(e)))) if e.kind() == io::ErrorKind::NotFound => {
+                    report.browser_error = Some(format!("{}: {}", self.browser, e));
+                }
+                Some(Ok((_, screenshot, Err(e)))) => debug!("no screenshot of {}: {}", screenshot.url, e),
+                Some(Err(_)) => {}
+                None => break,
+            }
+        }
+        tokio::fs::write(dir.join("index.html"), render_index(target, &report.screenshots)).await?;
+        Ok(report)
+    }
+
+    fn command(&self, name: &str, address: IpAddr, url: &str, file: &Path) -> Command {
+        let mut command = Command::new(&self.browser);
+        command
+            .args(["--headless", "--disable-gpu", "--no-sandbox", "--no-first-run", "--hide-scrollbars"])
+            .args(["--ignore-certificate-errors", "--window-size=1280,800"])
+            // Load the page from the address the scan found, not whatever
+            // the system resolver says.
+            .arg(format!("--host-resolver-rules=MAP {} {}", name, address))
+            .arg(format!("--screenshot={}", file.display()))
+            .arg(url)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true);
+        command
+    }
+}
+
+async fn run(mut command: Command, limit: Duration, file: &Path) -> io::Result<()> {
+    let status = timeout(limit, command.status())
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "page did not load in time"))??;
+    if !status.success() {
+        return Err(io::Error::other(format!("browser exited with {}", status)));
+    }
+    // Chromium exits cleanly without a file when the page never loads.
+    tokio::fs::metadata(file).await.map(|_| ())
+}
+
+/// `https://name/` when 443 accepts a connection, else `http://name/` when 80 does.
+async fn live_url(name: &str, address: IpAddr) -> Option<String> {
+    for (port, scheme) in [(443, "https"), (80, "http")] {
+        if let Ok(Ok(_)) = timeout(CONNECT_TIMEOUT, TcpStream::connect(SocketAddr::new(address, port))).await {
+            return Some(format!("{}://{}/", scheme, name));
+        }
+    }
+    None
+}
+
+/// A name safe to use as a file name on any platform.
+pub fn file_name(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' })
+        .collect()
+}
+
+/// A page of thumbnails, each linking to its full screenshot and its URL.
+pub fn render_index(target: &str, screenshots: &BTreeMap<String, Screenshot>) -> String {
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{} screenshots</title>\n\
+         <style>body{{font-family:sans-serif}} figure{{display:inline-block;margin:8px}} img{{width:320px;border:1px solid #ccc}}</style>\n\
+         </head>\n<body>\n<h1>{}</h1>\n",
+        escape(target),
+        escape(target)
+    );
+    for (name, screenshot) in screenshots {
+        html.push_str(&format!(
+            "<figure><a href=\"{file}\"><img src=\"{file}\" alt=\"{name}\"></a><figcaption><a href=\"{url}\">{name}</a></figcaption></figure>\n",
+            file = escape(&screenshot.file),
+            url = escape(&screenshot.url),
+            name = escape(name),
+        ));
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_index() {
+        assert_eq!(file_name("WWW.Example.com"), "www.example.com");
+        assert_eq!(file_name("*.example.com"), "_.example.com");
+
+        let mut screenshots = BTreeMap::new();
+        screenshots.insert(
+            "www.example.com".to_string(),
+            Screenshot {
+                url: "https://www.example.com/".to_string(),
+                file: "www.example.com.png".to_string(),
+            },
+        );
+        let html = render_index("example.com\"<", &screenshots);
+        assert!(html.contains("<title>example.com&quot;&lt; screenshots</title>"));
+        assert!(html.contains("<img src=\"www.example.com.png\""));
+        assert!(html.contains("<a href=\"https://www.example.com/\">www.example.com</a>"));
+    }
+}
@@ -0,0 +1,185 @@
+//! `subscan selftest`: runs the query engine flat out against the embedded
+//! mock server and reports the rate it reached, how long names take to come
+//! out of the pipeline and the memory it took, to size a host or catch a
+//! slowdown between versions.
+//!
+//! Both ends share the machine, so the rate is a floor for what the host
+//! can send to a real resolver, not a promise.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::engine::EngineKind;
+use crate::printer::ShowMode;
+use crate::scanner::SubdomainScanner;
+use crate::testing::MockDns;
+
+/// Zone the benchmark names are under (`.test` is reserved for this).
+pub const ZONE: &str = "selftest.test";
+/// One name in this many exists; the rest are NXDOMAIN, as in a real scan.
+pub const FOUND_EVERY: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct SelftestConfig {
+    pub queries: usize,
+    pub concurrency: u32,
+    pub engine: EngineKind,
+    pub sockets: usize,
+    pub timeout: Duration,
+    pub qps_target: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelftestReport {
+    pub engine: EngineKind,
+    pub concurrency: u32,
+    pub queries: u64,
+    pub found: u64,
+    pub errors: u64,
+    pub elapsed_ms: u64,
+    pub qps: u64,
+    pub qps_target: u64,
+    pub met_target: bool,
+    /// From the start of the scan to the first query at the server.
+    pub startup_ms: u64,
+    /// From a found name's query reaching the server to the name leaving
+    /// the pipeline: the answer, parsing, verification and enrichment.
+    pub answer_to_finding: Latencies,
+    /// From the last query reaching the server to the end of the scan.
+    pub drain_ms: u64,
+    /// Peak resident memory of the process, where the OS reports it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peak_memory_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Latencies {
+    pub samples: usize,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+impl Latencies {
+    pub fn of(mut samples: Vec<Duration>) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        samples.sort();
+        let at = |share: f64| samples[((samples.len() - 1) as f64 * share).round() as usize].as_micros() as u64;
+        Self {
+            samples: samples.len(),
+            p50_us: at(0.5),
+            p90_us: at(0.9),
+            p99_us: at(0.99),
+            max_us: at(1.0),
+        }
+    }
+}
+
+/// The names queried, `queries` of them, every [`FOUND_EVERY`]th existing.
+pub fn names(queries: usize) -> Vec<String> {
+    (0..queries).map(|i| format!("w{}.{}", i, ZONE)).collect()
+}
+
+pub async fn run(config: &SelftestConfig) -> io::Result<SelftestReport> {
+    let names = names(config.queries);
+    let mut zone = MockDns::new();
+    for name in names.iter().step_by(FOUND_EVERY) {
+        zone = zone.with_a(name, Ipv4Addr::new(192, 0, 2, 1));
+    }
+    let server = zone.start().await?;
+    let (found_tx, mut found_rx) = mpsc::unbounded_channel();
+    let scanner = SubdomainScanner::for_names(vec![server.addr()], names, config.timeout, config.concurrency)
+        .map_err(|e| io::Error::other(e.to_string()))?
+        .with_engine(config.engine)
+        .with_socket_count(config.sockets)
+        .with_show(ShowMode::None)
+        .with_found_sender(found_tx);
+    let found_at = tokio::spawn(async move {
+        let mut found_at = HashMap::new();
+        while let Some((name, _)) = found_rx.recv().await {
+            found_at.insert(name, Instant::now());
+        }
+        found_at
+    });
+
+    let started = Instant::now();
+    let result = scanner.scan().await;
+    let elapsed = started.elapsed();
+    drop(scanner);
+    let found_at = found_at.await.unwrap_or_default();
+
+    let arrivals = server.arrivals();
+    let first = arrivals.values().min().copied();
+    let last = arrivals.values().max().copied();
+    let finding = found_at
+        .iter()
+        .filter_map(|(name, found)| Some(found.saturating_duration_since(*arrivals.get(name)?)))
+        .collect();
+    let queries = result.results.queries_sent;
+    let qps = (queries as f64 / elapsed.as_secs_f64().max(f64::EPSILON)) as u64;
+    Ok(SelftestReport {
+        engine: config.engine,
+        concurrency: config.concurrency,
+        queries,
+        found: result.results.subdomain.len() as u64,
+        errors: result.results.errors.total,
+        elapsed_ms: elapsed.as_millis() as u64,
+        qps,
+        qps_target: config.qps_target,
+        met_target: qps >= config.qps_target,
+        startup_ms: first.map_or(0, |first| first.saturating_duration_since(started).as_millis() as u64),
+        answer_to_finding: Latencies::of(finding),
+        drain_ms: last.map_or(0, |last| (started + elapsed).saturating_duration_since(last).as_millis() as u64),
+        peak_memory_bytes: peak_memory(),
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|rest| rest.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map(|kib| kib << 10)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peak_memory() -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_selftest() {
+        assert_eq!(Latencies::of(Vec::new()), Latencies::default());
+        let latencies = Latencies::of((1..=100).rev().map(Duration::from_micros).collect());
+        assert_eq!((latencies.p50_us, latencies.p99_us, latencies.max_us), (51, 99, 100));
+
+        let config = SelftestConfig {
+            queries: 500,
+            concurrency: 50,
+            engine: EngineKind::Hickory,
+            sockets: 1,
+            timeout: Duration::from_secs(2),
+            qps_target: 1,
+        };
+        let report = run(&config).await.unwrap();
+        assert_eq!(report.queries, 500);
+        assert_eq!(report.found, 5);
+        assert_eq!(report.errors, 0);
+        assert!(report.met_target);
+        assert_eq!(report.answer_to_finding.samples, 5);
+    }
+}
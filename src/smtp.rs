@@ -0,0 +1,321 @@
+//! A small SMTP client for monitor mail: immediate mail for high-severity
+//! alerts and periodic digests of every change, for teams that have a mail
+//! server but no chat webhook. Configured under `smtp:` in the alerts file:
+//!
+//! ```yaml
+//! smtp:
+//!   server: smtp.example.com
+//!   tls: starttls             # starttls (port 587), tls (465) or none (25)
+//!   username: alerts@example.com
+//!   password_env: SUBSCAN_SMTP_PASSWORD
+//!   from: subscan@example.com
+//!   to: [secops@example.com]
+//!   digest_every: 86400       # seconds; omit for no digest
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Deserialize;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore};
+
+/// How long one message may take, connection to QUIT.
+const SEND_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain connection upgraded with STARTTLS, which is required.
+    #[default]
+    StartTls,
+    /// TLS from the first byte.
+    Tls,
+    /// No encryption, for a relay on localhost or a trusted network.
+    None,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmtpConfig {
+    pub server: String,
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    /// The environment variable holding the password, so the file can be
+    /// shared without it.
+    pub password_env: Option<String>,
+    pub from: String,
+    /// Who gets high-severity alerts and digests.
+    #[serde(default)]
+    pub to: Vec<String>,
+    /// Seconds between digests of every change; none when unset.
+    pub digest_every: Option<u64>,
+}
+
+/// A checked [`SmtpConfig`], ready to send.
+#[derive(Clone)]
+pub struct Smtp {
+    pub config: SmtpConfig,
+    password: Option<String>,
+}
+
+/// Leaves the password out of logs and panic messages.
+impl fmt::Debug for Smtp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Smtp")
+            .field("config", &self.config)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl Smtp {
+    pub fn new(config: SmtpConfig) -> Result<Self, String> {
+        if config.server.is_empty() {
+            return Err("smtp server is empty".to_string());
+        }
+        if let Some(address) = std::iter::once(&config.from).chain(&config.to).find(|address| !is_address(address)) {
+            return Err(format!("'{}' is not a mail address", address));
+        }
+        if config.digest_every == Some(0) {
+            return Err("smtp digest_every must be at least 1".to_string());
+        }
+        if config.digest_every.is_some() && config.to.is_empty() {
+            return Err("smtp digest_every needs someone to send to under `to`".to_string());
+        }
+        let password = match &config.password_env {
+            Some(var) => Some(std::env::var(var).map_err(|_| format!("smtp password_env {} is not set", var))?),
+            None => None,
+        };
+        if password.is_some() != config.username.is_some() {
+            return Err("smtp username and password_env go together".to_string());
+        }
+        Ok(Self { config, password })
+    }
+
+    fn port(&self) -> u16 {
+        self.config.port.unwrap_or(match self.config.tls {
+            SmtpTls::StartTls => 587,
+            SmtpTls::Tls => 465,
+            SmtpTls::None => 25,
+        })
+    }
+
+    /// Sends one plain-text message to `to`.
+    pub async fn send(&self, to: &[String], subject: &str, body: &str) -> Result<(), String> {
+        if let Some(address) = to.iter().find(|address| !is_address(address)) {
+            return Err(format!("'{}' is not a mail address", address));
+        }
+        let message = message(&self.config.from, to, subject, body);
+        timeout(SEND_TIMEOUT, self.transmit(to, &message))
+            .await
+            .map_err(|_| format!("{}:{} did not finish within {}s", self.config.server, self.port(), SEND_TIMEOUT.as_secs()))?
+    }
+
+    async fn transmit(&self, to: &[String], message: &str) -> Result<(), String> {
+        let server = format!("{}:{}", self.config.server, self.port());
+        let tcp = TcpStream::connect(&server).await.map_err(|e| format!("could not connect to {}: {}", server, e))?;
+        match self.config.tls {
+            SmtpTls::None => self.deliver(&mut BufReader::new(tcp), false, to, message).await,
+            SmtpTls::Tls => {
+                let tls = self.handshake(tcp).await?;
+                self.deliver(&mut BufReader::new(tls), false, to, message).await
+            }
+            SmtpTls::StartTls => {
+                let mut plain = BufReader::new(tcp);
+                reply(&mut plain, 220).await?;
+                command(&mut plain, "EHLO localhost", 250).await?;
+                command(&mut plain, "STARTTLS", 220).await?;
+                let tls = self.handshake(plain.into_inner()).await?;
+                self.deliver(&mut BufReader::new(tls), true, to, message).await
+            }
+        }
+    }
+
+    async fn handshake(&self, tcp: TcpStream) -> Result<tokio_rustls::client::TlsStream<TcpStream>, String> {
+        let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let config = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let name = ServerName::try_from(self.config.server.clone()).map_err(|e| format!("bad smtp server name: {}", e))?;
+        TlsConnector::from(Arc::new(config))
+            .connect(name, tcp)
+            .await
+            .map_err(|e| format!("TLS with {} failed: {}", self.config.server, e))
+    }
+
+    /// The session from EHLO on; `greeted` when the 220 was read before a
+    /// STARTTLS.
+    async fn deliver<S: AsyncRead + AsyncWrite + Unpin>(&self, conn: &mut BufReader<S>, greeted: bool, to: &[String], message: &str) -> Result<(), String> {
+        if !greeted {
+            reply(conn, 220).await?;
+        }
+        command(conn, "EHLO localhost", 250).await?;
+        if let (Some(username), Some(password)) = (&self.config.username, &self.password) {
+            let credentials = STANDARD.encode(format!("\0{}\0{}", username, password));
+            command(conn, &format!("AUTH PLAIN {}", credentials), 235).await?;
+        }
+        command(conn, &format!("MAIL FROM:<{}>", self.config.from), 250).await?;
+        for address in to {
+            command(conn, &format!("RCPT TO:<{}>", address), 250).await?;
+        }
+        command(conn, "DATA", 354).await?;
+        conn.get_mut().write_all(message.as_bytes()).await.map_err(|e| e.to_string())?;
+        command(conn, ".", 250).await?;
+        // The message is accepted; a server hanging up early is not an error.
+        let _ = command(conn, "QUIT", 221).await;
+        Ok(())
+    }
+}
+
+async fn command<S: AsyncRead + AsyncWrite + Unpin>(conn: &mut BufReader<S>, line: &str, expected: u16) -> Result<(), String> {
+    conn.get_mut().write_all(format!("{}\r\n", line).as_bytes()).await.map_err(|e| e.to_string())?;
+    conn.get_mut().flush().await.map_err(|e| e.to_string())?;
+    reply(conn, expected).await.map_err(|e| match line.split_whitespace().next() {
+        // Neither credentials nor the message go into errors.
+        Some("AUTH") => format!("AUTH: {}", e),
+        Some(verb) if verb != "." => format!("{}: {}", verb, e),
+        _ => format!("message: {}", e),
+    })
+}
+
+/// Reads one reply, continuation lines (`250-`) included.
+async fn reply<S: AsyncRead + Unpin>(conn: &mut BufReader<S>, expected: u16) -> Result<(), String> {
+    let mut text = String::new();
+    loop {
+        let mut line = String::new();
+        if conn.read_line(&mut line).await.map_err(|e| e.to_string())? == 0 {
+            return Err("the server closed the connection".to_string());
+        }
+        text.push_str(line.trim_end());
+        if line.as_bytes().get(3) != Some(&b'-') {
+            break;
+        }
+        text.push(' ');
+    }
+    match text.get(..3).and_then(|code| code.parse::<u16>().ok()) {
+        Some(code) if code == expected => Ok(()),
+        _ => Err(format!("server replied '{}'", text)),
+    }
+}
+
+fn is_address(address: &str) -> bool {
+    address.split_once('@').is_some_and(|(local, domain)| !local.is_empty() && !domain.is_empty())
+        && !address.chars().any(|c| c.is_whitespace() || c.is_control() || matches!(c, '<' | '>' | ','))
+}
+
+/// The message as sent after DATA: headers, the body with CRLF line ends
+/// and dot-stuffed, and the terminating line left to the caller.
+fn message(from: &str, to: &[String], subject: &str, body: &str) -> String {
+    let subject = subject.replace(['\r', '\n'], " ");
+    let mut message = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+        from,
+        to.join(", "),
+        subject,
+        chrono::Utc::now().to_rfc2822()
+    );
+    for line in body.lines() {
+        if line.starts_with('.') {
+            message.push('.');
+        }
+        message.push_str(line);
+        message.push_str("\r\n");
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_send() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = BufReader::new(stream);
+            let mut session = Vec::new();
+            conn.get_mut().write_all(b"220 mx.example.com ESMTP\r\n").await.unwrap();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if conn.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end().to_string();
+                let answer: &[u8] = match line.as_str() {
+                    "." if in_data => {
+                        in_data = false;
+                        b"250 queued\r\n"
+                    }
+                    _ if in_data => b"",
+                    "EHLO localhost" => b"250-mx.example.com\r\n250 8BITMIME\r\n",
+                    "DATA" => {
+                        in_data = true;
+                        b"354 go ahead\r\n"
+                    }
+                    "QUIT" => b"221 bye\r\n",
+                    _ => b"250 ok\r\n",
+                };
+                session.push(line);
+                conn.get_mut().write_all(answer).await.unwrap();
+            }
+            session
+        });
+
+        let smtp = Smtp::new(SmtpConfig {
+            server: "127.0.0.1".to_string(),
+            port: Some(port),
+            tls: SmtpTls::None,
+            username: None,
+            password_env: None,
+            from: "subscan@example.com".to_string(),
+            to: vec!["secops@example.com".to_string()],
+            digest_every: None,
+        })
+        .unwrap();
+        smtp.send(&smtp.config.to.clone(), "2 changes", "gone: old.example.com\n.hidden line").await.unwrap();
+        let session = server.await.unwrap();
+        assert_eq!(session[1], "MAIL FROM:<subscan@example.com>");
+        assert_eq!(session[2], "RCPT TO:<secops@example.com>");
+        assert!(session.contains(&"Subject: 2 changes".to_string()));
+        assert!(session.contains(&"..hidden line".to_string()));
+        assert_eq!(session.last().unwrap(), "QUIT");
+
+        assert!(smtp.send(&["not an address".to_string()], "x", "y").await.is_err());
+        let digest_to_nobody = SmtpConfig { to: Vec::new(), digest_every: Some(3600), ..smtp.config.clone() };
+        assert!(Smtp::new(digest_to_nobody).unwrap_err().contains("digest_every"));
+    }
+
+    #[test]
+    fn test_debug_redacts_password() {
+        let config = SmtpConfig {
+            server: "smtp.example.com".to_string(),
+            port: None,
+            tls: SmtpTls::StartTls,
+            username: Some("alerts@example.com".to_string()),
+            password_env: Some("SUBSCAN_SMTP_PASSWORD".to_string()),
+            from: "subscan@example.com".to_string(),
+            to: Vec::new(),
+            digest_every: None,
+        };
+        let smtp = Smtp { config, password: Some("hunter2".to_string()) };
+        let debug = format!("{:?}", smtp);
+        assert!(!debug.contains("hunter2"), "{}", debug);
+        assert!(debug.contains("<redacted>"));
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::BTreeSet;
+
+use url::Url;
+use serde::Deserialize;
+
+use super::{Page, PageRequest, PassiveName, Source, SourceResult, clean_name};
+
+const WAYBACK_PAGE_SIZE: usize = 10000;
+
+/// Hostnames seen in URLs archived by the Wayback Machine (CDX API).
+pub struct Wayback;
+
+impl Wayback {
+    fn request(domain: &str, resume_key: Option<&str>) -> PageRequest {
+        let mut url = format!(
+            "https://web.archive.org/cdx/search/cdx?url=*.{}/*&output=json&fl=original&collapse=urlkey&limit={}&showResumeKey=true",
+            domain, WAYBACK_PAGE_SIZE
+        );
+        if let Some(key) = resume_key {
+            url.push_str("&resumeKey=");
+            url.push_str(key);
+        }
+        PageRequest::get(url)
+    }
+}
+
+impl Source for Wayback {
+    fn name(&self) -> &'static str {
+        "wayback"
+    }
+
+    fn first_page(&self, domain: &str) -> PageRequest {
+        Self::request(domain, None)
+    }
+
+    fn parse(&self, domain: &str, body: &str) -> SourceResult<Page> {
+        if body.trim().is_empty() {
+            return Ok(Page::default());
+        }
+        // Rows are ["original"] with a header row first; with showResumeKey the
+        // last two rows are an empty separator and [resume_key].
+        let rows: Vec<Vec<String>> = serde_json::from_str(body)?;
+        let mut hosts = BTreeSet::new();
+        let mut resume_key = None;
+        let mut after_separator = false;
+        for row in rows.iter().skip(1) {
+            match row.as_slice() {
+                [] => after_separator = true,
+                [key] if after_separator => resume_key = Some(key.clone()),
+                [url, ..] => hosts.extend(host_of(url)),
+            }
+        }
+
+        Ok(Page {
+            names: into_names(hosts, self.name()),
+            next: resume_key.map(|key| Self::request(domain, Some(&key))),
+        })
+    }
+}
+
+/// Hostnames from the latest Common Crawl URL index. The first request lists
+/// the available crawls; the second queries the newest one.
+pub struct CommonCrawl;
+
+#[derive(Deserialize)]
+struct CrawlInfo {
+    #[serde(rename = "cdx-api")]
+    cdx_api: String,
+}
+
+#[derive(Deserialize)]
+struct IndexRecord {
+    url: String,
+}
+
+impl Source for CommonCrawl {
+    fn name(&self) -> &'static str {
+        "commoncrawl"
+    }
+
+    fn first_page(&self, _domain: &str) -> PageRequest {
+        PageRequest::get("https://index.commoncrawl.org/collinfo.json")
+    }
+
+    fn parse(&self, domain: &str, body: &str) -> SourceResult<Page> {
+        let body = body.trim();
+        if body.starts_with('[') {
+            let crawls: Vec<CrawlInfo> = serde_json::from_str(body)?;
+            let next = crawls.first().map(|latest| {
+                PageRequest::get(format!("{}?url=*.{}&output=json&fl=url", latest.cdx_api, domain))
+            });
+            return Ok(Page { names: Vec::new(), next });
+        }
+
+        let hosts: BTreeSet<String> = body
+            .lines()
+            .filter_map(|line| serde_json::from_str::<IndexRecord>(line).ok())
+            .filter_map(|record| host_of(&record.url))
+            .collect();
+        Ok(Page {
+            names: into_names(hosts, self.name()),
+            next: None,
+        })
+    }
+}
+
+fn host_of(raw: &str) -> Option<String> {
+    let parsed = if raw.contains("://") {
+        Url::parse(raw)
+    } else {
+        Url::parse(&format!("http://{}", raw))
+    };
+    let host = parsed.ok()?.host_str()?.to_string();
+    Some(clean_name(&host))
+}
+
+fn into_names(hosts: BTreeSet<String>, source: &'static str) -> Vec<PassiveName> {
+    hosts.into_iter().map(|host| PassiveName::new(host, source)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wayback_pagination() {
+        let body = r#"[["original"],
+            ["http://www.example.com/a"],
+            ["https://API.example.com:8443/b?c=d"],
+            ["www.example.com/c"],
+            [],
+            ["resume123"]]"#;
+        let page = Wayback.parse("example.com", body).unwrap();
+        let names: Vec<_> = page.names.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, vec!["api.example.com", "www.example.com"]);
+        assert!(page.next.unwrap().url.ends_with("&resumeKey=resume123"));
+    }
+
+    #[test]
+    fn test_commoncrawl_two_step() {
+        let page = CommonCrawl
+            .parse("example.com", r#"[{"id": "CC-1", "cdx-api": "https://index.commoncrawl.org/CC-1-index"}]"#)
+            .unwrap();
+        assert_eq!(
+            page.next.unwrap().url,
+            "https://index.commoncrawl.org/CC-1-index?url=*.example.com&output=json&fl=url"
+        );
+
+        let page = CommonCrawl
+            .parse("example.com", "{\"url\": \"https://shop.example.com/\"}\n{\"url\": \"http://example.com/x\"}\n")
+            .unwrap();
+        let names: Vec<_> = page.names.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, vec!["example.com", "shop.example.com"]);
+    }
+}
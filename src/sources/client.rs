@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use reqwest::StatusCode;
+use reqwest::header::RETRY_AFTER;
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use tokio::time::{Instant, sleep_until};
+use tracing::{debug, warn};
+
+use super::{PageRequest, SourceResult};
+
+const MAX_RETRIES: u32 = 4;
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// HTTP client shared by all passive sources. It spaces out requests per
+/// provider, retries rate-limited (429) and transient 5xx responses, and caches
+/// successful bodies on disk so repeated scans don't hit the APIs again.
+pub struct ApiClient {
+    http: reqwest::Client,
+    request_timeout: Duration,
+    cache: Option<ResponseCache>,
+    offline: bool,
+    next_slot: Mutex<HashMap<&'static str, Instant>>,
+}
+
+impl ApiClient {
+    pub fn new(request_timeout: Duration) -> reqwest::Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("subscan/", env!("CARGO_PKG_VERSION")))
+            .timeout(request_timeout)
+            .build()?;
+        Ok(Self {
+            http,
+            request_timeout,
+            cache: None,
+            offline: false,
+            next_slot: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn with_cache(mut self, cache: Option<ResponseCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Refuses every request that would go to the network; cached bodies are
+    /// still served. This is the one place offline mode is enforced, so no
+    /// provider can bypass it.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Fetches `request` on behalf of `provider`, waiting at least `interval`
+    /// since that provider's previous request.
+    pub async fn get(&self, provider: &'static str, interval: Duration, request: &PageRequest) -> SourceResult<String> {
+        if let Some(body) = self.cache.as_ref().and_then(|cache| cache.load(&request.url)) {
+            debug!("{}: cache hit for {}", provider, request.url);
+            return Ok(body);
+        }
+        if self.offline {
+            return Err(format!("offline mode: refusing HTTP request to {}", request.url).into());
+        }
+
+        let mut attempt = 0;
+        loop {
+            self.wait_turn(provider, interval).await;
+
+            let mut builder = self.http.get(&request.url);
+            for (name, value) in &request.headers {
+                builder = builder.header(name, value);
+            }
+            let response = builder.send().await?;
+            let status = response.status();
+
+            if status.is_success() {
+                let body = response.text().await?;
+                if let Some(cache) = &self.cache {
+                    cache.store(&request.url, &body);
+                }
+                return Ok(body);
+            }
+
+            let retryable = status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error();
+            if !retryable || attempt >= MAX_RETRIES {
+                return Err(format!("{} returned {} for {}", provider, status, request.url).into());
+            }
+
+            let backoff = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or_else(|| Duration::from_secs(1 << attempt));
+            warn!("{} returned {}, retrying in {:?}", provider, status, backoff);
+            self.push_back(provider, backoff).await;
+            attempt += 1;
+        }
+    }
+
+    /// Fetches `url` from a found host at `address`, the one the scan
+    /// resolved it to, uncached, unpaced and without retries. Certificates
+    /// are not checked; found hosts often have none that verify. `None` for
+    /// anything but a 200 with a body of at most `limit` bytes.
+    pub async fn fetch_from_host(&self, url: &str, address: IpAddr, limit: usize) -> SourceResult<Option<Vec<u8>>> {
+        if self.offline {
+            return Err(format!("offline mode: refusing HTTP request to {}", url).into());
+        }
+        let host = reqwest::Url::parse(url)?.host_str().unwrap_or_default().to_string();
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("subscan/", env!("CARGO_PKG_VERSION")))
+            .timeout(self.request_timeout.min(Duration::from_secs(10)))
+            .danger_accept_invalid_certs(true)
+            // Port 0 keeps the URL's port.
+            .resolve(&host, SocketAddr::new(address, 0))
+            .build()?;
+        let response = client.get(url).send().await?;
+        if response.status() != StatusCode::OK || response.content_length().is_some_and(|len| len as usize > limit) {
+            return Ok(None);
+        }
+        let body = response.bytes().await?;
+        Ok((!body.is_empty() && body.len() <= limit).then(|| body.to_vec()))
+    }
+
+    async fn wait_turn(&self, provider: &'static str, interval: Duration) {
+        let slot = {
+            let mut slots = self.next_slot.lock().await;
+            let now = Instant::now();
+            let slot = slots.get(provider).copied().unwrap_or(now).max(now);
+            slots.insert(provider, slot + interval);
+            slot
+        };
+        sleep_until(slot).await;
+    }
+
+    async fn push_back(&self, provider: &'static str, delay: Duration) {
+        let mut slots = self.next_slot.lock().await;
+        let resume = Instant::now() + delay;
+        let slot = slots.entry(provider).or_insert(resume);
+        *slot = (*slot).max(resume);
+    }
+}
+
+/// On-disk cache of API response bodies keyed by request URL.
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl ResponseCache {
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Self { dir, ttl }
+    }
+
+    /// A cache in `dir` with the default TTL.
+    pub fn in_dir(dir: PathBuf) -> Self {
+        Self::new(dir, DEFAULT_CACHE_TTL)
+    }
+
+    /// `$XDG_CACHE_HOME/subscan/http`, falling back to `~/.cache/subscan/http`.
+    pub fn default_location() -> Option<Self> {
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(Self::new(base.join("subscan").join("http"), DEFAULT_CACHE_TTL))
+    }
+
+    fn path_for(&self, url: &str) -> PathBuf {
+        self.dir.join(format!("{:x}", Sha256::digest(url.as_bytes())))
+    }
+
+    pub fn load(&self, url: &str) -> Option<String> {
+        let path = self.path_for(url);
+        let modified = std::fs::metadata(&path).ok()?.modified().ok()?;
+        let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+        if age > self.ttl {
+            return None;
+        }
+        std::fs::read_to_string(path).ok()
+    }
+
+    pub fn store(&self, url: &str, body: &str) {
+        let result = std::fs::create_dir_all(&self.dir).and_then(|_| std::fs::write(self.path_for(url), body));
+        if let Err(e) = result {
+            debug!("could not cache response for {}: {}", url, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_round_trip_and_expiry() {
+        let dir = std::env::temp_dir().join(format!("subscan-cache-{}", std::process::id()));
+        let cache = ResponseCache::new(dir.clone(), Duration::from_secs(60));
+        assert_eq!(cache.load("https://example.com/a"), None);
+        cache.store("https://example.com/a", "body");
+        assert_eq!(cache.load("https://example.com/a").as_deref(), Some("body"));
+
+        let expired = ResponseCache::new(dir.clone(), Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(expired.load("https://example.com/a"), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_offline_refuses_requests() {
+        let client = ApiClient::new(Duration::from_secs(1)).unwrap().with_offline(true);
+        let err = client
+            .get("test", Duration::ZERO, &PageRequest::get("http://192.0.2.1/".to_string()))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("offline mode"));
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDateTime, Utc};
+use serde::Deserialize;
+
+use super::{Page, PageRequest, PassiveName, Source, SourceResult, clean_name};
+
+/// Certificate Transparency logs via crt.sh. Expired certificates are kept on
+/// purpose: names that only appear in them are tagged `historical`, since
+/// decommissioned hosts often still resolve or are open to takeover.
+pub struct CrtSh;
+
+#[derive(Deserialize)]
+struct Entry {
+    name_value: String,
+    #[serde(default)]
+    not_after: Option<String>,
+}
+
+impl Source for CrtSh {
+    fn name(&self) -> &'static str {
+        "crtsh"
+    }
+
+    fn first_page(&self, domain: &str) -> PageRequest {
+        PageRequest::get(format!("https://crt.sh/?q=%25.{}&output=json", domain))
+    }
+
+    fn parse(&self, _domain: &str, body: &str) -> SourceResult<Page> {
+        let entries: Vec<Entry> = serde_json::from_str(body)?;
+        let now = Utc::now().naive_utc();
+
+        // name -> (seen in a valid cert, seen as a wildcard)
+        let mut names: BTreeMap<String, (bool, bool)> = BTreeMap::new();
+        for entry in entries {
+            let current = entry
+                .not_after
+                .as_deref()
+                .and_then(|s| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S").ok())
+                .is_none_or(|not_after| not_after >= now);
+            for raw in entry.name_value.lines() {
+                let wildcard = raw.trim().starts_with("*.");
+                let state = names.entry(clean_name(raw)).or_default();
+                state.0 |= current;
+                state.1 |= wildcard;
+            }
+        }
+
+        let names = names
+            .into_iter()
+            .filter(|(name, _)| !name.is_empty() && !name.contains('*'))
+            .map(|(name, (current, wildcard))| {
+                let mut found = PassiveName::new(name, self.name());
+                if !current {
+                    found = found.tagged("historical");
+                }
+                if wildcard {
+                    found = found.tagged("wildcard");
+                }
+                found
+            })
+            .collect();
+        Ok(Page { names, next: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tags_expired_and_wildcard_names() {
+        let body = r#"[
+            {"name_value": "www.example.com\nold.example.com", "not_after": "2001-01-01T00:00:00"},
+            {"name_value": "www.example.com\n*.dev.example.com", "not_after": "2999-01-01T00:00:00"}
+        ]"#;
+        let page = CrtSh.parse("example.com", body).unwrap();
+        let tags: Vec<_> = page.names.iter().map(|n| (n.name.as_str(), n.tags.clone())).collect();
+        assert_eq!(
+            tags,
+            vec![
+                ("dev.example.com", vec!["wildcard"]),
+                ("old.example.com", vec!["historical"]),
+                ("www.example.com", vec![]),
+            ]
+        );
+    }
+}
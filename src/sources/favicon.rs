@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Serialize;
+use tokio::task::JoinSet;
+use tracing::debug;
+
+use super::ApiClient;
+
+/// Favicons larger than this are not hashed.
+const MAX_FAVICON_BYTES: usize = 1 << 20;
+/// Hosts fetched from at once.
+const CONCURRENCY: usize = 20;
+
+/// A host's `/favicon.ico` and its hash as Shodan and Censys index it,
+/// for finding related infrastructure with the same icon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Favicon {
+    pub url: String,
+    pub bytes: usize,
+    pub mmh3: i32,
+    /// Search filter for the same icon on Shodan.
+    pub shodan_query: String,
+}
+
+impl Favicon {
+    pub fn new(url: String, body: &[u8]) -> Self {
+        let mmh3 = favicon_hash(body);
+        Self {
+            url,
+            bytes: body.len(),
+            mmh3,
+            shodan_query: format!("http.favicon.hash:{}", mmh3),
+        }
+    }
+}
+
+/// Fetches the favicon of every name from its first address, over HTTPS
+/// and then plain HTTP. Names that serve none are left out.
+pub async fn fetch_all(client: Arc<ApiClient>, hosts: &[(String, IpAddr)]) -> BTreeMap<String, Favicon> {
+    let mut favicons = BTreeMap::new();
+    let mut set = JoinSet::new();
+    let mut pending = hosts.iter().cloned();
+    loop {
+        while set.len() < CONCURRENCY
+            && let Some((name, address)) = pending.next()
+        {
+            let client = client.clone();
+            set.spawn(async move {
+                for scheme in ["https", "http"] {
+                    let url = format!("{}://{}/favicon.ico", scheme, name);
+                    match client.fetch_from_host(&url, address, MAX_FAVICON_BYTES).await {
+                        Ok(Some(body)) => return Some((name, Favicon::new(url, &body))),
+                        Ok(None) => {}
+                        Err(e) => debug!("no favicon from {}: {}", url, e),
+                    }
+                }
+                None
+            });
+        }
+        match set.join_next().await {
+            Some(Ok(Some((name, favicon)))) => {
+                favicons.insert(name, favicon);
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    favicons
+}
+
+/// Shodan's favicon hash: MurmurHash3 (x86, 32-bit, seed 0) of the body
+/// base64-encoded the way Python's `base64.encodebytes` does it, with a
+/// newline after every 76 characters and at the end.
+pub fn favicon_hash(body: &[u8]) -> i32 {
+    let encoded = STANDARD.encode(body);
+    let mut lines = String::with_capacity(encoded.len() + encoded.len() / 76 + 1);
+    for chunk in encoded.as_bytes().chunks(76) {
+        lines.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        lines.push('\n');
+    }
+    murmur3_32(lines.as_bytes(), 0) as i32
+}
+
+pub fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+    let mut hash = seed;
+    let mut blocks = data.chunks_exact(4);
+    for block in &mut blocks {
+        let k = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+        hash ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash = hash.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        let k = tail.iter().rev().fold(0u32, |k, &b| (k << 8) | b as u32);
+        hash ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ (hash >> 16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_murmur3() {
+        assert_eq!(murmur3_32(b"", 0), 0);
+        assert_eq!(murmur3_32(b"hello", 0), 613_153_351);
+        assert_eq!(murmur3_32(b"The quick brown fox jumps over the lazy dog", 0), 0x2e4f_f723);
+        // base64.encodebytes(b"hello") is "aGVsbG8=\n".
+        assert_eq!(favicon_hash(b"hello"), murmur3_32(b"aGVsbG8=\n", 0) as i32);
+        let long = vec![0u8; 100];
+        let encoded = STANDARD.encode(&long);
+        let expected = format!("{}\n{}\n", &encoded[..76], &encoded[76..]);
+        assert_eq!(favicon_hash(&long), murmur3_32(expected.as_bytes(), 0) as i32);
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use super::{Page, PageRequest, PassiveName, Source, SourceResult, extract_hosts};
+
+const PER_PAGE: u32 = 100;
+// The search API never returns more than 1000 results.
+const LAST_PAGE: u32 = 10;
+
+/// Hostnames mentioned in public code on GitHub. Code search requires a token.
+pub struct GitHub {
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct SearchResults {
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+struct Item {
+    #[serde(default)]
+    text_matches: Vec<TextMatch>,
+}
+
+#[derive(Deserialize)]
+struct TextMatch {
+    #[serde(default)]
+    fragment: String,
+}
+
+impl GitHub {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self { token: token.into() }
+    }
+
+    fn request(&self, domain: &str, page: u32) -> PageRequest {
+        PageRequest::get(format!(
+            "https://api.github.com/search/code?q=%22{}%22&per_page={}&page={}",
+            domain, PER_PAGE, page
+        ))
+        .with_header("Authorization", format!("Bearer {}", self.token))
+        .with_header("Accept", "application/vnd.github.text-match+json")
+        .with_header("X-GitHub-Api-Version", "2022-11-28")
+    }
+}
+
+impl Source for GitHub {
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
+    // Code search allows 10 requests per minute.
+    fn rate_limit(&self) -> Duration {
+        Duration::from_secs(6)
+    }
+
+    fn first_page(&self, domain: &str) -> PageRequest {
+        self.request(domain, 1)
+    }
+
+    fn parse(&self, domain: &str, body: &str) -> SourceResult<Page> {
+        let results: SearchResults = serde_json::from_str(body)?;
+        let hosts: BTreeSet<String> = results
+            .items
+            .iter()
+            .flat_map(|item| &item.text_matches)
+            .flat_map(|m| extract_hosts(&m.fragment, domain))
+            .collect();
+        Ok(Page {
+            names: hosts.into_iter().map(|host| PassiveName::new(host, self.name())).collect(),
+            next: None,
+        })
+    }
+
+    fn next_page(&self, domain: &str, current: &PageRequest, page: &Page) -> Option<PageRequest> {
+        if page.names.is_empty() {
+            return None;
+        }
+        let number: u32 = current.url.rsplit("&page=").next()?.parse().ok()?;
+        (number < LAST_PAGE).then(|| self.request(domain, number + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_paginate() {
+        let github = GitHub::new("t");
+        let body = r#"{"items": [{"text_matches": [
+            {"fragment": "BASE_URL=https://staging.example.com/api\nhost: db-1.internal.example.com;"}
+        ]}]}"#;
+        let page = github.parse("example.com", body).unwrap();
+        let names: Vec<_> = page.names.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, vec!["db-1.internal.example.com", "staging.example.com"]);
+
+        let first = github.first_page("example.com");
+        assert!(github.next_page("example.com", &first, &page).unwrap().url.ends_with("&page=2"));
+        let last = github.request("example.com", LAST_PAGE);
+        assert_eq!(github.next_page("example.com", &last, &page), None);
+    }
+}
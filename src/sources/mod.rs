@@ -0,0 +1,237 @@
+//! Passive subdomain sources. Each provider only describes how to build its
+//! requests and parse its responses; [`ApiClient`] handles rate limiting,
+//! retries and caching for all of them. WASM builds get the providers but
+//! not the client: the host fetches each [`PageRequest`] itself and hands
+//! the body to [`Source::parse`].
+
+mod archive;
+#[cfg(not(target_family = "wasm"))]
+mod client;
+mod crtsh;
+#[cfg(not(target_family = "wasm"))]
+pub mod favicon;
+mod github;
+#[cfg(not(target_family = "wasm"))]
+mod pdns;
+#[cfg(not(target_family = "wasm"))]
+mod rdap;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(not(target_family = "wasm"))]
+use tokio::task::JoinSet;
+#[cfg(not(target_family = "wasm"))]
+use tracing::{info, warn};
+
+pub use crate::passive::{PassiveName, PassiveSet};
+pub use archive::{CommonCrawl, Wayback};
+#[cfg(not(target_family = "wasm"))]
+pub use client::{ApiClient, ResponseCache};
+pub use crtsh::CrtSh;
+pub use github::GitHub;
+#[cfg(not(target_family = "wasm"))]
+pub use pdns::{PassiveDns, PdnsRecord, RecordChange};
+#[cfg(not(target_family = "wasm"))]
+pub use rdap::{DomainRegistration, Netblock, OwnershipMismatch, Rdap, RdapReport, same_org};
+
+pub type SourceResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+#[cfg(not(target_family = "wasm"))]
+// Guards against providers whose pagination never terminates.
+const MAX_PAGES: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageRequest {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+impl PageRequest {
+    pub fn get(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            headers: Vec::new(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Page {
+    pub names: Vec<PassiveName>,
+    pub next: Option<PageRequest>,
+}
+
+pub trait Source: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Minimum spacing between two requests to this provider.
+    fn rate_limit(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn first_page(&self, domain: &str) -> PageRequest;
+
+    fn parse(&self, domain: &str, body: &str) -> SourceResult<Page>;
+
+    /// Next request for sources that paginate by counter rather than naming
+    /// their successor in the response. Only consulted when `page.next` is empty.
+    fn next_page(&self, _domain: &str, _current: &PageRequest, _page: &Page) -> Option<PageRequest> {
+        None
+    }
+}
+
+/// Credentials for sources that need them, read from the environment.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKeys {
+    pub github: Option<String>,
+    pub pdns_key: Option<String>,
+    pub pdns_basic_auth: Option<String>,
+}
+
+impl ApiKeys {
+    pub fn from_env() -> Self {
+        Self {
+            github: env_key("GITHUB_TOKEN"),
+            pdns_key: env_key("PDNS_API_KEY"),
+            pdns_basic_auth: env_key("PDNS_BASIC_AUTH"),
+        }
+    }
+}
+
+fn env_key(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+pub const AVAILABLE: &[&str] = &["crtsh", "wayback", "commoncrawl", "github"];
+
+pub fn by_name(name: &str, keys: &ApiKeys) -> Result<Arc<dyn Source>, String> {
+    match name {
+        "crtsh" => Ok(Arc::new(CrtSh)),
+        "wayback" => Ok(Arc::new(Wayback)),
+        "commoncrawl" => Ok(Arc::new(CommonCrawl)),
+        "github" => match &keys.github {
+            Some(token) => Ok(Arc::new(GitHub::new(token))),
+            None => Err("the github source needs a token, set GITHUB_TOKEN".to_string()),
+        },
+        _ => Err(format!("unknown source '{}' (available: {})", name, AVAILABLE.join(", "))),
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+/// Pages through one source and returns every name it reported under `domain`.
+pub async fn fetch_all(client: &ApiClient, source: &dyn Source, domain: &str) -> SourceResult<Vec<PassiveName>> {
+    let mut names = Vec::new();
+    let mut request = Some(source.first_page(domain));
+    let mut pages = 0;
+
+    while let Some(current) = request.take() {
+        let body = client.get(source.name(), source.rate_limit(), &current).await?;
+        let mut page = source.parse(domain, &body)?;
+        let next = page.next.take().or_else(|| source.next_page(domain, &current, &page));
+        names.extend(page.names.into_iter().filter(|n| is_under(&n.name, domain)));
+
+        pages += 1;
+        if pages >= MAX_PAGES {
+            warn!("{}: stopping after {} pages", source.name(), pages);
+            break;
+        }
+        request = next;
+    }
+    Ok(names)
+}
+
+#[cfg(not(target_family = "wasm"))]
+/// Queries every source concurrently. A failing source is logged and skipped so
+/// the others still contribute.
+/// Runs `sources` concurrently and merges what they report. With a
+/// `time_limit`, sources still running when it expires are dropped and the
+/// names gathered so far are returned.
+pub async fn collect(
+    client: Arc<ApiClient>,
+    sources: Vec<Arc<dyn Source>>,
+    domain: &str,
+    time_limit: Option<Duration>,
+) -> Vec<PassiveName> {
+    let deadline = time_limit.map(|limit| tokio::time::Instant::now() + limit);
+    let mut tasks = JoinSet::new();
+    for source in sources {
+        let client = client.clone();
+        let domain = domain.to_string();
+        tasks.spawn(async move {
+            let result = fetch_all(&client, source.as_ref(), &domain).await;
+            (source.name(), result)
+        });
+    }
+
+    let mut names = PassiveSet::default();
+    loop {
+        let next = match deadline {
+            Some(at) => match tokio::time::timeout_at(at, tasks.join_next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    warn!("passive source time limit reached, dropping {} unfinished sources", tasks.len());
+                    tasks.abort_all();
+                    break;
+                }
+            },
+            None => tasks.join_next().await,
+        };
+        let Some(joined) = next else {
+            break;
+        };
+        let Ok((source, result)) = joined else {
+            continue;
+        };
+        match result {
+            Ok(found) => {
+                info!("{}: {} names", source, found.len());
+                names.extend(found);
+            }
+            Err(e) => warn!("{}: {}", source, e),
+        }
+    }
+    names.into_names()
+}
+
+/// Lowercases a reported name, strips wildcard prefixes and the trailing dot.
+pub fn clean_name(raw: &str) -> String {
+    raw.trim()
+        .trim_start_matches("*.")
+        .trim_end_matches('.')
+        .to_lowercase()
+}
+
+/// Pulls every hostname under `domain` out of free text such as code or HTML.
+pub fn extract_hosts(text: &str, domain: &str) -> Vec<String> {
+    text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '-'))
+        .map(|token| clean_name(token.trim_start_matches(['.', '-'])))
+        .filter(|host| is_under(host, domain))
+        .collect()
+}
+
+fn is_under(name: &str, domain: &str) -> bool {
+    name == domain || name.strip_suffix(domain).is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_name() {
+        assert_eq!(clean_name(" *.API.Example.com. "), "api.example.com");
+    }
+
+    #[test]
+    fn test_is_under() {
+        assert!(is_under("a.example.com", "example.com"));
+        assert!(is_under("example.com", "example.com"));
+        assert!(!is_under("badexample.com", "example.com"));
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::warn;
+
+use super::{ApiClient, PageRequest, SourceResult};
+
+const PROVIDER: &str = "pdns";
+const DAY: u64 = 24 * 60 * 60;
+
+/// Passive DNS lookups against any server speaking the Common Output Format
+/// (CIRCL pDNS, Farsight DNSDB and compatibles): `<base_url>/<name>` returning
+/// one JSON record per line, optionally wrapped DNSDB-style in `{"obj": ...}`.
+pub struct PassiveDns {
+    base_url: String,
+    auth: Option<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PdnsRecord {
+    pub rrname: String,
+    pub rrtype: String,
+    #[serde(deserialize_with = "rdata_strings")]
+    pub rdata: Vec<String>,
+    #[serde(alias = "zone_time_first")]
+    pub time_first: u64,
+    #[serde(alias = "zone_time_last")]
+    pub time_last: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecordChange {
+    pub kind: &'static str,
+    pub record: PdnsRecord,
+}
+
+impl PassiveDns {
+    /// `api_key` is sent as `X-API-Key` (DNSDB); `basic_auth` as `user:password`
+    /// HTTP basic credentials (CIRCL).
+    pub fn new(base_url: &str, api_key: Option<&str>, basic_auth: Option<&str>) -> Self {
+        let auth = match (api_key, basic_auth) {
+            (Some(key), _) => Some(("X-API-Key".to_string(), key.to_string())),
+            (None, Some(credentials)) => Some((
+                "Authorization".to_string(),
+                format!("Basic {}", STANDARD.encode(credentials)),
+            )),
+            (None, None) => None,
+        };
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            auth,
+        }
+    }
+
+    pub async fn history(&self, client: &ApiClient, name: &str) -> SourceResult<Vec<PdnsRecord>> {
+        let mut request = PageRequest::get(format!("{}/{}", self.base_url, name));
+        if let Some((header, value)) = &self.auth {
+            request = request.with_header(header, value.clone());
+        }
+        let body = client.get(PROVIDER, Duration::from_millis(500), &request).await?;
+        Ok(parse_cof(&body))
+    }
+
+    /// The names passive DNS has with `value` as the data of one of
+    /// `rrtypes`: the other names on an address, nameserver or mail
+    /// exchanger. Servers answer a query for an address or a server name
+    /// with the records pointing at it.
+    pub async fn reverse(&self, client: &ApiClient, value: &str, rrtypes: &[&str]) -> SourceResult<Vec<String>> {
+        Ok(reverse_names(&self.history(client, value).await?, value, rrtypes))
+    }
+
+    /// History and recent changes for each of `names`, keyed by name. Names
+    /// the server has nothing for are left out.
+    pub async fn report(&self, client: &ApiClient, names: &[String], window_days: u64) -> serde_json::Map<String, Value> {
+        let now = chrono::Utc::now().timestamp().max(0) as u64;
+        let mut report = serde_json::Map::new();
+        for name in names {
+            match self.history(client, name).await {
+                Ok(records) if !records.is_empty() => {
+                    let changes = recent_changes(&records, now, window_days);
+                    report.insert(name.clone(), json!({ "records": records, "recent_changes": changes }));
+                }
+                Ok(_) => {}
+                Err(e) => warn!("pdns lookup for {} failed: {}", name, e),
+            }
+        }
+        report
+    }
+}
+
+pub fn parse_cof(body: &str) -> Vec<PdnsRecord> {
+    body.lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line.trim()).ok())
+        .map(|value| match value {
+            Value::Object(mut map) if map.contains_key("obj") => map.remove("obj").unwrap_or_default(),
+            other => other,
+        })
+        .filter_map(|value| serde_json::from_value(value).ok())
+        .collect()
+}
+
+pub fn reverse_names(records: &[PdnsRecord], value: &str, rrtypes: &[&str]) -> Vec<String> {
+    let value = value.trim_end_matches('.').to_lowercase();
+    let mut names: Vec<String> = records
+        .iter()
+        .filter(|record| rrtypes.iter().any(|rrtype| record.rrtype.eq_ignore_ascii_case(rrtype)))
+        // MX data is `preference exchange`.
+        .filter(|record| {
+            record
+                .rdata
+                .iter()
+                .any(|rdata| rdata.split_whitespace().last().is_some_and(|data| data.trim_end_matches('.').eq_ignore_ascii_case(&value)))
+        })
+        .map(|record| record.rrname.trim_end_matches('.').to_lowercase())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Records that changed within `window_days` of `now`: ones first observed in
+/// the window (`added`), and ones last observed in the window while another
+/// record of the same type has been seen since (`replaced`).
+pub fn recent_changes(records: &[PdnsRecord], now: u64, window_days: u64) -> Vec<RecordChange> {
+    let since = now.saturating_sub(window_days * DAY);
+    let mut latest_by_type: HashMap<&str, u64> = HashMap::new();
+    for record in records {
+        let latest = latest_by_type.entry(&record.rrtype).or_default();
+        *latest = (*latest).max(record.time_last);
+    }
+
+    let mut changes = Vec::new();
+    for record in records {
+        if record.time_first >= since {
+            changes.push(RecordChange {
+                kind: "added",
+                record: record.clone(),
+            });
+        } else if record.time_last >= since && record.time_last + DAY < latest_by_type[record.rrtype.as_str()] {
+            changes.push(RecordChange {
+                kind: "replaced",
+                record: record.clone(),
+            });
+        }
+    }
+    changes
+}
+
+fn rdata_strings<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| item.as_str().map(str::to_string).unwrap_or_else(|| item.to_string()))
+            .collect(),
+        Value::String(s) => vec![s],
+        other => vec![other.to_string()],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_cof_and_dnsdb_wrapping() {
+        let body = concat!(
+            r#"{"rrname": "www.example.com", "rrtype": "A", "rdata": "1.1.1.1", "time_first": 10, "time_last": 20}"#,
+            "\n",
+            r#"{"obj": {"rrname": "www.example.com.", "rrtype": "A", "rdata": ["2.2.2.2"], "time_first": 30, "time_last": 40}}"#,
+            "\n",
+            "not json\n"
+        );
+        let records = parse_cof(body);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].rdata, vec!["1.1.1.1"]);
+        assert_eq!(records[1].rdata, vec!["2.2.2.2"]);
+    }
+
+    #[test]
+    fn test_reverse_names() {
+        let body = concat!(
+            r#"{"rrname": "example.org.", "rrtype": "MX", "rdata": "10 mx.example.net.", "time_first": 10, "time_last": 20}"#,
+            "\n",
+            r#"{"rrname": "mx.example.net", "rrtype": "A", "rdata": "192.0.2.25", "time_first": 10, "time_last": 20}"#,
+            "\n",
+            r#"{"rrname": "Example.com", "rrtype": "MX", "rdata": "20 MX.example.net", "time_first": 10, "time_last": 20}"#,
+        );
+        assert_eq!(reverse_names(&parse_cof(body), "mx.example.net", &["MX"]), ["example.com", "example.org"]);
+    }
+
+    #[test]
+    fn test_recent_changes() {
+        let now = 1000 * DAY;
+        let record = |rdata: &str, first: u64, last: u64| PdnsRecord {
+            rrname: "www.example.com".to_string(),
+            rrtype: "A".to_string(),
+            rdata: vec![rdata.to_string()],
+            time_first: first * DAY,
+            time_last: last * DAY,
+        };
+        let records = vec![
+            record("1.1.1.1", 100, 990),
+            record("2.2.2.2", 991, 1000),
+            record("3.3.3.3", 100, 1000),
+        ];
+        let changes: Vec<_> = recent_changes(&records, now, 30)
+            .into_iter()
+            .map(|c| (c.kind, c.record.rdata[0].clone()))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![("replaced", "1.1.1.1".to_string()), ("added", "2.2.2.2".to_string())]
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::Value;
+use tracing::warn;
+
+use super::{ApiClient, PageRequest, SourceResult};
+
+const PROVIDER: &str = "rdap";
+/// Netblocks looked up per scan; addresses inside a netblock already
+/// fetched need no lookup of their own.
+const MAX_IP_LOOKUPS: usize = 100;
+
+/// Registration data over RDAP: who registered the target domain and who
+/// owns the netblocks its names resolve to. `base_url` is a bootstrap
+/// service such as https://rdap.org that redirects to the right registry.
+pub struct Rdap {
+    base_url: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DomainRegistration {
+    pub handle: Option<String>,
+    /// Left out when the registry redacts it.
+    pub registrant_org: Option<String>,
+    pub registrar: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Netblock {
+    pub handle: Option<String>,
+    pub name: Option<String>,
+    pub start: IpAddr,
+    pub end: IpAddr,
+    pub country: Option<String>,
+    pub org: Option<String>,
+    /// Found names with an address in the block.
+    pub names: Vec<String>,
+}
+
+/// A found name whose address sits in a netblock some other organization
+/// holds: hosted elsewhere, or a record left behind.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OwnershipMismatch {
+    pub name: String,
+    pub address: IpAddr,
+    pub netblock_org: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RdapReport {
+    pub domain: Option<DomainRegistration>,
+    /// The organization netblocks are compared with: `--rdap-org`, or the
+    /// domain's registrant.
+    pub expected_org: Option<String>,
+    pub netblocks: Vec<Netblock>,
+    pub mismatches: Vec<OwnershipMismatch>,
+}
+
+impl Rdap {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    async fn fetch(&self, client: &ApiClient, path: &str) -> SourceResult<Value> {
+        let request = PageRequest::get(format!("{}/{}", self.base_url, path)).with_header("Accept", "application/rdap+json");
+        let body = client.get(PROVIDER, Duration::from_millis(500), &request).await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Who registered `domain`.
+    pub async fn domain(&self, client: &ApiClient, domain: &str) -> SourceResult<DomainRegistration> {
+        Ok(parse_domain(&self.fetch(client, &format!("domain/{}", domain)).await?))
+    }
+
+    /// Looks up `domain` and the netblocks of every address in `found`
+    /// (name and addresses), and lists the names whose netblock belongs to
+    /// someone other than `expected_org` (by default the registrant).
+    pub async fn report(
+        &self,
+        client: &ApiClient,
+        domain: &str,
+        found: &[(String, Vec<IpAddr>)],
+        expected_org: Option<&str>,
+    ) -> RdapReport {
+        let mut report = RdapReport::default();
+        match self.domain(client, domain).await {
+            Ok(registration) => report.domain = Some(registration),
+            Err(e) => warn!("rdap lookup for {} failed: {}", domain, e),
+        }
+        report.expected_org = expected_org
+            .map(str::to_string)
+            .or_else(|| report.domain.as_ref().and_then(|d| d.registrant_org.clone()));
+
+        let mut lookups = 0;
+        for (name, addresses) in found {
+            for address in addresses.iter().filter(|a| is_public(**a)) {
+                let index = match report.netblocks.iter().position(|block| block.contains(*address)) {
+                    Some(index) => index,
+                    None if lookups < MAX_IP_LOOKUPS => {
+                        lookups += 1;
+                        match self.fetch(client, &format!("ip/{}", address)).await.map(|body| parse_netblock(&body)) {
+                            Ok(Some(block)) if block.contains(*address) => {
+                                report.netblocks.push(block);
+                                report.netblocks.len() - 1
+                            }
+                            Ok(_) => continue,
+                            Err(e) => {
+                                warn!("rdap lookup for {} failed: {}", address, e);
+                                continue;
+                            }
+                        }
+                    }
+                    None => continue,
+                };
+                let block = &mut report.netblocks[index];
+                if !block.names.contains(name) {
+                    block.names.push(name.clone());
+                }
+                if let (Some(expected), Some(org)) = (&report.expected_org, &block.org)
+                    && !same_org(expected, org)
+                {
+                    report.mismatches.push(OwnershipMismatch {
+                        name: name.clone(),
+                        address: *address,
+                        netblock_org: org.clone(),
+                    });
+                }
+            }
+        }
+        if lookups == MAX_IP_LOOKUPS {
+            warn!("stopped rdap netblock lookups after {}", MAX_IP_LOOKUPS);
+        }
+        report
+    }
+}
+
+impl Netblock {
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.start, self.end, address) {
+            (IpAddr::V4(start), IpAddr::V4(end), IpAddr::V4(a)) => (start..=end).contains(&a),
+            (IpAddr::V6(start), IpAddr::V6(end), IpAddr::V6(a)) => (start..=end).contains(&a),
+            _ => false,
+        }
+    }
+}
+
+// Registries hold nothing useful on private and reserved space.
+fn is_public(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(a) => !(a.is_private() || a.is_loopback() || a.is_link_local() || a.is_unspecified() || a.is_documentation()),
+        IpAddr::V6(a) => !(a.is_loopback() || a.is_unspecified() || (a.segments()[0] & 0xfe00) == 0xfc00 || (a.segments()[0] & 0xffc0) == 0xfe80),
+    }
+}
+
+pub fn parse_domain(body: &Value) -> DomainRegistration {
+    DomainRegistration {
+        handle: body["handle"].as_str().map(str::to_string),
+        registrant_org: entity_org(body, "registrant"),
+        registrar: entity_org(body, "registrar"),
+    }
+}
+
+pub fn parse_netblock(body: &Value) -> Option<Netblock> {
+    Some(Netblock {
+        handle: body["handle"].as_str().map(str::to_string),
+        name: body["name"].as_str().map(str::to_string),
+        start: body["startAddress"].as_str()?.parse().ok()?,
+        end: body["endAddress"].as_str()?.parse().ok()?,
+        country: body["country"].as_str().map(str::to_string),
+        org: entity_org(body, "registrant").or_else(|| body["name"].as_str().map(str::to_string)),
+        names: Vec::new(),
+    })
+}
+
+/// The organization (or full name) on the vCard of the first entity with
+/// `role`, searching nested entities too. Redacted values count as none.
+fn entity_org(object: &Value, role: &str) -> Option<String> {
+    for entity in object["entities"].as_array().into_iter().flatten() {
+        let has_role = entity["roles"].as_array().into_iter().flatten().any(|r| r.as_str() == Some(role));
+        if has_role {
+            let card = entity["vcardArray"][1].as_array().into_iter().flatten();
+            let mut fields: BTreeMap<&str, &str> = BTreeMap::new();
+            for property in card {
+                if let (Some(key), Some(value)) = (property[0].as_str(), property[3].as_str()) {
+                    fields.entry(key).or_insert(value);
+                }
+            }
+            let org = fields.get("org").or(fields.get("fn")).map(|s| s.trim().to_string());
+            if let Some(org) = org.filter(|org| !org.is_empty() && !is_redacted(org)) {
+                return Some(org);
+            }
+        }
+        if let Some(org) = entity_org(entity, role) {
+            return Some(org);
+        }
+    }
+    None
+}
+
+fn is_redacted(value: &str) -> bool {
+    let value = value.to_lowercase();
+    value.contains("redacted") || value.contains("privacy") || value.contains("not disclosed")
+}
+
+/// Whether two organization names are the same, ignoring case,
+/// punctuation and legal suffixes; one containing the other counts.
+pub fn same_org(a: &str, b: &str) -> bool {
+    let (a, b) = (org_key(a), org_key(b));
+    !a.is_empty() && !b.is_empty() && (a.contains(&b) || b.contains(&a))
+}
+
+fn org_key(name: &str) -> String {
+    const SUFFIXES: &[&str] = &["inc", "llc", "ltd", "limited", "corp", "corporation", "gmbh", "ag", "sa", "bv", "plc", "co"];
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !SUFFIXES.contains(word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entity(role: &str, org: &str) -> Value {
+        json!({ "roles": [role], "vcardArray": ["vcard", [["version", {}, "text", "4.0"], ["fn", {}, "text", org]]] })
+    }
+
+    #[test]
+    fn test_parse_and_match() {
+        let domain = json!({
+            "handle": "D1",
+            "entities": [entity("registrar", "Registrar LLC"), entity("registrant", "REDACTED FOR PRIVACY")],
+        });
+        let registration = parse_domain(&domain);
+        assert_eq!(registration.registrant_org, None);
+        assert_eq!(registration.registrar.as_deref(), Some("Registrar LLC"));
+
+        let block = json!({
+            "handle": "NET-192-0-2-0-1",
+            "startAddress": "192.0.2.0",
+            "endAddress": "192.0.2.255",
+            "name": "EXAMPLE-NET",
+            "entities": [{ "roles": ["administrative"], "entities": [entity("registrant", "Example, Inc.")] }],
+        });
+        let block = parse_netblock(&block).unwrap();
+        assert_eq!(block.org.as_deref(), Some("Example, Inc."));
+        assert!(block.contains("192.0.2.77".parse().unwrap()));
+        assert!(!block.contains("192.0.3.1".parse().unwrap()));
+        assert!(!block.contains("::1".parse().unwrap()));
+
+        assert!(same_org("Example Inc", "EXAMPLE, INC."));
+        assert!(same_org("Example", "Example Holdings Ltd"));
+        assert!(!same_org("Example Inc", "Amazon Technologies Inc."));
+        assert!(!same_org("Inc.", "Ltd"));
+    }
+}
@@ -0,0 +1,130 @@
+//! Found names past `--spill-after`, written to disk as JSON lines instead
+//! of being kept for the result document, so a scan that finds millions of
+//! names runs in bounded memory. The document keeps the first names in
+//! full and says where the rest went.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::attribution::Attribution;
+use crate::entropy::NameScore;
+use crate::passive::PassiveName;
+use crate::scanner::Resolution;
+
+/// Lines waiting for the disk before the scan waits for it in turn.
+const QUEUE: usize = 1024;
+
+/// Everything the result document would hold about one found name, on one
+/// line. `found` is always true, so the file can be concatenated with
+/// `--negative-output`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SpilledName {
+    pub name: String,
+    pub found: bool,
+    pub resolution: Resolution,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origins: Option<Vec<PassiveName>>,
+    pub attribution: Attribution,
+    pub name_score: NameScore,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport: Option<&'static str>,
+}
+
+/// Where the names that did not fit in memory went.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpillReport {
+    pub path: PathBuf,
+    pub names: u64,
+}
+
+/// An open spill file. A blocking task owns the file, as for
+/// [`crate::negative::NegativeLog`], but the queue is bounded so a slow
+/// disk slows the scan down instead of filling memory.
+pub struct SpillFile {
+    path: PathBuf,
+    names: u64,
+    tx: mpsc::Sender<String>,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl SpillFile {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let out = BufWriter::new(File::create(path)?);
+        let (tx, rx) = mpsc::channel(QUEUE);
+        let handle = tokio::task::spawn_blocking(move || write_lines(out, rx));
+        Ok(Self {
+            path: path.to_path_buf(),
+            names: 0,
+            tx,
+            handle,
+        })
+    }
+
+    pub async fn write(&mut self, name: &SpilledName) {
+        if let Ok(line) = serde_json::to_string(name)
+            && self.tx.send(line).await.is_ok()
+        {
+            self.names += 1;
+        }
+    }
+
+    /// Flushes the file.
+    pub async fn finish(self) -> io::Result<SpillReport> {
+        drop(self.tx);
+        self.handle.await.map_err(io::Error::other)??;
+        Ok(SpillReport {
+            path: self.path,
+            names: self.names,
+        })
+    }
+}
+
+fn write_lines(mut out: BufWriter<File>, mut rx: mpsc::Receiver<String>) -> io::Result<()> {
+    while let Some(line) = rx.blocking_recv() {
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attribution::{self, Evidence};
+
+    #[tokio::test]
+    async fn test_spill_file() {
+        let path = std::env::temp_dir().join(format!("subscan-spill-{}.jsonl", std::process::id()));
+        let mut spill = SpillFile::create(&path).unwrap();
+        for name in ["www.example.com", "api.example.com"] {
+            let evidence = Evidence {
+                sources: vec![attribution::BRUTEFORCE.to_string()],
+                ..Default::default()
+            };
+            spill
+                .write(&SpilledName {
+                    name: name.to_string(),
+                    found: true,
+                    resolution: Resolution::default(),
+                    origins: None,
+                    attribution: Attribution::new(evidence),
+                    name_score: crate::entropy::score(name, "example.com"),
+                    transport: None,
+                })
+                .await;
+        }
+        let report = spill.finish().await.unwrap();
+        assert_eq!(report.names, 2);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["name"], "api.example.com");
+        assert_eq!(lines[1]["attribution"]["confidence"], "medium");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::{Value, json};
+use tracing::info;
+
+/// How long one phase of a run took and how much DNS/API traffic it produced.
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseTiming {
+    pub phase: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub elapsed_ms: u128,
+    pub queries: u64,
+    pub answers: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct PhaseTimings {
+    pub phases: Vec<PhaseTiming>,
+}
+
+/// Started with [`PhaseTimings::start`]; recorded once its counts are known.
+pub struct PhaseTimer {
+    phase: &'static str,
+    target: Option<String>,
+    started: Instant,
+}
+
+impl PhaseTimings {
+    pub fn start(&self, phase: &'static str, target: Option<&str>) -> PhaseTimer {
+        PhaseTimer {
+            phase,
+            target: target.map(str::to_string),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn record(&mut self, timer: PhaseTimer, queries: u64, answers: u64) {
+        self.phases.push(PhaseTiming {
+            phase: timer.phase,
+            target: timer.target,
+            elapsed_ms: timer.started.elapsed().as_millis(),
+            queries,
+            answers,
+        });
+    }
+
+    pub fn total(&self) -> Duration {
+        Duration::from_millis(self.phases.iter().map(|p| p.elapsed_ms as u64).sum())
+    }
+
+    /// Logs one line per phase for the end-of-run summary.
+    pub fn log_summary(&self) {
+        for p in &self.phases {
+            info!(
+                "{:<20} {:>8} ms {:>8} queries {:>8} answers{}",
+                p.phase,
+                p.elapsed_ms,
+                p.queries,
+                p.answers,
+                p.target.as_deref().map(|t| format!("  ({})", t)).unwrap_or_default()
+            );
+        }
+        info!("{:<20} {:>8} ms", "total", self.total().as_millis());
+    }
+}
+
+/// The one-line run health record printed to stderr at the end of a run,
+/// whatever the output sink.
+pub fn run_summary(results: &[Value], elapsed: Duration, exit_code: i32) -> Value {
+    let sum = |pick: &dyn Fn(&Value) -> u64| results.iter().map(pick).sum::<u64>();
+    json!({
+        "targets": results.len(),
+        "findings": sum(&|r| {
+            let spilled = r["results"]["spilled"]["names"].as_u64().unwrap_or_default();
+            r["results"]["subdomain"].as_array().map_or(0, |names| names.len() as u64) + spilled
+        }),
+        "queries": sum(&|r| r["results"]["queries_sent"].as_u64().unwrap_or_default()),
+        "errors": sum(&|r| r["results"]["errors"]["total"].as_u64().unwrap_or_default()),
+        "duration_ms": elapsed.as_millis(),
+        "exit_code": exit_code,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_summary() {
+        let result = json!({ "results": { "subdomain": ["a.example.com"], "queries_sent": 7, "errors": { "total": 2 } } });
+        let summary = run_summary(&[result.clone(), result], Duration::from_millis(1500), 0);
+        assert_eq!(
+            summary.to_string(),
+            r#"{"duration_ms":1500,"errors":4,"exit_code":0,"findings":2,"queries":14,"targets":2}"#
+        );
+    }
+
+    #[test]
+    fn test_record_phases() {
+        let mut timings = PhaseTimings::default();
+        let timer = timings.start("brute_force", Some("example.com"));
+        timings.record(timer, 10, 3);
+        let timer = timings.start("enrichment", None);
+        timings.record(timer, 3, 1);
+
+        let json = serde_json::to_value(&timings).unwrap();
+        assert_eq!(json["phases"][0]["phase"], "brute_force");
+        assert_eq!(json["phases"][0]["target"], "example.com");
+        assert_eq!(json["phases"][0]["queries"], 10);
+        assert!(json["phases"][1].get("target").is_none());
+    }
+}
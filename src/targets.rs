@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// Everything that may differ between targets of one run. Command-line
+/// flags give the defaults; a targets file can override them per line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetConfig {
+    pub domain: String,
+    pub wordlist: String,
+    pub resolvers: String,
+    pub thread: u32,
+    pub max_queries: Option<u64>,
+    pub sources: Vec<String>,
+}
+
+const KEYS: &str = "wordlist, resolvers, threads, max-queries, sources";
+
+impl TargetConfig {
+    pub fn with_domain(&self, domain: &str) -> Self {
+        Self {
+            domain: domain.to_string(),
+            ..self.clone()
+        }
+    }
+
+    /// Parses `example.com wordlist=small.txt threads=200` on top of
+    /// `defaults`. `sources=none` turns passive sources off for the target.
+    pub fn parse(line: &str, defaults: &TargetConfig) -> Result<Self, String> {
+        let mut fields = line.split_whitespace();
+        let domain = fields.next().ok_or("missing domain")?;
+        let mut target = defaults.with_domain(domain);
+        for field in fields {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{}'", field))?;
+            if value.is_empty() {
+                return Err(format!("'{}' has no value", key));
+            }
+            match key {
+                "wordlist" => target.wordlist = value.to_string(),
+                "resolvers" => target.resolvers = value.to_string(),
+                "threads" | "thread" => {
+                    target.thread = value
+                        .parse()
+                        .map_err(|_| format!("threads must be a positive number, got '{}'", value))?
+                }
+                "max-queries" => {
+                    target.max_queries = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("max-queries must be a number, got '{}'", value))?,
+                    )
+                }
+                "sources" if value == "none" => target.sources.clear(),
+                "sources" => target.sources = value.split(',').map(str::to_string).collect(),
+                _ => return Err(format!("unknown option '{}', expected one of {}", key, KEYS)),
+            }
+        }
+        if target.thread == 0 {
+            return Err("threads must be a positive number, got '0'".to_string());
+        }
+        Ok(target)
+    }
+}
+
+/// Reads a targets file: one target per line, blank lines and `#` comments
+/// skipped. Every bad line is reported, not just the first.
+pub fn load(path: &str, defaults: &TargetConfig) -> Result<Vec<TargetConfig>, Vec<String>> {
+    let file = File::open(path).map_err(|e| vec![format!("could not open targets file '{}': {}", path, e)])?;
+    let mut targets = Vec::new();
+    let mut problems = Vec::new();
+    for (n, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| vec![format!("could not read targets file '{}': {}", path, e)])?;
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        match TargetConfig::parse(line, defaults) {
+            Ok(target) => targets.push(target),
+            Err(e) => problems.push(format!("targets file '{}' line {}: {}", path, n + 1, e)),
+        }
+    }
+    if problems.is_empty() { Ok(targets) } else { Err(problems) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> TargetConfig {
+        TargetConfig {
+            domain: String::new(),
+            wordlist: "words.txt".to_string(),
+            resolvers: "resolvers.txt".to_string(),
+            thread: 1000,
+            max_queries: None,
+            sources: vec!["crtsh".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_parse_overrides() {
+        let target = TargetConfig::parse("example.com wordlist=small.txt threads=200 sources=none", &defaults()).unwrap();
+        assert_eq!(target.domain, "example.com");
+        assert_eq!(target.wordlist, "small.txt");
+        assert_eq!(target.thread, 200);
+        assert!(target.sources.is_empty());
+        assert_eq!(target.resolvers, "resolvers.txt");
+
+        assert_eq!(TargetConfig::parse("example.org", &defaults()).unwrap(), defaults().with_domain("example.org"));
+        assert!(TargetConfig::parse("example.com depth=2", &defaults()).unwrap_err().contains("unknown option 'depth'"));
+        assert!(TargetConfig::parse("example.com threads=0", &defaults()).is_err());
+        assert!(TargetConfig::parse("example.com wordlist", &defaults()).unwrap_err().contains("key=value"));
+    }
+}
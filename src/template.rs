@@ -0,0 +1,172 @@
+//! `--output-template`: one line per found name (per address when the
+//! template uses `{{ip}}`) with `{{field}}` placeholders filled in, for
+//! line formats the built-in outputs do not cover.
+
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::output;
+
+/// The placeholders a template may use.
+pub const FIELDS: &[&str] = &["target", "name", "ip", "ips", "cname", "source", "ttl"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(&'static str),
+}
+
+impl FromStr for OutputTemplate {
+    type Err = String;
+
+    /// Parses `{{field}}` placeholders; `\t`, `\n` and `\\` in the text
+    /// stand for a tab, a newline and a backslash.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find("{{") {
+            parts.push(Part::Text(unescape(&rest[..start])));
+            let end = rest[start..].find("}}").ok_or_else(|| format!("Unclosed placeholder in template: {}", s))?;
+            let field = rest[start + 2..start + end].trim();
+            let field = FIELDS
+                .iter()
+                .find(|known| **known == field)
+                .ok_or_else(|| format!("Unknown template field: {} (one of {})", field, FIELDS.join(", ")))?;
+            parts.push(Part::Field(field));
+            rest = &rest[start + end + 2..];
+        }
+        parts.push(Part::Text(unescape(rest)));
+        parts.retain(|part| *part != Part::Text(String::new()));
+        Ok(Self { parts })
+    }
+}
+
+impl OutputTemplate {
+    /// The lines for every found name of `results`, each ending in a newline.
+    pub fn render(&self, results: &[Value]) -> String {
+        let per_address = self.parts.contains(&Part::Field("ip"));
+        let mut out = String::new();
+        for result in results {
+            let target = result["target"].as_str().unwrap_or_default();
+            for name in result["results"]["subdomain"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                let record = &result["results"]["records"][name];
+                let addresses = strings(&record["addresses"]);
+                let line = Line {
+                    target,
+                    name,
+                    ips: addresses.join(","),
+                    cname: strings(&record["cname_chain"]).join(","),
+                    source: output::sources(result, name).join(","),
+                    ttl: record["ttl"].as_u64().map(|ttl| ttl.to_string()).unwrap_or_default(),
+                };
+                if per_address && !addresses.is_empty() {
+                    for ip in &addresses {
+                        self.write(&mut out, &line, ip);
+                    }
+                } else {
+                    self.write(&mut out, &line, "");
+                }
+            }
+        }
+        out
+    }
+
+    fn write(&self, out: &mut String, line: &Line, ip: &str) {
+        for part in &self.parts {
+            out.push_str(match part {
+                Part::Text(text) => text,
+                Part::Field("target") => line.target,
+                Part::Field("name") => line.name,
+                Part::Field("ip") => ip,
+                Part::Field("ips") => &line.ips,
+                Part::Field("cname") => &line.cname,
+                Part::Field("source") => &line.source,
+                Part::Field("ttl") => &line.ttl,
+                Part::Field(_) => "",
+            });
+        }
+        out.push('\n');
+    }
+}
+
+struct Line<'a> {
+    target: &'a str,
+    name: &'a str,
+    ips: String,
+    cname: String,
+    source: String,
+    ttl: String,
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    value.as_array().into_iter().flatten().filter_map(|v| v.as_str().map(str::to_string)).collect()
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn results() -> Vec<Value> {
+        vec![json!({
+            "target": "example.com",
+            "results": {
+                "subdomain": ["www.example.com", "cdn.example.com"],
+                "records": {
+                    "www.example.com": { "cname_chain": [], "addresses": ["192.0.2.1", "192.0.2.2"], "ttl": 300 },
+                    "cdn.example.com": { "cname_chain": ["edge.example.net"], "addresses": [], "ttl": 60 },
+                },
+                "origins": { "cdn.example.com": [{ "name": "cdn.example.com", "source": "crtsh", "tags": [] }] },
+            }
+        })]
+    }
+
+    #[test]
+    fn test_render() {
+        let template: OutputTemplate = "{{name}},{{ ip }},{{source}}".parse().unwrap();
+        assert_eq!(
+            template.render(&results()),
+            "www.example.com,192.0.2.1,dns_bruteforce\nwww.example.com,192.0.2.2,dns_bruteforce\ncdn.example.com,,crtsh\n"
+        );
+        let template: OutputTemplate = "{{name}}\\t{{ips}}\\t{{cname}}\\t{{ttl}}".parse().unwrap();
+        assert_eq!(
+            template.render(&results()),
+            "www.example.com\t192.0.2.1,192.0.2.2\t\t300\ncdn.example.com\t\tedge.example.net\t60\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("{{nmae}}".parse::<OutputTemplate>().is_err());
+        assert!("{{name".parse::<OutputTemplate>().is_err());
+        assert!("plain".parse::<OutputTemplate>().is_ok());
+    }
+}
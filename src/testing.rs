@@ -0,0 +1,652 @@
+//! An in-process DNS server to scan against in tests: zones are set up in
+//! code, and names can be made to fail (SERVFAIL, no answer for the first
+//! few queries) or answer slowly. Used by this crate's end-to-end tests and
+//! exported with the `testing` feature for code built on the scanner.
+//!
+//! The server answers on `127.0.0.1` over UDP, and over TCP on the same
+//! port, and stops when dropped.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hickory_client::proto::op::{Message, MessageType, OpCode, ResponseCode};
+use hickory_client::proto::rr::rdata::{A, AAAA, CNAME};
+use hickory_client::proto::rr::{Name, RData, Record, RecordType};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::JoinHandle;
+
+/// TTL of every answer.
+pub const TTL: u32 = 300;
+/// CNAMEs followed within the zone at most.
+const MAX_CHAIN: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MockRecord {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(String),
+}
+
+/// What the server knows and how it misbehaves. Names are matched without
+/// regard to case or a trailing dot.
+#[derive(Debug, Clone, Default)]
+pub struct MockDns {
+    records: HashMap<String, Vec<MockRecord>>,
+    /// Zones every name below which resolves to the address.
+    wildcards: Vec<(String, Ipv4Addr)>,
+    servfail: HashSet<String>,
+    /// Queries for the name still to go unanswered.
+    dropped: HashMap<String, u32>,
+    /// Names answered over UDP with the TC bit set and no records.
+    truncated: HashSet<String>,
+    latency: Duration,
+}
+
+type Received = Arc<Mutex<Vec<(String, RecordType, Instant)>>>;
+
+impl MockDns {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_a(self, name: &str, address: Ipv4Addr) -> Self {
+        self.with_record(name, MockRecord::A(address))
+    }
+
+    pub fn with_aaaa(self, name: &str, address: Ipv6Addr) -> Self {
+        self.with_record(name, MockRecord::Aaaa(address))
+    }
+
+    /// Points `name` at `target`, which is followed when the server has it.
+    pub fn with_cname(self, name: &str, target: &str) -> Self {
+        self.with_record(name, MockRecord::Cname(key(target)))
+    }
+
+    /// Answers every name below `zone` it has no records for with `address`,
+    /// like a `*.zone` record.
+    pub fn with_wildcard(mut self, zone: &str, address: Ipv4Addr) -> Self {
+        self.wildcards.push((key(zone), address));
+        self
+    }
+
+    pub fn with_servfail(mut self, name: &str) -> Self {
+        self.servfail.insert(key(name));
+        self
+    }
+
+    /// Leaves the first `times` queries for `name` unanswered, so they time
+    /// out; `u32::MAX` for every query.
+    pub fn with_dropped(mut self, name: &str, times: u32) -> Self {
+        self.dropped.insert(key(name), times);
+        self
+    }
+
+    /// Answers `name` over UDP truncated, with no records, so only a
+    /// query over TCP gets them.
+    pub fn with_truncated(mut self, name: &str) -> Self {
+        self.truncated.insert(key(name));
+        self
+    }
+
+    /// Waits this long before each answer.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    fn with_record(mut self, name: &str, record: MockRecord) -> Self {
+        self.records.entry(key(name)).or_default().push(record);
+        self
+    }
+
+    /// Starts answering on a free port.
+    pub async fn start(self) -> io::Result<MockServer> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await?);
+        let addr = socket.local_addr()?;
+        let listener = TcpListener::bind(addr).await?;
+        let queries: Received = Arc::new(Mutex::new(Vec::new()));
+        let zones = Arc::new(Mutex::new(self));
+        let (log, udp_zones) = (queries.clone(), zones.clone());
+        let task = tokio::spawn(async move {
+            let mut buf = vec![0u8; 4096];
+            loop {
+                let Ok((len, from)) = socket.recv_from(&mut buf).await else {
+                    continue;
+                };
+                let Some((response, latency)) = respond(&udp_zones, &log, &buf[..len], true) else {
+                    continue;
+                };
+                if latency.is_zero() {
+                    let _ = socket.send_to(&response, from).await;
+                    continue;
+                }
+                let socket = socket.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(latency).await;
+                    let _ = socket.send_to(&response, from).await;
+                });
+            }
+        });
+        let log = queries.clone();
+        let tcp = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_tcp(stream, zones.clone(), log.clone()));
+            }
+        });
+        Ok(MockServer { addr, queries, task, tcp })
+    }
+
+    /// The response to `request`, or `None` to leave it unanswered.
+    fn answer(&mut self, request: &Message, udp: bool) -> Option<Message> {
+        let query = request.queries().first()?;
+        let name = key(&query.name().to_utf8());
+        if let Some(left) = self.dropped.get_mut(&name)
+            && *left > 0
+        {
+            if *left != u32::MAX {
+                *left -= 1;
+            }
+            return None;
+        }
+        let mut response = Message::new();
+        response
+            .set_id(request.id())
+            .set_message_type(MessageType::Response)
+            .set_op_code(OpCode::Query)
+            .set_authoritative(true)
+            .set_recursion_desired(request.recursion_desired())
+            .set_recursion_available(true)
+            .add_query(query.clone());
+        if self.servfail.contains(&name) {
+            response.set_response_code(ResponseCode::ServFail);
+            return Some(response);
+        }
+        let mut owner = name;
+        for _ in 0..MAX_CHAIN {
+            let records = match self.records.get(&owner) {
+                Some(records) => records.clone(),
+                None => match self.wildcards.iter().find(|(zone, _)| owner.ends_with(&format!(".{}", zone))) {
+                    Some((_, address)) => vec![MockRecord::A(*address)],
+                    None if response.answers().is_empty() => {
+                        response.set_response_code(ResponseCode::NXDomain);
+                        break;
+                    }
+                    None => break,
+                },
+            };
+            let owner_name = Name::from_str(&format!("{}.", owner)).ok()?;
+            let mut next = None;
+            for record in records {
+                let data = match (record, query.query_type()) {
+                    (MockRecord::A(address), RecordType::A) => RData::A(A(address)),
+                    (MockRecord::Aaaa(address), RecordType::AAAA) => RData::AAAA(AAAA(address)),
+                    (MockRecord::Cname(target), _) => {
+                        let data = RData::CNAME(CNAME(Name::from_str(&format!("{}.", target)).ok()?));
+                        next = Some(target);
+                        data
+                    }
+                    _ => continue,
+                };
+                response.add_answer(Record::from_rdata(owner_name.clone(), TTL, data));
+            }
+            match next {
+                Some(target) => owner = target,
+                None => break,
+            }
+        }
+        if udp && self.truncated.contains(&key(&query.name().to_utf8())) {
+            response.take_answers();
+            response.set_truncated(true);
+        }
+        Some(response)
+    }
+}
+
+/// The response to the query in `request` and how long to wait before
+/// sending it, logging the query.
+fn respond(zones: &Mutex<MockDns>, log: &Received, request: &[u8], udp: bool) -> Option<(Vec<u8>, Duration)> {
+    let request = Message::from_vec(request).ok()?;
+    let query = request.queries().first()?;
+    log.lock().unwrap().push((key(&query.name().to_utf8()), query.query_type(), Instant::now()));
+    let mut zones = zones.lock().unwrap();
+    Some((zones.answer(&request, udp)?.to_vec().ok()?, zones.latency))
+}
+
+/// Answers length-prefixed queries on one connection until it closes.
+async fn serve_tcp(mut stream: TcpStream, zones: Arc<Mutex<MockDns>>, log: Received) {
+    loop {
+        let Ok(len) = stream.read_u16().await else {
+            return;
+        };
+        let mut request = vec![0u8; len as usize];
+        if stream.read_exact(&mut request).await.is_err() {
+            return;
+        }
+        let Some((response, latency)) = respond(&zones, &log, &request, false) else {
+            continue;
+        };
+        tokio::time::sleep(latency).await;
+        let mut framed = (response.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(&response);
+        if stream.write_all(&framed).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// A running [`MockDns`].
+pub struct MockServer {
+    addr: SocketAddr,
+    queries: Received,
+    task: JoinHandle<()>,
+    tcp: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Where to send queries: a resolver for the scanner.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Every query received so far, in order, answered or not.
+    pub fn queries(&self) -> Vec<(String, RecordType)> {
+        self.queries.lock().unwrap().iter().map(|(name, record_type, _)| (name.clone(), *record_type)).collect()
+    }
+
+    /// Queries received for `name`.
+    pub fn queries_for(&self, name: &str) -> usize {
+        let name = key(name);
+        self.queries.lock().unwrap().iter().filter(|(asked, _, _)| *asked == name).count()
+    }
+
+    /// When the first query for each name arrived.
+    pub fn arrivals(&self) -> HashMap<String, Instant> {
+        let mut arrivals = HashMap::new();
+        for (name, _, at) in self.queries.lock().unwrap().iter() {
+            arrivals.entry(name.clone()).or_insert(*at);
+        }
+        arrivals
+    }
+
+    /// Writes a resolver file naming this server, for the file-based
+    /// constructors.
+    pub fn write_resolvers(&self, path: &std::path::Path) -> io::Result<()> {
+        std::fs::write(path, format!("{}\n", self.addr))
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        self.tcp.abort();
+    }
+}
+
+fn key(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    use crate::answers::{AnswerPolicy, IpVersion};
+    use crate::budget::QueryBudget;
+    use crate::checkpoint::ResumePoint;
+    use crate::consistency;
+    use crate::engine::EngineKind;
+    use crate::escalation;
+    use crate::exit::Exit;
+    use crate::geo;
+    use crate::printer::{Printer, ShowMode};
+    use crate::ptr;
+    use crate::scanner::{QueryFlags, ScanResult, SubdomainScanner};
+    use crate::schedule::Scheduler;
+    use crate::traffic::Traffic;
+
+    /// A scanner of example.com with `words` against `server`, answers
+    /// timing out after a second.
+    async fn scanner(server: &MockServer, test: &str, words: &[&str]) -> (SubdomainScanner, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("subscan-testing-{}-{}", std::process::id(), test));
+        std::fs::create_dir_all(&dir).unwrap();
+        server.write_resolvers(&dir.join("resolvers.txt")).unwrap();
+        std::fs::write(dir.join("words.txt"), words.join("\n")).unwrap();
+        let path = |file: &str| dir.join(file).display().to_string();
+        let scanner = SubdomainScanner::new(&path("resolvers.txt"), &path("words.txt"), "example.com", 1, 10)
+            .await
+            .unwrap()
+            .with_show(ShowMode::None);
+        (scanner, dir)
+    }
+
+    fn found(result: &ScanResult) -> Vec<&str> {
+        let mut names: Vec<&str> = result.results.subdomain.iter().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn test_scan() {
+        let server = MockDns::new()
+            .with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1))
+            .with_cname("api.example.com", "www.example.com")
+            .with_cname("shop.example.com", "shops.hosting.example.net")
+            .with_aaaa("v6.example.com", "2001:db8::1".parse().unwrap())
+            .with_servfail("broken.example.com")
+            .with_latency(Duration::from_millis(50))
+            .start()
+            .await
+            .unwrap();
+        let (scanner, dir) = scanner(&server, "scan", &["www", "api", "shop", "v6", "broken", "missing"]).await;
+        let result = scanner.scan().await;
+        assert_eq!(found(&result), ["api.example.com", "shop.example.com", "www.example.com"]);
+        let api = &result.results.records["api.example.com"];
+        assert_eq!(api.cname_chain, ["www.example.com"]);
+        assert_eq!(api.addresses, [std::net::IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))]);
+        assert_eq!(result.results.records["www.example.com"].ttl, Some(TTL));
+        assert!(result.results.records["shop.example.com"].addresses.is_empty());
+        assert_eq!(result.results.queries_sent, 6);
+        assert_eq!(result.results.errors.total, 0);
+        assert_eq!(server.queries_for("missing.example.com."), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_retries() {
+        let server = MockDns::new()
+            .with_a("db.example.com", Ipv4Addr::new(192, 0, 2, 2))
+            .with_dropped("db.example.com", 1)
+            .with_a("vpn.example.com", Ipv4Addr::new(192, 0, 2, 3))
+            .with_dropped("vpn.example.com", u32::MAX)
+            .start()
+            .await
+            .unwrap();
+        let (scanner, dir) = scanner(&server, "retries", &["db", "vpn"]).await;
+        let result = scanner.with_retries(1).scan().await;
+        assert_eq!(found(&result), ["db.example.com"]);
+        assert_eq!(server.queries_for("db.example.com"), 2);
+        assert_eq!(server.queries_for("vpn.example.com"), 2);
+        assert_eq!(result.results.errors.count("timeout"), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// A scan of `names` against `server`, which drops every query for
+    /// them, answers timing out after 200ms.
+    fn unanswered(server: &MockServer, names: &[&str]) -> SubdomainScanner {
+        let names = names.iter().map(|name| name.to_string()).collect();
+        SubdomainScanner::for_names(vec![server.addr()], names, Duration::from_millis(200), 10).unwrap().with_show(ShowMode::None)
+    }
+
+    #[tokio::test]
+    async fn test_retries_against_dead_resolvers() {
+        let names = ["www.example.com", "api.example.com", "dev.example.com"];
+        let server = names.iter().fold(MockDns::new(), |mock, name| mock.with_dropped(name, u32::MAX)).start().await.unwrap();
+        let result = unanswered(&server, &names).with_retries(1).scan().await;
+        // Each name fails twice but is one error; every query failed.
+        assert_eq!((result.results.queries_sent, result.results.queries_failed), (6, 6));
+        assert_eq!(result.results.errors.count("timeout"), 3);
+        assert_eq!(Exit::from_results(&[serde_json::to_value(&result).unwrap()], false), Exit::ResolversUnusable);
+        assert!(result.check().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_both_families_against_dead_resolvers() {
+        let names = ["www.example.com", "api.example.com", "dev.example.com"];
+        let server = names.iter().fold(MockDns::new(), |mock, name| mock.with_dropped(name, u32::MAX)).start().await.unwrap();
+        let result = unanswered(&server, &names)
+            .with_answer_policy(AnswerPolicy { ip_version: IpVersion::Both, allow_private: false })
+            .scan()
+            .await;
+        // An A and an AAAA query per name, both failed, one error per name.
+        assert_eq!((result.results.queries_sent, result.results.queries_failed), (6, 6));
+        assert_eq!(result.results.errors.count("timeout"), 3);
+        assert_eq!(Exit::from_results(&[serde_json::to_value(&result).unwrap()], false), Exit::ResolversUnusable);
+    }
+
+    #[tokio::test]
+    async fn test_wildcard() {
+        let server = MockDns::new()
+            .with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1))
+            .with_wildcard("example.com", Ipv4Addr::new(192, 0, 2, 99))
+            .start()
+            .await
+            .unwrap();
+        let (scanner, dir) = scanner(&server, "wildcard", &["www", "anything", "x7qz9k2vw8jt4m"]).await;
+        // Under a wildcard every candidate resolves; the random-looking ones
+        // can at least be set apart.
+        let result = scanner.with_drop_random_looking(true).scan().await;
+        assert_eq!(found(&result), ["anything.example.com", "www.example.com"]);
+        assert_eq!(result.results.records["anything.example.com"].addresses, [std::net::IpAddr::V4(Ipv4Addr::new(192, 0, 2, 99))]);
+        assert_eq!(result.results.random_looking, ["x7qz9k2vw8jt4m.example.com"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_answer_policy() {
+        let server = MockDns::new()
+            .with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1))
+            .with_aaaa("www.example.com", "2001:db8::1".parse().unwrap())
+            .with_aaaa("v6.example.com", "2001:db8::2".parse().unwrap())
+            .with_a("ads.example.com", Ipv4Addr::UNSPECIFIED)
+            .with_a("db.example.com", Ipv4Addr::new(10, 0, 0, 5))
+            .start()
+            .await
+            .unwrap();
+        let (both, dir) = scanner(&server, "answers", &["www", "v6", "ads", "db"]).await;
+        let result = both.with_answer_policy(AnswerPolicy { ip_version: IpVersion::Both, allow_private: false }).scan().await;
+        assert_eq!(found(&result), ["v6.example.com", "www.example.com"]);
+        assert_eq!(result.results.records["www.example.com"].addresses.len(), 2);
+        assert_eq!(result.results.queries_sent, 8);
+        assert_eq!(server.queries().iter().filter(|(_, record_type)| *record_type == RecordType::AAAA).count(), 4);
+        let filtered = &result.results.filtered_answers;
+        assert_eq!((filtered.filtered["unspecified"], filtered.filtered["private"]), (1, 1));
+        // Left out of the findings, but reported as a disclosure.
+        let disclosure = &result.results.internal_ip_disclosure;
+        assert_eq!((disclosure.len(), disclosure[0].name.as_str(), disclosure[0].severity), (1, "db.example.com", "medium"));
+
+        let (private, _) = scanner(&server, "answers", &["www", "v6", "ads", "db"]).await;
+        let result = private.with_answer_policy(AnswerPolicy { ip_version: IpVersion::V4, allow_private: true }).scan().await;
+        assert_eq!(found(&result), ["db.example.com", "www.example.com"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_record_types_over_tcp() {
+        let server = MockDns::new()
+            .with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1))
+            .with_aaaa("v6.example.com", "2001:db8::2".parse().unwrap())
+            .start()
+            .await
+            .unwrap();
+        let names = vec!["www.example.com".to_string(), "v6.example.com".to_string()];
+        let result = SubdomainScanner::for_names(vec![server.addr()], names, Duration::from_secs(1), 10)
+            .unwrap()
+            .with_show(ShowMode::None)
+            .with_answer_policy(AnswerPolicy { ip_version: IpVersion::Both, allow_private: false })
+            .with_record_types(vec![RecordType::A, RecordType::AAAA])
+            .with_query_flags(QueryFlags { tcp: true, ..QueryFlags::default() })
+            .scan()
+            .await;
+        assert_eq!(found(&result), ["v6.example.com", "www.example.com"]);
+        assert_eq!(result.results.queries_sent, 4);
+        assert_eq!(server.queries().iter().filter(|(_, record_type)| *record_type == RecordType::AAAA).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_skip_after_nxdomain() {
+        let server = MockDns::new().with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1)).start().await.unwrap();
+        let names = vec!["www.example.com".to_string(), "missing.example.com".to_string()];
+        let scanner = |skip| {
+            SubdomainScanner::for_names(vec![server.addr()], names.clone(), Duration::from_secs(1), 1)
+                .unwrap()
+                .with_show(ShowMode::None)
+                .with_answer_policy(AnswerPolicy { ip_version: IpVersion::Both, allow_private: false })
+                .with_skip_after_nxdomain(skip)
+                .with_max_queries(Some(10))
+        };
+        let result = scanner(true).scan().await;
+        assert_eq!(found(&result), ["www.example.com"]);
+        // The AAAA query of a name whose A query was NXDOMAIN is neither
+        // sent nor counted against the budget.
+        assert_eq!(result.results.queries_sent, 3);
+        assert_eq!((server.queries_for("www.example.com"), server.queries_for("missing.example.com")), (2, 1));
+
+        let result = scanner(false).scan().await;
+        assert_eq!(result.results.queries_sent, 4);
+        assert_eq!(server.queries_for("missing.example.com"), 3);
+    }
+
+    #[tokio::test]
+    async fn test_budget_short_of_a_candidate() {
+        let server = MockDns::new().with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1)).start().await.unwrap();
+        let names = vec!["www.example.com".to_string(), "api.example.com".to_string(), "dev.example.com".to_string()];
+        // Each name takes an A and an AAAA query, so the third query is
+        // never sent and the budget still counts as spent.
+        let result = SubdomainScanner::for_names(vec![server.addr()], names, Duration::from_secs(1), 1)
+            .unwrap()
+            .with_show(ShowMode::None)
+            .with_answer_policy(AnswerPolicy { ip_version: IpVersion::Both, allow_private: false })
+            .with_max_queries(Some(3))
+            .scan()
+            .await;
+        assert_eq!(result.results.queries_sent, 2);
+        assert_eq!(server.queries().len(), 2);
+        assert!(result.results.budget_exhausted);
+    }
+
+    #[tokio::test]
+    async fn test_shared_pool_usage() {
+        let server = MockDns::new().with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1)).start().await.unwrap();
+        let (printer, task) = Printer::spawn(ShowMode::None);
+        let scheduler = Scheduler::new(10, printer);
+        let (first, dir) = scanner(&server, "shared-pool-first", &["www", "api", "dev"]).await;
+        let (second, other_dir) = scanner(&server, "shared-pool-second", &["www", "mail"]).await;
+        let first = first.with_scheduler(scheduler.clone()).scan().await;
+        let second = second.with_scheduler(scheduler.clone()).scan().await;
+        // One pool for both, but each reports only its own queries.
+        let queries = |result: &ScanResult| result.results.resolver_usage.iter().map(|usage| usage.queries).sum::<u64>();
+        assert_eq!((queries(&first), queries(&second)), (3, 2));
+        drop(scheduler);
+        task.finish().await;
+        std::fs::remove_dir_all(dir).unwrap();
+        std::fs::remove_dir_all(other_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resumed_budget() {
+        let server = MockDns::new()
+            .with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1))
+            .with_a("api.example.com", Ipv4Addr::new(192, 0, 2, 2))
+            .start()
+            .await
+            .unwrap();
+        let names = vec!["www.example.com".to_string(), "api.example.com".to_string(), "dev.example.com".to_string()];
+        let paused = ResumePoint { position: 1, queries_sent: 2, ..ResumePoint::default() };
+        let result = SubdomainScanner::for_names(vec![server.addr()], names, Duration::from_secs(1), 1)
+            .unwrap()
+            .with_show(ShowMode::None)
+            .with_max_queries(Some(3))
+            .with_resume(paused)
+            .scan()
+            .await;
+        assert_eq!(found(&result), ["api.example.com"]);
+        assert_eq!(server.queries_for("dev.example.com"), 0);
+        assert_eq!((result.results.queries_sent, result.results.resume_point.queries_sent), (1, 3));
+        assert!(result.results.budget_exhausted);
+    }
+
+    #[tokio::test]
+    async fn test_resume_after_budget() {
+        let server = MockDns::new().with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1)).start().await.unwrap();
+        let words = ["www", "api", "dev", "mail", "vpn", "shop"];
+        let (paused, dir) = scanner(&server, "resume-after-budget", &words).await;
+        let paused = paused.with_max_queries(Some(2)).with_resume(ResumePoint::default()).scan().await;
+        assert!(paused.results.budget_exhausted);
+        // Candidates queued when the budget ran out were not sent; the
+        // resumed scan asks them along with the rest of the list.
+        let (resumed, _) = scanner(&server, "resume-after-budget", &words).await;
+        let resumed = resumed.with_max_queries(Some(10)).with_resume(paused.results.resume_point.clone()).scan().await;
+        assert!(!resumed.results.budget_exhausted);
+        for word in words {
+            assert_eq!(server.queries_for(&format!("{}.example.com", word)), 1, "{}", word);
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_escalation() {
+        let server = MockDns::new().with_a("mail.example.com", Ipv4Addr::new(192, 0, 2, 25)).start().await.unwrap();
+        let names = ["mail.example.com", "mx.example.com"].map(String::from);
+        let report = escalation::escalate(&names, escalation::RULES, server.addr(), Duration::from_secs(1), &Traffic::default()).await;
+        // mx.example.com is NXDOMAIN for MX, so its TXT is not asked.
+        assert_eq!((report.queries, report.skipped), (3, 1));
+        assert_eq!(server.queries_for("mx.example.com"), 1);
+
+        let capped = Traffic::default().with_budget(QueryBudget::new(Some(1)));
+        let report = escalation::escalate(&names, escalation::RULES, server.addr(), Duration::from_secs(1), &capped).await;
+        assert_eq!((report.queries, report.skipped), (1, 3));
+    }
+
+    #[tokio::test]
+    async fn test_consistency_budget() {
+        let first = MockDns::new().with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1)).start().await.unwrap();
+        let second = MockDns::new().with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1)).start().await.unwrap();
+        let names = ["www.example.com", "api.example.com"].map(String::from);
+        let capped = Traffic::default().with_budget(QueryBudget::new(Some(3)));
+        let report = consistency::check_all(&names, vec![first.addr(), second.addr()], Duration::from_secs(1), IpVersion::V4, &capped).await;
+        assert_eq!((report.queries, report.skipped), (3, 1));
+        assert_eq!(first.queries().len() + second.queries().len(), 3);
+        assert_eq!(report.verdicts.get("unchecked"), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_per_tag_budget() {
+        let eu = MockDns::new().with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1)).start().await.unwrap();
+        let us = MockDns::new().with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1)).start().await.unwrap();
+        let tags = BTreeMap::from([("eu".to_string(), vec![eu.addr()]), ("us".to_string(), vec![us.addr()])]);
+        let names = ["www.example.com", "api.example.com"].map(String::from);
+        let capped = Traffic::default().with_budget(QueryBudget::new(Some(3)));
+        let report = geo::resolve_all(&names, tags, Duration::from_secs(1), IpVersion::V4, &capped).await;
+        assert_eq!((report.queries, report.skipped), (3, 1));
+        assert_eq!(eu.queries().len() + us.queries().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_reverse_lookup_budget() {
+        let server = MockDns::new().start().await.unwrap();
+        let addresses = (1..=3).map(|host| std::net::IpAddr::V4(Ipv4Addr::new(192, 0, 2, host))).collect();
+        let capped = Traffic::default().with_budget(QueryBudget::new(Some(1)));
+        let report = ptr::lookup_all(addresses, &[server.addr()], Duration::from_secs(1), &capped).await;
+        assert_eq!((report.queries, report.skipped), (1, 2));
+        assert_eq!(server.queries().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_truncation() {
+        let server = MockDns::new()
+            .with_a("txt.example.com", Ipv4Addr::new(192, 0, 2, 4))
+            .with_truncated("txt.example.com")
+            .with_a("www.example.com", Ipv4Addr::new(192, 0, 2, 1))
+            .start()
+            .await
+            .unwrap();
+        for engine in [EngineKind::Hickory, EngineKind::Raw] {
+            let (scanner, dir) = scanner(&server, "truncation", &["txt", "www"]).await;
+            let result = scanner.with_engine(engine).scan().await;
+            assert_eq!(found(&result), ["txt.example.com", "www.example.com"]);
+            assert_eq!(result.results.records["txt.example.com"].addresses, [std::net::IpAddr::V4(Ipv4Addr::new(192, 0, 2, 4))]);
+            let truncation = &result.results.truncation;
+            assert_eq!((truncation.truncated, truncation.recovered, truncation.lost), (1, 1, 0));
+            assert_eq!(truncation.by_type["A"].recovered, 1);
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+        assert_eq!(server.queries_for("txt.example.com"), 4);
+    }
+}
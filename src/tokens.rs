@@ -0,0 +1,127 @@
+//! Target-specific tokens (`--tokens acme,payments`), combined with the
+//! generic wordlist entries as `token-word` and `word-token`. Names like
+//! `acme-vpn` or `staging-payments` are common and no generic list has
+//! them. Tokens can also come from the apex website's title and meta tags.
+
+use std::collections::HashMap;
+
+/// Tokens taken from the apex website at most.
+pub const MAX_SITE_TOKENS: usize = 8;
+/// Words too common on a home page to say anything about the target.
+const STOPWORDS: &[&str] = &[
+    "about", "all", "and", "are", "best", "com", "company", "contact", "for", "from", "fast", "free", "get", "home", "inc", "into",
+    "llc", "ltd", "more", "new", "now", "official", "one", "our", "page", "site", "that", "the", "their", "this", "with", "welcome",
+    "website", "www", "you", "your",
+];
+
+/// Checks a `--tokens` value: one DNS label, lowercased.
+pub fn parse_token(token: &str) -> Result<String, String> {
+    let token = token.trim().to_lowercase();
+    let valid = !token.is_empty()
+        && token.len() <= 63
+        && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && !token.starts_with('-')
+        && !token.ends_with('-');
+    if !valid {
+        return Err(format!("'{}' is not a token, expected one DNS label like acme or acme-pay", token));
+    }
+    Ok(token)
+}
+
+/// The candidates words `word` makes with each token. Words of more than
+/// one label, and the token itself, are left alone; a combination longer
+/// than a label is dropped.
+pub fn combine<'a>(tokens: &'a [String], word: &'a str) -> impl Iterator<Item = String> + 'a {
+    let single = !word.is_empty() && !word.contains('.');
+    tokens
+        .iter()
+        .filter(move |token| single && token.as_str() != word)
+        .flat_map(move |token| [format!("{}-{}", token, word), format!("{}-{}", word, token)])
+        .filter(|combined| combined.len() <= 63)
+}
+
+/// Tokens from a home page: the domain's own first label, then the words
+/// of the title and the description, keyword and site name meta tags,
+/// the most frequent first.
+pub fn from_page(html: &str, domain: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = domain.split('.').next().and_then(|label| parse_token(label).ok()).into_iter().collect();
+    let mut text = String::new();
+    let lower = html.to_lowercase();
+    if let Some(start) = lower.find("<title")
+        && let Some(open) = lower[start..].find('>')
+        && let Some(end) = lower[start + open..].find("</title")
+    {
+        text.push_str(&lower[start + open + 1..start + open + end]);
+    }
+    for tag in lower.split("<meta").skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+        let wanted = ["description", "keywords", "og:site_name", "og:title", "application-name"]
+            .iter()
+            .any(|name| tag.contains(&format!("name=\"{}\"", name)) || tag.contains(&format!("property=\"{}\"", name)));
+        if wanted && let Some(content) = attribute(tag, "content") {
+            text.push(' ');
+            text.push_str(content);
+        }
+    }
+
+    let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+    for (position, word) in text.split(|c: char| !c.is_ascii_alphanumeric()).enumerate() {
+        if (3..=20).contains(&word.len()) && !word.chars().all(|c| c.is_ascii_digit()) && !STOPWORDS.contains(&word) {
+            counts.entry(word).or_insert((0, position)).0 += 1;
+        }
+    }
+    let mut ranked: Vec<(&str, (usize, usize))> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.0.cmp(&a.1.0).then(a.1.1.cmp(&b.1.1)));
+    for (word, _) in ranked {
+        if tokens.len() >= MAX_SITE_TOKENS {
+            break;
+        }
+        if !tokens.iter().any(|token| token == word) {
+            tokens.push(word.to_string());
+        }
+    }
+    tokens
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!("{}=\"", name))? + name.len() + 2;
+    let end = tag[start..].find('"')?;
+    Some(&tag[start..start + end])
+}
+
+/// Fetches the apex website, over HTTPS and then plain HTTP, and takes
+/// tokens from it.
+#[cfg(all(feature = "sources", not(target_family = "wasm")))]
+pub async fn from_site(client: &crate::sources::ApiClient, domain: &str) -> crate::sources::SourceResult<Vec<String>> {
+    let mut last_error = None;
+    for scheme in ["https", "http"] {
+        let request = crate::sources::PageRequest::get(format!("{}://{}/", scheme, domain));
+        match client.get("site", std::time::Duration::ZERO, &request).await {
+            Ok(body) => return Ok(from_page(&body, domain)),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| "no response".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens() {
+        assert_eq!(parse_token(" Acme "), Ok("acme".to_string()));
+        assert!(parse_token("acme.com").is_err());
+        assert!(parse_token("-acme").is_err());
+
+        let tokens = vec!["acme".to_string(), "pay".to_string()];
+        assert_eq!(combine(&tokens, "vpn").collect::<Vec<_>>(), ["acme-vpn", "vpn-acme", "pay-vpn", "vpn-pay"]);
+        assert_eq!(combine(&tokens, "acme").collect::<Vec<_>>(), ["pay-acme", "acme-pay"]);
+        assert_eq!(combine(&tokens, "v1.api").count(), 0);
+
+        let html = r#"<html><head><title>Acme Payments | Blue Payments for the web</title>
+            <meta name="description" content="Acme Blue: payments for your company.">
+            <meta name="viewport" content="width=device-width"></head><body>ignored text</body></html>"#;
+        assert_eq!(from_page(html, "acme-corp.com"), ["acme-corp", "payments", "acme", "blue", "web"]);
+    }
+}
@@ -0,0 +1,33 @@
+//! What every query of a scan shares, whichever phase sends it: the dnstap
+//! log, the `--max-queries` budget and the tally of truncated responses.
+//! The scanner owns one per target and hands it to the lookups made after
+//! the scan too, so their queries are logged, capped and counted the same
+//! way as the scan's own.
+
+use std::sync::Arc;
+
+use crate::budget::QueryBudget;
+use crate::querylog::QueryLog;
+use crate::truncation::TruncationTally;
+
+/// Cheap to clone; clones share the budget and the tally. The default is
+/// unlimited and unlogged, for lookups outside any scan.
+#[derive(Clone, Default)]
+pub struct Traffic {
+    pub log: QueryLog,
+    pub budget: Arc<QueryBudget>,
+    pub truncation: Arc<TruncationTally>,
+}
+
+impl Traffic {
+    pub fn with_log(mut self, log: QueryLog) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Caps the queries sent, by every phase together.
+    pub fn with_budget(mut self, budget: QueryBudget) -> Self {
+        self.budget = Arc::new(budget);
+        self
+    }
+}
@@ -0,0 +1,87 @@
+//! UDP responses that came back truncated (TC bit set) and whether asking
+//! again over TCP recovered them. Large TXT sets, names with many
+//! addresses and signed answers overflow the 1232-byte EDNS payload; a
+//! truncated answer kept as it is loses records without any error.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use hickory_client::proto::rr::RecordType;
+use serde::Serialize;
+
+/// Names listed in [`TruncationReport::lost_names`] at most.
+pub const MAX_LOST_NAMES: usize = 100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct TypeCounts {
+    pub truncated: u64,
+    pub recovered: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TruncationReport {
+    /// Responses with the TC bit set.
+    pub truncated: u64,
+    /// Of those, answered in full over TCP.
+    pub recovered: u64,
+    /// Of those, whose TCP retry failed, so the partial answer was kept.
+    pub lost: u64,
+    /// The same counts per record type asked.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub by_type: BTreeMap<String, TypeCounts>,
+    /// Names whose answers may be missing records, the first
+    /// [`MAX_LOST_NAMES`] of them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub lost_names: Vec<String>,
+}
+
+impl TruncationReport {
+    pub fn record(&mut self, name: &str, record_type: RecordType, recovered: bool) {
+        let counts = self.by_type.entry(record_type.to_string()).or_default();
+        counts.truncated += 1;
+        self.truncated += 1;
+        if recovered {
+            counts.recovered += 1;
+            self.recovered += 1;
+            return;
+        }
+        self.lost += 1;
+        let name = name.trim_end_matches('.');
+        if self.lost_names.len() < MAX_LOST_NAMES && !self.lost_names.iter().any(|lost| lost == name) {
+            self.lost_names.push(name.to_string());
+        }
+    }
+}
+
+/// A [`TruncationReport`] filled in by the query tasks of one scan.
+#[derive(Debug, Default)]
+pub struct TruncationTally(Mutex<TruncationReport>);
+
+impl TruncationTally {
+    pub fn record(&self, name: &str, record_type: RecordType, recovered: bool) {
+        self.0.lock().unwrap().record(name, record_type, recovered);
+    }
+
+    pub fn report(&self) -> TruncationReport {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncation_report() {
+        let tally = TruncationTally::default();
+        tally.record("txt.example.com.", RecordType::TXT, true);
+        tally.record("big.example.com.", RecordType::TXT, false);
+        tally.record("big.example.com.", RecordType::TXT, false);
+        tally.record("many.example.com.", RecordType::A, true);
+        let report = tally.report();
+        assert_eq!((report.truncated, report.recovered, report.lost), (4, 2, 2));
+        assert_eq!(report.by_type["TXT"], TypeCounts { truncated: 3, recovered: 1 });
+        assert_eq!(report.by_type["A"], TypeCounts { truncated: 1, recovered: 1 });
+        assert_eq!(report.lost_names, ["big.example.com"]);
+    }
+}
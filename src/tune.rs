@@ -0,0 +1,215 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use tokio::sync::Semaphore;
+use tracing::info;
+
+const INITIAL_CONCURRENCY: usize = 50;
+const ADJUST_INTERVAL: Duration = Duration::from_secs(1);
+// Windows smaller than this are too noisy to act on.
+const MIN_SAMPLES: u64 = 50;
+const LOW_TIMEOUT_RATE: f64 = 0.02;
+const HIGH_TIMEOUT_RATE: f64 = 0.10;
+
+/// A self-hosted resolver's own view of its load since the last report.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BackendLoad {
+    /// Queries it dropped because its request list was full.
+    pub dropped: u64,
+    /// Its queue of queries waiting on upstream answers is at the limit.
+    pub saturated: bool,
+    /// Share of its queries answered from cache.
+    pub cache_hit_rate: f64,
+}
+
+/// Grows the number of in-flight queries while the timeout rate stays low and
+/// backs off when it climbs, so the scan settles near the highest concurrency
+/// the network and resolvers can sustain. A resolver that reports its own
+/// load backs concurrency off when it drops queries and holds it while its
+/// queue is full.
+pub struct AutoTuner {
+    semaphore: Arc<Semaphore>,
+    max: usize,
+    state: Mutex<TunerState>,
+    completed: AtomicU64,
+    timed_out: AtomicU64,
+    backend: Mutex<Option<BackendLoad>>,
+}
+
+struct TunerState {
+    current: usize,
+    // Permits still owed to a decrease because they were held at the time.
+    debt: usize,
+    // Once a decrease has happened, growth is additive instead of multiplicative.
+    backed_off: bool,
+}
+
+impl AutoTuner {
+    /// Creates a tuner capped at `max` concurrent queries along with the
+    /// semaphore it steers.
+    pub fn new(max: usize) -> Arc<Self> {
+        let max = max.max(1);
+        let initial = INITIAL_CONCURRENCY.min(max);
+        Arc::new(Self {
+            semaphore: Arc::new(Semaphore::new(initial)),
+            max,
+            state: Mutex::new(TunerState {
+                current: initial,
+                debt: 0,
+                backed_off: false,
+            }),
+            completed: AtomicU64::new(0),
+            timed_out: AtomicU64::new(0),
+            backend: Mutex::new(None),
+        })
+    }
+
+    pub fn semaphore(&self) -> Arc<Semaphore> {
+        self.semaphore.clone()
+    }
+
+    pub fn concurrency(&self) -> usize {
+        self.state.lock().unwrap().current
+    }
+
+    pub fn record(&self, timed_out: bool) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+        if timed_out {
+            self.timed_out.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Takes the resolver's load into account at the next adjustment.
+    pub fn report_backend(&self, load: BackendLoad) {
+        *self.backend.lock().unwrap() = Some(load);
+    }
+
+    /// Adjusts concurrency once per interval until the task is aborted.
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(ADJUST_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            self.adjust();
+        }
+    }
+
+    fn adjust(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.debt > 0 {
+            state.debt -= self.semaphore.forget_permits(state.debt);
+        }
+
+        let backend = self.backend.lock().unwrap().take();
+        let load = backend.unwrap_or_default();
+        let completed = self.completed.load(Ordering::Relaxed);
+        // Drops are acted on at once: the timeouts they cause arrive later.
+        if completed < MIN_SAMPLES && load.dropped == 0 {
+            return;
+        }
+        let timed_out = self.timed_out.swap(0, Ordering::Relaxed);
+        self.completed.store(0, Ordering::Relaxed);
+        let rate = if completed == 0 { 0.0 } else { timed_out as f64 / completed as f64 };
+
+        let previous = state.current;
+        if rate > HIGH_TIMEOUT_RATE || load.dropped > 0 {
+            let target = (state.current / 2).max(1);
+            let excess = state.current - target;
+            let forgotten = self.semaphore.forget_permits(excess);
+            state.debt += excess - forgotten;
+            state.current = target;
+            state.backed_off = true;
+        } else if rate < LOW_TIMEOUT_RATE && !load.saturated && state.current < self.max {
+            let step = if state.backed_off {
+                (state.current / 10).max(1)
+            } else {
+                state.current
+            };
+            let target = (state.current + step).min(self.max);
+            // Settle outstanding debt before handing out new permits.
+            let repaid = (target - state.current).min(state.debt);
+            state.debt -= repaid;
+            self.semaphore.add_permits(target - state.current - repaid);
+            state.current = target;
+        }
+
+        if state.current != previous {
+            let resolver = backend.map_or(String::new(), |load| {
+                format!(", resolver dropped {} with {:.0}% cache hits", load.dropped, load.cache_hit_rate * 100.0)
+            });
+            info!(
+                "auto-tune: concurrency {} -> {} ({:.1}% timeouts over {} queries{})",
+                previous,
+                state.current,
+                rate * 100.0,
+                completed,
+                resolver
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(tuner: &AutoTuner, total: u64, timeouts: u64) {
+        for i in 0..total {
+            tuner.record(i < timeouts);
+        }
+        tuner.adjust();
+    }
+
+    #[test]
+    fn test_grows_while_timeouts_are_low() {
+        let tuner = AutoTuner::new(1000);
+        assert_eq!(tuner.concurrency(), INITIAL_CONCURRENCY);
+        feed(&tuner, 100, 0);
+        assert_eq!(tuner.concurrency(), 100);
+        assert_eq!(tuner.semaphore().available_permits(), 100);
+        feed(&tuner, 100, 0);
+        feed(&tuner, 100, 0);
+        feed(&tuner, 100, 0);
+        assert_eq!(tuner.concurrency(), 800);
+        feed(&tuner, 100, 0);
+        assert_eq!(tuner.concurrency(), 1000);
+    }
+
+    #[test]
+    fn test_backs_off_on_timeouts() {
+        let tuner = AutoTuner::new(1000);
+        feed(&tuner, 100, 0);
+        feed(&tuner, 100, 30);
+        assert_eq!(tuner.concurrency(), 50);
+        assert_eq!(tuner.semaphore().available_permits(), 50);
+        feed(&tuner, 100, 0);
+        assert_eq!(tuner.concurrency(), 55);
+    }
+
+    #[test]
+    fn test_backend_load() {
+        let tuner = AutoTuner::new(1000);
+        tuner.report_backend(BackendLoad {
+            saturated: true,
+            ..BackendLoad::default()
+        });
+        feed(&tuner, 100, 0);
+        assert_eq!(tuner.concurrency(), INITIAL_CONCURRENCY);
+        // Drops back off even before a full window of samples.
+        tuner.report_backend(BackendLoad {
+            dropped: 3,
+            ..BackendLoad::default()
+        });
+        feed(&tuner, 10, 0);
+        assert_eq!(tuner.concurrency(), 25);
+    }
+
+    #[test]
+    fn test_ignores_small_windows() {
+        let tuner = AutoTuner::new(1000);
+        feed(&tuner, 10, 10);
+        assert_eq!(tuner.concurrency(), INITIAL_CONCURRENCY);
+    }
+}
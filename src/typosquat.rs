@@ -0,0 +1,305 @@
+//! Lookalike domains for `subscan typosquat`: typo and confusable-character
+//! variants of the target's apex, and of its key names run into it
+//! (`loginexample.com`), checked for registration. A brand that finds
+//! `examp1e.com` registered by someone else wants to know.
+//!
+//! A variant counts as registered when a resolver has the name (NOERROR),
+//! whether or not it has addresses; NXDOMAIN leaves it out, and anything
+//! else is counted as unchecked rather than guessed.
+
+use std::collections::BTreeSet;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+
+use hickory_client::proto::op::ResponseCode;
+use hickory_client::proto::rr::{Name, RData, RecordType};
+use serde::Serialize;
+use tokio::task::JoinSet;
+use tracing::debug;
+
+use crate::net::{SocketTuning, TunedRuntimeProvider};
+use crate::printer::Printer;
+use crate::scanner::{self, QueryFlags, QueryOutcome, SubdomainScanner};
+use crate::traffic::Traffic;
+use crate::truncation::TruncationReport;
+
+/// Suffixes a squatter might register the brand under instead.
+const TLDS: &[&str] = &["com", "net", "org", "co", "io", "info", "biz", "app", "dev", "xyz", "online", "site", "us", "co.uk", "de", "cn", "ru"];
+/// Characters and sequences read as one another in most fonts; the
+/// non-ASCII ones make IDN homographs.
+const HOMOGLYPHS: &[(&str, &[&str])] = &[
+    ("a", &["4", "а", "à", "á"]),
+    ("b", &["d", "lb"]),
+    ("c", &["e", "с"]),
+    ("d", &["b", "cl"]),
+    ("e", &["3", "е", "é"]),
+    ("g", &["q", "9"]),
+    ("i", &["1", "l", "і", "í"]),
+    ("l", &["1", "i", "ӏ"]),
+    ("m", &["rn", "nn"]),
+    ("n", &["m", "r"]),
+    ("o", &["0", "о", "ο", "ó"]),
+    ("p", &["р"]),
+    ("q", &["g"]),
+    ("s", &["5", "ѕ"]),
+    ("u", &["v", "ս"]),
+    ("v", &["u"]),
+    ("w", &["vv"]),
+    ("x", &["х"]),
+    ("y", &["у"]),
+    ("z", &["2"]),
+    ("rn", &["m"]),
+    ("vv", &["w"]),
+    ("cl", &["d"]),
+];
+const KEYBOARD: &[(char, &str)] = &[
+    ('a', "qwsz"),
+    ('b', "vghn"),
+    ('c', "xdfv"),
+    ('d', "serfcx"),
+    ('e', "wsdr"),
+    ('f', "drtgvc"),
+    ('g', "ftyhbv"),
+    ('h', "gyujnb"),
+    ('i', "ujko"),
+    ('j', "huikmn"),
+    ('k', "jiolm"),
+    ('l', "kop"),
+    ('m', "njk"),
+    ('n', "bhjm"),
+    ('o', "iklp"),
+    ('p', "ol"),
+    ('q', "wa"),
+    ('r', "edft"),
+    ('s', "awedxz"),
+    ('t', "rfgy"),
+    ('u', "yhji"),
+    ('v', "cfgb"),
+    ('w', "qase"),
+    ('x', "zsdc"),
+    ('y', "tghu"),
+    ('z', "asx"),
+    ('1', "2q"),
+    ('2', "13wq"),
+    ('3', "24ew"),
+    ('4', "35re"),
+    ('5', "46tr"),
+    ('6', "57yt"),
+    ('7', "68uy"),
+    ('8', "79iu"),
+    ('9', "80oi"),
+    ('0', "9po"),
+];
+const VOWELS: &str = "aeiou";
+
+/// A lookalike of the target and how it was made.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Variant {
+    /// As queried: punycode for an IDN.
+    pub name: String,
+    /// The name as it displays, for IDN homographs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unicode: Option<String>,
+    pub technique: &'static str,
+}
+
+/// A variant that is registered.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Lookalike {
+    #[serde(flatten)]
+    pub variant: Variant,
+    pub nameservers: Vec<String>,
+    pub addresses: Vec<IpAddr>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TyposquatReport {
+    pub target: String,
+    pub variants: usize,
+    /// Variants no resolver gave a clear answer for.
+    pub unchecked: usize,
+    pub lookalikes: Vec<Lookalike>,
+    /// NS answers too large for UDP, and whether TCP recovered them.
+    pub truncation: TruncationReport,
+}
+
+/// Every variant of `apex` (a registrable domain) and of each of `names`
+/// (its key subdomains' first labels) run into it, without duplicates.
+pub fn variants(apex: &str, names: &[String]) -> Vec<Variant> {
+    let apex = apex.trim_end_matches('.').to_lowercase();
+    let Some((brand, suffix)) = apex.split_once('.') else {
+        return Vec::new();
+    };
+    let chars: Vec<char> = brand.chars().collect();
+    let mut labels: Vec<(String, &'static str)> = Vec::new();
+    for i in 0..chars.len() {
+        let (before, after) = (&chars[..i], &chars[i + 1..]);
+        let join = |middle: &str| format!("{}{}{}", before.iter().collect::<String>(), middle, after.iter().collect::<String>());
+        labels.push((join(""), "omission"));
+        labels.push((join(&format!("{0}{0}", chars[i])), "repetition"));
+        if i + 1 < chars.len() && chars[i] != chars[i + 1] {
+            let mut swapped = chars.clone();
+            swapped.swap(i, i + 1);
+            labels.push((swapped.iter().collect(), "transposition"));
+        }
+        if let Some((_, keys)) = KEYBOARD.iter().find(|(key, _)| *key == chars[i]) {
+            labels.extend(keys.chars().map(|key| (join(&key.to_string()), "replacement")));
+        }
+        if VOWELS.contains(chars[i]) {
+            labels.extend(VOWELS.chars().filter(|v| *v != chars[i]).map(|v| (join(&v.to_string()), "vowel-swap")));
+        }
+        if i > 0 && chars[i - 1] != '-' && chars[i] != '-' {
+            labels.push((join(&format!("-{}", chars[i])), "hyphenation"));
+        }
+    }
+    for (from, to) in HOMOGLYPHS {
+        for (start, _) in brand.match_indices(from) {
+            for glyph in *to {
+                labels.push((format!("{}{}{}", &brand[..start], glyph, &brand[start + from.len()..]), "homoglyph"));
+            }
+        }
+    }
+
+    let mut names_seen = BTreeSet::from([apex.clone()]);
+    let mut variants = Vec::new();
+    let mut push = |name: String, technique: &'static str| {
+        if let Some(variant) = variant(&name, technique)
+            && names_seen.insert(variant.name.clone())
+        {
+            variants.push(variant);
+        }
+    };
+    for (label, technique) in labels {
+        push(format!("{}.{}", label, suffix), technique);
+    }
+    for tld in TLDS.iter().filter(|tld| **tld != suffix) {
+        push(format!("{}.{}", brand, tld), "tld-swap");
+    }
+    for name in names {
+        push(format!("{}{}.{}", name, brand, suffix), "subdomain");
+        push(format!("{}-{}.{}", name, brand, suffix), "subdomain");
+    }
+    variants
+}
+
+/// `name` as a checkable variant: every label valid, IDNs converted.
+fn variant(name: &str, technique: &'static str) -> Option<Variant> {
+    let labels_valid = name
+        .split('.')
+        .all(|label| !label.is_empty() && !label.starts_with('-') && !label.ends_with('-') && label.chars().all(|c| c.is_alphanumeric() || c == '-'));
+    if !labels_valid {
+        return None;
+    }
+    let ascii = Name::from_utf8(name).ok()?.to_ascii().trim_end_matches('.').to_string();
+    if ascii.split('.').any(|label| label.len() > 63) {
+        return None;
+    }
+    Some(Variant {
+        unicode: (ascii != name).then(|| name.to_string()),
+        name: ascii,
+        technique,
+    })
+}
+
+/// Checks every variant, `concurrency` at a time, across `resolvers` in
+/// turn, printing the registered ones as they are found.
+pub async fn check_all(
+    resolvers: &[SocketAddr],
+    timeout: Duration,
+    concurrency: usize,
+    target: &str,
+    variants: Vec<Variant>,
+    printer: &Printer,
+) -> TyposquatReport {
+    let mut report = TyposquatReport {
+        target: target.to_string(),
+        variants: variants.len(),
+        ..Default::default()
+    };
+    let traffic = Traffic::default();
+    let mut set = JoinSet::new();
+    let mut pending = variants.into_iter().enumerate();
+    loop {
+        while set.len() < concurrency.max(1)
+            && let Some((index, variant)) = pending.next()
+        {
+            let resolver = resolvers[index % resolvers.len()];
+            let traffic = traffic.clone();
+            set.spawn(async move { check(resolver, timeout, variant, &traffic).await });
+        }
+        match set.join_next().await {
+            Some(Ok(Ok(Some(lookalike)))) => {
+                printer.outcome(&lookalike.variant.name, "found");
+                report.lookalikes.push(lookalike);
+            }
+            Some(Ok(Ok(None))) => {}
+            Some(Ok(Err(e))) => {
+                debug!("{}", e);
+                report.unchecked += 1;
+            }
+            Some(Err(_)) => report.unchecked += 1,
+            None => break,
+        }
+    }
+    report.lookalikes.sort_by(|a, b| a.variant.name.cmp(&b.variant.name));
+    report.truncation = traffic.truncation.report();
+    report
+}
+
+/// `None` when the variant does not exist.
+async fn check(resolver: SocketAddr, timeout: Duration, variant: Variant, traffic: &Traffic) -> Result<Option<Lookalike>, String> {
+    let name = Name::from_str(&format!("{}.", variant.name)).map_err(|e| format!("{}: {}", variant.name, e))?;
+    let message = scanner::build_query(name, RecordType::NS, &QueryFlags::default());
+    let response = scanner::exchange(resolver, timeout, provider(), message, traffic)
+        .await
+        .map_err(|(_, e)| format!("{} via {}: {}", variant.name, resolver, e))?;
+    match response.response_code() {
+        ResponseCode::NoError => {}
+        ResponseCode::NXDomain => return Ok(None),
+        code => return Err(format!("{} via {}: {}", variant.name, resolver, code)),
+    }
+    let mut nameservers: Vec<String> = response
+        .answers()
+        .iter()
+        .filter_map(|record| match record.data() {
+            RData::NS(ns) => Some(ns.0.to_utf8().trim_end_matches('.').to_lowercase()),
+            _ => None,
+        })
+        .collect();
+    nameservers.sort();
+    nameservers.dedup();
+    let addresses = match SubdomainScanner::try_resolve_once(resolver, timeout, provider(), variant.name.clone(), &QueryFlags::default(), traffic).await {
+        QueryOutcome::Found(_, resolution) => resolution.addresses,
+        _ => Vec::new(),
+    };
+    Ok(Some(Lookalike { variant, nameservers, addresses }))
+}
+
+fn provider() -> TunedRuntimeProvider {
+    TunedRuntimeProvider::new(SocketTuning::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variants() {
+        let all = variants("Example.com", &["login".to_string()]);
+        let names: Vec<&str> = all.iter().map(|v| v.name.as_str()).collect();
+        for expected in ["exmple.com", "exaample.com", "examlpe.com", "exampke.com", "exampla.com", "ex-ample.com", "examp1e.com", "example.net", "loginexample.com", "login-example.com"] {
+            assert!(names.contains(&expected), "{} missing", expected);
+        }
+        assert!(!names.contains(&"example.com"));
+        assert!(!names.contains(&"-example.com"));
+        assert_eq!(names.len(), all.iter().map(|v| &v.name).collect::<BTreeSet<_>>().len());
+
+        let homograph = all.iter().find(|v| v.unicode.as_deref() == Some("еxample.com")).unwrap();
+        assert_eq!(homograph.name, "xn--xample-2of.com");
+        assert_eq!(homograph.technique, "homoglyph");
+        assert!(all.iter().any(|v| v.name == "exarnple.com"));
+
+        assert!(variants("localhost", &[]).is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+//! Statistics from a self-hosted unbound resolver over `unbound-control`,
+//! polled during a scan. The resolver knows things the scan cannot see
+//! from outside: it reports queries it dropped because its request list
+//! was full long before they show up here as timeouts.
+
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::process::Command;
+use tracing::{debug, warn};
+
+use crate::tune::{AutoTuner, BackendLoad};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How unbound-control is run, e.g. `unbound-control -c /etc/unbound/unbound.conf
+/// -s 10.0.0.53@8953`, or behind `ssh resolver-box`. `stats_noreset` is appended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnboundControl {
+    pub command: Vec<String>,
+    /// Queries waiting on upstream answers above which the tuner stops
+    /// adding concurrency.
+    pub queue_limit: u64,
+}
+
+impl FromStr for UnboundControl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let command: Vec<String> = s.split_whitespace().map(str::to_string).collect();
+        if command.is_empty() {
+            return Err(format!("Unknown unbound-control command: {:?}", s));
+        }
+        Ok(Self { command, queue_limit: 800 })
+    }
+}
+
+/// The counters of one `stats_noreset`, summed over threads.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UnboundStats {
+    pub queries: u64,
+    pub cache_hits: u64,
+    /// Queries waiting on upstream answers now.
+    pub queue: u64,
+    /// Queries dropped or replaced because the request list was full.
+    pub dropped: u64,
+}
+
+/// What the resolver reported over the scan.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UnboundSummary {
+    pub samples: u64,
+    pub queries: u64,
+    pub cache_hit_rate: f64,
+    pub peak_queue: u64,
+    pub dropped: u64,
+}
+
+impl UnboundControl {
+    pub fn with_queue_limit(mut self, limit: u64) -> Self {
+        self.queue_limit = limit;
+        self
+    }
+
+    pub async fn stats(&self) -> Result<UnboundStats, String> {
+        let output = Command::new(&self.command[0])
+            .args(&self.command[1..])
+            .arg("stats_noreset")
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(POLL_INTERVAL, output)
+            .await
+            .map_err(|_| "timed out".to_string())?
+            .map_err(|e| format!("{}: {}", self.command[0], e))?;
+        if !output.status.success() {
+            return Err(format!("{} exited with {}: {}", self.command[0], output.status, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        parse_stats(&String::from_utf8_lossy(&output.stdout)).ok_or_else(|| "no total.num.queries in its output".to_string())
+    }
+}
+
+/// Reads the `total.*` counters out of `key=value` lines.
+pub fn parse_stats(text: &str) -> Option<UnboundStats> {
+    let mut stats = UnboundStats::default();
+    let mut seen = false;
+    for (key, value) in text.lines().filter_map(|line| line.trim().split_once('=')) {
+        // Averages and timings are floats; the counters used here are not.
+        let Ok(value) = value.trim().parse::<u64>() else {
+            continue;
+        };
+        match key {
+            "total.num.queries" => {
+                stats.queries = value;
+                seen = true;
+            }
+            "total.num.cachehits" => stats.cache_hits = value,
+            "total.requestlist.current.all" => stats.queue = value,
+            "total.requestlist.exceeded" | "total.requestlist.overwritten" => stats.dropped += value,
+            _ => {}
+        }
+    }
+    seen.then_some(stats)
+}
+
+/// Polls unbound-control and hands what changed to the tuner.
+pub struct UnboundMonitor {
+    control: UnboundControl,
+    state: Mutex<MonitorState>,
+}
+
+#[derive(Default)]
+struct MonitorState {
+    first: Option<UnboundStats>,
+    last: Option<UnboundStats>,
+    peak_queue: u64,
+    samples: u64,
+    failures: u64,
+}
+
+impl UnboundMonitor {
+    pub fn new(control: UnboundControl) -> Arc<Self> {
+        Arc::new(Self {
+            control,
+            state: Mutex::default(),
+        })
+    }
+
+    /// Samples every poll interval until the task is aborted.
+    pub async fn run(self: Arc<Self>, tuner: Option<Arc<AutoTuner>>) {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.sample(tuner.as_deref()).await;
+        }
+    }
+
+    pub async fn sample(&self, tuner: Option<&AutoTuner>) {
+        let stats = match self.control.stats().await {
+            Ok(stats) => stats,
+            Err(e) => {
+                let mut state = self.state.lock().unwrap();
+                state.failures += 1;
+                if state.failures == 1 {
+                    warn!("could not read unbound statistics: {}", e);
+                } else {
+                    debug!("could not read unbound statistics: {}", e);
+                }
+                return;
+            }
+        };
+        let load = {
+            let mut state = self.state.lock().unwrap();
+            state.samples += 1;
+            state.peak_queue = state.peak_queue.max(stats.queue);
+            state.first.get_or_insert(stats);
+            let load = state.last.map(|last| self.load(last, stats));
+            state.last = Some(stats);
+            load
+        };
+        if let (Some(tuner), Some(load)) = (tuner, load) {
+            tuner.report_backend(load);
+        }
+    }
+
+    fn load(&self, last: UnboundStats, now: UnboundStats) -> BackendLoad {
+        let queries = now.queries.saturating_sub(last.queries);
+        let hits = now.cache_hits.saturating_sub(last.cache_hits);
+        BackendLoad {
+            dropped: now.dropped.saturating_sub(last.dropped),
+            saturated: now.queue >= self.control.queue_limit,
+            cache_hit_rate: if queries == 0 { 0.0 } else { hits as f64 / queries as f64 },
+        }
+    }
+
+    /// `None` when no sample was ever read.
+    pub fn summary(&self) -> Option<UnboundSummary> {
+        let state = self.state.lock().unwrap();
+        let (first, last) = (state.first?, state.last?);
+        let queries = last.queries.saturating_sub(first.queries);
+        let hits = last.cache_hits.saturating_sub(first.cache_hits);
+        Some(UnboundSummary {
+            samples: state.samples,
+            queries,
+            cache_hit_rate: if queries == 0 { 0.0 } else { hits as f64 / queries as f64 },
+            peak_queue: state.peak_queue,
+            dropped: last.dropped.saturating_sub(first.dropped),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stats() {
+        let text = "thread0.num.queries=10\ntotal.num.queries=1200\ntotal.num.cachehits=300\n\
+                    total.num.cachemiss=900\ntotal.requestlist.avg=12.5\ntotal.requestlist.exceeded=4\n\
+                    total.requestlist.overwritten=1\ntotal.requestlist.current.all=37\n";
+        let stats = parse_stats(text).unwrap();
+        assert_eq!(
+            stats,
+            UnboundStats {
+                queries: 1200,
+                cache_hits: 300,
+                queue: 37,
+                dropped: 5,
+            }
+        );
+        assert!(parse_stats("error: connect: Connection refused").is_none());
+
+        let monitor = UnboundMonitor::new("unbound-control".parse::<UnboundControl>().unwrap().with_queue_limit(30));
+        let load = monitor.load(UnboundStats { queries: 200, cache_hits: 100, queue: 0, dropped: 5 }, stats);
+        assert_eq!(load.dropped, 0);
+        assert!(load.saturated);
+        assert_eq!(load.cache_hit_rate, 0.2);
+    }
+}
@@ -0,0 +1,252 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::names::{self, Underscores};
+use crate::scanner::parse_resolver;
+
+/// Checks the target and input files before anything is scanned and returns
+/// every problem found, each phrased as something the user can act on.
+pub fn check_inputs(domain: &str, wordlist: &str, resolvers: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    if let Err(e) = check_domain(domain) {
+        problems.push(e);
+    }
+    if let Err(e) = check_wordlist(wordlist) {
+        problems.push(e);
+    }
+    if let Err(e) = check_resolvers(resolvers) {
+        problems.push(e);
+    }
+    problems
+}
+
+pub fn check_domain(domain: &str) -> Result<(), String> {
+    let domain = domain.trim();
+    if domain.is_empty() {
+        return Err("no target domain given, pass one with --domain example.com".to_string());
+    }
+    if let Some((_, rest)) = domain.split_once("://") {
+        let host = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+        let host = host.rsplit('@').next().unwrap_or(host);
+        let host = host.split(':').next().unwrap_or(host);
+        return Err(format!("domain '{}' contains a scheme, did you mean {}?", domain, host));
+    }
+    if let Some((host, _)) = domain.split_once('/') {
+        return Err(format!("domain '{}' contains a path, did you mean {}?", domain, host));
+    }
+    if let Some(rest) = domain.strip_prefix("*.") {
+        return Err(format!("domain '{}' is a wildcard, did you mean {}?", domain, rest));
+    }
+    if let Some((host, port)) = domain.rsplit_once(':')
+        && port.chars().all(|c| c.is_ascii_digit())
+    {
+        return Err(format!("domain '{}' contains a port, did you mean {}?", domain, host));
+    }
+    let bare = domain.strip_suffix('.').unwrap_or(domain);
+    names::check(bare, Underscores::Allow).map_err(|e| format!("domain {}", e))?;
+    if !bare.contains('.') {
+        return Err(format!("domain '{}' has no dot, expected something like example.com", domain));
+    }
+    Ok(())
+}
+
+pub fn check_wordlist(path: &str) -> Result<(), String> {
+    let reader = open_input("wordlist", "--wordlist", path)?;
+    // Only look for the first usable line; wordlists can be huge.
+    for line in reader.lines() {
+        let line = line.map_err(|e| format!("could not read wordlist '{}': {}", path, e))?;
+        if !line.trim().is_empty() {
+            return Ok(());
+        }
+    }
+    Err(format!("wordlist '{}' is empty", path))
+}
+
+/// Reads the names of a `resolve` input: one per line, blank lines and
+/// `#` comments skipped.
+pub fn read_name_list(path: &str) -> Result<Vec<String>, String> {
+    let reader = open_input("name list", "--domains", path)?;
+    let mut names = Vec::new();
+    for line in reader.lines() {
+        let line = line.map_err(|e| format!("could not read name list '{}': {}", path, e))?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            names.push(line.to_string());
+        }
+    }
+    if names.is_empty() {
+        return Err(format!("name list '{}' has no names", path));
+    }
+    Ok(names)
+}
+
+/// Returns how many usable resolvers the file lists.
+pub fn check_resolvers(path: &str) -> Result<usize, String> {
+    let reader = open_input("resolver file", "--resolvers", path)?;
+    let mut valid = 0;
+    let mut invalid = Vec::new();
+    for (line_num, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| format!("could not read resolver file '{}': {}", path, e))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_resolver(line) {
+            Some(_) => valid += 1,
+            None => invalid.push((line_num + 1, line.to_string())),
+        }
+    }
+
+    if valid == 0 {
+        return Err(format!(
+            "resolver file '{}' has no valid resolvers, expected one IP, IP:port or quic:// resolver per line{}",
+            path,
+            invalid
+                .first()
+                .map(|(n, l)| format!(" (line {}: '{}')", n, l))
+                .unwrap_or_default()
+        ));
+    }
+    if let Some((n, l)) = invalid.first() {
+        warn!(
+            "skipping {} invalid resolver lines in '{}' (first at line {}: '{}')",
+            invalid.len(),
+            path,
+            n,
+            l
+        );
+    }
+    Ok(valid)
+}
+
+/// Checks that `path` can be created or replaced: its directory exists and
+/// it is not a directory itself.
+pub fn check_output(what: &str, flag: &str, path: &str) -> Result<(), String> {
+    let p = Path::new(path);
+    if p.is_dir() {
+        return Err(format!("{} '{}' is a directory, pass a file name to {}", what, path, flag));
+    }
+    match p.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        Some(parent) if !parent.is_dir() => Err(format!(
+            "{} '{}' is in a directory that does not exist, create it with mkdir -p {}",
+            what,
+            path,
+            parent.display()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Where `program` would be run from: itself when it names a path, else the
+/// first match on `PATH`.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    if program.contains(std::path::MAIN_SEPARATOR) {
+        return Path::new(program).is_file().then(|| PathBuf::from(program));
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+/// The candidate closest to a misspelled `word`, if any is close enough to
+/// be what was meant.
+pub fn closest<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(word, candidate), *candidate))
+        .filter(|(distance, candidate)| *distance <= candidate.len().max(word.len()) / 3 + 1)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1).min(row[j] + 1).min(diagonal + (ca != *cb) as usize);
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+fn open_input(what: &str, flag: &str, path: &str) -> Result<BufReader<File>, String> {
+    if path.is_empty() {
+        return Err(format!("no {} given, pass one with {} FILE", what, flag));
+    }
+    let p = Path::new(path);
+    if !p.exists() {
+        return Err(format!("{} '{}' does not exist", what, path));
+    }
+    if p.is_dir() {
+        return Err(format!("{} '{}' is a directory, expected a file", what, path));
+    }
+    File::open(p)
+        .map(BufReader::new)
+        .map_err(|e| format!("could not open {} '{}': {}", what, path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_domain() {
+        assert!(check_domain("example.com").is_ok());
+        assert!(check_domain("example.co.uk.").is_ok());
+        assert_eq!(
+            check_domain("https://www.example.com/path").unwrap_err(),
+            "domain 'https://www.example.com/path' contains a scheme, did you mean www.example.com?"
+        );
+        assert!(check_domain("*.example.com").unwrap_err().ends_with("did you mean example.com?"));
+        assert!(check_domain("example.com:443").unwrap_err().ends_with("did you mean example.com?"));
+        assert!(check_domain("").unwrap_err().contains("--domain"));
+        assert!(check_domain("localhost").unwrap_err().contains("no dot"));
+        assert!(check_domain("exa mple.com").unwrap_err().contains("invalid character"));
+        assert!(check_domain("a..com").unwrap_err().contains("empty label"));
+    }
+
+    #[test]
+    fn test_check_files() {
+        let dir = std::env::temp_dir().join(format!("subscan-validate-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let empty = dir.join("empty.txt");
+        std::fs::write(&empty, "\n  \n").unwrap();
+        let resolvers = dir.join("resolvers.txt");
+        std::fs::write(&resolvers, "# comment\nbogus\n1.1.1.1\n").unwrap();
+
+        assert!(check_wordlist("").unwrap_err().contains("--wordlist"));
+        assert_eq!(
+            check_wordlist(empty.to_str().unwrap()).unwrap_err(),
+            format!("wordlist '{}' is empty", empty.display())
+        );
+        assert!(check_wordlist(dir.join("missing").to_str().unwrap()).unwrap_err().ends_with("does not exist"));
+        assert!(check_resolvers(dir.to_str().unwrap()).unwrap_err().contains("is a directory"));
+        assert_eq!(read_name_list(resolvers.to_str().unwrap()), Ok(vec!["bogus".to_string(), "1.1.1.1".to_string()]));
+        assert!(read_name_list(empty.to_str().unwrap()).unwrap_err().contains("has no names"));
+        assert_eq!(check_resolvers(resolvers.to_str().unwrap()), Ok(1));
+        assert!(check_resolvers(empty.to_str().unwrap()).unwrap_err().contains("no valid resolvers"));
+
+        assert!(check_output("output", "--output", resolvers.to_str().unwrap()).is_ok());
+        assert!(check_output("output", "--output", dir.to_str().unwrap()).unwrap_err().contains("is a directory"));
+        assert!(check_output("output", "--output", dir.join("no/out.json").to_str().unwrap()).unwrap_err().contains("mkdir -p"));
+        assert!(check_output("output", "--output", "out.json").is_ok());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_closest() {
+        let sources = ["crtsh", "wayback", "commoncrawl", "github"];
+        assert_eq!(closest("crt.sh", &sources), Some("crtsh"));
+        assert_eq!(closest("waybak", &sources), Some("wayback"));
+        assert_eq!(closest("shodan", &sources), None);
+    }
+}
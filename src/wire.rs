@@ -0,0 +1,148 @@
+//! Hand-encoded queries for the raw engine. Everything that is the same for
+//! every candidate of a target (header flags, the target's own labels, the
+//! question type and the EDNS record) is encoded once; per candidate only
+//! the ID and the leading labels are written.
+
+use hickory_client::proto::rr::RecordType;
+
+use crate::scanner::QueryFlags;
+
+const HEADER_LEN: usize = 12;
+const MAX_LABEL: usize = 63;
+const MAX_NAME: usize = 255;
+// Same UDP payload size hickory advertises, so both engines look alike on
+// the wire.
+const EDNS_PAYLOAD: u16 = 1232;
+
+/// The fixed parts of every query for names under one domain, or for any
+/// name when the domain is empty (the root).
+#[derive(Debug, Clone)]
+pub struct QueryTemplate {
+    /// `.example.com`, for splitting the candidate's own labels off.
+    suffix: String,
+    /// Header after the ID: flags and section counts.
+    header: [u8; HEADER_LEN - 2],
+    /// The domain's labels, the root label, QTYPE and QCLASS.
+    question_tail: Vec<u8>,
+    /// The OPT record.
+    additional: Vec<u8>,
+}
+
+impl QueryTemplate {
+    pub fn new(domain: &str, record_type: RecordType, flags: &QueryFlags) -> Result<Self, String> {
+        let domain = domain.trim_end_matches('.');
+        let mut question_tail = Vec::with_capacity(domain.len() + 6);
+        if !domain.is_empty() {
+            encode_labels(domain, &mut question_tail)?;
+        }
+        question_tail.push(0);
+        question_tail.extend_from_slice(&u16::from(record_type).to_be_bytes());
+        question_tail.extend_from_slice(&1u16.to_be_bytes()); // IN
+
+        let flags_word: u16 = if flags.recursion_desired { 0x0100 } else { 0 };
+        let mut header = [0u8; HEADER_LEN - 2];
+        header[0..2].copy_from_slice(&flags_word.to_be_bytes());
+        header[2..4].copy_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+        header[8..10].copy_from_slice(&1u16.to_be_bytes()); // ARCOUNT
+
+        let mut options = Vec::new();
+        for option in &flags.edns_options {
+            options.extend_from_slice(&option.code.to_be_bytes());
+            options.extend_from_slice(&(option.data.len() as u16).to_be_bytes());
+            options.extend_from_slice(&option.data);
+        }
+        let mut additional = vec![0]; // root owner name
+        additional.extend_from_slice(&41u16.to_be_bytes()); // OPT
+        additional.extend_from_slice(&EDNS_PAYLOAD.to_be_bytes());
+        additional.extend_from_slice(&[0, 0, 0, 0]); // extended rcode, version, flags
+        additional.extend_from_slice(&(options.len() as u16).to_be_bytes());
+        additional.extend_from_slice(&options);
+
+        Ok(Self {
+            suffix: if domain.is_empty() { String::new() } else { format!(".{}", domain) },
+            header,
+            question_tail,
+            additional,
+        })
+    }
+
+    /// Writes the query for `name`, which must be under the template's
+    /// domain, into `buf`.
+    pub fn encode(&self, name: &str, id: u16, buf: &mut Vec<u8>) -> Result<(), String> {
+        let label = name
+            .trim_end_matches('.')
+            .strip_suffix(&self.suffix)
+            .ok_or_else(|| format!("{} is not under {}", name, &self.suffix[1..]))?;
+        buf.clear();
+        buf.extend_from_slice(&id.to_be_bytes());
+        buf.extend_from_slice(&self.header);
+        encode_labels(label, buf)?;
+        if buf.len() - HEADER_LEN + self.question_tail.len() - 4 > MAX_NAME {
+            return Err(format!("{}: name longer than {} bytes", name, MAX_NAME));
+        }
+        buf.extend_from_slice(&self.question_tail);
+        buf.extend_from_slice(&self.additional);
+        Ok(())
+    }
+}
+
+/// The ID of a DNS message, if it is long enough to have a header.
+pub fn message_id(message: &[u8]) -> Option<u16> {
+    (message.len() >= HEADER_LEN).then(|| u16::from_be_bytes([message[0], message[1]]))
+}
+
+fn encode_labels(name: &str, buf: &mut Vec<u8>) -> Result<(), String> {
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > MAX_LABEL {
+            return Err(format!("{}: labels must be 1 to {} bytes", name, MAX_LABEL));
+        }
+        if !label.is_ascii() {
+            return Err(format!("{}: non-ASCII label", name));
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::build_query;
+    use hickory_client::proto::rr::Name;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_matches_hickory_encoding() {
+        let flags = QueryFlags {
+            recursion_desired: false,
+            edns_options: vec!["10:0102030405060708".parse().unwrap()],
+            ..QueryFlags::default()
+        };
+        for flags in [QueryFlags::default(), flags] {
+            let template = QueryTemplate::new("example.com", RecordType::A, &flags).unwrap();
+            let mut buf = Vec::new();
+            template.encode("v1.api.example.com", 0x1234, &mut buf).unwrap();
+
+            let mut message = build_query(Name::from_str("v1.api.example.com.").unwrap(), RecordType::A, &flags);
+            message.set_id(0x1234);
+            assert_eq!(buf, message.to_vec().unwrap());
+            assert_eq!(message_id(&buf), Some(0x1234));
+
+            let mut from_root = Vec::new();
+            QueryTemplate::new("", RecordType::A, &flags).unwrap().encode("v1.api.example.com", 0x1234, &mut from_root).unwrap();
+            assert_eq!(from_root, buf);
+        }
+    }
+
+    #[test]
+    fn test_rejects_bad_names() {
+        let template = QueryTemplate::new("example.com", RecordType::A, &QueryFlags::default()).unwrap();
+        let mut buf = Vec::new();
+        assert!(template.encode("bad..x.example.com", 1, &mut buf).is_err());
+        assert!(template.encode("www.example.org", 1, &mut buf).is_err());
+        assert!(template.encode(&format!("{}.example.com", "a".repeat(64)), 1, &mut buf).is_err());
+        let long = vec!["a".repeat(60); 5].join(".");
+        assert!(template.encode(&format!("{}.example.com", long), 1, &mut buf).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io;
+use std::sync::Arc;
+
+use memmap2::Mmap;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+
+/// Scan candidates, read from a memory-mapped wordlist so startup costs one
+/// pass over the file and no allocation per line. Names added afterwards
+/// (from passive sources) are kept separately.
+///
+/// The file is expected to stay unchanged while the scan runs.
+#[derive(Clone, Default)]
+pub struct Wordlist {
+    map: Option<Arc<Mmap>>,
+    /// Usable lines in the file.
+    lines: usize,
+    extra: Vec<String>,
+    /// Custom candidate order, only built by [`Wordlist::shuffle`]: byte
+    /// offsets of file lines, then `map.len() + i` for `extra[i]`.
+    order: Option<Vec<u64>>,
+}
+
+impl Wordlist {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        if file.metadata()?.len() == 0 {
+            return Ok(Self::default());
+        }
+        // SAFETY: the map is read-only; a wordlist modified mid-scan may
+        // yield garbled candidates but nothing is written through it.
+        let map = unsafe { Mmap::map(&file)? };
+        let lines = file_lines(&map).count();
+        Ok(Self {
+            map: Some(Arc::new(map)),
+            lines,
+            extra: Vec::new(),
+            order: None,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines + self.extra.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds every label not already in the list, in one pass over the file,
+    /// and returns the ones it added.
+    pub fn extend_missing(&mut self, labels: Vec<String>) -> Vec<String> {
+        let mut missing: HashSet<String> = labels.iter().cloned().collect();
+        for (_, line) in file_lines(self.bytes()) {
+            if let Ok(line) = std::str::from_utf8(line) {
+                missing.remove(line);
+            }
+        }
+        for label in &self.extra {
+            missing.remove(label);
+        }
+        let file_len = self.bytes().len();
+        let mut added = Vec::new();
+        for label in labels {
+            if missing.remove(&label) {
+                if let Some(order) = &mut self.order {
+                    order.push((file_len + self.extra.len()) as u64);
+                }
+                self.extra.push(label.clone());
+                added.push(label);
+            }
+        }
+        added
+    }
+
+    pub fn shuffle(&mut self, rng: &mut ChaCha8Rng) {
+        let file_len = self.bytes().len() as u64;
+        let mut order: Vec<u64> = file_lines(self.bytes()).map(|(start, _)| start as u64).collect();
+        order.extend((0..self.extra.len() as u64).map(|i| file_len + i));
+        order.shuffle(rng);
+        self.order = Some(order);
+    }
+
+    /// Moves the entries in `first` to the front, in that order, and keeps
+    /// the rest in their current order. Entries match ignoring case. Returns
+    /// how many entries moved.
+    pub fn promote(&mut self, first: &[String]) -> usize {
+        let rank: HashMap<&str, usize> = first.iter().enumerate().map(|(i, word)| (word.as_str(), i)).collect();
+        let file_len = self.bytes().len() as u64;
+        let mut order = self.order.take().unwrap_or_else(|| {
+            file_lines(self.bytes())
+                .map(|(start, _)| start as u64)
+                .chain((0..self.extra.len() as u64).map(|i| file_len + i))
+                .collect()
+        });
+        let mut promoted = 0;
+        order.sort_by_cached_key(|&entry| {
+            let word = self.entry(entry).map(|word| word.trim().to_ascii_lowercase());
+            let position = word.and_then(|word| rank.get(word.as_str()).copied());
+            promoted += position.is_some() as usize;
+            position.unwrap_or(usize::MAX)
+        });
+        self.order = Some(order);
+        promoted
+    }
+
+    /// Candidates in scan order. File lines that are not valid UTF-8 are
+    /// skipped, like unreadable lines always were.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &str> + Send + '_> {
+        let bytes = self.bytes();
+        match &self.order {
+            Some(order) => Box::new(order.iter().filter_map(move |&entry| self.entry(entry))),
+            None => Box::new(
+                file_lines(bytes)
+                    .filter_map(|(_, line)| std::str::from_utf8(line).ok())
+                    .chain(self.extra.iter().map(String::as_str)),
+            ),
+        }
+    }
+
+    /// The entry at a position in `order`.
+    fn entry(&self, entry: u64) -> Option<&str> {
+        let (bytes, entry) = (self.bytes(), entry as usize);
+        match entry.checked_sub(bytes.len()) {
+            Some(i) => Some(self.extra[i].as_str()),
+            None => std::str::from_utf8(line_at(bytes, entry)).ok(),
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        self.map.as_deref().map_or(&[], |map| &map[..])
+    }
+}
+
+/// Usable lines with their offsets: line endings stripped, blank lines
+/// skipped.
+fn file_lines(bytes: &[u8]) -> impl Iterator<Item = (usize, &[u8])> {
+    let mut start = 0;
+    std::iter::from_fn(move || {
+        while start < bytes.len() {
+            let line_start = start;
+            let line = line_at(bytes, line_start);
+            start = line_start + line.len() + 1;
+            if bytes.get(line_start + line.len()) == Some(&b'\r') {
+                start += 1;
+            }
+            if !line.trim_ascii().is_empty() {
+                return Some((line_start, line));
+            }
+        }
+        None
+    })
+}
+
+fn line_at(bytes: &[u8], start: usize) -> &[u8] {
+    let rest = &bytes[start..];
+    let end = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+    rest[..end].strip_suffix(b"\r").unwrap_or(&rest[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use std::io::Write;
+
+    #[test]
+    fn test_mapped_lines() {
+        let path = std::env::temp_dir().join(format!("subscan-wordlist-{}.txt", std::process::id()));
+        File::create(&path).unwrap().write_all(b"www\r\n\n  \nmail\napi").unwrap();
+        let mut wordlist = Wordlist::open(path.to_str().unwrap()).unwrap();
+        assert_eq!(wordlist.iter().collect::<Vec<_>>(), ["www", "mail", "api"]);
+
+        assert_eq!(wordlist.extend_missing(vec!["mail".to_string(), "dev".to_string(), "dev".to_string()]), ["dev"]);
+        assert_eq!(wordlist.len(), 4);
+        assert_eq!(wordlist.iter().last(), Some("dev"));
+
+        wordlist.shuffle(&mut ChaCha8Rng::seed_from_u64(7));
+        let mut shuffled: Vec<_> = wordlist.iter().collect();
+        shuffled.sort();
+        assert_eq!(shuffled, ["api", "dev", "mail", "www"]);
+
+        assert_eq!(wordlist.promote(&["dev".to_string(), "WWW".to_lowercase(), "vpn".to_string()]), 2);
+        assert_eq!(wordlist.iter().take(2).collect::<Vec<_>>(), ["dev", "www"]);
+        assert_eq!(wordlist.len(), 4);
+        std::fs::remove_file(path).unwrap();
+    }
+}